@@ -1,18 +1,64 @@
 use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 use std::{env, ffi::OsString};
 
-use signatures::sig_set::{dynamic_set::DynSet, heuristic_set::HeurSet, sha_set::ShaSet, SigSet};
+use signatures::sig_set::{
+    anomaly_set::AnomalySet,
+    block_set::BlockSet,
+    code_set::CodeSet,
+    dynamic_set::DynSet,
+    fuzzy_set::FuzzySet,
+    heuristic_set::HeurSet,
+    model_set::ModelSet,
+    pattern_set::PatternSet,
+    pe_hash_set::PeHashSet,
+    set_signing::{self, TrustedKeys},
+    sha_set::ShaSet,
+    sig_lint,
+    sig_source::SigSource,
+    sig_test,
+    sigset_container::SetContainer,
+    sigset_file::{self, SigSetFile},
+    sigset_info::SetInfo,
+    string_set::StringSet,
+    SigSet,
+};
 
+/// Each file of "dir" becomes a signature: md5, sha1, sha256 and sha512 of file, its ssdeep with
+/// "-f", with "-e" signatures of its imphash, Rich header and executable sections, or with "-k"
+/// hashes of basic blocks of its code
 #[derive(clap::Args)]
 pub struct CompileRaw {
+    /// Create Set from ssdeep hashes of files
+    #[clap(short = 'f')]
+    fuzzy_set: bool,
+    /// Create Set from PE structure hashes of files
+    #[clap(short = 'e', conflicts_with = "fuzzy_set")]
+    pe_hash_set: bool,
+    /// Create Set from hashes of basic blocks of PE files
+    #[clap(short = 'k', conflicts_with_all = ["fuzzy_set", "pe_hash_set"])]
+    block_set: bool,
+    /// Dir of benign files. Their blocks are left out of signatures of "-k"
+    #[clap(long, requires = "block_set")]
+    benign_dir: Option<String>,
+    /// Fraction of blocks which must be found for signature of "-k" to match. Half if not given
+    #[clap(long, requires = "block_set")]
+    fraction: Option<f64>,
     /// Malware dir
     #[clap(short, long)]
     dir: String,
     /// Out path of sigset. Extenstion should be "sset"
     #[clap(short, long)]
     out_path: String,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
 }
 
+/// With one of "-s", "-i", "-d", "-p", "-f", "-e", "-a", "-t", "-x", "-k", "-m" single set is
+/// created from signatures in "dir". Otherwise container is created from chosen (or all)
+/// subdirectories of "dir": "sha", "heur", "dyn", "pattern", "fuzzy", "pehash", "anomaly",
+/// "strings", "code", "blocks" and "model"
 #[derive(clap::Args)]
 pub struct Compile {
     /// Create Set from sha signatures
@@ -24,30 +70,220 @@ pub struct Compile {
     /// Create Set from dynamic signatures
     #[clap(short = 'd')]
     dynamic_set: bool,
-    /// Signature directory
+    /// Create Set from byte pattern signatures
+    #[clap(short = 'p')]
+    pattern_set: bool,
+    /// Create Set from fuzzy hash signatures
+    #[clap(short = 'f')]
+    fuzzy_set: bool,
+    /// Create Set from PE hash signatures
+    #[clap(short = 'e')]
+    pe_hash_set: bool,
+    /// Create Set from PE anomaly signatures
+    #[clap(short = 'a')]
+    anomaly_set: bool,
+    /// Create Set from string signatures
+    #[clap(short = 't')]
+    string_set: bool,
+    /// Create Set from code signatures
+    #[clap(short = 'x')]
+    code_set: bool,
+    /// Create Set from basic block signatures
+    #[clap(short = 'k')]
+    block_set: bool,
+    /// Create Set from model signatures
+    #[clap(short = 'm')]
+    model_set: bool,
+    /// Compiled behavioural set added to container. Optional
+    #[clap(short, long)]
+    bedet_set: Option<String>,
+    /// Signature directory. Subdirectories are read too
     #[clap(long)]
     dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
     /// Output name/path of sigset. Extenstion should be "sset"
     #[clap(short, long)]
     out_path: String,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
 }
 
+/// Signatures of single set are unpacked to "out_dir", signatures of container to its
+/// subdirectories "sha", "heur", "dyn", "pattern", "fuzzy", "pehash", "anomaly", "strings", "code",
+/// "blocks" and "model"
 #[derive(clap::Args)]
 pub struct Unpack {
-    /// Path to sset
-    #[clap(short, long)]
-    sha_set: String,
+    /// Path to sset or container
+    #[clap(short, long, alias = "sha-set")]
+    set_path: String,
     /// Directory where sigs should be unpack
     #[clap(short, long)]
     out_dir: String,
 }
 
+#[derive(clap::Args)]
+pub struct List {
+    /// Path to sset or container
+    #[clap(value_name = "SET")]
+    set_path: String,
+    /// List only signatures which name contains given text
+    #[clap(short, long)]
+    name: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Add {
+    /// Path to sset or container
+    #[clap(value_name = "SET")]
+    set_path: String,
+    /// Signatures to add. Each goes to set of its type
+    #[clap(value_name = "SIG", required = true)]
+    sig_paths: Vec<String>,
+    /// Output path. By default set is changed in place
+    #[clap(short, long)]
+    out_path: Option<String>,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
+}
+
+#[derive(clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct RemoveBy {
+    /// Name of signature to remove
+    #[clap(long)]
+    name: Option<String>,
+    /// md5, sha1, sha256 or sha512 of hash signature to remove
+    #[clap(long, alias = "sha256")]
+    hash: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Remove {
+    /// Path to sset or container
+    #[clap(value_name = "SET")]
+    set_path: String,
+    #[clap(flatten)]
+    remove_by: RemoveBy,
+    /// Output path. By default set is changed in place
+    #[clap(short, long)]
+    out_path: Option<String>,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Merge {
+    /// Path to first sset or container
+    #[clap(value_name = "SET")]
+    first: String,
+    /// Path to second sset or container. Its signatures are added after signatures of first one
+    #[clap(value_name = "SET")]
+    second: String,
+    /// Output path. Sets of different types are merged into container
+    #[clap(short, long)]
+    out_path: String,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Lint {
+    /// Signature directory. Subdirectories are checked too
+    #[clap(long)]
+    dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
+}
+
+#[derive(clap::Args)]
+pub struct Test {
+    /// Signature directory. Subdirectories are read too
+    #[clap(long)]
+    dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
+}
+
+/// Logistic regression is fitted on PE files of "malicious_dir" and "benign_dir" and written as
+/// model set
+#[derive(clap::Args)]
+pub struct Train {
+    /// Dir of malicious files
+    #[clap(long)]
+    malicious_dir: String,
+    /// Dir of benign files
+    #[clap(long)]
+    benign_dir: String,
+    /// Name of model signature
+    #[clap(long, default_value = "Model.Malware")]
+    name: String,
+    /// Probability from which file is reported. 0.5 if not given
+    #[clap(long)]
+    threshold: Option<f64>,
+    /// Out path of set. Extension should be "sset"
+    #[clap(short, long)]
+    out_path: String,
+    /// Ed25519 secret key file (32 bytes in hex) the set is signed with. Signature is written
+    /// to "{out_path}.sig"
+    #[clap(long)]
+    sign_key: Option<String>,
+}
+
+/// Globs without '/' match file names, other globs match paths relative to signature directory
+#[derive(clap::Args)]
+pub struct SourceFilter {
+    /// Only signature files matching any of these globs are read, e.g. "emotet/*/*.yml"
+    #[clap(long)]
+    include: Vec<String>,
+    /// Signature files matching any of these globs are skipped
+    #[clap(long)]
+    exclude: Vec<String>,
+}
+
+/// Sets are loaded only if they are signed with one of trusted keys
+#[derive(clap::Args)]
+pub struct Trust {
+    /// File with trusted Ed25519 public keys in hex, one per line
+    #[clap(long)]
+    trusted_keys: Option<String>,
+    /// Load also unsigned sets and sets not signed with trusted key
+    #[clap(long)]
+    allow_untrusted: bool,
+}
+
 #[derive(Subcommand)]
 pub enum SignatureCommand {
     CompileRaw(CompileRaw),
     Compile(Compile),
     Unpack(Unpack),
-    //List(List), - todo in future
+    /// Add signatures to compiled set
+    Add(Add),
+    /// Remove signatures from compiled set
+    Remove(Remove),
+    /// Merge two compiled sets
+    Merge(Merge),
+    /// Print header and signatures of compiled set
+    List(List),
+    /// Check signatures before they are compiled. Fails if any problem is found
+    Lint(Lint),
+    /// Compile signatures in memory and check samples declared in their "tests". Fails if any
+    /// sample is not matched as declared
+    Test(Test),
+}
+
+#[derive(Subcommand)]
+pub enum ModelCommand {
+    /// Train model on labelled files
+    Train(Train),
 }
 
 #[derive(Subcommand)]
@@ -55,6 +291,9 @@ enum Commands {
     /// Build malware signature set
     #[command(subcommand)]
     Signature(SignatureCommand),
+    /// Build local model which tells malicious files from benign
+    #[command(subcommand)]
+    Model(ModelCommand),
     /// Evaluate a suspected file
     Evaluate {
         /// Path to sha signature set. Optional
@@ -63,18 +302,49 @@ enum Commands {
         /// Path to heur signature set. Optional
         #[clap(short = 'i')]
         heur_sig_path: Option<String>,
+        /// Path to byte pattern signature set. Optional
+        #[clap(short = 'p')]
+        pattern_sig_path: Option<String>,
+        /// Path to fuzzy hash signature set. Optional
+        #[clap(short = 'f')]
+        fuzzy_sig_path: Option<String>,
+        /// Path to PE hash signature set. Optional
+        #[clap(short = 'e')]
+        pe_hash_sig_path: Option<String>,
+        /// Path to PE anomaly signature set. Optional
+        #[clap(short = 'a')]
+        anomaly_sig_path: Option<String>,
+        /// Path to string signature set. Optional
+        #[clap(short = 't')]
+        string_sig_path: Option<String>,
+        /// Path to code signature set. Optional
+        #[clap(short = 'x')]
+        code_sig_path: Option<String>,
+        /// Path to basic block signature set. Optional
+        #[clap(short = 'k')]
+        block_sig_path: Option<String>,
+        /// Path to model set. Optional
+        #[clap(short = 'm')]
+        model_sig_path: Option<String>,
+        /// Path to signature container. Optional
+        #[clap(short)]
+        container_path: Option<String>,
         /// Path to scan. Dir or file
         #[clap(value_name = "PATH")]
         file_path: String,
+        #[clap(flatten)]
+        trust: Trust,
     },
     /// Sandbox a suspected file
     Sandbox {
-        /// Path to dynamic signature set. Optional
+        /// Path to dynamic signature set or container with it
         #[clap(short = 'd')]
         dyn_sig_path: String,
         /// Path to scan. Dir or file
         #[clap(value_name = "PATH")]
         file_path: String,
+        #[clap(flatten)]
+        trust: Trust,
     },
 }
 
@@ -112,30 +382,38 @@ pub fn main() -> anyhow::Result<()> {
     match args.commands {
         Commands::Signature(signature_command) => match signature_command {
             SignatureCommand::Compile(args) => {
-                let (set_type, magic) = get_magic_for_sigset(&args);
-
-                let ser = match set_type {
-                    SetType::Sha => {
-                        let set = ShaSet::from_signatures(args.dir.as_str())?;
-                        set.to_sig_set()
-                    },
-                    SetType::Heur => {
-                        let set = HeurSet::from_signatures(args.dir.as_str())?;
-                        set.to_sig_set()
-                    },
-                    SetType::Dyn => {
-                        let set = DynSet::from_signatures(args.dir.as_str())?;
-                        set.to_sig_set()
-                    },
-                };
+                let magics = get_set_types(&args);
+
+                if let ([magic], None) = (magics.as_slice(), &args.bedet_set) {
+                    let source = sig_source(&args.dir, &args.filter)?;
+                    let ser = SigSetFile::compile_source(*magic, &source)?;
+                    let number = ser.serialize(&args.out_path, *magic)?;
+                    println!("SUCCESS to compile set. Count: {number}");
+                    return sign_set(&args.out_path, &args.sign_key);
+                }
 
-                match ser.serialize(&args.out_path, magic) {
-                    Ok(number) => println!("SUCCESS to compile set. Count: {number}"),
-                    Err(e) => log::error!("Failed to compile sigs. Err: {e}"),
+                let mut container = SetContainer::new_empty();
+                for magic in magics {
+                    let dir_name = sigset_file::section_dir_name(magic)?;
+                    let dir = std::path::Path::new(&args.dir).join(dir_name);
+                    if !dir.is_dir() {
+                        log::warn!("No {dir_name} signatures in {}", &args.dir);
+                        continue;
+                    }
+                    let source = sig_source(&dir.to_string_lossy(), &args.filter)?;
+                    let ser = SigSetFile::compile_source(magic, &source)?;
+                    container.add_section(&ser, magic)?;
+                }
+                if let Some(bedet_set) = &args.bedet_set {
+                    container.add_raw_section(std::fs::read(bedet_set)?)?;
                 }
+
+                let number = container.serialize(&args.out_path)?;
+                println!("SUCCESS to compile container. Sections: {number}");
+                sign_set(&args.out_path, &args.sign_key)?;
             },
             SignatureCommand::Unpack(args) => {
-                let sha_set = signatures::deserialize_sha_set_from_path(args.sha_set.as_str())?;
+                let set_file = SigSetFile::open(&args.set_path)?;
                 if std::path::Path::new(&args.out_dir).exists() {
                     let md = std::fs::metadata(&args.out_dir)?;
                     if md.is_file() {
@@ -151,35 +429,173 @@ pub fn main() -> anyhow::Result<()> {
                 if let Err(e) = res {
                     log::warn!("Failed to create dir: {}. Err: {e}", &args.out_dir);
                 } else {
-                    match sha_set.unpack_to_dir(&args.out_dir) {
-                        Ok(number) => println!("SUCCESS to unpack shaset. Count: {number}"),
+                    match set_file.unpack_to_dir(&args.out_dir) {
+                        Ok(number) => println!("SUCCESS to unpack set. Count: {number}"),
                         Err(e) => log::error!("Failed to create dir: {}. Err: {e}", &args.out_dir),
                     }
                 }
             },
+            SignatureCommand::List(args) => {
+                for set in SetInfo::read_all(&args.set_path)? {
+                    println!("magic: {}", set.magic);
+                    let checksum =
+                        signatures::sha256_utils::convert_sha256_to_string(&set.checksum)?;
+                    println!("checksum: {checksum}");
+                    println!("count: {}", set.elem_count);
+                    let signatures = set.signatures.iter().filter(|sig| {
+                        args.name
+                            .as_ref()
+                            .is_none_or(|name| sig.name.contains(name.as_str()))
+                    });
+                    for sig in signatures {
+                        println!("  - name: {}", sig.name);
+                        println!("    description: {}", sig.description);
+                        println!("    features: {}", sig.features.join(", "));
+                    }
+                }
+            },
+            SignatureCommand::Add(args) => {
+                let mut set_file = SigSetFile::open(&args.set_path)?;
+                for sig_path in &args.sig_paths {
+                    let description = std::fs::read_to_string(sig_path)?;
+                    set_file.add_signature(description)?;
+                }
+
+                let out_path = args.out_path.as_ref().unwrap_or(&args.set_path);
+                let number = set_file.save(out_path)?;
+                println!("SUCCESS to add signatures. Count: {number}");
+                sign_set(out_path, &args.sign_key)?;
+            },
+            SignatureCommand::Remove(args) => {
+                let mut set_file = SigSetFile::open(&args.set_path)?;
+                let removed = match (&args.remove_by.name, &args.remove_by.hash) {
+                    (Some(name), _) => set_file.remove_by_name(name)?,
+                    (_, Some(hash)) => set_file.remove_by_hash(hash)?,
+                    _ => 0,
+                };
+                if removed == 0 {
+                    log::warn!("No signature to remove");
+                    return Ok(());
+                }
+
+                let out_path = args.out_path.as_ref().unwrap_or(&args.set_path);
+                let number = set_file.save(out_path)?;
+                println!("SUCCESS to remove {removed} signatures. Count: {number}");
+                sign_set(out_path, &args.sign_key)?;
+            },
+            SignatureCommand::Merge(args) => {
+                let mut set_file = SigSetFile::open(&args.first)?;
+                set_file.merge(SigSetFile::open(&args.second)?)?;
+                let number = set_file.save(&args.out_path)?;
+                println!("SUCCESS to merge sets. Count: {number}");
+                sign_set(&args.out_path, &args.sign_key)?;
+            },
+            SignatureCommand::Lint(args) => {
+                let issues = sig_lint::lint(&sig_source(&args.dir, &args.filter)?)?;
+                for issue in &issues {
+                    println!("{issue}");
+                }
+                if !issues.is_empty() {
+                    anyhow::bail!("Found {} problems in signatures", issues.len());
+                }
+                println!("SUCCESS to lint signatures");
+            },
+            SignatureCommand::Test(args) => {
+                let results = sig_test::test_source(&sig_source(&args.dir, &args.filter)?)?;
+                for result in &results {
+                    println!("{result}");
+                }
+                let failed = results.iter().filter(|result| !result.passed()).count();
+                if failed > 0 {
+                    anyhow::bail!("{failed} of {} samples failed", results.len());
+                }
+                println!("SUCCESS to test signatures. Samples: {}", results.len());
+            },
             SignatureCommand::CompileRaw(args) => {
-                let sha_set = ShaSet::from_dir(args.dir.as_str())?;
-                let ser = sha_set.to_sig_set();
-                ser.serialize(&args.out_path, ShaSet::SET_MAGIC_U32)?;
+                if args.fuzzy_set {
+                    let fuzzy_set = FuzzySet::from_dir(args.dir.as_str())?;
+                    let ser = fuzzy_set.to_sig_set()?;
+                    ser.serialize(&args.out_path, FuzzySet::SET_MAGIC_U32)?;
+                } else if args.pe_hash_set {
+                    let pe_hash_set = PeHashSet::from_dir(args.dir.as_str())?;
+                    let ser = pe_hash_set.to_sig_set()?;
+                    ser.serialize(&args.out_path, PeHashSet::SET_MAGIC_U32)?;
+                } else if args.block_set {
+                    let block_set = BlockSet::from_dir(
+                        args.dir.as_str(),
+                        args.benign_dir.as_deref(),
+                        args.fraction,
+                    )?;
+                    let ser = block_set.to_sig_set()?;
+                    ser.serialize(&args.out_path, BlockSet::SET_MAGIC_U32)?;
+                } else {
+                    let sha_set = ShaSet::from_dir(args.dir.as_str())?;
+                    let ser = sha_set.to_sig_set()?;
+                    ser.serialize(&args.out_path, ShaSet::SET_MAGIC_U32)?;
+                }
+                sign_set(&args.out_path, &args.sign_key)?;
+            },
+        },
+        Commands::Model(model_command) => match model_command {
+            ModelCommand::Train(args) => {
+                let set = ModelSet::train(
+                    &args.malicious_dir,
+                    &args.benign_dir,
+                    &args.name,
+                    args.threshold,
+                )?;
+                let ser = set.to_sig_set()?;
+                ser.serialize(&args.out_path, ModelSet::SET_MAGIC_U32)?;
+                println!("SUCCESS to train model");
+                sign_set(&args.out_path, &args.sign_key)?;
             },
         },
         Commands::Evaluate {
             sha_sig_path,
             heur_sig_path,
+            pattern_sig_path,
+            fuzzy_sig_path,
+            pe_hash_sig_path,
+            anomaly_sig_path,
+            string_sig_path,
+            code_sig_path,
+            block_sig_path,
+            model_sig_path,
+            container_path,
             file_path,
+            trust,
         } => {
-            if sha_sig_path.is_none() && heur_sig_path.is_none() {
+            set_trusted_keys(&trust)?;
+            let sig_paths: Vec<String> = [
+                sha_sig_path,
+                heur_sig_path,
+                pattern_sig_path,
+                fuzzy_sig_path,
+                pe_hash_sig_path,
+                anomaly_sig_path,
+                string_sig_path,
+                code_sig_path,
+                block_sig_path,
+                model_sig_path,
+                container_path,
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if sig_paths.is_empty() {
                 //something wrong
                 log::warn!("You need specify at least one set");
                 return Ok(());
             } else {
-                scanner::scan_path(file_path.as_str(), sha_sig_path, heur_sig_path)?
+                scanner::scan_path(file_path.as_str(), sig_paths)?
             }
         },
         Commands::Sandbox {
             dyn_sig_path,
             file_path,
+            trust,
         } => {
+            set_trusted_keys(&trust)?;
             let v = sandbox::sandbox_path(file_path.as_str())?;
             scanner::scan_api_calls(v, dyn_sig_path)?
         },
@@ -188,24 +604,56 @@ pub fn main() -> anyhow::Result<()> {
     Ok(())
 }
 pub type Magic = u32;
-enum SetType {
-    Sha,
-    Heur,
-    Dyn,
-}
-
-fn get_magic_for_sigset(args: &Compile) -> (SetType, Magic) {
-    match (args.sha_set, args.heuristic_set, args.dynamic_set) {
-        (true, false, false) => (SetType::Sha, ShaSet::SET_MAGIC_U32),
-        (false, true, false) => (SetType::Heur, HeurSet::SET_MAGIC_U32),
-        (false, false, true) => (SetType::Dyn, DynSet::SET_MAGIC_U32),
-        _ => {
-            let mut cmd = Cli::command();
-            cmd.error(
-                ErrorKind::ArgumentConflict,
-                "You can choose only one version of signatures",
-            )
-            .exit();
-        },
+
+fn get_set_types(args: &Compile) -> Vec<Magic> {
+    let chosen = [
+        (args.sha_set, ShaSet::SET_MAGIC_U32),
+        (args.heuristic_set, HeurSet::SET_MAGIC_U32),
+        (args.dynamic_set, DynSet::SET_MAGIC_U32),
+        (args.pattern_set, PatternSet::SET_MAGIC_U32),
+        (args.fuzzy_set, FuzzySet::SET_MAGIC_U32),
+        (args.pe_hash_set, PeHashSet::SET_MAGIC_U32),
+        (args.anomaly_set, AnomalySet::SET_MAGIC_U32),
+        (args.string_set, StringSet::SET_MAGIC_U32),
+        (args.code_set, CodeSet::SET_MAGIC_U32),
+        (args.block_set, BlockSet::SET_MAGIC_U32),
+        (args.model_set, ModelSet::SET_MAGIC_U32),
+    ];
+    if chosen.iter().all(|(is_chosen, _)| !is_chosen) {
+        return chosen.into_iter().map(|(_, magic)| magic).collect();
     }
+    chosen
+        .into_iter()
+        .filter(|(is_chosen, _)| *is_chosen)
+        .map(|(_, magic)| magic)
+        .collect()
+}
+
+fn sig_source(dir: &str, filter: &SourceFilter) -> anyhow::Result<SigSource> {
+    let mut source = SigSource::new(dir);
+    for glob in &filter.include {
+        source = source.include(glob)?;
+    }
+    for glob in &filter.exclude {
+        source = source.exclude(glob)?;
+    }
+    Ok(source)
+}
+
+// set changed by any command has to be signed again, otherwise it is not trusted
+fn sign_set(set_path: &str, sign_key: &Option<String>) -> anyhow::Result<()> {
+    if let Some(sign_key) = sign_key {
+        let public_key = set_signing::sign_set_file(set_path, sign_key)?;
+        println!("SUCCESS to sign set. Public key: {public_key}");
+    }
+    Ok(())
+}
+
+fn set_trusted_keys(trust: &Trust) -> anyhow::Result<()> {
+    let keys = match &trust.trusted_keys {
+        Some(path) => TrustedKeys::from_file(path)?,
+        None => TrustedKeys::empty(),
+    };
+    set_signing::set_trusted_keys(keys.allow_untrusted(trust.allow_untrusted));
+    Ok(())
 }
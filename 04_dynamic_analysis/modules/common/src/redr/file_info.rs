@@ -8,7 +8,17 @@ pub struct FileInfo {
     pub name: String,
     pub path: PathBuf,
     pub canonical_path: String,
+    /// Digests of file, known once it was scanned by hash signatures
+    pub hashes: Option<FileHashes>,
+}
+
+/// Hex digests of file content. Only digests hash signatures are compared with are computed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileHashes {
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
     pub sha256: Option<String>,
+    pub sha512: Option<String>,
 }
 
 impl FileInfo {
@@ -28,7 +38,7 @@ impl FileInfo {
             name,
             path,
             canonical_path,
-            sha256: None,
+            hashes: None,
         }
     }
 }
@@ -42,3 +52,18 @@ impl Display for FileInfo {
         )
     }
 }
+
+impl Display for FileHashes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let hashes = [
+            ("md5", &self.md5),
+            ("sha1", &self.sha1),
+            ("sha256", &self.sha256),
+            ("sha512", &self.sha512),
+        ];
+        let hashes: Vec<String> = (hashes.iter())
+            .filter_map(|(name, hash)| Some(format!("{name}: \"{}\"", hash.as_ref()?)))
+            .collect();
+        write!(f, "{}", hashes.join(", "))
+    }
+}
@@ -3,7 +3,7 @@ use std::{cell::RefCell, path::PathBuf, rc::Rc};
 
 pub type RcMut<T> = Rc<RefCell<T>>;
 
-use crate::redr::FileInfo;
+use crate::redr::{FileHashes, FileInfo};
 
 pub enum FileScanInfo {
     RealFile(RcMut<FileInfo>),
@@ -20,10 +20,15 @@ impl FileScanInfo {
             FileScanInfo::RealFile(file) => {
                 let name: String = file.borrow().name.clone();
                 let path: String = file.borrow().canonical_path.clone();
+                let hashes = match &file.borrow().hashes {
+                    Some(hashes) => format!("{hashes}, "),
+                    None => String::new(),
+                };
 
                 format!(
-                    "\"{name}\" -> Malicious {{ path: \"{path}\", desc: {}, cause: {} }}",
-                    detection_info.desc, detection_info.cause
+                    "\"{name}\" -> Malicious {{ {hashes}path: \"{path}\", sig: {}, desc: {}, \
+                     cause: {} }}",
+                    detection_info.name, detection_info.desc, detection_info.cause
                 )
             },
             FileScanInfo::EmbeddedFile {
@@ -33,19 +38,18 @@ impl FileScanInfo {
                 let original_name: String = file.borrow().name.clone();
                 let path: String = file.borrow().canonical_path.clone();
 
-                let sha256 = file
-                    .borrow()
-                    .sha256
-                    .clone()
-                    .unwrap_or("UNKNOWN".to_string());
+                let hashes = match &file.borrow().hashes {
+                    Some(hashes) => hashes.to_string(),
+                    None => "hashes: UNKNOWN".to_string(),
+                };
 
                 let cause = format!(
-                    "EmbeddedFile: {{ name: {name}, desc: {}, cause: {} }}",
-                    detection_info.desc, detection_info.cause
+                    "EmbeddedFile: {{ name: {name}, sig: {}, desc: {}, cause: {} }}",
+                    detection_info.name, detection_info.desc, detection_info.cause
                 );
                 format!(
-                    "\"{original_name}\" -> Malicious {{ sha256: \"{sha256}\", path: \"{path}\", \
-                     cause: {cause} }}"
+                    "\"{original_name}\" -> Malicious {{ {hashes}, path: \"{path}\", cause: \
+                     {cause} }}"
                 )
             },
         }
@@ -61,9 +65,9 @@ impl FileScanInfo {
         }
     }
 
-    pub fn set_sha(&mut self, sha: String) {
+    pub fn set_hashes(&mut self, hashes: FileHashes) {
         if let FileScanInfo::RealFile(rc) = self {
-            rc.borrow_mut().sha256 = Some(sha);
+            rc.borrow_mut().hashes = Some(hashes);
         }
     }
 
@@ -4,19 +4,12 @@ use crate::{api_calls::eval_api_calls, error::ScanError, scan::scan_files};
 use common::redr;
 use signatures::sig_set::SigSet;
 
-pub fn scan_path(
-    target_path: &str,
-    sha_sig_path: Option<String>,
-    heur_sig_path: Option<String>,
-) -> Result<(), ScanError> {
+// each of sig_paths is single set or container with many sets
+pub fn scan_path(target_path: &str, sig_paths: Vec<String>) -> Result<(), ScanError> {
     let mut signatures_vec = vec![];
-    if let Some(sha_sig_path) = sha_sig_path {
-        let signatures = signatures::deserialize_set_from_path(sha_sig_path.as_str())?;
-        signatures_vec.push(signatures)
-    }
-    if let Some(heur_sig_path) = heur_sig_path {
-        let signatures = signatures::deserialize_set_from_path(heur_sig_path.as_str())?;
-        signatures_vec.push(signatures)
+    for sig_path in sig_paths {
+        let mut signatures = signatures::deserialize_sets_from_path(sig_path.as_str())?;
+        signatures_vec.append(&mut signatures)
     }
 
     if signatures_vec.is_empty() {
@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::error::ScanError;
-use common::redr;
+use common::{detection::DetectionReport, redr};
 use signatures::sig_set::SigSet;
 
 const MAX_FILE_TO_SCAN: usize = 0x100;
@@ -17,15 +17,18 @@ pub fn scan_files(
         if let Some((mut reader, mut variant)) = files_queue.pop_front() {
             log::debug!("Start scanning {i} file");
 
+            let mut detections = vec![];
             for signatures in &signatures_vec {
                 //set file pointer to 0 to be sure we read from the file beginning
                 reader.seek(Start(0))?;
 
-                if let Some(detection_info) = signatures.eval_file(&mut reader, &mut variant)? {
-                    //todo: do some action with detection info
-                    println!("{}", variant.get_malware_info(detection_info));
-                    break;
-                }
+                detections.append(&mut signatures.eval_file(&mut reader, &mut variant)?);
+            }
+
+            DetectionReport::sort_by_priority(&mut detections);
+            for detection_info in detections {
+                //todo: do some action with detection info
+                println!("{}", variant.get_malware_info(detection_info));
             }
 
             //set file pointer to 0 to be sure we read from the file beginning
@@ -1,3 +1,4 @@
+use crate::sig_set::sig_source::SigPosition;
 use std::ffi::OsString;
 
 use thiserror::Error;
@@ -8,14 +9,24 @@ pub enum SigSetError {
     BincodeDeserializeError(#[from] bincode::error::DecodeError),
     #[error("Bincode serialize error: {0}")]
     BincodeSerializeError(#[from] bincode::error::EncodeError),
+    #[error("Section '{0}' is already in container")]
+    DuplicatedSectionError(String),
+    #[error("Signature '{0}' is already in set")]
+    DuplicatedSignatureError(String),
     #[error("FileObjectError: {0}")]
     FileObjectError(#[from] object::Error),
+    #[error("Glob error: {0}")]
+    GlobError(#[from] globset::Error),
     #[error("Incorrect magic. Found '{current}'")]
     IncorrectMagicError { current: String },
     #[error("Incorrect checksum. Expected '{expected}' but found '{current}'")]
     IncorrectChecksumError { current: String, expected: String },
     #[error("Incorrect file size. Size: '{size}'")]
     IncorrectFileSizeError { size: u64 },
+    #[error("Incorrect key: {0}")]
+    IncorrectKeyError(String),
+    #[error("Incorrect .NET metadata: {0}")]
+    IncorrectMetadataError(String),
     #[error("Incorrect signature size. Size: '{size}'")]
     IncorrectSignatureSizeError { size: u32 },
     #[error("Incorrect signature. Info: '{info}'")]
@@ -24,12 +35,30 @@ pub enum SigSetError {
     IoError(#[from] std::io::Error),
     #[error("Given property doesn't exist in map: {0}")]
     NoSuchPropertyError(String),
+    #[error("There is no '{0}' set in file")]
+    NoSuchSectionError(String),
+    #[error("No PE files to train model on in '{0}'")]
+    NoTrainingFilesError(String),
+    #[error("Not a PE file: {0}")]
+    NotPeError(String),
     #[error("Can't convert OsString to String. After to_string_lossy(): {0}")]
     OsStringError(String),
     #[error("Serde yaml error: {0}")]
     SerdeYamlError(#[from] serde_yaml::Error),
+    #[error("{path}, {position}: {source}")]
+    SignatureFileError {
+        path: String,
+        position: SigPosition,
+        source: Box<SigSetError>,
+    },
     #[error("ToHex error: {0}")]
     ToHexError(#[from] hex::FromHexError),
+    #[error("Set '{0}' is not signed")]
+    UnsignedSetError(String),
+    #[error("Set '{0}' is not signed with trusted key")]
+    UntrustedSetError(String),
+    #[error("Unsupported version of container: {0}")]
+    UnsupportedVersionError(u32),
 }
 
 impl From<OsString> for SigSetError {
@@ -0,0 +1,183 @@
+use std::io;
+
+use crate::{sha256_utils::Sha256, SigSetError};
+use common::redr::FileHashes;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+
+/// Hash algorithm hash signatures may declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// In order signatures are keyed by: sha256 first, so keys of sha256 signatures don't change
+    pub const ALL: [HashAlgorithm; 4] = [Self::Sha256, Self::Sha512, Self::Sha1, Self::Md5];
+
+    /// Name of signature property
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    fn digest_len(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+
+    /// Algorithm of signature property, None if property is not a hash
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    /// Digest of file, None if it wasn't computed
+    pub fn digest(self, hashes: &FileDigests) -> Option<&[u8]> {
+        match self {
+            Self::Md5 => hashes.md5.as_ref().map(|digest| digest.as_slice()),
+            Self::Sha1 => hashes.sha1.as_ref().map(|digest| digest.as_slice()),
+            Self::Sha256 => hashes.sha256.as_ref().map(|digest| digest.as_slice()),
+            Self::Sha512 => hashes.sha512.as_ref().map(|digest| digest.as_slice()),
+        }
+    }
+
+    pub fn digest_from_string(self, s: &str) -> Result<Vec<u8>, SigSetError> {
+        let digest = hex::decode(s)?;
+        if digest.len() != self.digest_len() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("Can't convert {s} to {}", self.name()),
+            });
+        }
+        Ok(digest)
+    }
+}
+
+/// Digests of file of chosen algorithms, computed in one read
+#[derive(Debug, Clone, Default)]
+pub struct FileDigests {
+    pub md5: Option<[u8; 16]>,
+    pub sha1: Option<[u8; 20]>,
+    pub sha256: Option<Sha256>,
+    pub sha512: Option<[u8; 64]>,
+}
+
+impl FileDigests {
+    pub fn from_file_pointer(
+        file: &mut impl io::Read,
+        algorithms: &[HashAlgorithm],
+    ) -> Result<Self, io::Error> {
+        let chosen = |algorithm| algorithms.contains(&algorithm);
+        let mut md5 = chosen(HashAlgorithm::Md5).then(Md5::new);
+        let mut sha1 = chosen(HashAlgorithm::Sha1).then(Sha1::new);
+        let mut sha256 = chosen(HashAlgorithm::Sha256).then(sha2::Sha256::new);
+        let mut sha512 = chosen(HashAlgorithm::Sha512).then(Sha512::new);
+        let mut buffer = [0; 4096];
+        loop {
+            let bytes_read = file.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            let data = &buffer[..bytes_read];
+            md5.iter_mut().for_each(|hasher| hasher.update(data));
+            sha1.iter_mut().for_each(|hasher| hasher.update(data));
+            sha256.iter_mut().for_each(|hasher| hasher.update(data));
+            sha512.iter_mut().for_each(|hasher| hasher.update(data));
+        }
+        Ok(Self {
+            md5: md5.map(|hasher| hasher.finalize().into()),
+            sha1: sha1.map(|hasher| hasher.finalize().into()),
+            sha256: sha256.map(|hasher| hasher.finalize().into()),
+            sha512: sha512.map(|hasher| hasher.finalize().into()),
+        })
+    }
+
+    pub fn from_path(file_path: &str, algorithms: &[HashAlgorithm]) -> Result<Self, io::Error> {
+        Self::from_file_pointer(&mut std::fs::File::open(file_path)?, algorithms)
+    }
+
+    pub fn to_file_hashes(&self) -> FileHashes {
+        FileHashes {
+            md5: self.md5.map(hex::encode_upper),
+            sha1: self.sha1.map(hex::encode_upper),
+            sha256: self.sha256.map(hex::encode_upper),
+            sha512: self.sha512.map(hex::encode_upper),
+        }
+    }
+}
+
+/// Key of digest in sha set. sha256 is the key itself, other digests are hashed together with
+/// name of their algorithm, so digests of different algorithms never share key
+pub fn hash_key(algorithm: HashAlgorithm, digest: &[u8]) -> Sha256 {
+    match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut key = Sha256::default();
+            key.copy_from_slice(digest);
+            key
+        },
+        _ => sha2::Sha256::new()
+            .chain_update(algorithm.name())
+            .chain_update(b":")
+            .chain_update(digest)
+            .finalize()
+            .into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_of_all_algorithms() {
+        let digests =
+            FileDigests::from_file_pointer(&mut io::Cursor::new(b"abc"), &HashAlgorithm::ALL)
+                .unwrap();
+        let hashes = digests.to_file_hashes();
+        assert_eq!(hashes.md5.unwrap(), "900150983CD24FB0D6963F7D28E17F72");
+        assert_eq!(
+            hashes.sha1.unwrap(),
+            "A9993E364706816ABA3E25717850C26C9CD0D89D"
+        );
+        assert_eq!(
+            hashes.sha256.unwrap(),
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        );
+        assert!(hashes
+            .sha512
+            .unwrap()
+            .starts_with("DDAF35A193617ABACC417349AE204131"));
+
+        for algorithm in HashAlgorithm::ALL {
+            let digest = algorithm.digest(&digests).unwrap();
+            assert_eq!(HashAlgorithm::from_name(algorithm.name()), Some(algorithm));
+            let text = hex::encode(digest);
+            assert_eq!(algorithm.digest_from_string(&text).unwrap(), digest);
+            assert!(algorithm.digest_from_string(&text[2..]).is_err());
+        }
+        assert_eq!(
+            hash_key(HashAlgorithm::Sha256, &digests.sha256.unwrap()),
+            digests.sha256.unwrap()
+        );
+
+        // only chosen digests are computed
+        let digests =
+            FileDigests::from_file_pointer(&mut io::Cursor::new(b"abc"), &[HashAlgorithm::Md5])
+                .unwrap();
+        assert!(digests.md5.is_some());
+        assert!(digests.sha1.is_none() && digests.sha256.is_none() && digests.sha512.is_none());
+    }
+}
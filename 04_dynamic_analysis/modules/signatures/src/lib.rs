@@ -1,6 +1,7 @@
 extern crate core;
 
 pub mod error;
+pub mod file_hashes;
 pub mod sha256_utils;
 pub mod sig_set;
 
@@ -16,23 +17,21 @@ pub fn deserialize_set_from_path(set_path: &str) -> Result<Box<dyn SigSet>, SigS
     des.get_set()
 }
 
+// every set which can evaluate files, from single set file or from all sections of container
+pub fn deserialize_sets_from_path(set_path: &str) -> Result<Vec<Box<dyn SigSet>>, SigSetError> {
+    SigSetDeserializer::new_sections(set_path)?
+        .iter()
+        .filter(|des| des.magic() != DynSet::SET_MAGIC_U32)
+        .map(|des| des.get_set())
+        .collect()
+}
+
 pub fn deserialize_sha_set_from_path(set_path: &str) -> Result<ShaSet, SigSetError> {
-    let des = SigSetDeserializer::new(set_path)?;
+    let des = SigSetDeserializer::new_section(set_path, ShaSet::SET_MAGIC_U32)?;
     des.get_sha_set()
 }
 
 pub fn deserialize_dyn_set_from_path(set_path: &str) -> Result<DynSet, SigSetError> {
-    let des = SigSetDeserializer::new(set_path)?;
+    let des = SigSetDeserializer::new_section(set_path, DynSet::SET_MAGIC_U32)?;
     des.get_dyn_set()
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
@@ -1,16 +1,47 @@
-use crate::{sha256_utils::Sha256, DynSet, SigSetError};
+use crate::{sha256_utils::Sha256, SigSetError};
 use common::redr;
 use serde::Deserialize;
 
+pub mod anomaly_set;
+mod block_features;
+pub mod block_set;
+mod byte_pattern;
+mod clr_metadata;
+mod code_features;
+pub mod code_set;
+mod condition;
+mod described_set;
 pub mod dynamic_set;
+mod feature_index;
+mod fuzzy_hash;
+pub mod fuzzy_set;
 pub mod heuristic_set;
+mod import_name;
+mod import_set_view;
+mod model_features;
+pub mod model_set;
+pub mod pattern_set;
+mod pe_features;
+pub mod pe_hash_set;
+mod pe_hashes;
+mod set_kind;
+pub mod set_signing;
+mod set_view;
 pub mod sha_set;
+pub mod sig_lint;
+pub mod sig_source;
+pub mod sig_test;
 mod signature;
+pub mod sigset_container;
 pub mod sigset_deserializer;
+pub mod sigset_file;
+pub mod sigset_info;
 pub mod sigset_serializer;
+mod string_features;
+pub mod string_set;
 
 use crate::sig_set::{
-    heuristic_set::HeurSet, sha_set::ShaSet, sigset_serializer::SigSetSerializer,
+    condition::CompiledCondition, sig_source::SigSource, sigset_serializer::SigSetSerializer,
 };
 use common::detection::DetectionReport;
 use serde::Serialize;
@@ -25,13 +56,20 @@ struct SetHeader {
 }
 
 impl SetHeader {
-    const MAGIC_LIST: [u32; 3] = [
-        ShaSet::SET_MAGIC_U32,
-        HeurSet::SET_MAGIC_U32,
-        DynSet::SET_MAGIC_U32,
-    ];
+    const SIZE: usize = std::mem::size_of::<SetHeader>();
+
+    // header at the beginning of data. Magic is not verified
+    fn read(data: &[u8]) -> Result<Self, SigSetError> {
+        if data.len() < Self::SIZE {
+            return Err(SigSetError::IncorrectFileSizeError {
+                size: data.len() as u64,
+            });
+        }
+        Ok(bincode::serde::decode_from_slice(&data[..Self::SIZE], bincode::config::legacy())?.0)
+    }
+
     fn verify_magic(&self) -> Result<(), SigSetError> {
-        if !Self::MAGIC_LIST.contains(&self.magic) {
+        if !set_kind::is_known_magic(self.magic) {
             return Err(SigSetError::IncorrectMagicError {
                 current: String::from_utf8_lossy(&self.magic.to_le_bytes()).into(),
             });
@@ -42,6 +80,70 @@ impl SetHeader {
 
 pub(crate) type SigId = [u8; 32];
 
+// heuristic and dynamic sets keep u32 id of signature on first 4 bytes of SigId
+pub(crate) fn sig_id_from_u32(id: u32) -> SigId {
+    let mut sig_id = SigId::default();
+    sig_id[..4].copy_from_slice(&id.to_le_bytes());
+    sig_id
+}
+
+// files of directory, without subdirectories, sorted by name, so set compiled from the same files
+// is always the same
+pub(crate) fn dir_files(path: &str) -> Result<Vec<std::fs::DirEntry>, SigSetError> {
+    let mut entries = vec![];
+    for entry_res in std::fs::read_dir(path)? {
+        let entry = entry_res?;
+        if entry.file_type()?.is_file() {
+            entries.push(entry);
+        }
+    }
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+// set file (single set or container) is written aside and renamed over the old one. Old file is
+// never truncated or changed in place, so scanner which has it mapped keeps reading the old set
+pub(crate) fn write_set_file(path: &str, bytes: &[u8]) -> Result<(), SigSetError> {
+    let tmp_path = format!("{path}.tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    if let Err(e) = std::fs::rename(&tmp_path, path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+// byte 4 of SigId of heuristic and dynamic signature tells how its data is laid out:
+// LAYOUT_IMPORTS: imports count (u32), imports (sha256 each), yaml description
+// LAYOUT_CONDITION: like LAYOUT_IMPORTS, but between imports and description there is compiled
+// condition: its size (u32) and bincode of CompiledCondition
+pub(crate) const LAYOUT_IMPORTS: u8 = 0;
+pub(crate) const LAYOUT_CONDITION: u8 = 1;
+
+pub(crate) fn import_sig_data(
+    imports: &[Sha256],
+    condition: Option<&CompiledCondition>,
+    desc: &str,
+) -> (u8, Vec<u8>) {
+    let mut v = vec![];
+    v.extend_from_slice(&(imports.len() as u32).to_le_bytes());
+    for import in imports {
+        v.extend_from_slice(import);
+    }
+
+    let layout = match condition {
+        Some(condition) => {
+            let condition = condition.to_bytes();
+            v.extend_from_slice(&(condition.len() as u32).to_le_bytes());
+            v.extend_from_slice(&condition);
+            LAYOUT_CONDITION
+        },
+        None => LAYOUT_IMPORTS,
+    };
+    v.extend_from_slice(desc.as_bytes());
+    (layout, v)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct SigHeader {
     id: SigId,
@@ -49,50 +151,109 @@ pub(crate) struct SigHeader {
     offset: u32,
 }
 
-pub(crate) type ShaSigHeader = SigHeader;
-
 #[derive(Debug)]
 struct HeurSigHeader {
     id: u32,
+    layout: u8,
     size: u32,
-    offset: u32,
 }
 
 impl From<SigHeader> for HeurSigHeader {
     fn from(header: SigHeader) -> Self {
         Self {
             id: u32::from_le_bytes(header.id[0..4].try_into().unwrap()),
+            layout: header.id[4],
             size: header.size,
-            offset: header.offset,
         }
     }
 }
 
-pub(self) type DynSigHeader = HeurSigHeader;
-
-// #[derive(Debug, Serialize, Deserialize)]
-// struct SignatureHeader {
-//     id: [u8;32],
-//     size: u32,
-//     offset: u32,
-// }
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Signature {
-    header: SigHeader,
-    data: Vec<u8>,
-}
-
 pub trait SigSet {
     //fn append_signature(&mut self, sha: SigIdType, desc: Description);
     fn eval_file(
         &self,
         file: &mut redr::FileReader,
         variant: &mut redr::FileScanInfo,
-    ) -> Result<Option<DetectionReport>, SigSetError>;
+    ) -> Result<Vec<DetectionReport>, SigSetError>;
     fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError>
+    where
+        Self: Sized,
+    {
+        Self::from_source(&SigSource::new(path_to_dir))
+    }
+    /// Signatures of chosen files of directory tree, see [SigSource]
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError>
     where
         Self: Sized;
 
-    fn to_sig_set(&self) -> SigSetSerializer;
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sig_set::{heuristic_set::HeurSet, sha_set::ShaSet},
+        DynSet,
+    };
+
+    fn compile<T: SigSet>(dir: &std::path::Path, magic: u32) -> Vec<u8> {
+        T::from_signatures(dir.to_str().unwrap())
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(magic)
+            .unwrap()
+    }
+
+    #[test]
+    fn compiling_twice_gives_the_same_set() {
+        let dir = std::env::temp_dir().join(format!("sfi_reproducible_{}", std::process::id()));
+        for sub_dir in ["sha", "heur", "dyn", "raw"] {
+            std::fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+        for i in (0..50).rev() {
+            let name = format!("sig{i}.sig");
+            let sha = hex::encode_upper([i as u8; 32]);
+            let sha_sig = format!("name: s{i}\ndescription: s\nsha256: {sha}\n");
+            let heur_sig = format!("name: h{i}\ndescription: h\nimports: [a.dll+f{i}, b.dll+g]\n");
+            let dyn_sig = format!("name: d{i}\ndescription: d\ncalls: [Call{i}]\n");
+            std::fs::write(dir.join("sha").join(&name), sha_sig).unwrap();
+            std::fs::write(dir.join("heur").join(&name), heur_sig).unwrap();
+            std::fs::write(dir.join("dyn").join(&name), dyn_sig).unwrap();
+            std::fs::write(dir.join("raw").join(&name), [i as u8; 10]).unwrap();
+        }
+
+        for _ in 0..2 {
+            assert_eq!(
+                compile::<ShaSet>(&dir.join("sha"), ShaSet::SET_MAGIC_U32),
+                compile::<ShaSet>(&dir.join("sha"), ShaSet::SET_MAGIC_U32)
+            );
+            assert_eq!(
+                compile::<HeurSet>(&dir.join("heur"), HeurSet::SET_MAGIC_U32),
+                compile::<HeurSet>(&dir.join("heur"), HeurSet::SET_MAGIC_U32)
+            );
+            assert_eq!(
+                compile::<DynSet>(&dir.join("dyn"), DynSet::SET_MAGIC_U32),
+                compile::<DynSet>(&dir.join("dyn"), DynSet::SET_MAGIC_U32)
+            );
+            let raw = || {
+                ShaSet::from_dir(dir.join("raw").to_str().unwrap())
+                    .unwrap()
+                    .to_sig_set()
+                    .unwrap()
+                    .to_bytes(ShaSet::SET_MAGIC_U32)
+                    .unwrap()
+            };
+            assert_eq!(raw(), raw());
+        }
+
+        // ids are given in order of file names
+        let heurset = HeurSet::from_signatures(dir.join("heur").to_str().unwrap()).unwrap();
+        let descriptions = heurset.descriptions().unwrap();
+        assert!(descriptions[0].starts_with("name: h0\n"));
+        assert!(descriptions[1].starts_with("name: h1\n"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
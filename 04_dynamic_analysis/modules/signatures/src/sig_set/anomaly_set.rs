@@ -0,0 +1,283 @@
+use crate::{
+    sig_set::{
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        pe_features::PeFeatures,
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{AnomalyMatch, PeAnomaly, PeFeature, SigAnomaly},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::{collections::BTreeMap, io::Read};
+
+type AnomalySigId = u32;
+// anomaly as it is stored in set: feature, its bounds and weight. Anomaly given by feature alone
+// has no bounds, it is present the same way
+type StoredAnomaly = (PeFeature, Option<f64>, Option<f64>, u32);
+
+pub struct AnomalySet {
+    sig_id_to_description: BTreeMap<AnomalySigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // anomalies and threshold of each signature. They are stored in set
+    sig_id_to_anomalies: BTreeMap<AnomalySigId, (Vec<PeAnomaly>, u32)>,
+}
+
+impl AnomalySet {
+    pub const SET_MAGIC_U32: u32 = 0x54453541; //A5ET
+
+    // signature must have anomalies, bounds which can hold and threshold it can reach
+    fn verify(sig: &SigAnomaly) -> Result<(), SigSetError> {
+        let incorrect = |info: String| {
+            Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: {info}", sig.sig_base.name),
+            })
+        };
+        if sig.anomalies.is_empty() {
+            return incorrect("no anomalies".into());
+        }
+        for anomaly in &sig.anomalies {
+            if let PeAnomaly::Bounded {
+                min: Some(min),
+                max: Some(max),
+                ..
+            } = anomaly
+            {
+                if min > max {
+                    return incorrect(format!("empty range of {}", anomaly.feature().name()));
+                }
+            }
+        }
+        // total weight is threshold of signature without one, it must not wrap
+        let Some(total) = sig.total_weight() else {
+            return incorrect(format!("total weight is larger than {}", u32::MAX));
+        };
+        match sig.threshold {
+            Some(0) => incorrect("threshold is 0".into()),
+            Some(threshold) if threshold > total => incorrect(format!(
+                "threshold {threshold} is above total weight {total}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // signatures whose present anomalies reach their threshold, in order of ids
+    fn match_(&self, features: &PeFeatures) -> Vec<(AnomalySigId, Vec<String>, u32)> {
+        let mut matches = vec![];
+        for (sig_id, (anomalies, threshold)) in &self.sig_id_to_anomalies {
+            let mut present = vec![];
+            let mut score: u32 = 0;
+            for anomaly in anomalies {
+                let value = features.value(anomaly.feature());
+                if anomaly.is_present(value) {
+                    present.push(format!("{}: {value}", anomaly.feature().name()));
+                    score = score.saturating_add(anomaly.weight());
+                }
+            }
+            if score >= *threshold {
+                matches.push((*sig_id, present, score));
+            }
+        }
+        matches
+    }
+}
+
+impl DescribedSet for AnomalySet {
+    const KIND: &'static str = "anomaly";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sig_id_to_anomalies: Default::default(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: AnomalySigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigAnomaly = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        Self::verify(&sig)?;
+
+        let threshold = sig.threshold();
+        (self.sig_id_to_anomalies).insert(sig_id, (sig.anomalies, threshold));
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        let stored: BTreeMap<AnomalySigId, (Vec<StoredAnomaly>, u32)> =
+            (self.sig_id_to_anomalies.iter())
+                .map(|(sig_id, (anomalies, threshold))| {
+                    let anomalies = (anomalies.iter())
+                        .map(|anomaly| match anomaly {
+                            PeAnomaly::Plain(feature) => (*feature, None, None, 1),
+                            PeAnomaly::Bounded {
+                                feature,
+                                min,
+                                max,
+                                weight,
+                            } => (*feature, *min, *max, *weight),
+                        })
+                        .collect();
+                    (*sig_id, (anomalies, *threshold))
+                })
+                .collect();
+        encode_lookup(&stored)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let stored: BTreeMap<AnomalySigId, (Vec<StoredAnomaly>, u32)> = decode_lookup(&view)?;
+        let mut set = Self::new_empty();
+        for (sig_id, (anomalies, threshold)) in stored {
+            let anomalies = (anomalies.into_iter())
+                .map(|(feature, min, max, weight)| PeAnomaly::Bounded {
+                    feature,
+                    min,
+                    max,
+                    weight,
+                })
+                .collect();
+            (set.sig_id_to_anomalies).insert(sig_id, (anomalies, threshold));
+        }
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for AnomalySet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let features = match PeFeatures::parse(&buffer) {
+            Ok(features) => features,
+            Err(e) => {
+                log::debug!("Not executable: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+        log::debug!("PE features: {:?}", features);
+
+        let mut reports = vec![];
+        for (sig_id, present, score) in self.match_(&features) {
+            let sig: SigAnomaly = self.decode_sig(sig_id)?;
+            reports.push(
+                AnomalyMatch {
+                    sig,
+                    present,
+                    score,
+                }
+                .into(),
+            );
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn anomalies_are_scored_against_threshold() {
+        let descriptions = vec![
+            "name: Packed\ndescription: p\nanomalies:\n  - {feature: max_section_entropy, min: \
+             7.2}\n  - writable_executable_sections\n  - entry_point_outside_text\n"
+                .to_string(),
+            "name: Dropper\ndescription: d\nthreshold: 3\nanomalies:\n  - {feature: overlay_size, \
+             min: 4096, weight: 2}\n  - missing_rich_header\n  - {feature: import_count, max: 5}\n"
+                .to_string(),
+        ];
+        let set = AnomalySet::from_descriptions(descriptions).unwrap();
+        let names = |features: &PeFeatures| -> Vec<String> {
+            (set.match_(features).iter())
+                .map(|(sig_id, _, _)| set.decode_sig::<SigAnomaly>(*sig_id).unwrap().sig_base.name)
+                .collect()
+        };
+
+        let mut features = PeFeatures {
+            max_section_entropy: 7.9,
+            writable_executable_sections: 1,
+            import_count: 80,
+            ..Default::default()
+        };
+        assert!(names(&features).is_empty());
+        features.entry_point_outside_text = true;
+        assert_eq!(names(&features), ["Packed"]);
+
+        features.overlay_size = 100_000;
+        assert_eq!(names(&features), ["Packed"]);
+        features.import_count = 3;
+        assert_eq!(names(&features), ["Packed", "Dropper"]);
+        features.overlay_size = 0;
+        features.missing_rich_header = true;
+        assert_eq!(names(&features), ["Packed"]);
+
+        let (_, present, score) = set.match_(&features).remove(0);
+        assert_eq!(
+            present,
+            [
+                "max_section_entropy: 7.9",
+                "writable_executable_sections: 1",
+                "entry_point_outside_text: 1"
+            ]
+        );
+        assert_eq!(score, 3);
+
+        // anomalies stored in set are matched the same
+        let bytes = (set.to_sig_set().unwrap())
+            .to_bytes(AnomalySet::SET_MAGIC_U32)
+            .unwrap();
+        let loaded = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<AnomalySet>()
+            .unwrap();
+        assert!(loaded.view.is_some());
+        features.overlay_size = 100_000;
+        assert_eq!(loaded.match_(&features), set.match_(&features));
+        assert_eq!(loaded.match_(&features).len(), 2);
+
+        for incorrect in [
+            "anomalies: []",
+            "anomalies: [packed]",
+            "anomalies: [{feature: overlay_size, min: 10, max: 1}]",
+            "threshold: 2\nanomalies: [tls_callbacks]",
+            "threshold: 1\nanomalies: [{feature: tls_callbacks, weight: 4294967295}, \
+             overlay_size]",
+        ] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                AnomalySet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::SigSetError;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction, OpKind, Register};
+use object::{
+    pe::{ImageNtHeaders32, ImageNtHeaders64, IMAGE_SCN_MEM_EXECUTE},
+    read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile},
+    FileKind, LittleEndian as LE,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+// shorter blocks, as moves of arguments before call or epilogues, are in any program
+const MIN_BLOCK_INSTRUCTIONS: usize = 5;
+
+/// Hashes of basic blocks of executable sections of PE, with number of instructions of each block
+pub(crate) fn block_hashes(data: &[u8]) -> Result<BTreeMap<String, usize>, SigSetError> {
+    match FileKind::parse(data)? {
+        FileKind::Pe32 => from_pe(&PeFile::<ImageNtHeaders32>::parse(data)?, data, 32),
+        FileKind::Pe64 => from_pe(&PeFile::<ImageNtHeaders64>::parse(data)?, data, 64),
+        kind => Err(SigSetError::NotPeError(format!("{kind:?}"))),
+    }
+}
+
+fn from_pe<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+    data: &[u8],
+    bitness: u32,
+) -> Result<BTreeMap<String, usize>, SigSetError> {
+    let optional_header = pe.nt_headers().optional_header();
+    let image_base = optional_header.image_base();
+    let image = image_base..image_base.saturating_add(optional_header.size_of_image() as u64);
+
+    let mut hashes = BTreeMap::new();
+    for section in pe.section_table().iter() {
+        if section.characteristics.get(LE) & IMAGE_SCN_MEM_EXECUTE == 0 {
+            continue;
+        }
+        // raw data is padded up to file alignment, code ends at virtual size
+        let offset = section.pointer_to_raw_data.get(LE) as usize;
+        let mut size = section.size_of_raw_data.get(LE) as usize;
+        let virtual_size = section.virtual_size.get(LE) as usize;
+        if virtual_size != 0 {
+            size = size.min(virtual_size);
+        }
+        let Some(code) = data.get(offset..offset.saturating_add(size)) else {
+            continue;
+        };
+        let address = image_base.wrapping_add(section.virtual_address.get(LE) as u64);
+        for block in basic_blocks(code, address, bitness, &image) {
+            if block.len() >= MIN_BLOCK_INSTRUCTIONS {
+                hashes.insert(hash_block(&block), block.len());
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+// normalised instructions of code split after branches and returns and before targets of
+// branches and calls. Code is decoded linearly, so blocks with invalid instructions (data among
+// code) and block at end of code without branch are dropped
+fn basic_blocks(code: &[u8], address: u64, bitness: u32, image: &Range<u64>) -> Vec<Vec<String>> {
+    let code_range = address..address.saturating_add(code.len() as u64);
+    let mut decoder = Decoder::with_ip(bitness, code, address, DecoderOptions::NONE);
+    let targets: BTreeSet<u64> = (decoder.iter())
+        .filter(|instruction| {
+            matches!(
+                instruction.flow_control(),
+                FlowControl::UnconditionalBranch
+                    | FlowControl::ConditionalBranch
+                    | FlowControl::Call
+            )
+        })
+        .map(|instruction| instruction.near_branch_target())
+        .filter(|target| code_range.contains(target))
+        .collect();
+
+    let mut decoder = Decoder::with_ip(bitness, code, address, DecoderOptions::NONE);
+    let mut blocks = vec![];
+    let mut block = vec![];
+    let mut valid = true;
+    let mut instruction = Instruction::default();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        if targets.contains(&instruction.ip()) && !block.is_empty() {
+            if valid {
+                blocks.push(std::mem::take(&mut block));
+            }
+            block.clear();
+            valid = true;
+        }
+        valid &= !instruction.is_invalid();
+        block.push(normalise(&instruction, image));
+        if !matches!(
+            instruction.flow_control(),
+            FlowControl::Next | FlowControl::Call | FlowControl::IndirectCall
+        ) {
+            if valid {
+                blocks.push(std::mem::take(&mut block));
+            }
+            block.clear();
+            valid = true;
+        }
+    }
+    blocks
+}
+
+// the first 8 bytes of sha256 of instructions, in hex
+fn hash_block(block: &[String]) -> String {
+    let digest = Sha256::digest(block.join("\n"));
+    hex::encode(&digest[..8])
+}
+
+// instruction without addresses, which change with layout of file and with base it is loaded
+// at: targets of branches are "rel", values in image and ip relative memory "addr", e.g.
+// "mov eax,[ebp+0x8]" or "push addr"
+fn normalise(instruction: &Instruction, image: &Range<u64>) -> String {
+    let value = |value: u64| {
+        if image.contains(&value) {
+            "addr".to_string()
+        } else {
+            format!("{value:#x}")
+        }
+    };
+    let register = |register: Register| format!("{register:?}").to_lowercase();
+
+    let mnemonic = format!("{:?}", instruction.mnemonic()).to_lowercase();
+    let operands: Vec<String> = (0..instruction.op_count())
+        .map(|operand| match instruction.op_kind(operand) {
+            OpKind::Register => register(instruction.op_register(operand)),
+            OpKind::NearBranch16
+            | OpKind::NearBranch32
+            | OpKind::NearBranch64
+            | OpKind::FarBranch16
+            | OpKind::FarBranch32 => "rel".into(),
+            OpKind::Immediate8
+            | OpKind::Immediate8_2nd
+            | OpKind::Immediate16
+            | OpKind::Immediate32
+            | OpKind::Immediate64
+            | OpKind::Immediate8to16
+            | OpKind::Immediate8to32
+            | OpKind::Immediate8to64
+            | OpKind::Immediate32to64 => value(instruction.immediate(operand)),
+            OpKind::Memory if instruction.is_ip_rel_memory_operand() => "[addr]".into(),
+            OpKind::Memory => {
+                let mut parts = vec![];
+                if instruction.memory_base() != Register::None {
+                    parts.push(register(instruction.memory_base()));
+                }
+                if instruction.memory_index() != Register::None {
+                    parts.push(format!(
+                        "{}*{}",
+                        register(instruction.memory_index()),
+                        instruction.memory_index_scale()
+                    ));
+                }
+                let displacement = instruction.memory_displacement64();
+                if displacement != 0 || parts.is_empty() {
+                    parts.push(value(displacement));
+                }
+                format!("[{}]", parts.join("+"))
+            },
+            // implicit memory of string instructions
+            _ => "mem".into(),
+        })
+        .collect();
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{mnemonic} {}", operands.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_split_at_branches_and_hashed_without_addresses() {
+        let code = |absolute: [u8; 4]| {
+            let mut code = vec![
+                0x55, // push ebp
+                0x8B, 0xEC, // mov ebp, esp
+                0x8B, 0x45, 0x08, // mov eax, [ebp+8]
+                0x83, 0xF8, 0x05, // cmp eax, 5
+                0x75, 0x07, // jne xor
+                0xA1, // mov eax, [absolute]
+            ];
+            code.extend(absolute);
+            code.extend([
+                0x5D, // pop ebp
+                0xC3, // ret
+                0x33, 0xC0, // xor eax, eax
+                0x5D, // pop ebp
+                0xC3, // ret
+                0xFF, // incomplete
+            ]);
+            code
+        };
+        let blocks = basic_blocks(
+            &code(0x401000u32.to_le_bytes()),
+            0x401000,
+            32,
+            &(0x400000..0x402000),
+        );
+        assert_eq!(
+            blocks,
+            [
+                vec![
+                    "push ebp",
+                    "mov ebp,esp",
+                    "mov eax,[ebp+0x8]",
+                    "cmp eax,0x5",
+                    "jne rel"
+                ],
+                vec!["mov eax,[addr]", "pop ebp", "ret"],
+                vec!["xor eax,eax", "pop ebp", "ret"],
+            ]
+        );
+
+        // the same code relocated to other base
+        let relocated = basic_blocks(
+            &code(0x10001000u32.to_le_bytes()),
+            0x10001000,
+            32,
+            &(0x10000000..0x10002000),
+        );
+        assert_eq!(blocks, relocated);
+        assert_eq!(hash_block(&blocks[0]).len(), 16);
+        assert_ne!(hash_block(&blocks[1]), hash_block(&blocks[2]));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    sha256_utils::{sha256_from_vec, Sha256},
+    sig_set::{
+        block_features::block_hashes,
+        described_set::{lookup_data, DescribedSet},
+        dir_files,
+        feature_index::{FeatureIndex, StoredIndex},
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{BlocksMatch, SigBase, SigBlocks},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+};
+
+type BlockSigId = u32;
+
+// blocks of signature compiled from sample. The longest blocks are kept, they are the least
+// likely to be in other programs
+const MAX_SAMPLE_BLOCKS: usize = 1024;
+
+pub struct BlockSet {
+    sig_id_to_description: BTreeMap<BlockSigId, Description>,
+    // signatures of loaded set. Their index is searched in place and descriptions are read only
+    // when they match
+    view: Option<SetView>,
+    // signatures by hashes of their blocks. Each found block scores 1, signature is matched when
+    // found blocks reach fraction of its blocks
+    index: FeatureIndex,
+}
+
+impl BlockSet {
+    pub const SET_MAGIC_U32: u32 = 0x5445354B; //K5ET
+
+    // signatures with enough of their blocks found and number of found blocks, in order of ids
+    fn match_<'a>(
+        &self,
+        hashes: impl Iterator<Item = &'a String>,
+    ) -> Result<Vec<(BlockSigId, usize)>, SigSetError> {
+        let features = hashes
+            .map(|hash| block_feature(hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        let matched = match &self.view {
+            Some(view) => StoredIndex::new(lookup_data(view)?)?.match_scores(&features)?,
+            None => self.index.match_scores(&features),
+        };
+        Ok((matched.into_iter())
+            .map(|(sig_id, found)| (sig_id, found as usize))
+            .collect())
+    }
+
+    // every PE file of "path_to_dir" gives signature of its blocks which are not in any file of
+    // "benign_dir"
+    pub fn from_dir(
+        path_to_dir: &str,
+        benign_dir: Option<&str>,
+        fraction: Option<f64>,
+    ) -> Result<BlockSet, SigSetError> {
+        let mut benign = BTreeSet::new();
+        if let Some(benign_dir) = benign_dir {
+            for entry in dir_files(benign_dir)? {
+                match block_hashes(&std::fs::read(entry.path())?) {
+                    Ok(hashes) => benign.extend(hashes.into_keys()),
+                    Err(e) => log::debug!("Skipped {:?}: {e}", entry.file_name()),
+                }
+            }
+            log::info!("benign blocks: {}", benign.len());
+        }
+
+        let mut descriptions = vec![];
+        for entry in dir_files(path_to_dir)? {
+            let file_name = entry.file_name().into_string()?;
+            let hashes = match block_hashes(&std::fs::read(entry.path())?) {
+                Ok(hashes) => hashes,
+                Err(e) => {
+                    log::warn!("Skipped {file_name}: {e}");
+                    continue;
+                },
+            };
+            let mut blocks: Vec<(String, usize)> = (hashes.into_iter())
+                .filter(|(hash, _)| !benign.contains(hash))
+                .collect();
+            if blocks.is_empty() {
+                log::warn!("Skipped {file_name}: no blocks which are not benign");
+                continue;
+            }
+            blocks.sort_by_key(|(hash, len)| (Reverse(*len), hash.clone()));
+            blocks.truncate(MAX_SAMPLE_BLOCKS);
+            let mut blocks: Vec<String> = blocks.into_iter().map(|(hash, _)| hash).collect();
+            blocks.sort();
+
+            let sig = SigBlocks {
+                sig_base: SigBase {
+                    name: file_name.clone(),
+                    description: format!("Code blocks of {file_name}"),
+                    priority: 0,
+                    tests: Default::default(),
+                },
+                blocks,
+                fraction,
+            };
+            descriptions.push(serde_yaml::to_string(&sig)?);
+            log::trace!("path: {:?}", &entry);
+        }
+
+        let set = Self::from_descriptions(descriptions)?;
+        log::info!("block set size: {}", set.sig_id_to_description.len());
+        Ok(set)
+    }
+}
+
+// feature of index of lowercase hash of block
+fn block_feature(hash: &str) -> Result<Sha256, SigSetError> {
+    Ok(sha256_from_vec(hash.as_bytes().to_vec())?)
+}
+
+impl DescribedSet for BlockSet {
+    const KIND: &'static str = "block";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            index: FeatureIndex::new_empty(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: BlockSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let mut sig: SigBlocks = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        let incorrect = |info: String| SigSetError::IncorrectSignatureError {
+            info: format!("{}: {info}", sig.sig_base.name),
+        };
+        if sig.blocks.is_empty() {
+            return Err(incorrect("no blocks".into()));
+        }
+        let fraction = sig.fraction();
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(incorrect(format!("fraction {fraction} is not in (0, 1]")));
+        }
+        let mut hashes = BTreeSet::new();
+        for hash in &mut sig.blocks {
+            if hash.len() != 16 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(incorrect(format!("{hash} is not hash of block")));
+            }
+            hash.make_ascii_lowercase();
+            if !hashes.insert(hash.clone()) {
+                return Err(incorrect(format!("block {hash} is repeated")));
+            }
+        }
+
+        let features: Vec<(Sha256, u32)> = (hashes.iter())
+            .map(|hash| Ok((block_feature(hash)?, 1)))
+            .collect::<Result<_, SigSetError>>()?;
+        // found blocks are whole number, so they reach fraction when they reach its ceiling
+        let threshold = (fraction * hashes.len() as f64).ceil() as u32;
+        (self.index).insert_weighted(sig_id, &features, Some(threshold));
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        Ok(self.index.to_bytes(&BTreeSet::new()))
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        StoredIndex::new(lookup_data(&view)?)?;
+        let mut set = Self::new_empty();
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for BlockSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let hashes = match block_hashes(&buffer) {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                log::debug!("Not executable: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+
+        let mut reports = vec![];
+        for (sig_id, found) in self.match_(hashes.keys())? {
+            let sig: SigBlocks = self.decode_sig(sig_id)?;
+            reports.push(BlocksMatch { sig, found }.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn fraction_of_blocks_must_be_found() {
+        let hash = |c: char| c.to_string().repeat(16);
+        let descriptions = vec![
+            format!(
+                "name: half\ndescription: h\nblocks: [{}, {}, {}, {}]\n",
+                hash('a'),
+                hash('b'),
+                hash('c'),
+                hash('d')
+            ),
+            format!(
+                "name: all\ndescription: a\nfraction: 1\nblocks: [{}, {}]\n",
+                hash('E'),
+                hash('f')
+            ),
+        ];
+        let compiled = BlockSet::from_descriptions(descriptions).unwrap();
+        let bytes = (compiled.to_sig_set().unwrap())
+            .to_bytes(BlockSet::SET_MAGIC_U32)
+            .unwrap();
+        // index of loaded set is searched in place
+        let loaded = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<BlockSet>()
+            .unwrap();
+        assert!(loaded.view.is_some());
+
+        for set in [&compiled, &loaded] {
+            let found = |hashes: &[String]| -> Vec<(String, usize)> {
+                (set.match_(hashes.iter()).unwrap().into_iter())
+                    .map(|(sig_id, found)| {
+                        let sig: SigBlocks = set.decode_sig(sig_id).unwrap();
+                        (sig.sig_base.name, found)
+                    })
+                    .collect()
+            };
+
+            assert!(found(&[hash('a'), hash('e')]).is_empty());
+            assert_eq!(
+                found(&[hash('a'), hash('c'), hash('e')]),
+                [("half".to_string(), 2)]
+            );
+            assert_eq!(
+                found(&[hash('a'), hash('b'), hash('c'), hash('e'), hash('f')]),
+                [("half".to_string(), 3), ("all".to_string(), 2)]
+            );
+        }
+
+        for incorrect in [
+            "blocks: []".to_string(),
+            format!("blocks: [{}]\nfraction: 0", hash('a')),
+            format!("blocks: [{}]\nfraction: 1.5", hash('a')),
+            format!("blocks: [{}, {}]", hash('a'), hash('A')),
+            "blocks: [abc]".to_string(),
+        ] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                BlockSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+}
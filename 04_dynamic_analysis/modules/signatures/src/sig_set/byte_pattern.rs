@@ -0,0 +1,261 @@
+use crate::SigSetError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Token {
+    Byte(u8),
+    // "??"
+    Any,
+    // "[min-max]" or "[n]", any bytes in between
+    Jump { min: usize, max: usize },
+}
+
+impl Token {
+    fn max_len(&self) -> usize {
+        match self {
+            Token::Byte(_) | Token::Any => 1,
+            Token::Jump { max, .. } => *max,
+        }
+    }
+}
+
+/// Hex byte pattern, e.g. "4D 5A ?? 00 [4-16] 50 45". Pattern is found by its atom: the longest
+/// run of fixed bytes. Bytes around found atom are matched with tokens before atom (backwards)
+/// and after it. Parsed pattern is stored in set, so loaded set doesn't parse it again
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BytePattern {
+    tokens: Vec<Token>,
+    // tokens before atom, in reverse order
+    prefix_rev: Vec<Token>,
+    atom: Vec<u8>,
+    suffix: Vec<Token>,
+    /// Pattern must start at this offset of file. Anchored patterns are not searched by atom
+    pub(crate) offset: Option<u64>,
+}
+
+impl BytePattern {
+    // pattern can't be longer than that, jumps counted with their max
+    pub(crate) const MAX_LEN: usize = 0x1000;
+    // shorter atoms are found almost in every file
+    const MIN_ATOM_LEN: usize = 2;
+
+    pub(crate) fn parse(text: &str, offset: Option<u64>) -> Result<Self, SigSetError> {
+        let error = |info: &str| SigSetError::IncorrectSignatureError {
+            info: format!("Pattern '{text}': {info}"),
+        };
+
+        let tokens = tokenize(text).ok_or_else(|| error("incorrect syntax"))?;
+        if matches!(tokens.first(), None | Some(Token::Jump { .. }))
+            || matches!(tokens.last(), Some(Token::Jump { .. }))
+        {
+            return Err(error("must start and end with byte or '??'"));
+        }
+        if tokens.iter().map(Token::max_len).sum::<usize>() > Self::MAX_LEN {
+            return Err(error(&format!("longer than {} bytes", Self::MAX_LEN)));
+        }
+
+        let (start, end) = longest_byte_run(&tokens);
+        if offset.is_none() && end - start < Self::MIN_ATOM_LEN {
+            return Err(error(&format!(
+                "needs {} fixed bytes in a row",
+                Self::MIN_ATOM_LEN
+            )));
+        }
+
+        let atom = tokens[start..end]
+            .iter()
+            .map(|token| match token {
+                Token::Byte(byte) => *byte,
+                _ => unreachable!("atom has only bytes"),
+            })
+            .collect();
+        Ok(Self {
+            prefix_rev: tokens[..start].iter().rev().cloned().collect(),
+            atom,
+            suffix: tokens[end..].to_vec(),
+            tokens,
+            offset,
+        })
+    }
+
+    pub(crate) fn atom(&self) -> &[u8] {
+        &self.atom
+    }
+
+    pub(crate) fn max_before(&self) -> usize {
+        self.prefix_rev.iter().map(Token::max_len).sum()
+    }
+
+    pub(crate) fn max_after(&self) -> usize {
+        self.suffix.iter().map(Token::max_len).sum()
+    }
+
+    pub(crate) fn max_len(&self) -> usize {
+        self.tokens.iter().map(Token::max_len).sum()
+    }
+
+    /// Data starts with pattern
+    pub(crate) fn matches_at_start(&self, data: &[u8]) -> bool {
+        match_tokens(&self.tokens, data).is_some()
+    }
+
+    /// Atom was found in data at atom_pos. Returns position in data where whole pattern starts
+    pub(crate) fn matches_around_atom(&self, data: &[u8], atom_pos: usize) -> Option<usize> {
+        let after = &data[atom_pos + self.atom.len()..];
+        match_tokens(&self.suffix, after)?;
+
+        let before: Vec<u8> = data[..atom_pos].iter().rev().copied().collect();
+        let prefix_len = match_tokens(&self.prefix_rev, &before)?;
+        Some(atom_pos - prefix_len)
+    }
+}
+
+// None if text is not correct pattern
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '[' {
+            let len = chars[i..].iter().position(|c| *c == ']')?;
+            let jump: String = chars[i + 1..i + len].iter().collect();
+            let (min, max) = match jump.split_once('-') {
+                Some((min, max)) => (min.parse().ok()?, max.parse().ok()?),
+                None => (jump.parse().ok()?, jump.parse().ok()?),
+            };
+            if min > max || max == 0 {
+                return None;
+            }
+            tokens.push(Token::Jump { min, max });
+            i += len + 1;
+            continue;
+        }
+
+        let pair: String = chars.get(i..i + 2)?.iter().collect();
+        tokens.push(match pair.as_str() {
+            "??" => Token::Any,
+            _ => Token::Byte(u8::from_str_radix(&pair, 16).ok()?),
+        });
+        i += 2;
+    }
+    Some(tokens)
+}
+
+// range of tokens with the longest run of bytes, the first one if there are more
+fn longest_byte_run(tokens: &[Token]) -> (usize, usize) {
+    let (mut best, mut start) = ((0, 0), 0);
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token, Token::Byte(_)) {
+            start = i + 1;
+        } else if i + 1 - start > best.1 - best.0 {
+            best = (start, i + 1);
+        }
+    }
+    best
+}
+
+// data starts with tokens. Returns number of matched bytes. Shorter jumps are tried first
+fn match_tokens(tokens: &[Token], data: &[u8]) -> Option<usize> {
+    match_from(tokens, data, 0, 0, &mut HashSet::new())
+}
+
+// tokens from index token match data at offset. Returns offset where match ends. Whether the
+// rest matches doesn't depend on jumps taken before, so failed pairs of token and offset are
+// remembered and each is tried once. Otherwise pattern with many jumps is matched in exponential
+// time
+fn match_from(
+    tokens: &[Token],
+    data: &[u8],
+    token: usize,
+    offset: usize,
+    failed: &mut HashSet<(usize, usize)>,
+) -> Option<usize> {
+    let Some(current) = tokens.get(token) else {
+        return Some(offset);
+    };
+    if failed.contains(&(token, offset)) {
+        return None;
+    }
+    let end = match current {
+        Token::Byte(byte) if data.get(offset) == Some(byte) => {
+            match_from(tokens, data, token + 1, offset + 1, failed)
+        },
+        Token::Any if offset < data.len() => {
+            match_from(tokens, data, token + 1, offset + 1, failed)
+        },
+        Token::Jump { min, max } => (*min..=*max)
+            .take_while(|skip| offset + skip <= data.len())
+            .find_map(|skip| match_from(tokens, data, token + 1, offset + skip, failed)),
+        _ => None,
+    };
+    if end.is_none() {
+        failed.insert((token, offset));
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pattern() {
+        let pattern = BytePattern::parse("4D 5A ?? 00 [2-4] 50 45 00 00 ??", None).unwrap();
+        assert_eq!(pattern.atom(), [0x50, 0x45, 0, 0]);
+        assert_eq!(pattern.max_before(), 8);
+        assert_eq!(pattern.max_after(), 1);
+
+        for incorrect in [
+            "",
+            "4D 5",
+            "4D XY",
+            "[2] 4D 5A",
+            "4D 5A [3-2] 00",
+            "4D ?? 5A",
+            "4D[1",
+        ] {
+            assert!(BytePattern::parse(incorrect, None).is_err(), "{incorrect}");
+        }
+        // anchored pattern doesn't need atom
+        assert!(BytePattern::parse("4D ?? 5A", Some(0)).is_ok());
+    }
+
+    #[test]
+    fn match_with_wildcards_and_jumps() {
+        let pattern = BytePattern::parse("4D 5A ?? 00 [2-4] 50 45", None).unwrap();
+        assert_eq!(pattern.atom(), b"MZ");
+        let data = b"xxMZ\x90\x00abcPEyy";
+        assert_eq!(pattern.matches_around_atom(data, 2), Some(2));
+        assert!(pattern.matches_at_start(&data[2..]));
+        assert!(!pattern.matches_at_start(&data[1..]));
+
+        // jump too long
+        let data = b"MZ\x90\x00abcdePE";
+        assert_eq!(pattern.matches_around_atom(data, 0), None);
+
+        // atom in the middle is matched backwards
+        let pattern = BytePattern::parse("E8 [1-2] 5D C3 ?? 90", None).unwrap();
+        assert_eq!(
+            pattern.matches_around_atom(b"\xE8\x00\x00\x5D\xC3\x00\x90", 3),
+            Some(0)
+        );
+        assert_eq!(
+            pattern.matches_around_atom(b"\xE8\x00\x00\x5D\xC3\x00", 3),
+            None
+        );
+    }
+
+    #[test]
+    fn many_jumps_are_matched_in_time() {
+        // every split of bytes among jumps is tried once at most
+        let text = format!("41 41{} 43", " [1-64] 42".repeat(30));
+        let pattern = BytePattern::parse(&text, None).unwrap();
+        let mut data = b"AA".to_vec();
+        data.extend([b'B'; 1500]);
+        assert_eq!(pattern.matches_around_atom(&data, 0), None);
+
+        data.push(b'C');
+        assert_eq!(pattern.matches_around_atom(&data, 0), Some(0));
+    }
+}
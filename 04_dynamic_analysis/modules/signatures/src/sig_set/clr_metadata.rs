@@ -0,0 +1,437 @@
+use crate::SigSetError;
+use object::{
+    pe::{ImageCor20Header, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR},
+    read::pe::{ImageNtHeaders, PeFile},
+};
+
+const METADATA_SIGNATURE: u32 = 0x424A5342;
+
+// tables whose rows are read
+const TYPE_REF: usize = 0x01;
+const MEMBER_REF: usize = 0x0A;
+const MODULE_REF: usize = 0x1A;
+const IMPL_MAP: usize = 0x1C;
+
+// coded index may point to tables which don't exist, their row count is 0
+const NO_TABLE: usize = 0x3F;
+
+const TYPE_DEF_OR_REF: &[usize] = &[0x02, 0x01, 0x1B];
+const HAS_CONSTANT: &[usize] = &[0x04, 0x08, 0x17];
+const HAS_CUSTOM_ATTRIBUTE: &[usize] = &[
+    0x06, 0x04, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x00, 0x0E, 0x17, 0x14, 0x11, 0x1A, 0x1B, 0x20, 0x23,
+    0x26, 0x27, 0x28, 0x2A, 0x2C, 0x2B,
+];
+const HAS_FIELD_MARSHAL: &[usize] = &[0x04, 0x08];
+const HAS_DECL_SECURITY: &[usize] = &[0x02, 0x06, 0x20];
+const MEMBER_REF_PARENT: &[usize] = &[0x02, 0x01, 0x1A, 0x06, 0x1B];
+const HAS_SEMANTICS: &[usize] = &[0x14, 0x17];
+const METHOD_DEF_OR_REF: &[usize] = &[0x06, 0x0A];
+const MEMBER_FORWARDED: &[usize] = &[0x04, 0x06];
+const RESOLUTION_SCOPE: &[usize] = &[0x00, 0x1A, 0x23, 0x01];
+const CUSTOM_ATTRIBUTE_TYPE: &[usize] = &[NO_TABLE, NO_TABLE, 0x06, 0x0A, NO_TABLE];
+
+#[derive(Clone, Copy)]
+enum Column {
+    U16,
+    U32,
+    Strings,
+    Guids,
+    Blobs,
+    Table(usize),
+    Coded(&'static [usize]),
+}
+
+use Column::*;
+
+// columns of tables up to ImplMap, tables after it don't change where its rows are
+const SCHEMA: [&[Column]; IMPL_MAP + 1] = [
+    /* Module */ &[U16, Strings, Guids, Guids, Guids],
+    /* TypeRef */ &[Coded(RESOLUTION_SCOPE), Strings, Strings],
+    /* TypeDef */
+    &[
+        U32,
+        Strings,
+        Strings,
+        Coded(TYPE_DEF_OR_REF),
+        Table(0x04),
+        Table(0x06),
+    ],
+    /* FieldPtr */ &[Table(0x04)],
+    /* Field */ &[U16, Strings, Blobs],
+    /* MethodPtr */ &[Table(0x06)],
+    /* MethodDef */ &[U32, U16, U16, Strings, Blobs, Table(0x08)],
+    /* ParamPtr */ &[Table(0x08)],
+    /* Param */ &[U16, U16, Strings],
+    /* InterfaceImpl */ &[Table(0x02), Coded(TYPE_DEF_OR_REF)],
+    /* MemberRef */ &[Coded(MEMBER_REF_PARENT), Strings, Blobs],
+    /* Constant */ &[U16, Coded(HAS_CONSTANT), Blobs],
+    /* CustomAttribute */
+    &[
+        Coded(HAS_CUSTOM_ATTRIBUTE),
+        Coded(CUSTOM_ATTRIBUTE_TYPE),
+        Blobs,
+    ],
+    /* FieldMarshal */ &[Coded(HAS_FIELD_MARSHAL), Blobs],
+    /* DeclSecurity */ &[U16, Coded(HAS_DECL_SECURITY), Blobs],
+    /* ClassLayout */ &[U16, U32, Table(0x02)],
+    /* FieldLayout */ &[U32, Table(0x04)],
+    /* StandAloneSig */ &[Blobs],
+    /* EventMap */ &[Table(0x02), Table(0x14)],
+    /* EventPtr */ &[Table(0x14)],
+    /* Event */ &[U16, Strings, Coded(TYPE_DEF_OR_REF)],
+    /* PropertyMap */ &[Table(0x02), Table(0x17)],
+    /* PropertyPtr */ &[Table(0x17)],
+    /* Property */ &[U16, Strings, Blobs],
+    /* MethodSemantics */ &[U16, Table(0x06), Coded(HAS_SEMANTICS)],
+    /* MethodImpl */
+    &[
+        Table(0x02),
+        Coded(METHOD_DEF_OR_REF),
+        Coded(METHOD_DEF_OR_REF),
+    ],
+    /* ModuleRef */ &[Strings],
+    /* TypeSpec */ &[Blobs],
+    /* ImplMap */ &[U16, Coded(MEMBER_FORWARDED), Strings, Table(MODULE_REF)],
+];
+
+/// What .NET assembly uses from outside of it
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ClrImports {
+    /// Libraries and functions called through P/Invoke
+    pub(crate) pinvokes: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Methods and fields of other assemblies as "Namespace.Type::Member", nested types as
+    /// "Outer/Inner"
+    pub(crate) member_refs: Vec<String>,
+}
+
+/// Imports of .NET assembly. None if PE has no CLR header
+pub(crate) fn clr_imports<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+    data: &[u8],
+) -> Result<Option<ClrImports>, SigSetError> {
+    let Some(directory) = pe.data_directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR) else {
+        return Ok(None);
+    };
+    let sections = pe.section_table();
+    let (header, _) = object::pod::from_bytes::<ImageCor20Header>(directory.data(data, &sections)?)
+        .map_err(|_| error("CLR header is truncated"))?;
+    let metadata = header.meta_data.data(data, &sections)?;
+    Ok(Some(parse_metadata(metadata)?))
+}
+
+fn error(info: &str) -> SigSetError {
+    SigSetError::IncorrectMetadataError(info.into())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SigSetError> {
+        let bytes = (self.data.get(self.offset..))
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| error("metadata is truncated"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SigSetError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SigSetError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SigSetError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SigSetError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn index(&mut self, size: usize) -> Result<u32, SigSetError> {
+        match size {
+            2 => Ok(self.u16()? as u32),
+            _ => self.u32(),
+        }
+    }
+}
+
+// metadata root with its streams, ECMA-335 II.24.2
+fn parse_metadata(metadata: &[u8]) -> Result<ClrImports, SigSetError> {
+    let mut reader = Reader {
+        data: metadata,
+        offset: 0,
+    };
+    if reader.u32()? != METADATA_SIGNATURE {
+        return Err(error("no metadata signature"));
+    }
+    reader.bytes(8)?;
+    let version_len = reader.u32()? as usize;
+    reader.bytes(version_len)?;
+    reader.u16()?;
+
+    let (mut tables, mut strings) = (None, None);
+    for _ in 0..reader.u16()? {
+        let offset = reader.u32()? as usize;
+        let size = reader.u32()? as usize;
+        let name_start = reader.offset;
+        let name_len = (reader.data.get(name_start..).unwrap_or_default().iter())
+            .take(32)
+            .position(|c| *c == 0)
+            .ok_or_else(|| error("stream name is not terminated"))?;
+        let name = &reader.data[name_start..name_start + name_len];
+        // name is padded to 4 bytes
+        reader.offset = name_start + ((name_len + 4) & !3);
+
+        let stream = (metadata.get(offset..))
+            .and_then(|rest| rest.get(..size))
+            .ok_or_else(|| error("stream is out of metadata"))?;
+        match name {
+            b"#~" | b"#-" => tables = Some(stream),
+            b"#Strings" => strings = Some(stream),
+            _ => {},
+        }
+    }
+
+    let (Some(tables), Some(strings)) = (tables, strings) else {
+        return Err(error("no tables or strings stream"));
+    };
+    Tables::parse(tables, strings)?.imports()
+}
+
+struct Tables<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    heap_sizes: u8,
+    rows: [u32; 64],
+    offsets: [usize; SCHEMA.len()],
+}
+
+impl<'a> Tables<'a> {
+    fn parse(data: &'a [u8], strings: &'a [u8]) -> Result<Self, SigSetError> {
+        let mut reader = Reader { data, offset: 0 };
+        reader.bytes(6)?;
+        let heap_sizes = reader.u8()?;
+        reader.u8()?;
+        let valid = reader.u64()?;
+        reader.u64()?;
+        let mut rows = [0; 64];
+        for (table, rows) in rows.iter_mut().enumerate() {
+            if valid >> table & 1 == 1 {
+                *rows = reader.u32()?;
+            }
+        }
+        // obfuscators may put extra data after row counts
+        if heap_sizes & 0x40 != 0 {
+            reader.u32()?;
+        }
+
+        let mut tables = Self {
+            data,
+            strings,
+            heap_sizes,
+            rows,
+            offsets: [0; SCHEMA.len()],
+        };
+        let mut offset = reader.offset;
+        for table in 0..SCHEMA.len() {
+            tables.offsets[table] = offset;
+            offset += tables.rows[table] as usize * tables.row_size(table);
+        }
+        Ok(tables)
+    }
+
+    fn column_size(&self, column: Column) -> usize {
+        let heap_index = |flag: u8| if self.heap_sizes & flag != 0 { 4 } else { 2 };
+        match column {
+            U16 => 2,
+            U32 => 4,
+            Strings => heap_index(0x01),
+            Guids => heap_index(0x02),
+            Blobs => heap_index(0x04),
+            Table(table) if self.rows[table] < 1 << 16 => 2,
+            Table(_) => 4,
+            Coded(tables) => {
+                let max_rows = tables.iter().map(|t| self.rows[*t]).max().unwrap_or(0);
+                if max_rows < 1 << (16 - tag_bits(tables)) {
+                    2
+                } else {
+                    4
+                }
+            },
+        }
+    }
+
+    fn row_size(&self, table: usize) -> usize {
+        (SCHEMA[table].iter())
+            .map(|column| self.column_size(*column))
+            .sum()
+    }
+
+    // values of columns of row, rows are numbered from 1
+    fn row(&self, table: usize, index: u32) -> Result<Vec<u32>, SigSetError> {
+        if index == 0 || index > self.rows[table] {
+            return Err(error("row index is out of table"));
+        }
+        let mut reader = Reader {
+            data: self.data,
+            offset: self.offsets[table] + (index - 1) as usize * self.row_size(table),
+        };
+        (SCHEMA[table].iter())
+            .map(|column| reader.index(self.column_size(*column)))
+            .collect()
+    }
+
+    fn string(&self, index: u32) -> Result<String, SigSetError> {
+        let string = (self.strings.get(index as usize..))
+            .ok_or_else(|| error("string is out of heap"))?
+            .split(|c| *c == 0)
+            .next()
+            .unwrap_or_default();
+        Ok(String::from_utf8_lossy(string).into())
+    }
+
+    // full name of referenced type, nested type is resolved through its enclosing types
+    fn type_ref_name(&self, index: u32, depth: u32) -> Result<String, SigSetError> {
+        let row = self.row(TYPE_REF, index)?;
+        let (name, namespace) = (self.string(row[1])?, self.string(row[2])?);
+        let (scope_table, scope) = decode(row[0], RESOLUTION_SCOPE);
+        if scope_table == TYPE_REF && scope != 0 && depth < 8 {
+            return Ok(format!("{}/{name}", self.type_ref_name(scope, depth + 1)?));
+        }
+        Ok(match namespace.is_empty() {
+            true => name,
+            false => format!("{namespace}.{name}"),
+        })
+    }
+
+    fn imports(&self) -> Result<ClrImports, SigSetError> {
+        let mut imports = ClrImports::default();
+        for index in 1..=self.rows[MEMBER_REF] {
+            let row = self.row(MEMBER_REF, index)?;
+            // members of generic instances (TypeSpec) and of own types are not imports
+            let (table, type_ref) = decode(row[0], MEMBER_REF_PARENT);
+            if table == TYPE_REF {
+                let type_name = self.type_ref_name(type_ref, 0)?;
+                let member = self.string(row[1])?;
+                imports.member_refs.push(format!("{type_name}::{member}"));
+            }
+        }
+        for index in 1..=self.rows[IMPL_MAP] {
+            let row = self.row(IMPL_MAP, index)?;
+            let library = self.string(self.row(MODULE_REF, row[3])?[0])?;
+            let function = self.string(row[2])?;
+            imports
+                .pinvokes
+                .push((library.into_bytes(), function.into_bytes()));
+        }
+        Ok(imports)
+    }
+}
+
+fn tag_bits(tables: &[usize]) -> u32 {
+    usize::BITS - (tables.len() - 1).leading_zeros()
+}
+
+// table and row of coded index
+fn decode(value: u32, tables: &[usize]) -> (usize, u32) {
+    let bits = tag_bits(tables);
+    let tag = (value & ((1 << bits) - 1)) as usize;
+    (*tables.get(tag).unwrap_or(&NO_TABLE), value >> bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn member_refs_and_pinvokes_are_read() {
+        let names = [
+            "System.Reflection",
+            "Assembly",
+            "Load",
+            "kernel32",
+            "VirtualAlloc",
+            "Builder",
+        ];
+        let mut strings = vec![0u8];
+        let mut offsets = vec![];
+        for name in names {
+            offsets.push(strings.len() as u16);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        let resolution_scope = |table: u16, index: u16| (index << 2) | table;
+        let member_ref_parent = (1 << 3) | 1;
+
+        let mut tables = vec![0, 0, 0, 0, 2, 0, 0, 1];
+        let valid: u64 = (1 << TYPE_REF) | (1 << MEMBER_REF) | (1 << MODULE_REF) | (1 << IMPL_MAP);
+        tables.extend_from_slice(&valid.to_le_bytes());
+        tables.extend_from_slice(&0u64.to_le_bytes());
+        for rows in [2u32, 1, 1, 1] {
+            tables.extend_from_slice(&rows.to_le_bytes());
+        }
+        let rows: [u16; 14] = [
+            // TypeRef: Assembly of AssemblyRef, Builder nested in Assembly
+            resolution_scope(2, 1),
+            offsets[1],
+            offsets[0],
+            resolution_scope(3, 1),
+            offsets[5],
+            0,
+            // MemberRef: Load of TypeRef 1
+            member_ref_parent,
+            offsets[2],
+            0,
+            // ModuleRef
+            offsets[3],
+            // ImplMap: VirtualAlloc of ModuleRef 1
+            0,
+            (1 << 1) | 1,
+            offsets[4],
+            1,
+        ];
+        rows.iter()
+            .for_each(|value| tables.extend_from_slice(&value.to_le_bytes()));
+
+        let mut metadata = METADATA_SIGNATURE.to_le_bytes().to_vec();
+        metadata.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0, 0, 12, 0, 0, 0]);
+        metadata.extend_from_slice(b"v4.0.30319\0\0");
+        metadata.extend_from_slice(&[0, 0, 2, 0]);
+        let streams_offset = metadata.len() as u32 + 12 + 20;
+        for (offset, size, name) in [
+            (streams_offset, tables.len() as u32, &b"#~\0\0"[..]),
+            (
+                streams_offset + tables.len() as u32,
+                strings.len() as u32,
+                &b"#Strings\0\0\0\0"[..],
+            ),
+        ] {
+            metadata.extend_from_slice(&offset.to_le_bytes());
+            metadata.extend_from_slice(&size.to_le_bytes());
+            metadata.extend_from_slice(name);
+        }
+        metadata.extend_from_slice(&tables);
+        metadata.extend_from_slice(&strings);
+
+        let imports = parse_metadata(&metadata).unwrap();
+        assert_eq!(imports.member_refs, ["System.Reflection.Assembly::Load"]);
+        assert_eq!(
+            imports.pinvokes,
+            [(b"kernel32".to_vec(), b"VirtualAlloc".to_vec())]
+        );
+        let tables = Tables::parse(&tables, &strings).unwrap();
+        assert_eq!(
+            tables.type_ref_name(2, 0).unwrap(),
+            "System.Reflection.Assembly/Builder"
+        );
+
+        metadata.truncate(metadata.len() - strings.len() - 4);
+        assert!(parse_metadata(&metadata).is_err());
+    }
+}
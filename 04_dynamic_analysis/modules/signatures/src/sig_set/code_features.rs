@@ -0,0 +1,280 @@
+use crate::{sig_set::signature::Packer, SigSetError};
+use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic, OpKind};
+use object::{
+    pe::{ImageNtHeaders32, ImageNtHeaders64},
+    read::pe::{ExportTarget, ImageNtHeaders, ImageOptionalHeader, PeFile},
+    FileKind,
+};
+use std::collections::BTreeSet;
+
+// instructions decoded from each entry, stubs and prologues which signatures look at are short
+const MAX_INSTRUCTIONS: usize = 256;
+
+// stubs packers put at entry point, as normalised instructions
+const PACKER_STUBS: &[(Packer, u32, &[&str])] = &[
+    // pushad; mov esi, packed; lea edi, [esi - unpacked]; push edi; or ebp, -1
+    (
+        Packer::Upx,
+        32,
+        &[
+            "pushad",
+            "mov reg,imm",
+            "lea reg,mem",
+            "push reg",
+            "or reg,imm",
+        ],
+    ),
+    // push rbx; push rsi; push rdi; push rbp; lea rsi, packed; lea rdi, [rsi - unpacked]
+    (
+        Packer::Upx,
+        64,
+        &[
+            "push reg",
+            "push reg",
+            "push reg",
+            "push reg",
+            "lea reg,mem",
+            "lea reg,mem",
+            "push reg",
+        ],
+    ),
+    // pushad; call $+5; pop eax; add eax, offset of packed data
+    (
+        Packer::Mpress,
+        32,
+        &[
+            "pushad",
+            "call rel",
+            "pop reg",
+            "add reg,imm",
+            "mov reg,mem",
+        ],
+    ),
+    // push rdi; push rsi; push rbx; push rcx; push rdx; push r8; lea rax, packed data
+    (
+        Packer::Mpress,
+        64,
+        &[
+            "push reg",
+            "push reg",
+            "push reg",
+            "push reg",
+            "push reg",
+            "push reg",
+            "lea reg,mem",
+        ],
+    ),
+];
+
+/// Normalised instructions of code which starts at entry point or at exported function
+#[derive(Debug)]
+pub(crate) struct CodeBlock {
+    /// "entry point" or "export <name>"
+    pub(crate) origin: String,
+    pub(crate) instructions: Vec<String>,
+}
+
+/// Code of PE, disassembled from entry point and exported functions
+#[derive(Debug)]
+pub(crate) struct CodeFeatures {
+    pub(crate) bitness: u32,
+    pub(crate) blocks: Vec<CodeBlock>,
+}
+
+impl CodeFeatures {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, SigSetError> {
+        match FileKind::parse(data)? {
+            FileKind::Pe32 => Self::from_pe(&PeFile::<ImageNtHeaders32>::parse(data)?, data, 32),
+            FileKind::Pe64 => Self::from_pe(&PeFile::<ImageNtHeaders64>::parse(data)?, data, 64),
+            kind => Err(SigSetError::NotPeError(format!("{kind:?}"))),
+        }
+    }
+
+    fn from_pe<Pe: ImageNtHeaders>(
+        pe: &PeFile<Pe>,
+        data: &[u8],
+        bitness: u32,
+    ) -> Result<Self, SigSetError> {
+        let mut blocks = vec![];
+        let entry_point = pe.nt_headers().optional_header().address_of_entry_point();
+        // DLL without entry point has it zero
+        if entry_point != 0 {
+            blocks.push(CodeBlock {
+                origin: "entry point".into(),
+                instructions: disassemble(pe, data, bitness, entry_point),
+            });
+        }
+
+        let exports = match pe.export_table() {
+            Ok(Some(table)) => table.exports()?,
+            Ok(None) => vec![],
+            Err(e) => {
+                log::debug!("Incorrect export table: {e}");
+                vec![]
+            },
+        };
+        for export in exports {
+            let ExportTarget::Address(address) = export.target else {
+                continue;
+            };
+            if address == 0 {
+                continue;
+            }
+            let name = match export.name {
+                Some(name) => String::from_utf8_lossy(name).into_owned(),
+                None => format!("#{}", export.ordinal),
+            };
+            blocks.push(CodeBlock {
+                origin: format!("export {name}"),
+                instructions: disassemble(pe, data, bitness, address),
+            });
+        }
+        Ok(Self { bitness, blocks })
+    }
+
+    /// Packer whose stub starts code of entry point
+    pub(crate) fn packer(&self) -> Option<Packer> {
+        let entry = self
+            .blocks
+            .iter()
+            .find(|block| block.origin == "entry point")?;
+        PACKER_STUBS
+            .iter()
+            .find(|(_, bitness, stub)| {
+                *bitness == self.bitness
+                    && entry.instructions.len() >= stub.len()
+                    && stub.iter().zip(&entry.instructions).all(|(a, b)| a == b)
+            })
+            .map(|(packer, _, _)| *packer)
+    }
+}
+
+// instructions from address until return, invalid instruction or indirect jump. Direct jumps
+// are followed, so thunks and stubs which jump to code are read through
+fn disassemble<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+    data: &[u8],
+    bitness: u32,
+    address: u32,
+) -> Vec<String> {
+    let image_base = pe.nt_headers().optional_header().image_base();
+    let sections = pe.section_table();
+    let mut instructions = vec![];
+    let mut visited = BTreeSet::new();
+    let mut next = Some(address);
+    while let Some(address) = next.take() {
+        if !visited.insert(address) {
+            break;
+        }
+        let Some(code) = sections.pe_data_at(data, address) else {
+            break;
+        };
+        let mut decoder = Decoder::with_ip(
+            bitness,
+            code,
+            image_base.wrapping_add(address as u64),
+            DecoderOptions::NONE,
+        );
+        let mut instruction = Instruction::default();
+        while decoder.can_decode() && instructions.len() < MAX_INSTRUCTIONS {
+            decoder.decode_out(&mut instruction);
+            if instruction.is_invalid() {
+                return instructions;
+            }
+            instructions.push(normalise(&instruction));
+            match instruction.mnemonic() {
+                Mnemonic::Ret | Mnemonic::Retf | Mnemonic::Int3 | Mnemonic::Hlt => {
+                    return instructions
+                },
+                Mnemonic::Jmp => {
+                    let target = instruction.near_branch_target();
+                    if target != 0 {
+                        next = (target.checked_sub(image_base))
+                            .and_then(|rva| u32::try_from(rva).ok());
+                    }
+                    break;
+                },
+                _ => {},
+            }
+        }
+    }
+    instructions
+}
+
+// mnemonic and kinds of operands, e.g. "mov reg,imm"
+fn normalise(instruction: &Instruction) -> String {
+    let mnemonic = format!("{:?}", instruction.mnemonic()).to_lowercase();
+    let operands: Vec<&str> = (0..instruction.op_count())
+        .map(|operand| match instruction.op_kind(operand) {
+            OpKind::Register => "reg",
+            OpKind::NearBranch16
+            | OpKind::NearBranch32
+            | OpKind::NearBranch64
+            | OpKind::FarBranch16
+            | OpKind::FarBranch32 => "rel",
+            OpKind::Immediate8
+            | OpKind::Immediate8_2nd
+            | OpKind::Immediate16
+            | OpKind::Immediate32
+            | OpKind::Immediate64
+            | OpKind::Immediate8to16
+            | OpKind::Immediate8to32
+            | OpKind::Immediate8to64
+            | OpKind::Immediate32to64 => "imm",
+            _ => "mem",
+        })
+        .collect();
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{mnemonic} {}", operands.join(","))
+    }
+}
+
+/// Instruction of signature in form of disassembled one: lowercase mnemonic and operands
+/// separated by "," without spaces
+pub(crate) fn normalise_text(instruction: &str) -> String {
+    let instruction = instruction.trim().to_lowercase();
+    match instruction.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => {
+            let operands: Vec<&str> = operands.split(',').map(str::trim).collect();
+            format!("{mnemonic} {}", operands.join(","))
+        },
+        None => instruction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_are_normalised() {
+        let code = [
+            0x60, // pushad
+            0xBE, 0x00, 0x10, 0x40, 0x00, // mov esi, 0x401000
+            0x8D, 0xBE, 0x00, 0xF0, 0xFF, 0xFF, // lea edi, [esi-0x1000]
+            0x57, // push edi
+            0x83, 0xCD, 0xFF, // or ebp, -1
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call $+5
+            0xC3, // ret
+        ];
+        let mut decoder = Decoder::with_ip(32, &code, 0x1000, DecoderOptions::NONE);
+        let found: Vec<String> = decoder.iter().map(|i| normalise(&i)).collect();
+        assert_eq!(
+            found,
+            [
+                "pushad",
+                "mov reg,imm",
+                "lea reg,mem",
+                "push reg",
+                "or reg,imm",
+                "call rel",
+                "ret"
+            ]
+        );
+        assert_eq!(&found[..5], PACKER_STUBS[0].2);
+        assert_eq!(normalise_text(" MOV  reg , IMM "), "mov reg,imm");
+        assert_eq!(normalise_text("pushad"), "pushad");
+    }
+}
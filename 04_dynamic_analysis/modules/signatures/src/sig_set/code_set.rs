@@ -0,0 +1,248 @@
+use crate::{
+    sig_set::{
+        code_features::{normalise_text, CodeFeatures},
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{CodeMatch, Packer, SigCode},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::{collections::BTreeMap, io::Read};
+
+type CodeSigId = u32;
+
+// instruction of sequence which matches any instruction
+const ANY_INSTRUCTION: &str = "*";
+
+// sequence as it is given, with its normalised instructions
+type Sequence = (String, Vec<String>);
+
+pub struct CodeSet {
+    sig_id_to_description: BTreeMap<CodeSigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // packer and sequences of each signature, they are stored in set
+    sig_id_to_code: BTreeMap<CodeSigId, (Option<Packer>, Vec<Sequence>)>,
+}
+
+impl CodeSet {
+    pub const SET_MAGIC_U32: u32 = 0x54453543; //C5ET
+
+    // signatures whose packer and every sequence are found, with places they are found at, in
+    // order of ids
+    fn match_(&self, features: &CodeFeatures) -> Vec<(CodeSigId, Vec<String>)> {
+        let packer = features.packer();
+        let mut matches = vec![];
+        for (sig_id, (sig_packer, sequences)) in &self.sig_id_to_code {
+            let mut present = vec![];
+            if let Some(sig_packer) = sig_packer {
+                if packer != Some(*sig_packer) {
+                    continue;
+                }
+                present.push(format!("packer: {}", sig_packer.name()));
+            }
+
+            let found_all = (sequences.iter()).all(|(text, sequence)| {
+                match find_sequence(features, sequence) {
+                    Some(place) => {
+                        present.push(format!("{place}: {text}"));
+                        true
+                    },
+                    None => false,
+                }
+            });
+            if found_all {
+                matches.push((*sig_id, present));
+            }
+        }
+        matches
+    }
+}
+
+// place of the first occurrence of sequence, e.g. "entry point+0x3", offset is in instructions
+fn find_sequence(features: &CodeFeatures, sequence: &[String]) -> Option<String> {
+    features.blocks.iter().find_map(|block| {
+        (block.instructions.windows(sequence.len()))
+            .position(|window| {
+                (window.iter().zip(sequence))
+                    .all(|(found, expected)| expected == ANY_INSTRUCTION || found == expected)
+            })
+            .map(|index| format!("{}+{index}", block.origin))
+    })
+}
+
+impl DescribedSet for CodeSet {
+    const KIND: &'static str = "code";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sig_id_to_code: Default::default(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: CodeSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigCode = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        let incorrect = |info: &str| SigSetError::IncorrectSignatureError {
+            info: format!("{}: {info}", sig.sig_base.name),
+        };
+        if sig.code.is_empty() && sig.packer.is_none() {
+            return Err(incorrect("no code or packer"));
+        }
+
+        let mut sequences = vec![];
+        for sequence in sig.code {
+            let instructions: Vec<String> = (sequence.split(';'))
+                .map(normalise_text)
+                .filter(|instruction| !instruction.is_empty())
+                .collect();
+            if instructions
+                .iter()
+                .all(|instruction| instruction == ANY_INSTRUCTION)
+            {
+                return Err(incorrect("sequence matches any code"));
+            }
+            sequences.push((sequence, instructions));
+        }
+
+        (self.sig_id_to_code).insert(sig_id, (sig.packer, sequences));
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        encode_lookup(&self.sig_id_to_code)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        set.sig_id_to_code = decode_lookup(&view)?;
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for CodeSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let features = match CodeFeatures::parse(&buffer) {
+            Ok(features) => features,
+            Err(e) => {
+                log::debug!("Not executable: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+
+        let mut reports = vec![];
+        for (sig_id, present) in self.match_(&features) {
+            let sig: SigCode = self.decode_sig(sig_id)?;
+            reports.push(CodeMatch { sig, present }.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::code_features::CodeBlock;
+
+    #[test]
+    fn sequences_and_packer_must_match() {
+        let descriptions = vec![
+            "name: Upx\ndescription: u\npacker: upx\n".to_string(),
+            "name: Loader\ndescription: l\ncode: [\"push reg; call mem; * ; test reg, reg\", \
+             \"xor reg,reg\"]\n"
+                .to_string(),
+            "name: UpxLoader\ndescription: ul\npacker: upx\ncode: [\"xor reg,reg\"]\n".to_string(),
+        ];
+        let set = CodeSet::from_descriptions(descriptions).unwrap();
+        let names = |features: &CodeFeatures| -> Vec<String> {
+            (set.match_(features).iter())
+                .map(|(sig_id, _)| set.decode_sig::<SigCode>(*sig_id).unwrap().sig_base.name)
+                .collect()
+        };
+        let block = |origin: &str, instructions: &[&str]| CodeBlock {
+            origin: origin.into(),
+            instructions: instructions.iter().map(|i| i.to_string()).collect(),
+        };
+
+        let mut features = CodeFeatures {
+            bitness: 32,
+            blocks: vec![
+                block(
+                    "entry point",
+                    &["push reg", "mov reg,reg", "xor reg,reg", "ret"],
+                ),
+                block(
+                    "export Start",
+                    &["push reg", "call mem", "pop reg", "test reg,reg", "ret"],
+                ),
+            ],
+        };
+        assert_eq!(names(&features), ["Loader"]);
+        let (_, present) = set.match_(&features).remove(0);
+        assert_eq!(
+            present,
+            [
+                "export Start+0: push reg; call mem; * ; test reg, reg",
+                "entry point+2: xor reg,reg"
+            ]
+        );
+
+        features.blocks[0] = block(
+            "entry point",
+            &[
+                "pushad",
+                "mov reg,imm",
+                "lea reg,mem",
+                "push reg",
+                "or reg,imm",
+                "xor reg,reg",
+            ],
+        );
+        assert_eq!(names(&features), ["Upx", "Loader", "UpxLoader"]);
+        features.bitness = 64;
+        assert_eq!(names(&features), ["Loader"]);
+
+        for incorrect in ["code: []", "code: [\"*;*\"]", "packer: aspack"] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                CodeSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::feature_index::{FeatureIndex, SigIndex},
+    SigSetError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Boolean expression over features (imports, api calls) written in signature, e.g:
+/// ```yaml
+/// condition:
+///   all_of:
+///     - KERNEL32.dll+VirtualAllocEx
+///     - any_of: [KERNEL32.dll+WriteProcessMemory, ntdll.dll+NtWriteVirtualMemory]
+///     - at_least: 2
+///       of: [USER32.dll+BlockInput, USER32.dll+SetCursorPos, KERNEL32.dll+Beep]
+///     - none_of: [msi.dll+MsiInstallProductW]
+/// ```
+/// Group has exactly one key, e.g. `{all_of: [...], none_of: [...]}` is rejected, not read as
+/// `all_of` without its exclusions
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Condition {
+    Feature(String),
+    AllOf { all_of: Vec<Condition> },
+    AnyOf { any_of: Vec<Condition> },
+    NoneOf { none_of: Vec<Condition> },
+    AtLeast { at_least: u32, of: Vec<Condition> },
+}
+
+impl Condition {
+    // names of features in order of appearance. Compiled condition refers to features by position
+    // on this list
+    pub(crate) fn features(&self) -> Vec<&str> {
+        let mut features = vec![];
+        self.collect_features(&mut features);
+        features
+    }
+
+    fn collect_features<'a>(&'a self, features: &mut Vec<&'a str>) {
+        match self {
+            Condition::Feature(name) => features.push(name),
+            Condition::AllOf { all_of: group }
+            | Condition::AnyOf { any_of: group }
+            | Condition::NoneOf { none_of: group }
+            | Condition::AtLeast { of: group, .. } => {
+                group.iter().for_each(|c| c.collect_features(features))
+            },
+        }
+    }
+
+    // first_feature is position of first feature of this condition on the signature feature list
+    pub(crate) fn compile(&self, first_feature: u32) -> Result<CompiledCondition, SigSetError> {
+        let mut next_feature = first_feature;
+        self.compile_with(&mut next_feature)
+    }
+
+    fn compile_with(&self, next_feature: &mut u32) -> Result<CompiledCondition, SigSetError> {
+        fn compile_group(
+            group: &[Condition],
+            next_feature: &mut u32,
+        ) -> Result<Vec<CompiledCondition>, SigSetError> {
+            if group.is_empty() {
+                return Err(SigSetError::IncorrectSignatureError {
+                    info: "Empty group in condition".into(),
+                });
+            }
+            group.iter().map(|c| c.compile_with(next_feature)).collect()
+        }
+
+        Ok(match self {
+            Condition::Feature(_) => {
+                let feature = *next_feature;
+                *next_feature += 1;
+                CompiledCondition::Feature(feature)
+            },
+            Condition::AllOf { all_of } => {
+                CompiledCondition::AllOf(compile_group(all_of, next_feature)?)
+            },
+            Condition::AnyOf { any_of } => {
+                CompiledCondition::AnyOf(compile_group(any_of, next_feature)?)
+            },
+            Condition::NoneOf { none_of } => {
+                CompiledCondition::NoneOf(compile_group(none_of, next_feature)?)
+            },
+            Condition::AtLeast { at_least, of } => {
+                if *at_least == 0 || *at_least as usize > of.len() {
+                    return Err(SigSetError::IncorrectSignatureError {
+                        info: format!("'at_least: {at_least}' of {} conditions", of.len()),
+                    });
+                }
+                CompiledCondition::AtLeast(*at_least, compile_group(of, next_feature)?)
+            },
+        })
+    }
+}
+
+/// Condition from signature compiled to form which is stored in set. Features are referenced by
+/// their position on signature feature list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum CompiledCondition {
+    Feature(u32),
+    AllOf(Vec<CompiledCondition>),
+    AnyOf(Vec<CompiledCondition>),
+    NoneOf(Vec<CompiledCondition>),
+    AtLeast(u32, Vec<CompiledCondition>),
+    // sum of weights of present features must reach threshold
+    Weighted {
+        features: Vec<(u32, u32)>,
+        threshold: u32,
+    },
+}
+
+impl CompiledCondition {
+    pub(crate) fn eval(&self, sig_features: &[Sha256], found: &BTreeSet<&Sha256>) -> bool {
+        let is_found = |feature: &u32| {
+            sig_features
+                .get(*feature as usize)
+                .is_some_and(|sha| found.contains(sha))
+        };
+
+        match self {
+            CompiledCondition::Feature(feature) => is_found(feature),
+            CompiledCondition::AllOf(group) => group.iter().all(|c| c.eval(sig_features, found)),
+            CompiledCondition::AnyOf(group) => group.iter().any(|c| c.eval(sig_features, found)),
+            CompiledCondition::NoneOf(group) => !group.iter().any(|c| c.eval(sig_features, found)),
+            CompiledCondition::AtLeast(n, group) => {
+                group.iter().filter(|c| c.eval(sig_features, found)).count() >= *n as usize
+            },
+            CompiledCondition::Weighted {
+                features,
+                threshold,
+            } => {
+                let score = (features.iter())
+                    .filter(|(feature, _)| is_found(feature))
+                    .fold(0u32, |score, (_, weight)| score.saturating_add(*weight));
+                score >= *threshold
+            },
+        }
+    }
+
+    // lower bound of present features needed to satisfy condition. If it is 0 (e.g. only
+    // "none_of"), condition can be satisfied by file without any of signature features
+    fn min_present(&self) -> u32 {
+        match self {
+            CompiledCondition::Feature(_) => 1,
+            CompiledCondition::AllOf(group) => {
+                group.iter().map(|c| c.min_present()).max().unwrap_or(0)
+            },
+            CompiledCondition::AnyOf(group) | CompiledCondition::AtLeast(_, group) => {
+                group.iter().map(|c| c.min_present()).min().unwrap_or(0)
+            },
+            CompiledCondition::NoneOf(_) => 0,
+            CompiledCondition::Weighted { threshold, .. } => (*threshold > 0) as u32,
+        }
+    }
+
+    fn max_feature(&self) -> Option<u32> {
+        match self {
+            CompiledCondition::Feature(feature) => Some(*feature),
+            CompiledCondition::AllOf(group)
+            | CompiledCondition::AnyOf(group)
+            | CompiledCondition::NoneOf(group)
+            | CompiledCondition::AtLeast(_, group) => {
+                group.iter().filter_map(|c| c.max_feature()).max()
+            },
+            CompiledCondition::Weighted { features, .. } => {
+                features.iter().map(|(feature, _)| *feature).max()
+            },
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        // encoding of plain enum to vec can't fail
+        bincode::serde::encode_to_vec(self, bincode::config::legacy())
+            .expect("condition should be serializable")
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8], feature_count: usize) -> Result<Self, SigSetError> {
+        let condition: Self =
+            bincode::serde::decode_from_slice(bytes, bincode::config::legacy())?.0;
+        if condition
+            .max_feature()
+            .is_some_and(|feature| feature as usize >= feature_count)
+        {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: "Condition refers to not existing feature".into(),
+            });
+        }
+        Ok(condition)
+    }
+}
+
+// Signatures with condition can't be matched by FeatureIndex alone. Index gives only candidates
+// which contain at least one of signature features, then condition decides. Signatures whose
+// condition may be true without any feature present are evaluated for every file
+pub(crate) struct ConditionalSigs {
+    conditions: BTreeMap<SigIndex, CompiledCondition>,
+    always_evaluated: BTreeSet<SigIndex>,
+}
+
+impl ConditionalSigs {
+    pub(crate) fn new_empty() -> Self {
+        Self {
+            conditions: Default::default(),
+            always_evaluated: Default::default(),
+        }
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        index: &mut FeatureIndex,
+        sig_id: SigIndex,
+        features: &[Sha256],
+        condition: CompiledCondition,
+    ) {
+        if condition.min_present() == 0 {
+            self.always_evaluated.insert(sig_id);
+        } else {
+            let candidates: Vec<_> = features.iter().map(|sha| (*sha, 1)).collect();
+            index.insert_weighted(sig_id, &candidates, Some(1));
+        }
+        self.conditions.insert(sig_id, condition);
+    }
+
+    pub(crate) fn get(&self, sig_id: &SigIndex) -> Option<&CompiledCondition> {
+        self.conditions.get(sig_id)
+    }
+
+    // signatures which condition can be met without any of their features
+    pub(crate) fn always_evaluated(&self) -> &BTreeSet<SigIndex> {
+        &self.always_evaluated
+    }
+
+    // index_matches are results of FeatureIndex. Returns matched signatures in order of ids
+    pub(crate) fn filter(
+        &self,
+        index_matches: Vec<SigIndex>,
+        sig_features: &BTreeMap<SigIndex, Vec<Sha256>>,
+        found: &[Sha256],
+    ) -> Vec<SigIndex> {
+        let found: BTreeSet<&Sha256> = found.iter().collect();
+        let is_matched = |sig_id: &SigIndex| match self.conditions.get(sig_id) {
+            Some(condition) => condition.eval(&sig_features[sig_id], &found),
+            None => true,
+        };
+
+        let mut matched: Vec<SigIndex> = index_matches
+            .into_iter()
+            .chain(self.always_evaluated.iter().copied())
+            .filter(is_matched)
+            .collect();
+        matched.sort_unstable();
+        matched.dedup();
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256_utils::sha256_from_vec;
+
+    fn feature(s: &str) -> Sha256 {
+        sha256_from_vec(s.as_bytes().to_vec()).unwrap()
+    }
+
+    fn compile(yaml: &str) -> (Vec<Sha256>, CompiledCondition) {
+        let condition: Condition = serde_yaml::from_str(yaml).unwrap();
+        let features = condition.features().into_iter().map(feature).collect();
+        (features, condition.compile(0).unwrap())
+    }
+
+    #[test]
+    fn nested_condition() {
+        let (features, condition) = compile(
+            "all_of:\n  - a\n  - any_of: [b, c]\n  - at_least: 2\n    of: [d, e, f]\n  - none_of: \
+             [x]\n",
+        );
+        assert_eq!(features.len(), 7);
+
+        let eval = |found: &[&str]| {
+            let found: Vec<_> = found.iter().map(|f| feature(f)).collect();
+            condition.eval(&features, &found.iter().collect())
+        };
+        assert!(eval(&["a", "c", "d", "f"]));
+        assert!(!eval(&["a", "c", "d", "f", "x"]));
+        assert!(!eval(&["a", "c", "d"]));
+        assert!(!eval(&["c", "d", "e"]));
+    }
+
+    #[test]
+    fn serialized_condition() {
+        let (features, condition) = compile("at_least: 1\nof: [a, none_of: [b]]\n");
+        let bytes = condition.to_bytes();
+        assert_eq!(
+            CompiledCondition::from_bytes(&bytes, features.len()).unwrap(),
+            condition
+        );
+        assert!(CompiledCondition::from_bytes(&bytes, 1).is_err());
+    }
+
+    #[test]
+    fn incorrect_condition() {
+        let condition: Condition = serde_yaml::from_str("at_least: 3\nof: [a, b]\n").unwrap();
+        assert!(condition.compile(0).is_err());
+        let condition: Condition = serde_yaml::from_str("any_of: []\n").unwrap();
+        assert!(condition.compile(0).is_err());
+
+        for incorrect in [
+            "all_of: [a]\nnone_of: [b]\n",
+            "any_of: [a, b]\nat_least: 1\n",
+            "at_least: 1\nof: [a]\nnone_of: [b]\n",
+            "all_of: [a, {any_of: [b], none_of: [c]}]\n",
+        ] {
+            assert!(
+                serde_yaml::from_str::<Condition>(incorrect).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+
+    #[test]
+    fn none_of_is_evaluated_without_features() {
+        let (features, condition) = compile("none_of: [a]\n");
+        let mut index = FeatureIndex::new_empty();
+        let mut conditional = ConditionalSigs::new_empty();
+        conditional.insert(&mut index, 0, &features, condition);
+        let sig_features = BTreeMap::from([(0, features)]);
+
+        let found = [feature("b")];
+        assert_eq!(
+            conditional.filter(index.match_(&found), &sig_features, &found),
+            vec![0]
+        );
+        let found = [feature("a")];
+        assert!(conditional
+            .filter(index.match_(&found), &sig_features, &found)
+            .is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    sig_set::{
+        import_set_view::INDEX_SIG_ID,
+        set_view::{SetSlice, SetView},
+        sig_id_from_u32,
+        sig_source::SigSource,
+        sigset_serializer::SigSetSerializer,
+        Description, HeurSigHeader, SigSet,
+    },
+    SigSetError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{borrow::Cow, collections::BTreeMap};
+
+/// Set whose signatures are serialized as their yaml descriptions. Each signature is compiled
+/// from its description when it is added. What signatures are matched by (their lookup
+/// structure) is stored after them, so loaded set doesn't parse descriptions, it reads only
+/// descriptions of matched signatures
+pub(crate) trait DescribedSet: SigSet + Sized {
+    /// Type of set in logs, e.g. "pattern"
+    const KIND: &'static str;
+
+    fn new_empty() -> Self;
+
+    fn add_description(&mut self, sig_id: u32, description: Description)
+        -> Result<(), SigSetError>;
+
+    // descriptions of set compiled from them, loaded set reads them from its view
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description>;
+
+    // signatures of loaded set, None for set compiled from descriptions
+    fn view(&self) -> Option<&SetView>;
+
+    /// Done when every signature is added, e.g. to build searcher over all of them
+    fn build(&mut self) -> Result<(), SigSetError> {
+        Ok(())
+    }
+
+    /// Lookup structure of compiled set as it is stored in record at INDEX_SIG_ID
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError>;
+
+    /// Set loaded with its stored lookup structure
+    fn from_stored(view: SetView) -> Result<Self, SigSetError>;
+
+    // signatures get ids in order of descriptions
+    fn from_descriptions(descriptions: Vec<Description>) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        for (sig_id, description) in (0..).zip(descriptions) {
+            set.add_description(sig_id, description)?;
+        }
+        set.build()?;
+        Ok(set)
+    }
+
+    fn description(&self, sig_id: u32) -> Result<Cow<'_, str>, SigSetError> {
+        let description = match self.view() {
+            Some(view) => (view.find_u32(sig_id)?).map(|(_, data)| String::from_utf8_lossy(data)),
+            None => (self.sig_id_to_description().get(&sig_id)).map(|d| Cow::from(d.as_str())),
+        };
+        description.ok_or_else(|| SigSetError::IncorrectSignatureError {
+            info: format!("Signature {sig_id} is not in set"),
+        })
+    }
+
+    // description of matched signature
+    fn decode_sig<S: DeserializeOwned>(&self, sig_id: u32) -> Result<S, SigSetError> {
+        Ok(serde_yaml::from_str(&self.description(sig_id)?)?)
+    }
+
+    // descriptions in order of signature ids
+    fn descriptions(&self) -> Result<Vec<Description>, SigSetError> {
+        let Some(view) = self.view() else {
+            return Ok(self.sig_id_to_description().values().cloned().collect());
+        };
+        let mut descriptions = vec![];
+        for sig in view.signatures() {
+            let (sig_header, data) = sig?;
+            if sig_header.id != INDEX_SIG_ID {
+                descriptions.push(String::from_utf8_lossy(data).into());
+            }
+        }
+        Ok(descriptions)
+    }
+
+    fn compile_source(source: &SigSource) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        source.compile(|sig_id, description| set.add_description(sig_id, description))?;
+        set.build()?;
+        log::info!(
+            "{} set size: {}",
+            Self::KIND,
+            set.sig_id_to_description().len()
+        );
+        Ok(set)
+    }
+
+    // set compiled before lookup structure was stored is rebuilt from its descriptions
+    fn from_view(view: &SetView) -> Result<Self, SigSetError> {
+        if view.index()?.is_some() {
+            return Self::from_stored(view.clone());
+        }
+
+        let mut set = Self::new_empty();
+        for sig in view.signatures() {
+            let (sig_header, data) = sig?;
+            let sig_header: HeurSigHeader = sig_header.into();
+            set.add_description(sig_header.id, String::from_utf8_lossy(data).into())?;
+        }
+        set.build()?;
+        Ok(set)
+    }
+
+    fn serialize_descriptions(&self) -> Result<SigSetSerializer, SigSetError> {
+        if let Some(view) = self.view() {
+            return view.to_sig_set();
+        }
+
+        let mut ser = SigSetSerializer::new_empty();
+        // in order of ids, so set compiled from the same signatures is always the same
+        for (sig_id, description) in self.sig_id_to_description() {
+            ser.serialize_signature(sig_id_from_u32(*sig_id), description.as_bytes().to_vec());
+        }
+        ser.serialize_index(self.stored_lookup()?);
+        Ok(ser)
+    }
+}
+
+// record with lookup structure of loaded set
+pub(crate) fn lookup_data(view: &SetView) -> Result<SetSlice<'_>, SigSetError> {
+    view.index()?.ok_or(SigSetError::IncorrectSignatureError {
+        info: "Set has no lookup structure".into(),
+    })
+}
+
+// lookup structure which is decoded when set is loaded, e.g. patterns searcher is built from
+pub(crate) fn encode_lookup(lookup: &impl Serialize) -> Result<Vec<u8>, SigSetError> {
+    Ok(bincode::serde::encode_to_vec(
+        lookup,
+        bincode::config::legacy(),
+    )?)
+}
+
+pub(crate) fn decode_lookup<T: DeserializeOwned>(view: &SetView) -> Result<T, SigSetError> {
+    let data = lookup_data(view)?.bytes()?;
+    Ok(bincode::serde::decode_from_slice(data, bincode::config::legacy())?.0)
+}
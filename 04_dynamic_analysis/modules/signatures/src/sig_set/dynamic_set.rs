@@ -1,24 +1,31 @@
 use crate::{
     sha256_utils,
     sha256_utils::{sha256_from_vec, Sha256},
-    sig_set::{signature::SigDyn, sigset_serializer::SigSetSerializer, Description, SigId, SigSet},
+    sig_set::{
+        condition::{CompiledCondition, ConditionalSigs},
+        feature_index::{FeatureIndex, SigIndex},
+        import_set_view::{ImportSetView, ImportSig},
+        import_sig_data, sig_id_from_u32,
+        sig_source::SigSource,
+        signature::{DynMatch, SigDyn},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
     SigSetError,
 };
 use common::{detection::DetectionReport, redr};
-use std::{
-    collections::{BTreeMap, HashMap},
-    io::{Read, Seek, SeekFrom},
-};
+use std::collections::{BTreeMap, BTreeSet};
 
-type DynSigId = u32;
-type ImportInSigs = u32;
+type DynSigId = SigIndex;
 
 pub struct DynSet {
-    import_count: u32,
-    sha_to_import_index: BTreeMap<Sha256, u32>,
-    imports_in_sig: Vec<ImportInSigs>,
-    sig_id_to_description: HashMap<DynSigId, Description>,
-    sig_id_to_imports: HashMap<DynSigId, Vec<Sha256>>,
+    // signatures compiled from yaml, kept in memory until set is serialized
+    index: FeatureIndex,
+    conditional: ConditionalSigs,
+    sig_id_to_description: BTreeMap<DynSigId, Description>,
+    sig_id_to_imports: BTreeMap<DynSigId, Vec<Sha256>>,
+    // signatures of loaded set with stored index. They are searched in place
+    view: Option<ImportSetView>,
 }
 
 impl DynSet {
@@ -29,76 +36,61 @@ impl DynSet {
 
     pub(crate) fn new_empty() -> Self {
         Self {
-            import_count: 0,
-            sha_to_import_index: Default::default(),
-            imports_in_sig: Default::default(),
+            index: FeatureIndex::new_empty(),
+            conditional: ConditionalSigs::new_empty(),
             sig_id_to_description: Default::default(),
             sig_id_to_imports: Default::default(),
+            view: None,
         }
     }
 
-    fn match_(&self, sha_vec: &Vec<Sha256>) -> Result<Option<SigDyn>, SigSetError> {
-        //--------------ALGORITHM------------------
-        // matching sha_vec with each signature has very low efficacy. There is better way
-        // imports_in_sig field tell as which signatures has particular import. For example:
-        //
-        // 1) lets assume, "kernel32+sleep" after converted to sha belongs to "self.imports" on
-        // first (zero index) position. Then if "kernel32+sleep" import appears in signatures
-        // with id's 2,3,7,11, then first (zero index) value in "self.imports_to_sig" is
-        // 1094, 0x446, 0b010001000110, because we fill "2,3,7,11" bits
-        //
-        // 2) next step is iterate by each import and if import exists in "imports_in_sig", then
-        // clear value for import_id
-        //
-        // 3) then if some bit is 0 in each "imports_in_sig" value, then we found our matched signature
-        // because each "import" hit clear for us one entry.
-        // 4) How to find out each "sig" is hit? perform in loop bitwise or on each "import_to_sig"
-        // then negate result, and then we know which sig is hit
-
-        //todo: add algorithm example step by step
-
-        let sig_count = self.sig_id_to_imports.len();
-        log::trace!("{:?}", self.imports_in_sig);
-        let mut imports_in_sig = self.imports_in_sig.clone();
-        for sha256 in sha_vec {
-            let Some(import_id) = self.sha_to_import_index.get(sha256) else {
-                continue;
-            };
-            log::debug!(
-                "self.imports_in_sig[*import_id as usize]: {:08b}",
-                self.imports_in_sig[*import_id as usize]
-            );
-            // if some imports hit, then we remove it from array. At the end it tell us in which
-            // signature all imports were hit
-
-            imports_in_sig[*import_id as usize] = 0;
-        }
-
-        // we need calculate mask. If we have 5 signatures, then mask should be
-        // 0x11111111111111111111111111100000, 5 first bits empty. So to get this in first step we get:
-        // 0x00000000000000000000000000011111 and then negate it
-        let mut shared_imports: u32 = (1 << sig_count) - 1;
-        shared_imports = !shared_imports;
-
-        for ids in imports_in_sig {
-            shared_imports |= ids;
+    pub(crate) fn from_view(view: ImportSetView) -> Self {
+        Self {
+            view: Some(view),
+            ..Self::new_empty()
         }
+    }
 
-        shared_imports = !shared_imports;
+    // returns every matched signature in order of ids
+    fn match_(&self, sha_vec: &[Sha256]) -> Result<Vec<DynMatch>, SigSetError> {
+        let found: BTreeSet<&Sha256> = sha_vec.iter().collect();
+        self.matched_sigs(sha_vec)?
+            .into_iter()
+            .map(|matched| {
+                let sig: SigDyn = serde_yaml::from_str(&matched.description)?;
+                let mut present: Vec<String> = vec![];
+                for (feature, sha) in sig.features().iter().zip(&matched.imports) {
+                    if found.contains(sha) && !present.iter().any(|p| p == feature) {
+                        present.push(feature.to_string());
+                    }
+                }
+                Ok(DynMatch { sig, present })
+            })
+            .collect()
+    }
 
-        if shared_imports == 0 {
-            // no match
-            return Ok(None);
+    fn matched_sigs(&self, sha_vec: &[Sha256]) -> Result<Vec<ImportSig>, SigSetError> {
+        if let Some(view) = &self.view {
+            return view.match_(sha_vec);
         }
-        //some signatures are matched. Take first signature matched
-        //todo: add to signatures Priority field in future
-        log::trace!("matched {} sigs", shared_imports.count_ones());
 
-        let matched_sig = shared_imports.trailing_zeros();
-        log::trace!("matched_sig {} id", matched_sig);
+        // feature_index tell us which signatures have all their calls in sha_vec. Look at
+        // FeatureIndex to see how it works. Signatures with condition are only candidates until
+        // their condition is evaluated
+        let matched_sigs =
+            self.conditional
+                .filter(self.index.match_(sha_vec), &self.sig_id_to_imports, sha_vec);
+        log::trace!("matched_sigs: {:?}", matched_sigs);
 
-        let properties: SigDyn = serde_yaml::from_str(&self.sig_id_to_description[&matched_sig])?;
-        return Ok(Some(properties));
+        Ok(matched_sigs
+            .into_iter()
+            .map(|sig_id| ImportSig {
+                id: sig_id,
+                imports: self.sig_id_to_imports[&sig_id].clone(),
+                condition: self.conditional.get(&sig_id).cloned(),
+                description: self.sig_id_to_description[&sig_id].clone(),
+            })
+            .collect())
     }
 
     pub(crate) fn append_signature(
@@ -106,40 +98,79 @@ impl DynSet {
         imports: Vec<Sha256>,
         sig_id: DynSigId,
         desc: Description,
+        condition: Option<CompiledCondition>,
     ) {
-        self.sig_id_to_imports.insert(sig_id, imports.clone());
-
-        for sha in imports {
-            let import_mask = 1 << sig_id;
-            if !self.sha_to_import_index.contains_key(&sha) {
-                log::trace!("{}", self.import_count);
-                self.sha_to_import_index.insert(sha, self.import_count);
-                self.imports_in_sig.push(import_mask);
-                self.import_count += 1;
-            } else {
-                let import_id = self.sha_to_import_index.get_mut(&sha).unwrap();
-                let import_ids = self.imports_in_sig.get_mut(*import_id as usize).unwrap();
-                *import_ids |= import_mask;
-            }
+        match condition {
+            Some(condition) => {
+                self.conditional
+                    .insert(&mut self.index, sig_id, &imports, condition)
+            },
+            None => self.index.insert(sig_id, &imports),
         }
+        self.sig_id_to_imports.insert(sig_id, imports);
         self.sig_id_to_description.insert(sig_id, desc);
     }
 
-    pub fn eval_api_calls(
-        &self,
-        calls: Vec<String>,
-    ) -> Result<Option<DetectionReport>, SigSetError> {
+    // signatures get ids in order of descriptions
+    pub(crate) fn from_descriptions(descriptions: Vec<Description>) -> Result<Self, SigSetError> {
+        let mut dynset = DynSet::new_empty();
+        for (sig_id, description) in (0..).zip(descriptions) {
+            dynset.add_description(sig_id, description)?;
+        }
+
+        log::info!("dynset size: {}", dynset.sig_id_to_description.len());
+        Ok(dynset)
+    }
+
+    pub(crate) fn add_description(
+        &mut self,
+        sig_id: u32,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let properties: SigDyn = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", properties);
+        if properties.calls.is_empty() && properties.condition.is_none() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: no calls and no condition", properties.sig_base.name),
+            });
+        }
+
+        let condition = properties.compile_condition()?;
+        let imports = properties
+            .features()
+            .iter()
+            .map(|s| sha256_utils::sha256_from_vec(s.as_bytes().to_vec()))
+            .collect::<Result<_, _>>()?;
+        self.append_signature(imports, sig_id, description, condition);
+        Ok(())
+    }
+
+    // descriptions in order of signature ids
+    pub(crate) fn descriptions(&self) -> Result<Vec<Description>, SigSetError> {
+        match &self.view {
+            Some(view) => Ok((view.signatures()?.into_iter())
+                .map(|sig| sig.description)
+                .collect()),
+            None => Ok(self.sig_id_to_description.values().cloned().collect()),
+        }
+    }
+
+    pub fn eval_api_calls(&self, calls: Vec<String>) -> Result<Vec<DetectionReport>, SigSetError> {
         let api_calls_res = parse_api_calls(calls);
         //let api_calls_res = get_calls(variant.get_origin_file().borrow().path.as_path());
         if let Err(e) = api_calls_res {
             log::debug!("Failed to run sandbox: {:?}", e);
-            return Ok(None);
+            return Ok(vec![]);
         }
         let api_calls = api_calls_res.unwrap();
 
-        let sig_info = self.match_(&api_calls)?;
-        let desc_and_info = sig_info.map(|sig| sig.into());
-        Ok(desc_and_info)
+        let mut reports: Vec<DetectionReport> = self
+            .match_(&api_calls)?
+            .into_iter()
+            .map(|sig| sig.into())
+            .collect();
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
     }
 }
 
@@ -151,7 +182,7 @@ fn parse_api_calls(imports: Vec<String>) -> Result<Vec<Sha256>, SigSetError> {
         Ok(sha256_from_vec(call.clone().into_bytes())?)
     }
 
-    imports.iter().map(|i| api_call_to_sha(i)).collect()
+    imports.iter().map(api_call_to_sha).collect()
 }
 
 impl SigSet for DynSet {
@@ -159,60 +190,144 @@ impl SigSet for DynSet {
         &self,
         _file: &mut redr::FileReader,
         _variant: &mut redr::FileScanInfo,
-    ) -> Result<Option<DetectionReport>, SigSetError> {
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
         todo!()
     }
 
-    fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError> {
-        let paths = std::fs::read_dir(path_to_dir)?;
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
         let mut dynset = DynSet::new_empty();
-
-        let mut sig_id = 0;
-        for entry_res in paths {
-            let entry = entry_res?;
-            //log::trace!("path: {:?}", &path);
-            if entry.file_type()?.is_file() {
-                let mut f = std::fs::File::open(entry.path())?;
-                let properties: SigDyn = serde_yaml::from_reader(&f)?;
-                log::info!("Properties: {:?}", properties);
-
-                let imports = properties
-                    .calls
-                    .iter()
-                    .map(|s| sha256_utils::sha256_from_vec(s.as_bytes().to_vec()).unwrap())
-                    .collect();
-                f.seek(SeekFrom::Start(0))?;
-                let mut data = Vec::new();
-                f.read_to_end(&mut data)?;
-                dynset.append_signature(imports, sig_id, String::from_utf8_lossy(&data).into());
-                sig_id += 1;
-            }
-        }
-
-        log::info!("dynset size: {}", sig_id);
+        source.compile(|sig_id, description| dynset.add_description(sig_id, description))?;
+        log::info!("dynset size: {}", dynset.sig_id_to_description.len());
         Ok(dynset)
     }
 
-    fn to_sig_set(&self) -> SigSetSerializer {
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        if let Some(view) = &self.view {
+            return view.to_sig_set();
+        }
+
         let mut ser = SigSetSerializer::new_empty();
+        // in order of ids, so set compiled from the same signatures is always the same
         for (sig_id, imports) in self.sig_id_to_imports.iter() {
-            let mut desc = self.sig_id_to_description[&sig_id].clone();
-
-            let mut v = vec![];
-            let imports_len = imports.len() as u32;
-            v.append(&mut imports_len.to_le_bytes().to_vec());
-            for import in imports {
-                v.append(&mut import.as_slice().to_vec());
-            }
-            unsafe {
-                v.append(desc.as_mut_vec());
-            }
-
-            let mut sig_id_bytes = [0u32; 4];
-            sig_id_bytes[0] = *sig_id;
-            let sig_id = unsafe { *(sig_id_bytes.as_ptr() as *const SigId) };
-            ser.serialize_signature(sig_id, v);
+            let (layout, v) = import_sig_data(
+                imports,
+                self.conditional.get(sig_id),
+                &self.sig_id_to_description[sig_id],
+            );
+
+            let mut id = sig_id_from_u32(*sig_id);
+            id[4] = layout;
+            ser.serialize_signature(id, v);
+        }
+        ser.serialize_index(self.index.to_bytes(self.conditional.always_evaluated()));
+        Ok(ser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn compile_serialize_and_match_many_signatures() {
+        const SIG_COUNT: u32 = 10_500;
+
+        let mut dynset = DynSet::new_empty();
+        for i in 0..SIG_COUNT {
+            let calls = vec![format!("Call{i}"), "Sleep".to_string()];
+            let desc = format!(
+                "name: sig{i}\ndescription: sig {i}\ncalls: [{}]\n",
+                calls.join(", ")
+            );
+            dynset.append_signature(parse_api_calls(calls).unwrap(), i, desc, None);
+        }
+
+        let bytes = dynset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(DynSet::SET_MAGIC_U32)
+            .unwrap();
+        let dynset = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_dyn_set()
+            .unwrap();
+
+        for i in [0, 32, 1_000, SIG_COUNT - 1] {
+            let calls = vec!["Sleep".to_string(), format!("Call{i}")];
+            let reports = dynset.eval_api_calls(calls).unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].desc, format!("sig {i}"));
         }
-        ser
+
+        let calls = vec!["Call7".to_string()];
+        assert!(dynset.eval_api_calls(calls).unwrap().is_empty());
+    }
+
+    #[test]
+    fn report_every_match_ordered_by_priority() {
+        let sigs = [
+            "name: Generic\ndescription: generic\ncalls: [Sleep]\n",
+            "name: Family\ndescription: family\npriority: 10\ncalls: [Sleep, BlockInput]\n",
+            "name: Other\ndescription: other\npriority: 20\ncalls: [Beep]\n",
+            "name: Generic2\ndescription: generic2\ncalls: [BlockInput]\n",
+        ];
+
+        let mut dynset = DynSet::new_empty();
+        for (sig_id, desc) in sigs.iter().enumerate() {
+            let sig: SigDyn = serde_yaml::from_str(desc).unwrap();
+            let calls = parse_api_calls(sig.calls).unwrap();
+            dynset.append_signature(calls, sig_id as u32, desc.to_string(), None);
+        }
+
+        let calls = vec!["BlockInput".to_string(), "Sleep".to_string()];
+        let reports = dynset.eval_api_calls(calls).unwrap();
+        let names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["Family", "Generic", "Generic2"]);
+    }
+
+    #[test]
+    fn condition_is_serialized_and_evaluated() {
+        let desc = "name: Keylogger\ndescription: keylogger\ncalls: \
+                    [SetWindowsHookExW]\ncondition:\n  at_least: 2\n  of: [GetAsyncKeyState, \
+                    GetKeyState, MapVirtualKeyW]\n";
+        let sig: SigDyn = serde_yaml::from_str(desc).unwrap();
+        let calls = parse_api_calls(sig.features().iter().map(|s| s.to_string()).collect());
+        let mut dynset = DynSet::new_empty();
+        dynset.append_signature(
+            calls.unwrap(),
+            0,
+            desc.to_string(),
+            sig.compile_condition().unwrap(),
+        );
+
+        let bytes = dynset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(DynSet::SET_MAGIC_U32)
+            .unwrap();
+        let dynset = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_dyn_set()
+            .unwrap();
+
+        let calls = |calls: &[&str]| calls.iter().map(|s| s.to_string()).collect();
+        let reports = dynset
+            .eval_api_calls(calls(&[
+                "SetWindowsHookExW",
+                "GetKeyState",
+                "MapVirtualKeyW",
+            ]))
+            .unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            reports[0].cause,
+            "Used Imports: [\"SetWindowsHookExW\", \"GetKeyState\", \"MapVirtualKeyW\"]"
+        );
+
+        let reports = dynset.eval_api_calls(calls(&["SetWindowsHookExW", "GetKeyState"]));
+        assert!(reports.unwrap().is_empty());
+        let reports = dynset.eval_api_calls(calls(&["GetKeyState", "MapVirtualKeyW"]));
+        assert!(reports.unwrap().is_empty());
     }
 }
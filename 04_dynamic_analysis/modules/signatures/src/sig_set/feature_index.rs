@@ -0,0 +1,371 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::set_view::{binary_search, SetSlice},
+    SigSetError,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem::size_of,
+};
+
+pub(crate) type SigIndex = u32;
+
+// Inverted index used by sets which match a signature when its features (imports, api calls)
+// are present. Instead of one bit per signature (which limits set to 32 signatures) each feature
+// keeps a posting list with ids of signatures containing it.
+//
+//--------------ALGORITHM------------------
+// 1) lets assume "kernel32+sleep" is feature with index 0 and it appears in signatures 2,3,7,11.
+// Then postings[0] == [(2, w2),(3, w3),(7, w7),(11, w11)] where "w" is weight of feature in
+// given signature (1 by default)
+//
+// 2) for each distinct feature found in file we walk its posting list and add its weight to
+// score of each signature on the list
+//
+// 3) signature is matched if its score reached its threshold. By default threshold is sum of
+// weights of all its features, so every feature must be present
+//
+// Cost of matching depends only on features found in file, not on the number of signatures
+pub(crate) struct FeatureIndex {
+    feature_to_index: BTreeMap<Sha256, u32>,
+    postings: Vec<Vec<(SigIndex, u32)>>,
+    sig_threshold: BTreeMap<SigIndex, u32>,
+}
+
+impl FeatureIndex {
+    pub(crate) fn new_empty() -> Self {
+        Self {
+            feature_to_index: Default::default(),
+            postings: Default::default(),
+            sig_threshold: Default::default(),
+        }
+    }
+
+    // every feature is required
+    pub(crate) fn insert(&mut self, sig_id: SigIndex, features: &[Sha256]) {
+        let weighted: Vec<_> = features.iter().map(|sha| (*sha, 1)).collect();
+        self.insert_weighted(sig_id, &weighted, None);
+    }
+
+    // signature is matched when sum of weights of present features reaches threshold. Without
+    // threshold every feature is required
+    pub(crate) fn insert_weighted(
+        &mut self,
+        sig_id: SigIndex,
+        features: &[(Sha256, u32)],
+        threshold: Option<u32>,
+    ) {
+        // the same feature listed twice counts once
+        let mut unique: BTreeMap<&Sha256, u32> = BTreeMap::new();
+        for (sha, weight) in features {
+            let entry = unique.entry(sha).or_default();
+            *entry = (*entry).max(*weight);
+        }
+
+        for (sha, weight) in unique.iter() {
+            let feature_id = match self.feature_to_index.get(*sha) {
+                Some(id) => *id,
+                None => {
+                    let id = self.postings.len() as u32;
+                    log::trace!("new feature: {id}");
+                    self.feature_to_index.insert(**sha, id);
+                    self.postings.push(vec![]);
+                    id
+                },
+            };
+            self.postings[feature_id as usize].push((sig_id, *weight));
+        }
+
+        let total_weight = (unique.values()).fold(0, |total: u32, w| total.saturating_add(*w));
+        self.sig_threshold
+            .insert(sig_id, threshold.unwrap_or(total_weight));
+    }
+
+    // returns ids of all matched signatures in ascending order
+    pub(crate) fn match_(&self, features: &[Sha256]) -> Vec<SigIndex> {
+        (self.match_scores(features).into_iter())
+            .map(|(sig_id, _)| sig_id)
+            .collect()
+    }
+
+    // matched signatures with their scores, in ascending order of ids
+    pub(crate) fn match_scores(&self, features: &[Sha256]) -> Vec<(SigIndex, u32)> {
+        let found: BTreeSet<u32> = features
+            .iter()
+            .filter_map(|sha| self.feature_to_index.get(sha).copied())
+            .collect();
+
+        let mut scores: HashMap<SigIndex, u32> = HashMap::new();
+        for feature_id in found {
+            for (sig_id, weight) in &self.postings[feature_id as usize] {
+                let score = scores.entry(*sig_id).or_default();
+                *score = score.saturating_add(*weight);
+            }
+        }
+
+        let mut matched: Vec<(SigIndex, u32)> = scores
+            .into_iter()
+            .filter(|(sig_id, score)| {
+                self.sig_threshold
+                    .get(sig_id)
+                    .is_some_and(|threshold| score >= threshold)
+            })
+            .collect();
+        matched.sort_unstable();
+
+        log::trace!("matched {} sigs", matched.len());
+        matched
+    }
+
+    // index as it is stored in set, see StoredIndex. Features are sorted by sha and thresholds by
+    // signature id, so both can be binary searched in place
+    pub(crate) fn to_bytes(&self, always_evaluated: &BTreeSet<SigIndex>) -> Vec<u8> {
+        let mut bytes = vec![];
+        let posting_count: usize = self.postings.iter().map(Vec::len).sum();
+        for count in [
+            self.feature_to_index.len(),
+            posting_count,
+            self.sig_threshold.len(),
+            always_evaluated.len(),
+        ] {
+            bytes.extend_from_slice(&(count as u32).to_le_bytes());
+        }
+
+        let mut first_posting = 0u32;
+        for (sha, feature_id) in &self.feature_to_index {
+            let len = self.postings[*feature_id as usize].len() as u32;
+            bytes.extend_from_slice(sha);
+            bytes.extend_from_slice(&first_posting.to_le_bytes());
+            bytes.extend_from_slice(&len.to_le_bytes());
+            first_posting += len;
+        }
+        for feature_id in self.feature_to_index.values() {
+            for (sig_id, weight) in &self.postings[*feature_id as usize] {
+                bytes.extend_from_slice(&sig_id.to_le_bytes());
+                bytes.extend_from_slice(&weight.to_le_bytes());
+            }
+        }
+        for (sig_id, threshold) in &self.sig_threshold {
+            bytes.extend_from_slice(&sig_id.to_le_bytes());
+            bytes.extend_from_slice(&threshold.to_le_bytes());
+        }
+        for sig_id in always_evaluated {
+            bytes.extend_from_slice(&sig_id.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+// FeatureIndex read in place from loaded set, so loading doesn't depend on number of signatures.
+// Layout, every number is u32 little endian:
+// counts of features, postings, thresholds and always evaluated signatures
+// features: sha256, first posting and number of postings, sorted by sha
+// postings: signature id and weight, postings of each feature follow each other
+// thresholds: signature id and threshold, sorted by id
+// always evaluated signatures: signature id (see ConditionalSigs)
+pub(crate) struct StoredIndex<'a> {
+    features: SetSlice<'a>,
+    postings: SetSlice<'a>,
+    thresholds: SetSlice<'a>,
+    always_evaluated: SetSlice<'a>,
+}
+
+impl<'a> StoredIndex<'a> {
+    const COUNTS_SIZE: usize = 4 * size_of::<u32>();
+    const FEATURE_SIZE: usize = size_of::<Sha256>() + 2 * size_of::<u32>();
+    const PAIR_SIZE: usize = 2 * size_of::<u32>();
+
+    // only counts are read here, parts of index are read when they are searched
+    pub(crate) fn new(data: SetSlice<'a>) -> Result<Self, SigSetError> {
+        let size_error = || SigSetError::IncorrectSignatureSizeError {
+            size: data.len() as u32,
+        };
+        let counts = data.get(0..Self::COUNTS_SIZE).map_err(|_| size_error())?;
+        let count = |i: usize| read_u32(counts, i * size_of::<u32>()) as usize;
+
+        let mut offset = Self::COUNTS_SIZE;
+        let mut take = |count: usize, item_size: usize| -> Result<SetSlice<'a>, SigSetError> {
+            let end = (count.checked_mul(item_size))
+                .and_then(|len| len.checked_add(offset))
+                .ok_or_else(size_error)?;
+            let taken = data.sub(offset..end).map_err(|_| size_error())?;
+            offset = end;
+            Ok(taken)
+        };
+        let index = Self {
+            features: take(count(0), Self::FEATURE_SIZE)?,
+            postings: take(count(1), Self::PAIR_SIZE)?,
+            thresholds: take(count(2), Self::PAIR_SIZE)?,
+            always_evaluated: take(count(3), size_of::<u32>())?,
+        };
+        if offset != data.len() {
+            return Err(size_error());
+        }
+        Ok(index)
+    }
+
+    // the same as FeatureIndex::match_
+    pub(crate) fn match_(&self, features: &[Sha256]) -> Result<Vec<SigIndex>, SigSetError> {
+        Ok((self.match_scores(features)?.into_iter())
+            .map(|(sig_id, _)| sig_id)
+            .collect())
+    }
+
+    // the same as FeatureIndex::match_scores
+    pub(crate) fn match_scores(
+        &self,
+        features: &[Sha256],
+    ) -> Result<Vec<(SigIndex, u32)>, SigSetError> {
+        let found: BTreeSet<&Sha256> = features.iter().collect();
+
+        let mut scores: HashMap<SigIndex, u32> = HashMap::new();
+        for sha in found {
+            let Some(feature) = self.find_feature(sha)? else {
+                continue;
+            };
+            let first = read_u32(feature, size_of::<Sha256>()) as usize;
+            let len = read_u32(feature, size_of::<Sha256>() + size_of::<u32>()) as usize;
+            let postings = (first.checked_add(len))
+                .and_then(|end| {
+                    Some(first.checked_mul(Self::PAIR_SIZE)?..end.checked_mul(Self::PAIR_SIZE)?)
+                })
+                .and_then(|range| self.postings.get(range).ok())
+                .ok_or(SigSetError::IncorrectSignatureSizeError { size: len as u32 })?;
+            for posting in postings.chunks_exact(Self::PAIR_SIZE) {
+                let score = scores.entry(read_u32(posting, 0)).or_default();
+                *score = score.saturating_add(read_u32(posting, 4));
+            }
+        }
+
+        let mut matched = vec![];
+        for (sig_id, score) in scores {
+            if self
+                .threshold(sig_id)?
+                .is_some_and(|threshold| score >= threshold)
+            {
+                matched.push((sig_id, score));
+            }
+        }
+        matched.sort_unstable();
+
+        log::trace!("matched {} sigs", matched.len());
+        Ok(matched)
+    }
+
+    pub(crate) fn always_evaluated(&self) -> Result<Vec<SigIndex>, SigSetError> {
+        Ok((self
+            .always_evaluated
+            .bytes()?
+            .chunks_exact(size_of::<u32>()))
+        .map(|id| read_u32(id, 0))
+        .collect())
+    }
+
+    fn find_feature(&self, sha: &Sha256) -> Result<Option<&'a [u8]>, SigSetError> {
+        let count = self.features.len() / Self::FEATURE_SIZE;
+        let feature =
+            |i: usize| (self.features).get(i * Self::FEATURE_SIZE..(i + 1) * Self::FEATURE_SIZE);
+        match binary_search(count, |i| Ok(feature(i)?[..size_of::<Sha256>()].cmp(sha)))? {
+            Some(i) => Ok(Some(feature(i)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn threshold(&self, sig_id: SigIndex) -> Result<Option<u32>, SigSetError> {
+        let count = self.thresholds.len() / Self::PAIR_SIZE;
+        let i = binary_search(count, |i| {
+            Ok(self.thresholds.read_u32(i * Self::PAIR_SIZE)?.cmp(&sig_id))
+        })?;
+        match i {
+            Some(i) => Ok(Some(self.thresholds.read_u32(i * Self::PAIR_SIZE + 4)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + size_of::<u32>()].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sha256_utils::sha256_from_vec, sig_set::set_view::SetBuffer};
+
+    fn feature(s: &str) -> Sha256 {
+        sha256_from_vec(s.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn matches_only_complete_signatures() {
+        let mut index = FeatureIndex::new_empty();
+        index.insert(0, &[feature("a"), feature("b")]);
+        index.insert(1, &[feature("b"), feature("c")]);
+        index.insert(2, &[feature("b"), feature("b")]);
+
+        assert_eq!(index.match_(&[feature("a"), feature("b")]), vec![0, 2]);
+        assert_eq!(
+            index.match_(&[feature("c"), feature("b"), feature("c")]),
+            vec![1, 2]
+        );
+        assert!(index.match_(&[feature("a"), feature("x")]).is_empty());
+    }
+
+    #[test]
+    fn weighted_features_reach_threshold() {
+        let mut index = FeatureIndex::new_empty();
+        let features = [(feature("a"), 5), (feature("b"), 1), (feature("c"), 1)];
+        index.insert_weighted(0, &features, Some(6));
+        index.insert_weighted(1, &features, None);
+
+        assert_eq!(index.match_(&[feature("a"), feature("b")]), vec![0]);
+        assert_eq!(index.match_(&[feature("a"), feature("c")]), vec![0]);
+        assert_eq!(
+            index.match_(&[feature("a"), feature("b"), feature("c")]),
+            vec![0, 1]
+        );
+        assert!(index.match_(&[feature("b"), feature("c")]).is_empty());
+    }
+
+    #[test]
+    fn more_than_32_signatures() {
+        let mut index = FeatureIndex::new_empty();
+        for i in 0..20_000u32 {
+            index.insert(i, &[feature(&format!("f{i}")), feature("shared")]);
+        }
+        assert_eq!(
+            index.match_(&[feature("shared"), feature("f19999")]),
+            vec![19_999]
+        );
+        assert_eq!(index.match_(&[feature("f40"), feature("shared")]), vec![40]);
+        assert!(index.match_(&[feature("f40")]).is_empty());
+    }
+
+    #[test]
+    fn stored_index_matches_in_place() {
+        let mut index = FeatureIndex::new_empty();
+        let features = [(feature("a"), 5), (feature("b"), 1), (feature("c"), 1)];
+        index.insert_weighted(3, &features, Some(6));
+        index.insert(1, &[feature("b"), feature("d")]);
+        index.insert(7, &[feature("d")]);
+        let bytes = index.to_bytes(&BTreeSet::from([2, 9]));
+        let buffer = SetBuffer::owned(bytes.clone());
+        let stored = StoredIndex::new(buffer.slice()).unwrap();
+
+        for found in [
+            vec![feature("a"), feature("b")],
+            vec![feature("d"), feature("b"), feature("c")],
+            vec![feature("a"), feature("x")],
+            vec![],
+        ] {
+            assert_eq!(stored.match_(&found).unwrap(), index.match_(&found));
+        }
+        assert_eq!(stored.always_evaluated().unwrap(), [2, 9]);
+
+        let short = SetBuffer::owned(bytes[..bytes.len() - 1].to_vec());
+        assert!(StoredIndex::new(short.slice()).is_err());
+        let mut bytes = bytes;
+        bytes.push(0);
+        assert!(StoredIndex::new(SetBuffer::owned(bytes).slice()).is_err());
+    }
+}
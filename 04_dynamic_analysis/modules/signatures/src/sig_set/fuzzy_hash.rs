@@ -0,0 +1,331 @@
+use crate::SigSetError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    io::{self, Read, Seek, SeekFrom},
+};
+
+const ROLLING_WINDOW: usize = 7;
+const MIN_BLOCK_SIZE: u32 = 3;
+const SPAMSUM_LENGTH: usize = 64;
+const HASH_INIT: u32 = 0x28021967;
+const HASH_PRIME: u32 = 0x01000193;
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// hash of last ROLLING_WINDOW bytes. Block ends where it hits block size
+#[derive(Default)]
+struct RollingHash {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl RollingHash {
+    fn update(&mut self, byte: u8) -> u32 {
+        let c = byte as u32;
+        self.h2 = self.h2.wrapping_sub(self.h1);
+        self.h2 = self.h2.wrapping_add(ROLLING_WINDOW as u32 * c);
+        self.h1 = self.h1.wrapping_add(c);
+        self.h1 = self
+            .h1
+            .wrapping_sub(self.window[self.n % ROLLING_WINDOW] as u32);
+        self.window[self.n % ROLLING_WINDOW] = byte;
+        self.n += 1;
+        self.h3 = (self.h3 << 5) ^ c;
+        self.sum()
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+// one part of digest: a character for each block of block size
+struct BlockHash {
+    block_size: u32,
+    limit: usize,
+    hash: u32,
+    digest: Vec<u8>,
+}
+
+impl BlockHash {
+    fn new(block_size: u32, limit: usize) -> Self {
+        Self {
+            block_size,
+            limit,
+            hash: HASH_INIT,
+            digest: Vec::with_capacity(limit),
+        }
+    }
+
+    fn update(&mut self, byte: u8, rolling: u32) {
+        self.hash = self.hash.wrapping_mul(HASH_PRIME) ^ byte as u32;
+        if rolling % self.block_size == self.block_size - 1 {
+            // when digest is full, its last character covers rest of file
+            let is_full = self.digest.len() >= self.limit - 1;
+            self.push_char();
+            if !is_full {
+                self.hash = HASH_INIT;
+            }
+        }
+    }
+
+    fn push_char(&mut self) {
+        let char = B64[self.hash as usize % 64];
+        if self.digest.len() < self.limit {
+            self.digest.push(char);
+        } else if let Some(last) = self.digest.last_mut() {
+            *last = char;
+        }
+    }
+}
+
+/// Context triggered piecewise hash of ssdeep (spamsum), "block_size:digest:digest". Files which
+/// differ only in some places have similar digests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FuzzyHash {
+    block_size: u32,
+    // digest of blocks of block size and of double block size
+    first: Vec<u8>,
+    second: Vec<u8>,
+}
+
+impl FuzzyHash {
+    pub(crate) fn parse(text: &str) -> Result<Self, SigSetError> {
+        let error = || SigSetError::IncorrectSignatureError {
+            info: format!("Can't convert {text} to ssdeep"),
+        };
+        let mut parts = text.trim().splitn(3, ':');
+        let (Some(block_size), Some(first), Some(second)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(error());
+        };
+
+        let block_size: u32 = block_size.parse().map_err(|_| error())?;
+        let is_digest = |digest: &str| {
+            digest.len() <= SPAMSUM_LENGTH && digest.bytes().all(|c| B64.contains(&c))
+        };
+        // block sizes are always minimal block size multiplied by power of 2
+        let is_block_size = block_size.is_multiple_of(MIN_BLOCK_SIZE)
+            && (block_size / MIN_BLOCK_SIZE).is_power_of_two();
+        if !is_block_size || !is_digest(first) || !is_digest(second) {
+            return Err(error());
+        }
+        Ok(Self {
+            block_size,
+            first: first.into(),
+            second: second.into(),
+        })
+    }
+
+    pub(crate) fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Digest of whole stream in one pass. Digests of every block size which may be chosen are
+    /// computed together
+    pub(crate) fn from_reader(reader: &mut (impl Read + Seek)) -> io::Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut max_block_size = MIN_BLOCK_SIZE;
+        while (max_block_size as u64) * (SPAMSUM_LENGTH as u64) < size {
+            max_block_size *= 2;
+        }
+        let block_sizes: Vec<u32> = std::iter::successors(Some(MIN_BLOCK_SIZE), |bs| Some(bs * 2))
+            .take_while(|bs| *bs <= max_block_size)
+            .collect();
+        let mut hashes: Vec<(BlockHash, BlockHash)> = (block_sizes.iter())
+            .map(|bs| {
+                (
+                    BlockHash::new(*bs, SPAMSUM_LENGTH),
+                    BlockHash::new(bs * 2, SPAMSUM_LENGTH / 2),
+                )
+            })
+            .collect();
+
+        let mut rolling = RollingHash::default();
+        let mut buffer = [0; 0x1000];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            for byte in &buffer[..read] {
+                let sum = rolling.update(*byte);
+                for (first, second) in &mut hashes {
+                    first.update(*byte, sum);
+                    second.update(*byte, sum);
+                }
+            }
+        }
+
+        // the largest block size, unless it gives too short digest
+        let mut chosen = hashes.len() - 1;
+        while chosen > 0 && hashes[chosen].0.digest.len() < SPAMSUM_LENGTH / 2 {
+            chosen -= 1;
+        }
+        let (mut first, mut second) = hashes.swap_remove(chosen);
+        if rolling.sum() != 0 {
+            first.push_char();
+            second.push_char();
+        }
+        Ok(Self {
+            block_size: first.block_size,
+            first: first.digest,
+            second: second.digest,
+        })
+    }
+
+    /// Similarity from 0 to 100. Digests can be compared only if their block sizes are equal or
+    /// one is double of other
+    pub(crate) fn similarity(&self, other: &FuzzyHash) -> u32 {
+        let (bs1, bs2) = (self.block_size, other.block_size);
+        if bs1 != bs2 && bs1 != bs2 * 2 && bs2 != bs1 * 2 {
+            return 0;
+        }
+
+        let (first1, second1) = (
+            eliminate_sequences(&self.first),
+            eliminate_sequences(&self.second),
+        );
+        let (first2, second2) = (
+            eliminate_sequences(&other.first),
+            eliminate_sequences(&other.second),
+        );
+        if bs1 == bs2 && first1 == first2 && second1 == second2 {
+            return 100;
+        }
+
+        if bs1 == bs2 {
+            score_digests(&first1, &first2, bs1).max(score_digests(&second1, &second2, bs1 * 2))
+        } else if bs1 == bs2 * 2 {
+            score_digests(&first1, &second2, bs1)
+        } else {
+            score_digests(&second1, &first2, bs2)
+        }
+    }
+}
+
+impl Display for FuzzyHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.block_size,
+            String::from_utf8_lossy(&self.first),
+            String::from_utf8_lossy(&self.second)
+        )
+    }
+}
+
+// runs of the same character longer than 3 say little about similarity
+fn eliminate_sequences(digest: &[u8]) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::with_capacity(digest.len());
+    for c in digest {
+        if result.len() < 3 || !result[result.len() - 3..].iter().all(|prev| prev == c) {
+            result.push(*c);
+        }
+    }
+    result
+}
+
+// digests must share ROLLING_WINDOW characters in a row, then they are scored by edit distance
+fn score_digests(digest1: &[u8], digest2: &[u8], block_size: u32) -> u32 {
+    if digest1.len() > SPAMSUM_LENGTH || digest2.len() > SPAMSUM_LENGTH {
+        return 0;
+    }
+    let windows: HashSet<&[u8]> = digest1.windows(ROLLING_WINDOW).collect();
+    if !digest2
+        .windows(ROLLING_WINDOW)
+        .any(|window| windows.contains(window))
+    {
+        return 0;
+    }
+
+    let len = (digest1.len() + digest2.len()) as u32;
+    let distance = edit_distance(digest1, digest2) * SPAMSUM_LENGTH as u32 / len;
+    let distance = 100 * distance / SPAMSUM_LENGTH as u32;
+    if distance >= 100 {
+        return 0;
+    }
+    let score = 100 - distance;
+
+    // short digests of small block sizes match by chance, their score is limited
+    let min_len = digest1.len().min(digest2.len()) as u32;
+    if block_size >= (99 + ROLLING_WINDOW as u32) / ROLLING_WINDOW as u32 * MIN_BLOCK_SIZE {
+        score
+    } else {
+        score.min(block_size / MIN_BLOCK_SIZE * min_len)
+    }
+}
+
+// insertion and deletion cost 1, replacement 2
+fn edit_distance(s1: &[u8], s2: &[u8]) -> u32 {
+    let mut previous: Vec<u32> = (0..=s2.len() as u32).collect();
+    for (i, c1) in s1.iter().enumerate() {
+        let mut current = vec![i as u32 + 1];
+        for (j, c2) in s2.iter().enumerate() {
+            let replace = previous[j] + if c1 == c2 { 0 } else { 2 };
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[s2.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // bytes which look random but are the same in every run
+    fn sample(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn hash(data: &[u8]) -> FuzzyHash {
+        FuzzyHash::from_reader(&mut io::Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn similar_files_have_similar_hashes() {
+        let original = sample(0x8000, 1);
+        let original_hash = hash(&original);
+        assert_eq!(
+            FuzzyHash::parse(&original_hash.to_string()).unwrap(),
+            original_hash
+        );
+        assert_eq!(original_hash.similarity(&original_hash), 100);
+
+        // a few bytes changed in the middle
+        let mut variant = original.clone();
+        variant[0x4000..0x4010].fill(0);
+        let similarity = original_hash.similarity(&hash(&variant));
+        assert!((50..100).contains(&similarity), "{similarity}");
+
+        assert_eq!(original_hash.similarity(&hash(&sample(0x8000, 2))), 0);
+    }
+
+    #[test]
+    fn parse_checks_format() {
+        assert_eq!(
+            FuzzyHash::parse("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+                .unwrap()
+                .block_size(),
+            3
+        );
+        for incorrect in ["", "3:abc", "5:abc:abc", "x:abc:abc", "3:a-c:abc"] {
+            assert!(FuzzyHash::parse(incorrect).is_err(), "{incorrect}");
+        }
+    }
+}
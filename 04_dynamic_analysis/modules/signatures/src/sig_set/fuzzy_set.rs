@@ -0,0 +1,219 @@
+use crate::{
+    sig_set::{
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        fuzzy_hash::FuzzyHash,
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{FuzzyMatch, SigBase, SigFuzzy},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::collections::BTreeMap;
+
+type FuzzySigId = u32;
+
+pub struct FuzzySet {
+    sig_id_to_description: BTreeMap<FuzzySigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // hash and threshold of each signature. They are stored in set
+    sig_id_to_hash: BTreeMap<FuzzySigId, (FuzzyHash, u32)>,
+    // only hashes of the same, half or double block size can be similar
+    block_size_to_sigs: BTreeMap<u32, Vec<FuzzySigId>>,
+}
+
+impl FuzzySet {
+    pub const SET_MAGIC_U32: u32 = 0x54453546; //F5ET
+    /// Similarity required by signatures without threshold
+    pub const DEFAULT_THRESHOLD: u32 = 80;
+
+    // each file is a signature. Files with the same hash (e.g. the same file under other name)
+    // give one signature, the last name is kept
+    pub fn from_dir(path_to_dir: &str) -> Result<FuzzySet, SigSetError> {
+        let mut entries = vec![];
+        for entry_res in std::fs::read_dir(path_to_dir)? {
+            let entry = entry_res?;
+            if entry.file_type()?.is_file() {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut hash_to_description = BTreeMap::new();
+        for entry in entries {
+            let hash = FuzzyHash::from_reader(&mut std::fs::File::open(entry.path())?)?;
+            let sig = SigFuzzy {
+                sig_base: SigBase {
+                    name: entry.file_name().into_string()?,
+                    description: format!("File size: {}", entry.metadata()?.len()),
+                    priority: 0,
+                    tests: Default::default(),
+                },
+                ssdeep: hash.to_string(),
+                threshold: None,
+            };
+            hash_to_description.insert(sig.ssdeep.clone(), serde_yaml::to_string(&sig)?);
+            log::trace!("path: {:?}", &entry);
+        }
+
+        let set = Self::from_descriptions(hash_to_description.into_values().collect())?;
+        log::info!("fuzzy set size: {}", set.sig_id_to_description.len());
+        Ok(set)
+    }
+
+    fn insert(&mut self, sig_id: FuzzySigId, hash: FuzzyHash, threshold: u32) {
+        (self.block_size_to_sigs.entry(hash.block_size()))
+            .or_default()
+            .push(sig_id);
+        self.sig_id_to_hash.insert(sig_id, (hash, threshold));
+    }
+}
+
+impl DescribedSet for FuzzySet {
+    const KIND: &'static str = "fuzzy";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sig_id_to_hash: Default::default(),
+            block_size_to_sigs: Default::default(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: FuzzySigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigFuzzy = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        let hash = FuzzyHash::parse(&sig.ssdeep)?;
+        let threshold = sig.threshold.unwrap_or(Self::DEFAULT_THRESHOLD);
+        if threshold > 100 {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: threshold {threshold} is above 100", sig.sig_base.name),
+            });
+        }
+
+        self.insert(sig_id, hash, threshold);
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        encode_lookup(&self.sig_id_to_hash)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let stored: BTreeMap<FuzzySigId, (FuzzyHash, u32)> = decode_lookup(&view)?;
+        let mut set = Self::new_empty();
+        for (sig_id, (hash, threshold)) in stored {
+            set.insert(sig_id, hash, threshold);
+        }
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for FuzzySet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let file_hash = FuzzyHash::from_reader(file)?;
+        let block_size = file_hash.block_size();
+        let block_sizes = [block_size / 2, block_size, block_size * 2];
+        let candidates = (block_sizes.iter())
+            .filter_map(|block_size| self.block_size_to_sigs.get(block_size))
+            .flatten();
+
+        let mut reports = vec![];
+        for sig_id in candidates {
+            let (hash, threshold) = &self.sig_id_to_hash[sig_id];
+            let similarity = file_hash.similarity(hash);
+            if similarity < *threshold {
+                continue;
+            }
+            reports.push(
+                FuzzyMatch {
+                    sig: self.decode_sig(*sig_id)?,
+                    ssdeep: file_hash.to_string(),
+                    similarity,
+                    threshold: *threshold,
+                }
+                .into(),
+            );
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn variants_of_sample_are_detected() {
+        let dir = std::env::temp_dir().join(format!("sfi_fuzzy_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut state = 7u32;
+        let sample: Vec<u8> = (0..0x8000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        std::fs::write(dir.join("sample.exe"), &sample).unwrap();
+        let set = FuzzySet::from_dir(dir.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let bytes = set
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(FuzzySet::SET_MAGIC_U32)
+            .unwrap();
+        let set = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<FuzzySet>()
+            .unwrap();
+        let eval = |data: Vec<u8>| {
+            let mut file = redr::FileReader::from_buff(std::io::Cursor::new(data));
+            let mut variant = redr::FileScanInfo::real_file("sample".into());
+            set.eval_file(&mut file, &mut variant).unwrap()
+        };
+
+        let mut rebuilt = sample.clone();
+        rebuilt[0x100..0x108].fill(0x90);
+        let reports = eval(rebuilt);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].name, "sample.exe");
+        assert!(reports[0].cause.contains("Similarity: "));
+
+        let mut other = sample;
+        other.reverse();
+        assert!(eval(other).is_empty());
+    }
+}
@@ -1,27 +1,49 @@
 use crate::{
     sha256_utils,
-    sha256_utils::{sha256_from_vec_of_vec, Sha256},
+    sha256_utils::Sha256,
     sig_set::{
-        signature::SigHeur, sigset_serializer::SigSetSerializer, Description, SigId, SigSet,
+        clr_metadata::clr_imports,
+        condition::{CompiledCondition, ConditionalSigs},
+        feature_index::{FeatureIndex, SigIndex},
+        import_name::{
+            canonical_feature, canonical_import, canonical_shared_library, CLR_PREFIX,
+            LIBRARY_PREFIX, SYMBOL_PREFIX,
+        },
+        import_set_view::{ImportSetView, ImportSig},
+        import_sig_data,
+        pe_hashes::{delay_load_imports, ordered_imports, ImportName, Imports},
+        sig_id_from_u32,
+        sig_source::SigSource,
+        signature::{FileFormat, HeurMatch, SigHeur},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
     },
     SigSetError,
 };
 use common::{detection::DetectionReport, redr};
-use object::{Import, Object};
+use object::{
+    elf::{FileHeader32, FileHeader64, DT_NEEDED},
+    read::{
+        elf::{Dyn, FileHeader},
+        pe::{ImageNtHeaders, PeFile, PeFile32, PeFile64},
+    },
+    Endianness, FileKind, Object,
+};
 use std::{
-    collections::{BTreeMap, HashMap},
-    io::{Read, Seek, SeekFrom},
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
 };
 
-type HeurSigId = u32;
-type ImportInSigs = u32;
+type HeurSigId = SigIndex;
 
 pub struct HeurSet {
-    import_count: u32,
-    sha_to_import_index: BTreeMap<Sha256, u32>,
-    imports_in_sig: Vec<ImportInSigs>,
-    sig_id_to_description: HashMap<HeurSigId, Description>,
-    sig_id_to_imports: HashMap<HeurSigId, Vec<Sha256>>,
+    // signatures compiled from yaml, kept in memory until set is serialized
+    index: FeatureIndex,
+    conditional: ConditionalSigs,
+    sig_id_to_description: BTreeMap<HeurSigId, Description>,
+    sig_id_to_imports: BTreeMap<HeurSigId, Vec<Sha256>>,
+    // signatures of loaded set with stored index. They are searched in place
+    view: Option<ImportSetView>,
 }
 
 impl HeurSet {
@@ -32,134 +54,325 @@ impl HeurSet {
 
     pub(crate) fn new_empty() -> Self {
         Self {
-            import_count: 0,
-            sha_to_import_index: Default::default(),
-            imports_in_sig: Default::default(),
+            index: FeatureIndex::new_empty(),
+            conditional: ConditionalSigs::new_empty(),
             sig_id_to_description: Default::default(),
             sig_id_to_imports: Default::default(),
+            view: None,
         }
     }
 
-    fn match_(&self, sha_vec: &Vec<Sha256>) -> Result<Option<SigHeur>, SigSetError> {
-        //--------------ALGORITHM------------------
-        // matching sha_vec with each signature has very low efficacy. There is better way
-        // imports_in_sig field tell as which signatures has particular import. For example:
-        //
-        // 1) lets assume, "kernel32+sleep" after converted to sha belongs to "self.imports" on
-        // first (zero index) position. Then if "kernel32+sleep" import appears in signatures
-        // with id's 2,3,7,11, then first (zero index) value in "self.imports_to_sig" is
-        // 1094, 0x446, 0b010001000110, because we fill "2,3,7,11" bits
-        //
-        // 2) next step is iterate by each import and if import exists in "imports_in_sig", then
-        // clear value for import_id
-        //
-        // 3) then if some bit is 0 in each "imports_in_sig" value, then we found our matched signature
-        // because each "import" hit clear for us one entry.
-        // 4) How to find out each "sig" is hit? perform in loop bitwise or on each "import_to_sig"
-        // then negate result, and then we know which sig is hit
-
-        //todo: add algorithm example step by step
-
-        let sig_count = self.sig_id_to_imports.len();
-        log::trace!("{:?}", self.imports_in_sig);
-        let mut imports_in_sig = self.imports_in_sig.clone();
-        for sha256 in sha_vec {
-            let Some(import_id) = self.sha_to_import_index.get(sha256) else {
-                continue;
-            };
-            log::debug!(
-                "self.imports_in_sig[*import_id as usize]: {:08b}",
-                self.imports_in_sig[*import_id as usize]
-            );
-            // if some imports hit, then we remove it from array. At the end it tell us in which
-            // signature all imports were hit
-
-            imports_in_sig[*import_id as usize] = 0;
+    pub(crate) fn from_view(view: ImportSetView) -> Self {
+        Self {
+            view: Some(view),
+            ..Self::new_empty()
         }
+    }
 
-        // we need calculate mask. If we have 5 signatures, then mask should be
-        // 0x11111111111111111111111111100000, 5 first bits empty. So to get this in first step we get:
-        // 0x00000000000000000000000000011111 and then negate it
-        let mut shared_imports: u32 = (1 << sig_count) - 1;
-        shared_imports = !shared_imports;
+    // returns every matched signature in order of ids
+    fn match_(&self, sha_vec: &[Sha256]) -> Result<Vec<HeurMatch>, SigSetError> {
+        let found: BTreeSet<&Sha256> = sha_vec.iter().collect();
+        self.matched_sigs(sha_vec)?
+            .into_iter()
+            .map(|sig| {
+                let sig_heur: SigHeur = serde_yaml::from_str(&sig.description)?;
+                Ok(Self::check_imports(sig_heur, &sig.imports, &found))
+            })
+            .collect()
+    }
 
-        for ids in imports_in_sig {
-            shared_imports |= ids;
+    fn matched_sigs(&self, sha_vec: &[Sha256]) -> Result<Vec<ImportSig>, SigSetError> {
+        if let Some(view) = &self.view {
+            return view.match_(sha_vec);
         }
 
-        shared_imports = !shared_imports;
+        // feature_index tell us which signatures have enough of their imports in sha_vec. Look
+        // at FeatureIndex to see how it works. Signatures with condition are only candidates
+        // until their condition is evaluated
+        let matched_sigs =
+            self.conditional
+                .filter(self.index.match_(sha_vec), &self.sig_id_to_imports, sha_vec);
+        log::trace!("matched_sigs: {:?}", matched_sigs);
+
+        Ok(matched_sigs
+            .into_iter()
+            .map(|sig_id| ImportSig {
+                id: sig_id,
+                imports: self.sig_id_to_imports[&sig_id].clone(),
+                condition: self.conditional.get(&sig_id).cloned(),
+                description: self.sig_id_to_description[&sig_id].clone(),
+            })
+            .collect())
+    }
 
-        if shared_imports == 0 {
-            // no match
-            return Ok(None);
+    // imports are hashed in the same order as they are listed in signature, followed by
+    // features of condition. Only imports rule gives score, condition features are reported
+    // when present
+    fn check_imports(sig: SigHeur, imports: &[Sha256], found: &BTreeSet<&Sha256>) -> HeurMatch {
+        let mut present = vec![];
+        let mut missing = vec![];
+        let mut score: u32 = 0;
+        for (import, sha) in sig.imports.iter().zip(imports) {
+            if found.contains(sha) {
+                present.push(import.name().to_string());
+                score = score.saturating_add(import.weight());
+            } else {
+                missing.push(import.name().to_string());
+            }
         }
-        //some signatures are matched. Take first signature matched
-        //todo: add to signatures Priority field in future
-        log::trace!("matched {} sigs", shared_imports.count_ones());
 
-        let matched_sig = shared_imports.trailing_zeros();
-        log::trace!("matched_sig {} id", matched_sig);
+        let features = sig.features();
+        for (feature, sha) in features.iter().zip(imports).skip(sig.imports.len()) {
+            if found.contains(sha) && !present.iter().any(|p| p == feature) {
+                present.push(feature.to_string());
+            }
+        }
 
-        let properties: SigHeur = serde_yaml::from_str(&self.sig_id_to_description[&matched_sig])?;
-        return Ok(Some(properties));
+        let threshold = sig.threshold.unwrap_or_else(|| {
+            (sig.imports.iter()).fold(0, |total, import| total.saturating_add(import.weight()))
+        });
+        HeurMatch {
+            sig,
+            present,
+            missing,
+            score,
+            threshold,
+        }
     }
 
     pub(crate) fn append_signature(
         &mut self,
         imports: Vec<Sha256>,
+        sig: &SigHeur,
         sig_id: HeurSigId,
         desc: Description,
+        condition: Option<CompiledCondition>,
     ) {
-        self.sig_id_to_imports.insert(sig_id, imports.clone());
-
-        for sha in imports {
-            let import_mask = 1 << sig_id;
-            if !self.sha_to_import_index.contains_key(&sha) {
-                log::trace!("{}", self.import_count);
-                self.sha_to_import_index.insert(sha, self.import_count);
-                self.imports_in_sig.push(import_mask);
-                self.import_count += 1;
-            } else {
-                let import_id = self.sha_to_import_index.get_mut(&sha).unwrap();
-                let import_ids = self.imports_in_sig.get_mut(*import_id as usize).unwrap();
-                *import_ids |= import_mask;
-            }
+        match condition {
+            Some(condition) => {
+                self.conditional
+                    .insert(&mut self.index, sig_id, &imports, condition)
+            },
+            None => {
+                let weighted: Vec<_> = imports.iter().copied().zip(sig.import_weights()).collect();
+                self.index.insert_weighted(sig_id, &weighted, sig.threshold);
+            },
         }
+        self.sig_id_to_imports.insert(sig_id, imports);
         self.sig_id_to_description.insert(sig_id, desc);
     }
+
+    // signatures get ids in order of descriptions
+    pub(crate) fn from_descriptions(descriptions: Vec<Description>) -> Result<Self, SigSetError> {
+        let mut heurset = HeurSet::new_empty();
+        for (sig_id, description) in (0..).zip(descriptions) {
+            heurset.add_description(sig_id, description)?;
+        }
+
+        log::info!("heurset size: {}", heurset.sig_id_to_description.len());
+        Ok(heurset)
+    }
+
+    pub(crate) fn add_description(
+        &mut self,
+        sig_id: u32,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let properties: SigHeur = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", properties);
+        Self::verify_threshold(&properties)?;
+
+        let condition = properties.compile_condition()?;
+        let imports = feature_hashes(&properties.features())?;
+        self.append_signature(imports, &properties, sig_id, description, condition);
+        Ok(())
+    }
+
+    // descriptions in order of signature ids
+    pub(crate) fn descriptions(&self) -> Result<Vec<Description>, SigSetError> {
+        match &self.view {
+            Some(view) => Ok((view.signatures()?.into_iter())
+                .map(|sig| sig.description)
+                .collect()),
+            None => Ok(self.sig_id_to_description.values().cloned().collect()),
+        }
+    }
+
+    fn verify_threshold(sig: &SigHeur) -> Result<(), SigSetError> {
+        if sig.imports.is_empty() && sig.condition.is_none() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: no imports and no condition", sig.sig_base.name),
+            });
+        }
+
+        // total weight is threshold of signature without one, it must not wrap
+        let Some(max_score) = sig.total_weight() else {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!(
+                    "{}: total weight of imports is larger than {}",
+                    sig.sig_base.name,
+                    u32::MAX
+                ),
+            });
+        };
+        let Some(threshold) = sig.threshold else {
+            return Ok(());
+        };
+
+        if threshold == 0 || threshold > max_score {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!(
+                    "{}: threshold {threshold} should be in range 1..={max_score}",
+                    sig.sig_base.name
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+// imports of signature are made canonical before hashing, the same way as imports of file, so
+// they match no matter how library and function are written. Used when set is compiled and loaded
+pub(crate) fn feature_hashes(features: &[&str]) -> Result<Vec<Sha256>, SigSetError> {
+    Ok(features
+        .iter()
+        .map(|s| sha256_utils::sha256_from_vec(canonical_feature(s).into_bytes()))
+        .collect::<Result<_, _>>()?)
 }
 
-fn get_characteristics(reader: &mut redr::FileReader) -> Result<Vec<Sha256>, SigSetError> {
+// format of file and hashes of its features. Format is None for object files which are not
+// executables
+fn get_characteristics(
+    reader: &mut redr::FileReader,
+) -> Result<(Option<FileFormat>, Vec<Sha256>), SigSetError> {
     let mut buffer = Vec::new();
     let _binary_data = reader.read_to_end(&mut buffer)?;
-    let file = object::File::parse(&*buffer)?;
-    get_imports(file.imports()?)
+    // imports by ordinal are only in import table of PE, object skips them
+    let (format, imports, features) = match FileKind::parse(&*buffer)? {
+        FileKind::Pe32 => {
+            let (imports, members) = pe_imports(&PeFile32::parse(&*buffer)?, &buffer)?;
+            (Some(FileFormat::Pe), imports, members)
+        },
+        FileKind::Pe64 => {
+            let (imports, members) = pe_imports(&PeFile64::parse(&*buffer)?, &buffer)?;
+            (Some(FileFormat::Pe), imports, members)
+        },
+        FileKind::Elf32 => {
+            let features = elf_features::<FileHeader32<Endianness>>(&buffer)?;
+            (Some(FileFormat::Elf), vec![], features)
+        },
+        FileKind::Elf64 => {
+            let features = elf_features::<FileHeader64<Endianness>>(&buffer)?;
+            (Some(FileFormat::Elf), vec![], features)
+        },
+        FileKind::MachO32 | FileKind::MachO64 => {
+            (Some(FileFormat::MachO), vec![], macho_features(&buffer)?)
+        },
+        _ => {
+            let imports = (object::File::parse(&*buffer)?.imports()?.iter())
+                .map(|import| {
+                    let name = ImportName::Name(import.name().to_vec());
+                    (import.library().to_vec(), name)
+                })
+                .collect();
+            (None, imports, vec![])
+        },
+    };
+    let mut hashes = get_imports(&imports)?;
+    hashes.append(&mut feature_hashes(
+        &features.iter().map(String::as_str).collect::<Vec<_>>(),
+    )?);
+    Ok((format, hashes))
 }
 
-fn get_imports(imports: Vec<Import>) -> Result<Vec<Sha256>, SigSetError> {
-    const DELIMITER: u8 = b'+';
+// libraries of DT_NEEDED entries and undefined dynamic symbols
+fn elf_features<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<Vec<String>, SigSetError> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.sections(endian, data)?;
+    let mut features = vec![];
+    if let Some((entries, link)) = sections.dynamic(endian, data)? {
+        let strings = sections.strings(endian, data, link)?;
+        for entry in entries
+            .iter()
+            .filter(|e| e.tag32(endian) == Some(DT_NEEDED))
+        {
+            let library = String::from_utf8_lossy(entry.string(endian, strings)?);
+            features.push(format!(
+                "{LIBRARY_PREFIX}{}",
+                canonical_shared_library(&library)
+            ));
+        }
+    }
+    for import in object::File::parse(data)?.imports()? {
+        let symbol = String::from_utf8_lossy(import.name());
+        features.push(format!("{SYMBOL_PREFIX}{symbol}"));
+    }
+    Ok(features)
+}
+
+// dylibs which symbols are imported from and the symbols, without underscore compiler adds
+fn macho_features(data: &[u8]) -> Result<Vec<String>, SigSetError> {
+    let mut libraries = BTreeSet::new();
+    let mut features = vec![];
+    for import in object::File::parse(data)?.imports()? {
+        if !import.library().is_empty() {
+            libraries.insert(canonical_shared_library(&String::from_utf8_lossy(
+                import.library(),
+            )));
+        }
+        let symbol = String::from_utf8_lossy(import.name());
+        let symbol = symbol.strip_prefix('_').unwrap_or(&symbol);
+        features.push(format!("{SYMBOL_PREFIX}{symbol}"));
+    }
+    features.extend((libraries.iter()).map(|library| format!("{LIBRARY_PREFIX}{library}")));
+    Ok(features)
+}
+
+// imports of import table, delay-load imports and P/Invoke of .NET assembly, followed by members
+// of other assemblies it uses as "clr:" features. Incorrect delay-load table or .NET metadata
+// only loses their features, packed files often have them broken
+fn pe_imports<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+    data: &[u8],
+) -> Result<(Imports, Vec<String>), SigSetError> {
+    let mut imports = ordered_imports(pe)?;
+    match delay_load_imports(pe) {
+        Ok(mut delayed) => imports.append(&mut delayed),
+        Err(e) => log::debug!("Incorrect delay-load imports: {e}"),
+    }
+
+    let mut members = vec![];
+    match clr_imports(pe, data) {
+        Ok(Some(clr)) => {
+            imports.extend(
+                (clr.pinvokes.into_iter())
+                    .map(|(library, function)| (library, ImportName::Name(function))),
+            );
+            members = (clr.member_refs.iter())
+                .map(|member| format!("{CLR_PREFIX}{member}"))
+                .collect();
+        },
+        Ok(None) => {},
+        Err(e) => log::debug!("Incorrect .NET metadata: {e}"),
+    }
+    Ok((imports, members))
+}
 
-    fn import_to_sha(import: &Import) -> Result<Sha256, SigSetError> {
+fn get_imports(imports: &[(Vec<u8>, ImportName)]) -> Result<Vec<Sha256>, SigSetError> {
+    fn import_to_sha(library: &[u8], name: &ImportName) -> Result<Sha256, SigSetError> {
+        let import = canonical_import(library, name);
         #[cfg(debug_assertions)]
-        log::debug!(
-            "import: \"{}{}{}\"",
-            String::from_utf8(import.library().to_vec())
-                .unwrap()
-                .to_lowercase(),
-            DELIMITER as char,
-            String::from_utf8(import.name().to_vec())
-                .unwrap()
-                .to_lowercase()
-        );
+        log::debug!("import: \"{import}\"");
 
-        Ok(sha256_from_vec_of_vec(vec![
-            import.library().to_vec(),
-            vec![DELIMITER],
-            import.name().to_vec(),
-        ])?)
+        Ok(sha256_utils::sha256_from_vec(import.into_bytes())?)
     }
 
-    imports.iter().map(|i| import_to_sha(i)).collect()
+    (imports.iter())
+        .map(|(library, name)| import_to_sha(library, name))
+        .collect()
 }
 
 impl SigSet for HeurSet {
@@ -167,75 +380,276 @@ impl SigSet for HeurSet {
         &self,
         file: &mut redr::FileReader,
         _variant: &mut redr::FileScanInfo,
-    ) -> Result<Option<DetectionReport>, SigSetError> {
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
         let imports_res = get_characteristics(file);
         if let Err(e) = imports_res {
             log::debug!("Not executable: {:?}", e);
-            return Ok(None);
+            return Ok(vec![]);
+        }
+        let (format, imports) = imports_res.unwrap();
+
+        // signatures of other format don't match, even if file has their features
+        let mut reports: Vec<DetectionReport> = self
+            .match_(&imports)?
+            .into_iter()
+            .filter(|sig| {
+                sig.sig
+                    .format
+                    .is_none_or(|sig_format| Some(sig_format) == format)
+            })
+            .map(|sig| sig.into())
+            .collect();
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        let mut heurset = HeurSet::new_empty();
+        source.compile(|sig_id, description| heurset.add_description(sig_id, description))?;
+        log::info!("heurset size: {}", heurset.sig_id_to_description.len());
+        Ok(heurset)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        if let Some(view) = &self.view {
+            return view.to_sig_set();
+        }
+
+        let mut ser = SigSetSerializer::new_empty();
+        // in order of ids, so set compiled from the same signatures is always the same
+        for (sig_id, imports) in self.sig_id_to_imports.iter() {
+            let (layout, v) = import_sig_data(
+                imports,
+                self.conditional.get(sig_id),
+                &self.sig_id_to_description[sig_id],
+            );
+
+            let mut id = sig_id_from_u32(*sig_id);
+            id[4] = layout;
+            ser.serialize_signature(id, v);
         }
-        let imports = imports_res.unwrap();
+        ser.serialize_index(self.index.to_bytes(self.conditional.always_evaluated()));
+        Ok(ser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
 
-        let sig_info = self.match_(&imports)?;
-        let desc_and_info = sig_info.map(|sig| sig.into());
-        Ok(desc_and_info)
+    fn import_sha(import: &str) -> Sha256 {
+        sha256_utils::sha256_from_vec(import.as_bytes().to_vec()).unwrap()
     }
 
-    fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError> {
-        let paths = std::fs::read_dir(path_to_dir)?;
+    #[test]
+    fn compile_serialize_and_match_many_signatures() {
+        const SIG_COUNT: u32 = 12_000;
+
         let mut heurset = HeurSet::new_empty();
+        for i in 0..SIG_COUNT {
+            let imports = [
+                format!("lib{i}.dll+fn{i}"),
+                "kernel32.dll+sleep".to_string(),
+            ];
+            let desc = format!(
+                "name: sig{i}\ndescription: sig {i}\nimports: [{}]\n",
+                imports.join(", ")
+            );
+            let sig: SigHeur = serde_yaml::from_str(&desc).unwrap();
+            heurset.append_signature(
+                imports.iter().map(|s| import_sha(s)).collect(),
+                &sig,
+                i,
+                desc,
+                None,
+            );
+        }
 
-        let mut sig_id = 0;
-        for entry_res in paths {
-            let entry = entry_res?;
-            //log::trace!("path: {:?}", &path);
-            if entry.file_type()?.is_file() {
-                let mut f = std::fs::File::open(entry.path())?;
-                let properties: SigHeur = serde_yaml::from_reader(&f)?;
-                log::info!("Properties: {:?}", properties);
-
-                // let imports_str = properties.get(HeurSet::PROPERTY_IMPORTS).ok_or(
-                //     SigSetError::NoSuchPropertyError(HeurSet::PROPERTY_IMPORTS.into()),
-                // )?;
-
-                let imports = properties
-                    .imports
-                    .iter()
-                    .map(|s| {
-                        sha256_utils::sha256_from_vec(s.to_lowercase().as_bytes().to_vec()).unwrap()
-                    })
-                    .collect();
-                f.seek(SeekFrom::Start(0))?;
-                let mut data = Vec::new();
-                f.read_to_end(&mut data)?;
-                heurset.append_signature(imports, sig_id, String::from_utf8_lossy(&data).into());
-                sig_id += 1;
-            }
+        let bytes = heurset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(HeurSet::SET_MAGIC_U32)
+            .unwrap();
+        let heurset = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_heur_set()
+            .unwrap();
+
+        for i in [0, 31, 32, 33, 4_096, SIG_COUNT - 1] {
+            let found = [
+                import_sha("kernel32.dll+sleep"),
+                import_sha("other.dll+fn"),
+                import_sha(&format!("lib{i}.dll+fn{i}")),
+            ];
+            let sigs = heurset.match_(&found).unwrap();
+            assert_eq!(sigs.len(), 1);
+            assert_eq!(sigs[0].sig.sig_base.name, format!("sig{i}"));
         }
 
-        log::info!("heurset size: {}", sig_id);
-        Ok(heurset)
+        let found = vec![import_sha(&format!("lib{}.dll+fn{}", SIG_COUNT, SIG_COUNT))];
+        assert!(heurset.match_(&found).unwrap().is_empty());
     }
 
-    fn to_sig_set(&self) -> SigSetSerializer {
-        let mut ser = SigSetSerializer::new_empty();
-        for (sig_id, imports) in self.sig_id_to_imports.iter() {
-            let mut desc = self.sig_id_to_description[&sig_id].clone();
+    #[test]
+    fn partial_match_with_weights_and_threshold() {
+        let desc = "name: Weighted\ndescription: weighted\nthreshold: 6\nimports:\n  - import: \
+                    kernel32.dll+virtualallocex\n    weight: 5\n  - \
+                    kernel32.dll+writeprocessmemory\n  - kernel32.dll+createremotethread\n";
+        let sig: SigHeur = serde_yaml::from_str(desc).unwrap();
+        HeurSet::verify_threshold(&sig).unwrap();
+        let imports = sig.imports.iter().map(|i| import_sha(i.name())).collect();
 
-            let mut v = vec![];
-            let imports_len = imports.len() as u32;
-            v.append(&mut imports_len.to_le_bytes().to_vec());
-            for import in imports {
-                v.append(&mut import.as_slice().to_vec());
-            }
-            unsafe {
-                v.append(desc.as_mut_vec());
-            }
+        let mut heurset = HeurSet::new_empty();
+        heurset.append_signature(imports, &sig, 0, desc.to_string(), None);
+
+        let found = [
+            import_sha("kernel32.dll+virtualallocex"),
+            import_sha("kernel32.dll+createremotethread"),
+        ];
+        let matches = heurset.match_(&found).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].score, 6);
+        assert_eq!(matches[0].missing, ["kernel32.dll+writeprocessmemory"]);
+
+        let report: DetectionReport = matches.into_iter().next().unwrap().into();
+        assert_eq!(
+            report.cause,
+            "Used Imports: [\"kernel32.dll+virtualallocex\", \
+             \"kernel32.dll+createremotethread\"], Missing Imports: \
+             [\"kernel32.dll+writeprocessmemory\"], Score: 6/6"
+        );
+
+        let found = [
+            import_sha("kernel32.dll+writeprocessmemory"),
+            import_sha("kernel32.dll+createremotethread"),
+        ];
+        assert!(heurset.match_(&found).unwrap().is_empty());
+    }
 
-            let mut sig_id_bytes = [0u32; 4];
-            sig_id_bytes[0] = *sig_id;
-            let sig_id = unsafe { *(sig_id_bytes.as_ptr() as *const SigId) };
-            ser.serialize_signature(sig_id, v);
+    #[test]
+    fn threshold_out_of_range() {
+        let desc = "name: Bad\ndescription: bad\nthreshold: 3\nimports: [a.dll+a, b.dll+b]\n";
+        let sig: SigHeur = serde_yaml::from_str(desc).unwrap();
+        assert!(HeurSet::verify_threshold(&sig).is_err());
+
+        // total weight wrapped to 1 would let threshold 1 pass, and without threshold the rule
+        // would need score 1
+        for threshold in ["threshold: 1\n", ""] {
+            let desc = format!(
+                "name: Big\ndescription: big\n{threshold}imports: [{{import: a.dll+a, weight: \
+                 4294967295}}, {{import: b.dll+b, weight: 2}}]\n"
+            );
+            let sig: SigHeur = serde_yaml::from_str(&desc).unwrap();
+            assert!(HeurSet::verify_threshold(&sig).is_err(), "{desc}");
         }
-        ser
+    }
+
+    #[test]
+    fn condition_is_serialized_and_evaluated() {
+        let sigs = [
+            "name: Injector\ndescription: injector\nimports: \
+             [kernel32.dll+openprocess]\ncondition:\n  all_of:\n    - \
+             kernel32.dll+virtualallocex\n    - any_of: [kernel32.dll+writeprocessmemory, \
+             ntdll.dll+ntwritevirtualmemory]\n    - none_of: [msi.dll+msiinstallproductw]\n",
+            "name: NoInstaller\ndescription: no installer\ncondition:\n  none_of: \
+             [msi.dll+msiinstallproductw]\n",
+        ];
+
+        let mut heurset = HeurSet::new_empty();
+        for (sig_id, desc) in sigs.iter().enumerate() {
+            let sig: SigHeur = serde_yaml::from_str(desc).unwrap();
+            HeurSet::verify_threshold(&sig).unwrap();
+            let imports = sig.features().iter().map(|s| import_sha(s)).collect();
+            let condition = sig.compile_condition().unwrap();
+            heurset.append_signature(imports, &sig, sig_id as u32, desc.to_string(), condition);
+        }
+
+        let bytes = heurset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(HeurSet::SET_MAGIC_U32)
+            .unwrap();
+        let heurset = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_heur_set()
+            .unwrap();
+
+        let names = |found: &[&str]| {
+            let found: Vec<_> = found.iter().map(|s| import_sha(s)).collect();
+            heurset
+                .match_(&found)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.sig.sig_base.name)
+                .collect::<Vec<_>>()
+        };
+
+        let injector = [
+            "kernel32.dll+openprocess",
+            "kernel32.dll+virtualallocex",
+            "ntdll.dll+ntwritevirtualmemory",
+        ];
+        assert_eq!(names(&injector), ["Injector", "NoInstaller"]);
+        assert_eq!(names(&injector[1..]), ["NoInstaller"]);
+        assert!(names(&[injector[0], injector[1], "msi.dll+msiinstallproductw"]).is_empty());
+
+        let found: Vec<_> = injector.iter().map(|s| import_sha(s)).collect();
+        let report: DetectionReport = heurset.match_(&found).unwrap().remove(0).into();
+        assert_eq!(
+            report.cause,
+            "Used Imports: [\"kernel32.dll+openprocess\", \"kernel32.dll+virtualallocex\", \
+             \"ntdll.dll+ntwritevirtualmemory\"]"
+        );
+    }
+
+    #[test]
+    fn imports_are_canonical_at_compile_and_scan() {
+        let desc = "name: Net\ndescription: net\nimports: [KERNEL32.dll+Sleep, ws2_32+#115, \
+                    WS2_32.dll+connect]\n";
+        let heurset = HeurSet::from_descriptions(vec![desc.to_string()]).unwrap();
+        let bytes = heurset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(HeurSet::SET_MAGIC_U32)
+            .unwrap();
+        let heurset = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_heur_set()
+            .unwrap();
+
+        let found = get_imports(&[
+            (
+                b"api-ms-win-core-synch-l1-2-0.dll".to_vec(),
+                ImportName::Name(b"Sleep".to_vec()),
+            ),
+            (b"WS2_32.dll".to_vec(), ImportName::Ordinal(115)),
+            (b"ws2_32.dll".to_vec(), ImportName::Ordinal(4)),
+        ])
+        .unwrap();
+        let matches = heurset.match_(&found).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].missing.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn signature_matches_only_files_of_its_format() {
+        let sigs = [
+            "name: Elf\ndescription: elf\nformat: elf\nimports: [lib:libc.so.6, sym:malloc]\n",
+            "name: Pe\ndescription: pe\nformat: pe\nimports: [lib:libc.so, sym:malloc]\n",
+            "name: Any\ndescription: any\nimports: [sym:malloc]\n",
+        ];
+        let heurset =
+            HeurSet::from_descriptions(sigs.iter().map(|s| s.to_string()).collect()).unwrap();
+
+        // test binary links libc dynamically
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let mut file = redr::FileReader::from_buff(std::io::Cursor::new(data));
+        let mut variant = redr::FileScanInfo::real_file("sample".into());
+        let reports = heurset.eval_file(&mut file, &mut variant).unwrap();
+        let mut names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["Any", "Elf"]);
     }
 }
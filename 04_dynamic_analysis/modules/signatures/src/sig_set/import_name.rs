@@ -0,0 +1,654 @@
+use crate::sig_set::pe_hashes::ImportName;
+
+// prefix of .NET member features
+pub(crate) const CLR_PREFIX: &str = "clr:";
+// prefixes of ELF and Mach-O features. Their symbols are not bound to libraries as imports of PE,
+// so libraries and symbols are separate features
+pub(crate) const LIBRARY_PREFIX: &str = "lib:";
+pub(crate) const SYMBOL_PREFIX: &str = "sym:";
+
+// api set contracts and dlls which implement them, more specific prefixes first
+const API_SETS: &[(&str, &str)] = &[
+    ("api-ms-win-core-com-", "ole32.dll"),
+    ("api-ms-win-core-registry-", "advapi32.dll"),
+    ("api-ms-win-core-rtlsupport-", "ntdll.dll"),
+    ("api-ms-win-core-shlwapi-", "shlwapi.dll"),
+    ("api-ms-win-core-", "kernel32.dll"),
+    ("api-ms-win-crt-", "ucrtbase.dll"),
+    ("api-ms-win-eventing-", "advapi32.dll"),
+    ("api-ms-win-security-", "advapi32.dll"),
+    ("api-ms-win-service-", "advapi32.dll"),
+    ("api-ms-win-shell-", "shell32.dll"),
+    ("ext-ms-win-gdi-", "gdi32.dll"),
+    ("ext-ms-win-ntuser-", "user32.dll"),
+];
+
+// ordinals of ws2_32.dll, named as by ordlookup of pefile. wsock32.dll is looked up in the same
+// table, as pefile does
+const WINSOCK_ORDINALS: &[(u16, &str)] = &[
+    (1, "accept"),
+    (2, "bind"),
+    (3, "closesocket"),
+    (4, "connect"),
+    (5, "getpeername"),
+    (6, "getsockname"),
+    (7, "getsockopt"),
+    (8, "htonl"),
+    (9, "htons"),
+    (10, "ioctlsocket"),
+    (11, "inet_addr"),
+    (12, "inet_ntoa"),
+    (13, "listen"),
+    (14, "ntohl"),
+    (15, "ntohs"),
+    (16, "recv"),
+    (17, "recvfrom"),
+    (18, "select"),
+    (19, "send"),
+    (20, "sendto"),
+    (21, "setsockopt"),
+    (22, "shutdown"),
+    (23, "socket"),
+    (24, "GetAddrInfoW"),
+    (25, "GetNameInfoW"),
+    (26, "WSApSetPostRoutine"),
+    (27, "FreeAddrInfoW"),
+    (28, "WPUCompleteOverlappedRequest"),
+    (29, "WSAAccept"),
+    (30, "WSAAddressToStringA"),
+    (31, "WSAAddressToStringW"),
+    (32, "WSACloseEvent"),
+    (33, "WSAConnect"),
+    (34, "WSACreateEvent"),
+    (35, "WSADuplicateSocketA"),
+    (36, "WSADuplicateSocketW"),
+    (37, "WSAEnumNameSpaceProvidersA"),
+    (38, "WSAEnumNameSpaceProvidersW"),
+    (39, "WSAEnumNetworkEvents"),
+    (40, "WSAEnumProtocolsA"),
+    (41, "WSAEnumProtocolsW"),
+    (42, "WSAEventSelect"),
+    (43, "WSAGetOverlappedResult"),
+    (44, "WSAGetQOSByName"),
+    (45, "WSAGetServiceClassInfoA"),
+    (46, "WSAGetServiceClassInfoW"),
+    (47, "WSAGetServiceClassNameByClassIdA"),
+    (48, "WSAGetServiceClassNameByClassIdW"),
+    (49, "WSAHtonl"),
+    (50, "WSAHtons"),
+    (51, "gethostbyaddr"),
+    (52, "gethostbyname"),
+    (53, "getprotobyname"),
+    (54, "getprotobynumber"),
+    (55, "getservbyname"),
+    (56, "getservbyport"),
+    (57, "gethostname"),
+    (58, "WSAInstallServiceClassA"),
+    (59, "WSAInstallServiceClassW"),
+    (60, "WSAIoctl"),
+    (61, "WSAJoinLeaf"),
+    (62, "WSALookupServiceBeginA"),
+    (63, "WSALookupServiceBeginW"),
+    (64, "WSALookupServiceEnd"),
+    (65, "WSALookupServiceNextA"),
+    (66, "WSALookupServiceNextW"),
+    (67, "WSANSPIoctl"),
+    (68, "WSANtohl"),
+    (69, "WSANtohs"),
+    (70, "WSAProviderConfigChange"),
+    (71, "WSARecv"),
+    (72, "WSARecvDisconnect"),
+    (73, "WSARecvFrom"),
+    (74, "WSARemoveServiceClass"),
+    (75, "WSAResetEvent"),
+    (76, "WSASend"),
+    (77, "WSASendDisconnect"),
+    (78, "WSASendTo"),
+    (79, "WSASetEvent"),
+    (80, "WSASetServiceA"),
+    (81, "WSASetServiceW"),
+    (82, "WSASocketA"),
+    (83, "WSASocketW"),
+    (84, "WSAStringToAddressA"),
+    (85, "WSAStringToAddressW"),
+    (86, "WSAWaitForMultipleEvents"),
+    (87, "WSCDeinstallProvider"),
+    (88, "WSCEnableNSProvider"),
+    (89, "WSCEnumProtocols"),
+    (90, "WSCGetProviderPath"),
+    (91, "WSCInstallNameSpace"),
+    (92, "WSCInstallProvider"),
+    (93, "WSCUnInstallNameSpace"),
+    (94, "WSCUpdateProvider"),
+    (95, "WSCWriteNameSpaceOrder"),
+    (96, "WSCWriteProviderOrder"),
+    (97, "freeaddrinfo"),
+    (98, "getaddrinfo"),
+    (99, "getnameinfo"),
+    (101, "WSAAsyncSelect"),
+    (102, "WSAAsyncGetHostByAddr"),
+    (103, "WSAAsyncGetHostByName"),
+    (104, "WSAAsyncGetProtoByNumber"),
+    (105, "WSAAsyncGetProtoByName"),
+    (106, "WSAAsyncGetServByPort"),
+    (107, "WSAAsyncGetServByName"),
+    (108, "WSACancelAsyncRequest"),
+    (109, "WSASetBlockingHook"),
+    (110, "WSAUnhookBlockingHook"),
+    (111, "WSAGetLastError"),
+    (112, "WSASetLastError"),
+    (113, "WSACancelBlockingCall"),
+    (114, "WSAIsBlocking"),
+    (115, "WSAStartup"),
+    (116, "WSACleanup"),
+    (151, "__WSAFDIsSet"),
+    (500, "WEP"),
+];
+
+// ordinals of oleaut32.dll, imported by ordinal by Visual Basic and Delphi binaries. Named as by
+// ordlookup of pefile
+const OLEAUT32_ORDINALS: &[(u16, &str)] = &[
+    (2, "SysAllocString"),
+    (3, "SysReAllocString"),
+    (4, "SysAllocStringLen"),
+    (5, "SysReAllocStringLen"),
+    (6, "SysFreeString"),
+    (7, "SysStringLen"),
+    (8, "VariantInit"),
+    (9, "VariantClear"),
+    (10, "VariantCopy"),
+    (11, "VariantCopyInd"),
+    (12, "VariantChangeType"),
+    (13, "VariantTimeToDosDateTime"),
+    (14, "DosDateTimeToVariantTime"),
+    (15, "SafeArrayCreate"),
+    (16, "SafeArrayDestroy"),
+    (17, "SafeArrayGetDim"),
+    (18, "SafeArrayGetElemsize"),
+    (19, "SafeArrayGetUBound"),
+    (20, "SafeArrayGetLBound"),
+    (21, "SafeArrayLock"),
+    (22, "SafeArrayUnlock"),
+    (23, "SafeArrayAccessData"),
+    (24, "SafeArrayUnaccessData"),
+    (25, "SafeArrayGetElement"),
+    (26, "SafeArrayPutElement"),
+    (27, "SafeArrayCopy"),
+    (28, "DispGetParam"),
+    (29, "DispGetIDsOfNames"),
+    (30, "DispInvoke"),
+    (31, "CreateDispTypeInfo"),
+    (32, "CreateStdDispatch"),
+    (33, "RegisterActiveObject"),
+    (34, "RevokeActiveObject"),
+    (35, "GetActiveObject"),
+    (36, "SafeArrayAllocDescriptor"),
+    (37, "SafeArrayAllocData"),
+    (38, "SafeArrayDestroyDescriptor"),
+    (39, "SafeArrayDestroyData"),
+    (40, "SafeArrayRedim"),
+    (41, "SafeArrayAllocDescriptorEx"),
+    (42, "SafeArrayCreateEx"),
+    (43, "SafeArrayCreateVectorEx"),
+    (44, "SafeArraySetRecordInfo"),
+    (45, "SafeArrayGetRecordInfo"),
+    (46, "VarParseNumFromStr"),
+    (47, "VarNumFromParseNum"),
+    (48, "VarI2FromUI1"),
+    (49, "VarI2FromI4"),
+    (50, "VarI2FromR4"),
+    (51, "VarI2FromR8"),
+    (52, "VarI2FromCy"),
+    (53, "VarI2FromDate"),
+    (54, "VarI2FromStr"),
+    (55, "VarI2FromDisp"),
+    (56, "VarI2FromBool"),
+    (57, "SafeArraySetIID"),
+    (58, "VarI4FromUI1"),
+    (59, "VarI4FromI2"),
+    (60, "VarI4FromR4"),
+    (61, "VarI4FromR8"),
+    (62, "VarI4FromCy"),
+    (63, "VarI4FromDate"),
+    (64, "VarI4FromStr"),
+    (65, "VarI4FromDisp"),
+    (66, "VarI4FromBool"),
+    (67, "SafeArrayGetIID"),
+    (68, "VarR4FromUI1"),
+    (69, "VarR4FromI2"),
+    (70, "VarR4FromI4"),
+    (71, "VarR4FromR8"),
+    (72, "VarR4FromCy"),
+    (73, "VarR4FromDate"),
+    (74, "VarR4FromStr"),
+    (75, "VarR4FromDisp"),
+    (76, "VarR4FromBool"),
+    (77, "SafeArrayGetVartype"),
+    (78, "VarR8FromUI1"),
+    (79, "VarR8FromI2"),
+    (80, "VarR8FromI4"),
+    (81, "VarR8FromR4"),
+    (82, "VarR8FromCy"),
+    (83, "VarR8FromDate"),
+    (84, "VarR8FromStr"),
+    (85, "VarR8FromDisp"),
+    (86, "VarR8FromBool"),
+    (87, "VarFormat"),
+    (88, "VarDateFromUI1"),
+    (89, "VarDateFromI2"),
+    (90, "VarDateFromI4"),
+    (91, "VarDateFromR4"),
+    (92, "VarDateFromR8"),
+    (93, "VarDateFromCy"),
+    (94, "VarDateFromStr"),
+    (95, "VarDateFromDisp"),
+    (96, "VarDateFromBool"),
+    (97, "VarFormatDateTime"),
+    (98, "VarCyFromUI1"),
+    (99, "VarCyFromI2"),
+    (100, "VarCyFromI4"),
+    (101, "VarCyFromR4"),
+    (102, "VarCyFromR8"),
+    (103, "VarCyFromDate"),
+    (104, "VarCyFromStr"),
+    (105, "VarCyFromDisp"),
+    (106, "VarCyFromBool"),
+    (107, "VarFormatNumber"),
+    (108, "VarBstrFromUI1"),
+    (109, "VarBstrFromI2"),
+    (110, "VarBstrFromI4"),
+    (111, "VarBstrFromR4"),
+    (112, "VarBstrFromR8"),
+    (113, "VarBstrFromCy"),
+    (114, "VarBstrFromDate"),
+    (115, "VarBstrFromDisp"),
+    (116, "VarBstrFromBool"),
+    (117, "VarFormatPercent"),
+    (118, "VarBoolFromUI1"),
+    (119, "VarBoolFromI2"),
+    (120, "VarBoolFromI4"),
+    (121, "VarBoolFromR4"),
+    (122, "VarBoolFromR8"),
+    (123, "VarBoolFromDate"),
+    (124, "VarBoolFromCy"),
+    (125, "VarBoolFromStr"),
+    (126, "VarBoolFromDisp"),
+    (127, "VarFormatCurrency"),
+    (128, "VarWeekdayName"),
+    (129, "VarMonthName"),
+    (130, "VarUI1FromI2"),
+    (131, "VarUI1FromI4"),
+    (132, "VarUI1FromR4"),
+    (133, "VarUI1FromR8"),
+    (134, "VarUI1FromCy"),
+    (135, "VarUI1FromDate"),
+    (136, "VarUI1FromStr"),
+    (137, "VarUI1FromDisp"),
+    (138, "VarUI1FromBool"),
+    (139, "VarFormatFromTokens"),
+    (140, "VarTokenizeFormatString"),
+    (141, "VarAdd"),
+    (142, "VarAnd"),
+    (143, "VarDiv"),
+    (146, "DispCallFunc"),
+    (147, "VariantChangeTypeEx"),
+    (148, "SafeArrayPtrOfIndex"),
+    (149, "SysStringByteLen"),
+    (150, "SysAllocStringByteLen"),
+    (152, "VarEqv"),
+    (153, "VarIdiv"),
+    (154, "VarImp"),
+    (155, "VarMod"),
+    (156, "VarMul"),
+    (157, "VarOr"),
+    (158, "VarPow"),
+    (159, "VarSub"),
+    (160, "CreateTypeLib"),
+    (161, "LoadTypeLib"),
+    (162, "LoadRegTypeLib"),
+    (163, "RegisterTypeLib"),
+    (164, "QueryPathOfRegTypeLib"),
+    (165, "LHashValOfNameSys"),
+    (166, "LHashValOfNameSysA"),
+    (167, "VarXor"),
+    (168, "VarAbs"),
+    (169, "VarFix"),
+    (170, "OaBuildVersion"),
+    (171, "ClearCustData"),
+    (172, "VarInt"),
+    (173, "VarNeg"),
+    (174, "VarNot"),
+    (175, "VarRound"),
+    (176, "VarCmp"),
+    (177, "VarDecAdd"),
+    (178, "VarDecDiv"),
+    (179, "VarDecMul"),
+    (180, "CreateTypeLib2"),
+    (181, "VarDecSub"),
+    (182, "VarDecAbs"),
+    (183, "LoadTypeLibEx"),
+    (184, "SystemTimeToVariantTime"),
+    (185, "VariantTimeToSystemTime"),
+    (186, "UnRegisterTypeLib"),
+    (187, "VarDecFix"),
+    (188, "VarDecInt"),
+    (189, "VarDecNeg"),
+    (190, "VarDecFromUI1"),
+    (191, "VarDecFromI2"),
+    (192, "VarDecFromI4"),
+    (193, "VarDecFromR4"),
+    (194, "VarDecFromR8"),
+    (195, "VarDecFromDate"),
+    (196, "VarDecFromCy"),
+    (197, "VarDecFromStr"),
+    (198, "VarDecFromDisp"),
+    (199, "VarDecFromBool"),
+    (200, "GetErrorInfo"),
+    (201, "SetErrorInfo"),
+    (202, "CreateErrorInfo"),
+    (203, "VarDecRound"),
+    (204, "VarDecCmp"),
+    (205, "VarI2FromI1"),
+    (206, "VarI2FromUI2"),
+    (207, "VarI2FromUI4"),
+    (208, "VarI2FromDec"),
+    (209, "VarI4FromI1"),
+    (210, "VarI4FromUI2"),
+    (211, "VarI4FromUI4"),
+    (212, "VarI4FromDec"),
+    (213, "VarR4FromI1"),
+    (214, "VarR4FromUI2"),
+    (215, "VarR4FromUI4"),
+    (216, "VarR4FromDec"),
+    (217, "VarR8FromI1"),
+    (218, "VarR8FromUI2"),
+    (219, "VarR8FromUI4"),
+    (220, "VarR8FromDec"),
+    (221, "VarDateFromI1"),
+    (222, "VarDateFromUI2"),
+    (223, "VarDateFromUI4"),
+    (224, "VarDateFromDec"),
+    (225, "VarCyFromI1"),
+    (226, "VarCyFromUI2"),
+    (227, "VarCyFromUI4"),
+    (228, "VarCyFromDec"),
+    (229, "VarBstrFromI1"),
+    (230, "VarBstrFromUI2"),
+    (231, "VarBstrFromUI4"),
+    (232, "VarBstrFromDec"),
+    (233, "VarBoolFromI1"),
+    (234, "VarBoolFromUI2"),
+    (235, "VarBoolFromUI4"),
+    (236, "VarBoolFromDec"),
+    (237, "VarUI1FromI1"),
+    (238, "VarUI1FromUI2"),
+    (239, "VarUI1FromUI4"),
+    (240, "VarUI1FromDec"),
+    (241, "VarDecFromI1"),
+    (242, "VarDecFromUI2"),
+    (243, "VarDecFromUI4"),
+    (244, "VarI1FromUI1"),
+    (245, "VarI1FromI2"),
+    (246, "VarI1FromI4"),
+    (247, "VarI1FromR4"),
+    (248, "VarI1FromR8"),
+    (249, "VarI1FromDate"),
+    (250, "VarI1FromCy"),
+    (251, "VarI1FromStr"),
+    (252, "VarI1FromDisp"),
+    (253, "VarI1FromBool"),
+    (254, "VarI1FromUI2"),
+    (255, "VarI1FromUI4"),
+    (256, "VarI1FromDec"),
+    (257, "VarUI2FromUI1"),
+    (258, "VarUI2FromI2"),
+    (259, "VarUI2FromI4"),
+    (260, "VarUI2FromR4"),
+    (261, "VarUI2FromR8"),
+    (262, "VarUI2FromDate"),
+    (263, "VarUI2FromCy"),
+    (264, "VarUI2FromStr"),
+    (265, "VarUI2FromDisp"),
+    (266, "VarUI2FromBool"),
+    (267, "VarUI2FromI1"),
+    (268, "VarUI2FromUI4"),
+    (269, "VarUI2FromDec"),
+    (270, "VarUI4FromUI1"),
+    (271, "VarUI4FromI2"),
+    (272, "VarUI4FromI4"),
+    (273, "VarUI4FromR4"),
+    (274, "VarUI4FromR8"),
+    (275, "VarUI4FromDate"),
+    (276, "VarUI4FromCy"),
+    (277, "VarUI4FromStr"),
+    (278, "VarUI4FromDisp"),
+    (279, "VarUI4FromBool"),
+    (280, "VarUI4FromI1"),
+    (281, "VarUI4FromUI2"),
+    (282, "VarUI4FromDec"),
+    (283, "BSTR_UserSize"),
+    (284, "BSTR_UserMarshal"),
+    (285, "BSTR_UserUnmarshal"),
+    (286, "BSTR_UserFree"),
+    (287, "VARIANT_UserSize"),
+    (288, "VARIANT_UserMarshal"),
+    (289, "VARIANT_UserUnmarshal"),
+    (290, "VARIANT_UserFree"),
+    (291, "LPSAFEARRAY_UserSize"),
+    (292, "LPSAFEARRAY_UserMarshal"),
+    (293, "LPSAFEARRAY_UserUnmarshal"),
+    (294, "LPSAFEARRAY_UserFree"),
+    (295, "LPSAFEARRAY_Size"),
+    (296, "LPSAFEARRAY_Marshal"),
+    (297, "LPSAFEARRAY_Unmarshal"),
+    (298, "VarDecCmpR8"),
+    (299, "VarCyAdd"),
+    (303, "VarCyMul"),
+    (304, "VarCyMulI4"),
+    (305, "VarCySub"),
+    (306, "VarCyAbs"),
+    (307, "VarCyFix"),
+    (308, "VarCyInt"),
+    (309, "VarCyNeg"),
+    (310, "VarCyRound"),
+    (311, "VarCyCmp"),
+    (312, "VarCyCmpR8"),
+    (313, "VarBstrCat"),
+    (314, "VarBstrCmp"),
+    (315, "VarR8Pow"),
+    (316, "VarR4CmpR8"),
+    (317, "VarR8Round"),
+    (318, "VarCat"),
+    (319, "VarDateFromUdateEx"),
+    (322, "GetRecordInfoFromGuids"),
+    (323, "GetRecordInfoFromTypeInfo"),
+    (401, "OleLoadPictureEx"),
+    (402, "OleLoadPictureFileEx"),
+    (411, "SafeArrayCreateVector"),
+    (412, "SafeArrayCopyData"),
+    (413, "VectorFromBstr"),
+    (414, "BstrFromVector"),
+    (415, "OleIconToCursor"),
+    (416, "OleCreatePropertyFrameIndirect"),
+    (417, "OleCreatePropertyFrame"),
+    (418, "OleLoadPicture"),
+    (419, "OleCreatePictureIndirect"),
+    (420, "OleCreateFontIndirect"),
+    (421, "OleTranslateColor"),
+    (422, "OleLoadPictureFile"),
+    (423, "OleSavePictureFile"),
+    (424, "OleLoadPicturePath"),
+];
+
+/// Library as imports are compared: lowercase, with ".dll" if it has no extension, and api set
+/// contract replaced by dll which implements it
+pub(crate) fn canonical_library(library: &str) -> String {
+    let library = library.trim().to_lowercase();
+    if let Some((_, host)) = API_SETS
+        .iter()
+        .find(|(prefix, _)| library.starts_with(prefix))
+    {
+        return host.to_string();
+    }
+    if library.is_empty() || library.contains('.') {
+        library
+    } else {
+        library + ".dll"
+    }
+}
+
+/// Name of function imported by ordinal. Library must be canonical
+pub(crate) fn ordinal_name(library: &str, ordinal: u16) -> Option<&'static str> {
+    let table = match library {
+        "ws2_32.dll" | "wsock32.dll" => WINSOCK_ORDINALS,
+        "oleaut32.dll" => OLEAUT32_ORDINALS,
+        _ => return None,
+    };
+    (table
+        .binary_search_by_key(&ordinal, |(ordinal, _)| *ordinal)
+        .ok())
+    .map(|index| table[index].1)
+}
+
+/// Import as it is hashed: "library+function", both canonical. Function imported by ordinal
+/// which isn't in table is "ord<N>"
+pub(crate) fn canonical_import(library: &[u8], name: &ImportName) -> String {
+    let library = canonical_library(&String::from_utf8_lossy(library));
+    let name = match name {
+        ImportName::Name(name) => String::from_utf8_lossy(name).to_lowercase(),
+        ImportName::Ordinal(ordinal) => match ordinal_name(&library, *ordinal) {
+            Some(name) => name.to_lowercase(),
+            None => format!("ord{ordinal}"),
+        },
+    };
+    format!("{library}+{name}")
+}
+
+/// Shared library of ELF or Mach-O as it is compared: lowercase file name without path and
+/// without version after ".so"
+pub(crate) fn canonical_shared_library(library: &str) -> String {
+    let name = (library.trim().rsplit('/').next())
+        .unwrap_or_default()
+        .to_lowercase();
+    match name.find(".so.") {
+        Some(end) => name[..end + 3].to_string(),
+        None => name,
+    }
+}
+
+fn strip_prefix<'a>(feature: &'a str, prefix: &str) -> Option<&'a str> {
+    (feature.get(..prefix.len()))
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &feature[prefix.len()..])
+}
+
+/// True if feature has form of import, one of ELF or Mach-O or member of .NET type
+pub(crate) fn is_import_feature(feature: &str) -> bool {
+    feature.contains('+')
+        || [CLR_PREFIX, LIBRARY_PREFIX, SYMBOL_PREFIX]
+            .iter()
+            .any(|prefix| strip_prefix(feature, prefix).is_some())
+}
+
+/// Import of signature made canonical, so it is hashed as the same import found in file.
+/// Function may be given by ordinal as "#<N>" or "ord<N>". Member of .NET type is
+/// "clr:Namespace.Type::Member", library and symbol of ELF or Mach-O are "lib:<library>" and
+/// "sym:<symbol>"
+pub(crate) fn canonical_feature(feature: &str) -> String {
+    if let Some(member) = strip_prefix(feature, CLR_PREFIX) {
+        return format!("{CLR_PREFIX}{}", member.trim().to_lowercase());
+    }
+    if let Some(library) = strip_prefix(feature, LIBRARY_PREFIX) {
+        return format!("{LIBRARY_PREFIX}{}", canonical_shared_library(library));
+    }
+    if let Some(symbol) = strip_prefix(feature, SYMBOL_PREFIX) {
+        return format!("{SYMBOL_PREFIX}{}", symbol.trim().to_lowercase());
+    }
+    let Some((library, name)) = feature.split_once('+') else {
+        return feature.to_lowercase();
+    };
+    let name = name.trim();
+    let ordinal = (name.strip_prefix('#'))
+        .or_else(|| {
+            (name.get(..3))
+                .filter(|prefix| prefix.eq_ignore_ascii_case("ord"))
+                .map(|_| &name[3..])
+        })
+        .and_then(|ordinal| ordinal.parse().ok());
+    let name = match ordinal {
+        Some(ordinal) => ImportName::Ordinal(ordinal),
+        None => ImportName::Name(name.as_bytes().to_vec()),
+    };
+    canonical_import(library.as_bytes(), &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_of_signature_is_the_same_as_import_of_file() {
+        let from_file =
+            |library: &str, name: ImportName| canonical_import(library.as_bytes(), &name);
+        let name = |name: &str| ImportName::Name(name.as_bytes().to_vec());
+
+        assert_eq!(
+            canonical_feature("KERNEL32.dll+Sleep"),
+            "kernel32.dll+sleep"
+        );
+        assert_eq!(
+            from_file("kernel32", name("Sleep")),
+            canonical_feature("KERNEL32.dll+Sleep")
+        );
+        assert_eq!(
+            from_file("api-ms-win-core-synch-l1-2-0.dll", name("Sleep")),
+            "kernel32.dll+sleep"
+        );
+        assert_eq!(
+            from_file(
+                "API-MS-WIN-CORE-REGISTRY-L1-1-0.DLL",
+                name("RegSetValueExW")
+            ),
+            "advapi32.dll+regsetvalueexw"
+        );
+
+        for feature in ["WS2_32.dll+WSAStartup", "ws2_32+#115", "ws2_32.dll+ord115"] {
+            assert_eq!(
+                canonical_feature(feature),
+                "ws2_32.dll+wsastartup",
+                "{feature}"
+            );
+        }
+        assert_eq!(
+            from_file("WS2_32.dll", ImportName::Ordinal(115)),
+            "ws2_32.dll+wsastartup"
+        );
+        assert_eq!(
+            from_file("OLEAUT32.dll", ImportName::Ordinal(6)),
+            "oleaut32.dll+sysfreestring"
+        );
+        assert_eq!(
+            from_file("ws2_32.dll", ImportName::Ordinal(999)),
+            canonical_feature("ws2_32.dll+#999")
+        );
+        assert_eq!(canonical_feature("ws2_32.dll+order"), "ws2_32.dll+order");
+        assert_eq!(
+            canonical_feature("CLR: System.Reflection.Assembly::Load"),
+            "clr:system.reflection.assembly::load"
+        );
+        assert_eq!(
+            canonical_feature("lib:/lib/x86_64-linux-gnu/libc.so.6"),
+            "lib:libc.so"
+        );
+        assert_eq!(
+            canonical_feature("LIB:/usr/lib/libSystem.B.dylib"),
+            "lib:libsystem.b.dylib"
+        );
+        assert_eq!(canonical_feature("sym: ptrace"), "sym:ptrace");
+        assert!(!is_import_feature("kernel32.dll"));
+
+        for table in [WINSOCK_ORDINALS, OLEAUT32_ORDINALS] {
+            assert!(table.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        }
+    }
+}
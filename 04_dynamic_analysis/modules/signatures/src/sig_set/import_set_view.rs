@@ -0,0 +1,156 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{
+        condition::CompiledCondition, feature_index::StoredIndex, set_view::SetView,
+        sigset_serializer::SigSetSerializer, Description, HeurSigHeader, SigHeader, SigId,
+        LAYOUT_CONDITION, LAYOUT_IMPORTS,
+    },
+    SigSetError,
+};
+use std::{collections::BTreeSet, mem::size_of};
+
+// id of record which follows signatures of set. Heuristic and dynamic set keep their FeatureIndex
+// in it (see StoredIndex), sha set its hash algorithms and other sets their lookup structure (see
+// DescribedSet). It is bigger than id of any signature, so headers stay sorted
+pub(crate) const INDEX_SIG_ID: SigId = [0xFF; 32];
+
+// heuristic or dynamic signature read from set
+pub(crate) struct ImportSig {
+    pub(crate) id: u32,
+    pub(crate) imports: Vec<Sha256>,
+    pub(crate) condition: Option<CompiledCondition>,
+    pub(crate) description: Description,
+}
+
+impl ImportSig {
+    // imports count (u32), imports (sha256 each), compiled condition if layout of signature has
+    // it, and yaml description
+    fn read(sig_header: SigHeader, data: &[u8]) -> Result<Self, SigSetError> {
+        let sig_header: HeurSigHeader = sig_header.into();
+        log::debug!("sig_header: {:?}", sig_header);
+
+        let size_error = || SigSetError::IncorrectSignatureSizeError {
+            size: sig_header.size,
+        };
+        let read_u32 = |offset: usize| -> Result<u32, SigSetError> {
+            let bytes = data
+                .get(offset..offset + size_of::<u32>())
+                .ok_or_else(size_error)?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        let imports_count = read_u32(0)? as usize;
+        log::debug!("imports_count: {:?}", imports_count);
+
+        let mut curr_offset = size_of::<u32>();
+        let imports_end = imports_count
+            .checked_mul(size_of::<Sha256>())
+            .and_then(|len| len.checked_add(curr_offset))
+            .ok_or_else(size_error)?;
+        let imports = (data.get(curr_offset..imports_end).ok_or_else(size_error)?)
+            .chunks_exact(size_of::<Sha256>())
+            .map(|sha| sha.try_into().unwrap())
+            .collect();
+        curr_offset = imports_end;
+
+        let condition = match sig_header.layout {
+            LAYOUT_IMPORTS => None,
+            LAYOUT_CONDITION => {
+                let condition_size = read_u32(curr_offset)?;
+                curr_offset += size_of::<u32>();
+
+                let condition_end = curr_offset + condition_size as usize;
+                let condition = data
+                    .get(curr_offset..condition_end)
+                    .ok_or_else(size_error)?;
+                curr_offset = condition_end;
+                Some(CompiledCondition::from_bytes(condition, imports_count)?)
+            },
+            layout => {
+                return Err(SigSetError::IncorrectSignatureError {
+                    info: format!("Unknown layout of signature: {layout}"),
+                })
+            },
+        };
+
+        Ok(Self {
+            id: sig_header.id,
+            imports,
+            condition,
+            description: String::from_utf8_lossy(&data[curr_offset..]).into(),
+        })
+    }
+
+    // every signature of set in order of ids. Record with index is skipped
+    pub(crate) fn read_all(view: &SetView) -> Result<Vec<Self>, SigSetError> {
+        let mut sigs = Vec::with_capacity(view.len());
+        for sig in view.signatures() {
+            let (sig_header, data) = sig?;
+            if sig_header.id != INDEX_SIG_ID {
+                sigs.push(Self::read(sig_header, data)?);
+            }
+        }
+        Ok(sigs)
+    }
+}
+
+// Loaded heuristic or dynamic set searched in place. Only stored index and signatures it matched
+// are read, so loading doesn't depend on number of signatures
+#[derive(Clone)]
+pub(crate) struct ImportSetView {
+    view: SetView,
+}
+
+impl ImportSetView {
+    // None for set compiled before index was stored with signatures
+    pub(crate) fn new(view: SetView) -> Result<Option<Self>, SigSetError> {
+        let set = Self { view };
+        match set.view.index()? {
+            Some(data) => {
+                StoredIndex::new(data)?;
+                Ok(Some(set))
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn signatures(&self) -> Result<Vec<ImportSig>, SigSetError> {
+        ImportSig::read_all(&self.view)
+    }
+
+    pub(crate) fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.view.to_sig_set()
+    }
+
+    // matched signatures in order of ids. Signatures with condition are only candidates until
+    // their condition is evaluated, the same as in ConditionalSigs::filter
+    pub(crate) fn match_(&self, sha_vec: &[Sha256]) -> Result<Vec<ImportSig>, SigSetError> {
+        let index_data = self
+            .view
+            .index()?
+            .ok_or(SigSetError::IncorrectSignatureError {
+                info: "Set has no index".into(),
+            })?;
+        let index = StoredIndex::new(index_data)?;
+
+        let mut candidates = index.match_(sha_vec)?;
+        candidates.extend(index.always_evaluated()?);
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let found: BTreeSet<&Sha256> = sha_vec.iter().collect();
+        let mut matched = vec![];
+        for sig_id in candidates {
+            let (sig_header, data) = self.view.find_u32(sig_id)?.ok_or_else(|| {
+                SigSetError::IncorrectSignatureError {
+                    info: format!("Indexed signature {sig_id} is not in set"),
+                }
+            })?;
+            let sig = ImportSig::read(sig_header, data)?;
+            if (sig.condition.as_ref()).is_none_or(|c| c.eval(&sig.imports, &found)) {
+                matched.push(sig);
+            }
+        }
+        Ok(matched)
+    }
+}
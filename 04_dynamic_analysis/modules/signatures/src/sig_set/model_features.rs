@@ -0,0 +1,185 @@
+use crate::{
+    sig_set::{
+        import_name::canonical_import,
+        pe_features::PeFeatures,
+        pe_hashes::{delay_load_imports, ordered_imports},
+        signature::{PeFeature, StringKind},
+        string_features::{classify_strings, data_strings},
+    },
+    SigSetError,
+};
+use object::{
+    pe::{
+        ImageNtHeaders32, ImageNtHeaders64, IMAGE_DIRECTORY_ENTRY_SECURITY,
+        IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE, IMAGE_DLLCHARACTERISTICS_NX_COMPAT, IMAGE_FILE_DLL,
+        IMAGE_SUBSYSTEM_WINDOWS_GUI,
+    },
+    read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile},
+    FileKind, LittleEndian as LE,
+};
+use std::collections::BTreeMap;
+
+pub(crate) const IMPORT_PREFIX: &str = "import: ";
+const PE_PREFIX: &str = "pe: ";
+const HEADER_PREFIX: &str = "header: ";
+const STRINGS_PREFIX: &str = "strings: ";
+
+// fields of headers, flags are 1 or 0
+const HEADER_FIELDS: &[&str] = &[
+    "64bit",
+    "aslr",
+    "dep",
+    "dll",
+    "gui",
+    "sections",
+    "signed",
+    "size_of_image",
+];
+
+// count of all strings, besides counts of kinds of strings
+const ALL_STRINGS: &str = "total";
+
+/// Features of PE by names, features which are 0 are left out. Counts and sizes are
+/// logarithmic, so weights of model don't depend on size of file
+pub(crate) fn model_features(data: &[u8]) -> Result<BTreeMap<String, f64>, SigSetError> {
+    let mut features = match FileKind::parse(data)? {
+        FileKind::Pe32 => from_pe(&PeFile::<ImageNtHeaders32>::parse(data)?, data)?,
+        FileKind::Pe64 => from_pe(&PeFile::<ImageNtHeaders64>::parse(data)?, data)?,
+        kind => return Err(SigSetError::NotPeError(format!("{kind:?}"))),
+    };
+
+    let strings = data_strings(data);
+    let mut counts = BTreeMap::<StringKind, u32>::new();
+    for string in classify_strings(&strings) {
+        *counts.entry(string.kind).or_default() += 1;
+    }
+    features.insert(
+        format!("{STRINGS_PREFIX}{ALL_STRINGS}"),
+        (strings.len() as f64).ln_1p(),
+    );
+    for (kind, count) in counts {
+        features.insert(
+            format!("{STRINGS_PREFIX}{}", kind.name()),
+            (count as f64).ln_1p(),
+        );
+    }
+
+    features.retain(|_, value| *value != 0.0);
+    Ok(features)
+}
+
+fn from_pe<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+    data: &[u8],
+) -> Result<BTreeMap<String, f64>, SigSetError> {
+    let mut features = BTreeMap::new();
+    let mut imports = ordered_imports(pe)?;
+    match delay_load_imports(pe) {
+        Ok(mut delayed) => imports.append(&mut delayed),
+        Err(e) => log::debug!("Incorrect delay-load imports: {e}"),
+    }
+    for (library, name) in &imports {
+        features.insert(
+            format!("{IMPORT_PREFIX}{}", canonical_import(library, name)),
+            1.0,
+        );
+    }
+
+    let pe_features = PeFeatures::from_pe(pe, data)?;
+    for feature in PeFeature::ALL {
+        let value = pe_features.value(feature);
+        let value = match feature {
+            PeFeature::MaxSectionEntropy => value / 8.0,
+            PeFeature::EntryPointOutsideText | PeFeature::MissingRichHeader => value,
+            _ => value.ln_1p(),
+        };
+        features.insert(format!("{PE_PREFIX}{}", feature.name()), value);
+    }
+
+    let file_header = pe.nt_headers().file_header();
+    let optional_header = pe.nt_headers().optional_header();
+    let dll_characteristics = optional_header.dll_characteristics();
+    let flag = |set: bool| if set { 1.0 } else { 0.0 };
+    let signed = (pe.data_directory(IMAGE_DIRECTORY_ENTRY_SECURITY))
+        .is_some_and(|security| security.size.get(LE) != 0);
+    for (field, value) in [
+        ("64bit", flag(pe.nt_headers().is_type_64())),
+        (
+            "aslr",
+            flag(dll_characteristics & IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE != 0),
+        ),
+        (
+            "dep",
+            flag(dll_characteristics & IMAGE_DLLCHARACTERISTICS_NX_COMPAT != 0),
+        ),
+        (
+            "dll",
+            flag(file_header.characteristics.get(LE) & IMAGE_FILE_DLL != 0),
+        ),
+        (
+            "gui",
+            flag(optional_header.subsystem() == IMAGE_SUBSYSTEM_WINDOWS_GUI),
+        ),
+        (
+            "sections",
+            (file_header.number_of_sections.get(LE) as f64).ln_1p(),
+        ),
+        ("signed", flag(signed)),
+        (
+            "size_of_image",
+            (optional_header.size_of_image() as f64).ln_1p(),
+        ),
+    ] {
+        features.insert(format!("{HEADER_PREFIX}{field}"), value);
+    }
+    Ok(features)
+}
+
+/// True if name is name of feature which model can be given
+pub(crate) fn is_model_feature(name: &str) -> bool {
+    if let Some(import) = name.strip_prefix(IMPORT_PREFIX) {
+        return import.contains('+') && import == import.to_lowercase();
+    }
+    if let Some(feature) = name.strip_prefix(PE_PREFIX) {
+        return (PeFeature::ALL.iter()).any(|known| known.name() == feature);
+    }
+    if let Some(field) = name.strip_prefix(HEADER_PREFIX) {
+        return HEADER_FIELDS.contains(&field);
+    }
+    if let Some(kind) = name.strip_prefix(STRINGS_PREFIX) {
+        return kind == ALL_STRINGS || (StringKind::ALL.iter()).any(|known| known.name() == kind);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_of_model_features() {
+        for name in [
+            "import: kernel32.dll+virtualalloc",
+            "pe: max_section_entropy",
+            "header: signed",
+            "strings: url",
+            "strings: total",
+        ] {
+            assert!(is_model_feature(name), "{name}");
+        }
+        for name in [
+            "import: KERNEL32.dll+VirtualAlloc",
+            "import: virtualalloc",
+            "pe: entropy",
+            "header: checksum",
+            "strings: email",
+            "url",
+        ] {
+            assert!(!is_model_feature(name), "{name}");
+        }
+        assert!(matches!(
+            model_features(b"\x7fELF"),
+            Err(SigSetError::FileObjectError(_)) | Err(SigSetError::NotPeError(_))
+        ));
+    }
+}
@@ -0,0 +1,521 @@
+use crate::{
+    sig_set::{
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        dir_files,
+        model_features::{is_model_feature, model_features, IMPORT_PREFIX},
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{FeatureScaling, ModelMatch, SigBase, SigModel},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap, io::Read};
+
+type ModelSigId = u32;
+// feature names with values, features missing in file are 0
+type FeatureVector = BTreeMap<String, f64>;
+
+// features which contributed most to probability, listed in report
+const TOP_FEATURES: usize = 5;
+
+// imports become features of model if at least this number of training files has them. Rarer
+// imports only let model remember single files
+const MIN_IMPORT_FILES: usize = 2;
+// the most common imports are kept, model stays small
+const MAX_IMPORT_FEATURES: usize = 512;
+
+// gradient descent of training. Weights are kept small by L2 regularization
+const EPOCHS: usize = 2000;
+const L2_REGULARIZATION: f64 = 0.001;
+
+pub struct ModelSet {
+    sig_id_to_description: BTreeMap<ModelSigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // models are stored in set
+    sig_id_to_model: BTreeMap<ModelSigId, Model>,
+}
+
+// what file is evaluated with, the same as in SigModel
+#[derive(Serialize, Deserialize)]
+struct Model {
+    threshold: f64,
+    bias: f64,
+    weights: BTreeMap<String, f64>,
+    scaling: BTreeMap<String, FeatureScaling>,
+}
+
+impl Model {
+    // value of feature as it is weighted
+    fn scaled(&self, name: &str, value: f64) -> f64 {
+        (self.scaling.get(name)).map_or(value, |scaling| scaling.scale(value))
+    }
+}
+
+impl ModelSet {
+    pub const SET_MAGIC_U32: u32 = 0x5445354D; //M5ET
+
+    // models whose probability reaches threshold, with the probability and features which raised
+    // it most, in order of ids
+    fn match_(&self, features: &FeatureVector) -> Vec<(ModelSigId, f64, Vec<String>)> {
+        let mut matches = vec![];
+        for (sig_id, sig) in &self.sig_id_to_model {
+            // scaled feature missing in file contributes as well, its value isn't 0
+            let mut contributions: Vec<(&String, f64)> = (sig.weights.iter())
+                .map(|(name, weight)| {
+                    let value = features.get(name).copied().unwrap_or_default();
+                    (name, weight * sig.scaled(name, value))
+                })
+                .collect();
+            let probability = sigmoid(sig.bias + contributions.iter().map(|(_, c)| c).sum::<f64>());
+            if probability < sig.threshold {
+                continue;
+            }
+
+            contributions.retain(|(_, contribution)| *contribution > 0.0);
+            contributions.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let top_features = (contributions.iter())
+                .take(TOP_FEATURES)
+                .map(|(name, contribution)| format!("{name} (+{contribution:.2})"))
+                .collect();
+            matches.push((*sig_id, probability, top_features));
+        }
+        matches
+    }
+
+    /// Logistic regression fitted on features of PE files of "malicious_dir" and "benign_dir".
+    /// Files which are not PE are skipped
+    pub fn train(
+        malicious_dir: &str,
+        benign_dir: &str,
+        name: &str,
+        threshold: Option<f64>,
+    ) -> Result<ModelSet, SigSetError> {
+        let mut samples = vec![];
+        for (dir, malicious) in [(malicious_dir, true), (benign_dir, false)] {
+            let count = samples.len();
+            for entry in dir_files(dir)? {
+                match model_features(&std::fs::read(entry.path())?) {
+                    Ok(features) => samples.push((features, malicious)),
+                    Err(e) => log::warn!("Skipped {:?}: {e}", entry.file_name()),
+                }
+            }
+            if samples.len() == count {
+                return Err(SigSetError::NoTrainingFilesError(dir.into()));
+            }
+        }
+
+        let malicious = samples.iter().filter(|(_, malicious)| *malicious).count();
+        let (bias, weights, scaling) = fit(&samples);
+        let sig = SigModel {
+            sig_base: SigBase {
+                name: name.into(),
+                description: format!(
+                    "Logistic regression trained on {malicious} malicious and {} benign files",
+                    samples.len() - malicious
+                ),
+                priority: 0,
+                tests: Default::default(),
+            },
+            threshold,
+            bias,
+            weights,
+            scaling,
+        };
+        let set = Self::from_descriptions(vec![serde_yaml::to_string(&sig)?])?;
+        log::info!("model weights: {}", set.sig_id_to_model[&0].weights.len());
+        Ok(set)
+    }
+}
+
+// bias, weights and feature scaling of logistic regression by batch gradient descent. Features
+// are standardized, so features of large values (e.g. logarithm of image size) don't outweigh
+// flags. Classes are weighted, so more files of one class don't push model towards it. Weights
+// are rounded, zero weights are left out with their scaling
+fn fit(
+    samples: &[(FeatureVector, bool)],
+) -> (f64, BTreeMap<String, f64>, BTreeMap<String, FeatureScaling>) {
+    let names = feature_names(samples);
+    let count = samples.len() as f64;
+    let scaling: Vec<FeatureScaling> = (names.iter())
+        .map(|name| {
+            let values: Vec<f64> = (samples.iter())
+                .map(|(features, _)| features.get(name).copied().unwrap_or_default())
+                .collect();
+            let mean = values.iter().sum::<f64>() / count;
+            let variance = values
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f64>()
+                / count;
+            // feature of the same value in every file is 0 after scaling
+            let deviation = if variance > 0.0 { variance.sqrt() } else { 1.0 };
+            FeatureScaling { mean, deviation }
+        })
+        .collect();
+    let rows: Vec<Vec<f64>> = (samples.iter())
+        .map(|(features, _)| {
+            (names.iter().zip(&scaling))
+                .map(|(name, scaling)| {
+                    let value = features.get(name).copied().unwrap_or_default();
+                    (value - scaling.mean) / scaling.deviation
+                })
+                .collect()
+        })
+        .collect();
+
+    let malicious = samples.iter().filter(|(_, malicious)| *malicious).count() as f64;
+    let class_weight = |malicious_sample: bool| match malicious_sample {
+        true => count / (2.0 * malicious),
+        false => count / (2.0 * (count - malicious)),
+    };
+
+    // loss changes by at most 1/4 of weighted squared norm of samples (bias is feature of value
+    // 1) per unit of step. Step of its inverse never overshoots minimum, so descent doesn't
+    // oscillate
+    let curvature = (rows.iter().zip(samples))
+        .map(|(row, (_, malicious_sample))| {
+            let norm = 1.0 + row.iter().map(|value| value * value).sum::<f64>();
+            class_weight(*malicious_sample) * norm
+        })
+        .sum::<f64>()
+        / (4.0 * count);
+    let step = 1.0 / (curvature + L2_REGULARIZATION);
+
+    let mut weights = vec![0.0; names.len()];
+    let mut bias = 0.0;
+    for _ in 0..EPOCHS {
+        let mut gradient = vec![0.0; names.len()];
+        let mut bias_gradient = 0.0;
+        for (row, (_, malicious_sample)) in rows.iter().zip(samples) {
+            let z = bias
+                + (weights.iter().zip(row))
+                    .map(|(weight, value)| weight * value)
+                    .sum::<f64>();
+            let target = if *malicious_sample { 1.0 } else { 0.0 };
+            let error = (sigmoid(z) - target) * class_weight(*malicious_sample);
+            for (gradient, value) in gradient.iter_mut().zip(row) {
+                *gradient += error * value;
+            }
+            bias_gradient += error;
+        }
+        for (weight, gradient) in weights.iter_mut().zip(gradient) {
+            *weight -= step * (gradient / count + L2_REGULARIZATION * *weight);
+        }
+        bias -= step * bias_gradient / count;
+    }
+
+    let round = |value: f64| (value * 1e6).round() / 1e6;
+    let mut kept_weights = BTreeMap::new();
+    let mut kept_scaling = BTreeMap::new();
+    for ((name, weight), scaling) in names.into_iter().zip(weights).zip(scaling) {
+        let weight = round(weight);
+        if weight == 0.0 {
+            continue;
+        }
+        kept_weights.insert(name.clone(), weight);
+        kept_scaling.insert(
+            name,
+            FeatureScaling {
+                mean: round(scaling.mean),
+                deviation: round(scaling.deviation),
+            },
+        );
+    }
+    (round(bias), kept_weights, kept_scaling)
+}
+
+// every feature which is not import, and imports of enough files, sorted
+fn feature_names(samples: &[(FeatureVector, bool)]) -> Vec<String> {
+    let mut names = BTreeMap::<&String, usize>::new();
+    for (features, _) in samples {
+        for name in features.keys() {
+            *names.entry(name).or_default() += 1;
+        }
+    }
+    let (imports, others): (Vec<_>, Vec<_>) =
+        (names.into_iter()).partition(|(name, _)| name.starts_with(IMPORT_PREFIX));
+    let mut imports: Vec<_> = (imports.into_iter())
+        .filter(|(_, files)| *files >= MIN_IMPORT_FILES)
+        .collect();
+    imports.sort_by_key(|(name, files)| (Reverse(*files), *name));
+    imports.truncate(MAX_IMPORT_FEATURES);
+
+    let mut names: Vec<String> = (others.into_iter().chain(imports))
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+impl DescribedSet for ModelSet {
+    const KIND: &'static str = "model";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sig_id_to_model: Default::default(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: ModelSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigModel = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig.sig_base);
+        let incorrect = |info: String| SigSetError::IncorrectSignatureError {
+            info: format!("{}: {info}", sig.sig_base.name),
+        };
+        if sig.weights.is_empty() {
+            return Err(incorrect("no weights".into()));
+        }
+        let threshold = sig.threshold();
+        if !(threshold > 0.0 && threshold < 1.0) {
+            return Err(incorrect(format!("threshold {threshold} is not in (0, 1)")));
+        }
+        if !sig.bias.is_finite() {
+            return Err(incorrect(format!("bias {} is not finite", sig.bias)));
+        }
+        for (name, weight) in &sig.weights {
+            if !is_model_feature(name) {
+                return Err(incorrect(format!("unknown feature \"{name}\"")));
+            }
+            if !weight.is_finite() {
+                return Err(incorrect(format!("weight of {name} is not finite")));
+            }
+        }
+        for (name, scaling) in &sig.scaling {
+            if !sig.weights.contains_key(name) {
+                return Err(incorrect(format!(
+                    "scaling of \"{name}\" which has no weight"
+                )));
+            }
+            if !(scaling.mean.is_finite()
+                && scaling.deviation.is_finite()
+                && scaling.deviation > 0.0)
+            {
+                return Err(incorrect(format!(
+                    "scaling of {name} is not finite and positive"
+                )));
+            }
+        }
+
+        let model = Model {
+            threshold,
+            bias: sig.bias,
+            weights: sig.weights,
+            scaling: sig.scaling,
+        };
+        self.sig_id_to_model.insert(sig_id, model);
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        encode_lookup(&self.sig_id_to_model)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        set.sig_id_to_model = decode_lookup(&view)?;
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for ModelSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let features = match model_features(&buffer) {
+            Ok(features) => features,
+            Err(e) => {
+                log::debug!("Not executable: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+
+        let mut reports = vec![];
+        for (sig_id, probability, top_features) in self.match_(&features) {
+            let sig: SigModel = self.decode_sig(sig_id)?;
+            let model_match = ModelMatch {
+                sig,
+                probability,
+                top_features,
+            };
+            reports.push(model_match.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitted_model_separates_classes_and_reports_top_features() {
+        let sample = |features: &[(&str, f64)], malicious: bool| {
+            let features = (features.iter())
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect::<FeatureVector>();
+            (features, malicious)
+        };
+        let mut samples = vec![];
+        for i in 0..4 {
+            let entropy = 0.9 + i as f64 * 0.02;
+            samples.push(sample(
+                &[
+                    ("import: kernel32.dll+virtualalloc", 1.0),
+                    ("pe: max_section_entropy", entropy),
+                    ("pe: missing_rich_header", 1.0),
+                ],
+                true,
+            ));
+            samples.push(sample(
+                &[
+                    ("import: user32.dll+messageboxw", 1.0),
+                    ("pe: max_section_entropy", entropy - 0.2),
+                    ("header: signed", 1.0),
+                ],
+                false,
+            ));
+        }
+        // import of single file isn't feature
+        samples.push(sample(&[("import: ws2_32.dll+connect", 1.0)], true));
+
+        let (bias, weights, scaling) = fit(&samples);
+        assert!(weights["import: kernel32.dll+virtualalloc"] > 0.0);
+        assert!(weights["header: signed"] < 0.0);
+        assert!(!weights.contains_key("import: ws2_32.dll+connect"));
+
+        let sig = SigModel {
+            sig_base: SigBase {
+                name: "Model".into(),
+                description: "m".into(),
+                priority: 0,
+                tests: Default::default(),
+            },
+            threshold: None,
+            bias,
+            weights,
+            scaling,
+        };
+        let set = ModelSet::from_descriptions(vec![serde_yaml::to_string(&sig).unwrap()]).unwrap();
+        for (features, malicious) in &samples[..8] {
+            assert_eq!(!set.match_(features).is_empty(), *malicious);
+        }
+        let (_, probability, top_features) = set.match_(&samples[0].0).remove(0);
+        assert!(probability > 0.5);
+        assert!(top_features
+            .iter()
+            .any(|feature| feature.starts_with("import: kernel32.dll+virtualalloc (+")));
+        assert!(top_features.len() <= TOP_FEATURES);
+
+        for incorrect in [
+            "bias: 0\nweights: {}",
+            "bias: 0\nthreshold: 1\nweights: {\"header: dll\": 1}",
+            "bias: 0\nweights: {\"header: checksum\": 1}",
+            "bias: .nan\nweights: {\"header: dll\": 1}",
+            "bias: 0\nweights: {\"header: dll\": 1}\nscaling: {\"header: dll\": {mean: 0, deviation: 0}}",
+            "bias: 0\nweights: {\"header: dll\": 1}\nscaling: {\"header: tls\": {mean: 0, deviation: 1}}",
+        ] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                ModelSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+
+    #[test]
+    fn fitted_model_learns_features_of_large_values() {
+        // logarithms of image size and string count are ~10 times flags, with small differences
+        // between classes
+        let mut samples = vec![];
+        for i in 0..6 {
+            let step = i as f64 / 5.0;
+            let mut malicious = FeatureVector::new();
+            malicious.insert("header: size_of_image".into(), 12.5 + step);
+            malicious.insert("strings: total".into(), 8.0 + step * 0.6);
+            if i % 2 == 0 {
+                malicious.insert("pe: missing_rich_header".into(), 1.0);
+            }
+            samples.push((malicious, true));
+
+            let mut benign = FeatureVector::new();
+            benign.insert("header: size_of_image".into(), 14.0 + step);
+            benign.insert("strings: total".into(), 9.2 + step * 0.8);
+            if i % 3 == 0 {
+                benign.insert("header: signed".into(), 1.0);
+            }
+            samples.push((benign, false));
+        }
+
+        let (bias, weights, scaling) = fit(&samples);
+        assert!(weights["header: size_of_image"] < 0.0);
+        assert!(scaling["header: size_of_image"].mean > 13.0);
+        let sig = SigModel {
+            sig_base: SigBase {
+                name: "Model".into(),
+                description: "m".into(),
+                priority: 0,
+                tests: Default::default(),
+            },
+            threshold: None,
+            bias,
+            weights,
+            scaling,
+        };
+        let set = ModelSet::from_descriptions(vec![serde_yaml::to_string(&sig).unwrap()]).unwrap();
+        let model = &set.sig_id_to_model[&0];
+        for (features, malicious) in &samples {
+            let probability = sigmoid(
+                model.bias
+                    + (model.weights.iter())
+                        .map(|(name, weight)| {
+                            let value = features.get(name).copied().unwrap_or_default();
+                            weight * model.scaled(name, value)
+                        })
+                        .sum::<f64>(),
+            );
+            match malicious {
+                true => assert!(probability > 0.9, "{features:?} {probability}"),
+                false => assert!(probability < 0.1, "{features:?} {probability}"),
+            }
+            assert_eq!(!set.match_(features).is_empty(), *malicious);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{
+    sig_set::{
+        byte_pattern::BytePattern,
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{PatternMatch, SigPattern},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use aho_corasick::{AhoCorasick, MatchKind};
+use common::{detection::DetectionReport, redr};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom},
+};
+
+type PatternSigId = u32;
+
+// atoms of not anchored patterns of all signatures, searched in one pass over file
+struct AtomSearcher {
+    automaton: AhoCorasick,
+    // (index of signature, index of its pattern) for each atom
+    atoms: Vec<(usize, usize)>,
+    // bytes kept from previous chunk, so atom crossing chunks is found and bytes before it are
+    // in buffer
+    keep: usize,
+}
+
+impl AtomSearcher {
+    fn new(sigs: &[(PatternSigId, Vec<BytePattern>)]) -> Result<Self, SigSetError> {
+        let mut atoms = vec![];
+        let mut keep = 0;
+        for (sig_index, (_, patterns)) in sigs.iter().enumerate() {
+            for (pattern_index, pattern) in patterns.iter().enumerate() {
+                if pattern.offset.is_none() {
+                    atoms.push((sig_index, pattern_index));
+                    keep = keep.max(pattern.max_before() + pattern.atom().len() - 1);
+                }
+            }
+        }
+
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::Standard)
+            .build(
+                atoms
+                    .iter()
+                    .map(|(sig, pattern)| sigs[*sig].1[*pattern].atom()),
+            )
+            .map_err(|e| SigSetError::IncorrectSignatureError {
+                info: format!("Can't build pattern searcher: {e}"),
+            })?;
+        Ok(Self {
+            automaton,
+            atoms,
+            keep,
+        })
+    }
+}
+
+pub struct PatternSet {
+    sig_id_to_description: BTreeMap<PatternSigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // compiled patterns of each signature, in order of ids. They are stored in set
+    sigs: Vec<(PatternSigId, Vec<BytePattern>)>,
+    // built when all signatures are added
+    searcher: Option<AtomSearcher>,
+}
+
+impl PatternSet {
+    pub const SET_MAGIC_U32: u32 = 0x54453550; //P5ET
+    const CHUNK_SIZE: usize = 0x10000;
+
+    // offset of each pattern of each signature, if it was found
+    fn find_patterns(
+        &self,
+        searcher: &AtomSearcher,
+        file: &mut redr::FileReader,
+    ) -> Result<Vec<Vec<Option<u64>>>, SigSetError> {
+        let mut found: Vec<Vec<Option<u64>>> = (self.sigs.iter())
+            .map(|(_, patterns)| vec![None; patterns.len()])
+            .collect();
+
+        file.seek(SeekFrom::Start(0))?;
+        for (sig_index, (_, patterns)) in self.sigs.iter().enumerate() {
+            for (pattern_index, pattern) in patterns.iter().enumerate() {
+                let Some(offset) = pattern.offset else {
+                    continue;
+                };
+                let data = read_at(file, offset, pattern.max_len())?;
+                if pattern.matches_at_start(&data) {
+                    found[sig_index][pattern_index] = Some(offset);
+                }
+            }
+        }
+
+        // file offset of buffer start and number of bytes of buffer searched in previous chunk
+        let (mut base, mut searched) = (0u64, 0);
+        let mut buffer = Vec::with_capacity(searcher.keep + Self::CHUNK_SIZE);
+        loop {
+            let eof = read_chunk(file, &mut buffer, Self::CHUNK_SIZE)?;
+            for atom_match in searcher.automaton.find_overlapping_iter(&buffer) {
+                let (sig_index, pattern_index) = searcher.atoms[atom_match.pattern().as_usize()];
+                if atom_match.end() <= searched || found[sig_index][pattern_index].is_some() {
+                    continue;
+                }
+
+                let pattern = &self.sigs[sig_index].1[pattern_index];
+                let start = atom_match.start().saturating_sub(pattern.max_before());
+                let end = atom_match.end() + pattern.max_after();
+                let atom_pos = atom_match.start() - start;
+                let pattern_start = if end <= buffer.len() || eof {
+                    let end = end.min(buffer.len());
+                    pattern.matches_around_atom(&buffer[start..end], atom_pos)
+                } else {
+                    // pattern may end in next chunk
+                    let data = read_at(file, base + start as u64, end - start)?;
+                    pattern.matches_around_atom(&data, atom_pos)
+                };
+                if let Some(pattern_start) = pattern_start {
+                    found[sig_index][pattern_index] = Some(base + (start + pattern_start) as u64);
+                }
+            }
+            if eof {
+                break;
+            }
+
+            let drained = buffer.len().saturating_sub(searcher.keep);
+            buffer.drain(..drained);
+            base += drained as u64;
+            searched = buffer.len();
+        }
+        Ok(found)
+    }
+}
+
+// reads up to len bytes at offset. Position of file is not changed
+fn read_at(file: &mut redr::FileReader, offset: u64, len: usize) -> Result<Vec<u8>, SigSetError> {
+    let position = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::with_capacity(len);
+    file.by_ref().take(len as u64).read_to_end(&mut data)?;
+    file.seek(SeekFrom::Start(position))?;
+    Ok(data)
+}
+
+// appends chunk to buffer. Returns true if end of file was reached
+fn read_chunk(
+    file: &mut redr::FileReader,
+    buffer: &mut Vec<u8>,
+    size: usize,
+) -> Result<bool, SigSetError> {
+    let read = file.by_ref().take(size as u64).read_to_end(buffer)?;
+    Ok(read < size)
+}
+
+impl DescribedSet for PatternSet {
+    const KIND: &'static str = "pattern";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sigs: vec![],
+            searcher: None,
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: PatternSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigPattern = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        if sig.patterns.is_empty() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: no patterns", sig.sig_base.name),
+            });
+        }
+
+        let patterns = sig
+            .patterns
+            .iter()
+            .map(|pattern| BytePattern::parse(pattern.pattern(), pattern.offset()))
+            .collect::<Result<_, _>>()?;
+        self.sigs.push((sig_id, patterns));
+        self.sig_id_to_description.insert(sig_id, description);
+        self.searcher = None;
+        Ok(())
+    }
+
+    // searcher of atoms of all signatures. Set is evaluated even if it is not built, but then
+    // searcher is built for each file
+    fn build(&mut self) -> Result<(), SigSetError> {
+        self.sigs.sort_by_key(|(sig_id, _)| *sig_id);
+        self.searcher = Some(AtomSearcher::new(&self.sigs)?);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        encode_lookup(&self.sigs)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        set.sigs = decode_lookup(&view)?;
+        set.view = Some(view);
+        set.build()?;
+        Ok(set)
+    }
+}
+
+impl SigSet for PatternSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let built;
+        let searcher = match &self.searcher {
+            Some(searcher) => searcher,
+            None => {
+                built = AtomSearcher::new(&self.sigs)?;
+                &built
+            },
+        };
+
+        let found = self.find_patterns(searcher, file)?;
+        let mut reports = vec![];
+        for ((sig_id, _), offsets) in self.sigs.iter().zip(found) {
+            let Some(offsets) = offsets.into_iter().collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let sig = self.decode_sig(*sig_id)?;
+            reports.push(PatternMatch { sig, offsets }.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    fn eval(set: &PatternSet, data: Vec<u8>) -> Vec<DetectionReport> {
+        let mut file = redr::FileReader::from_buff(std::io::Cursor::new(data));
+        let mut variant = redr::FileScanInfo::real_file("sample".into());
+        set.eval_file(&mut file, &mut variant).unwrap()
+    }
+
+    #[test]
+    fn patterns_are_found_across_chunks() {
+        let descriptions = vec![
+            "name: mz\ndescription: mz\npatterns: [{pattern: 4D 5A, offset: 0}]\n".to_string(),
+            "name: stub\ndescription: stub\npriority: 1\npatterns:\n  - 55 8B EC ?? [2-8] C3 \
+             CC\n  - 11 22 33 44 55 66\n"
+                .to_string(),
+            "name: missing\ndescription: missing\npatterns: [55 8B EC, 77 77 77]\n".to_string(),
+        ];
+        let bytes = PatternSet::from_descriptions(descriptions)
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(PatternSet::SET_MAGIC_U32)
+            .unwrap();
+        let set = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<PatternSet>()
+            .unwrap();
+
+        // the first pattern of "stub" crosses chunks, its bytes after atom are in next chunk
+        let mut data = b"MZ".to_vec();
+        let stub_offset = PatternSet::CHUNK_SIZE - 4;
+        data.resize(stub_offset, 0);
+        data.extend_from_slice(b"\x55\x8B\xEC\x90\xAA\xBB\xCC\xC3\xCC");
+        data.resize(3 * PatternSet::CHUNK_SIZE, 0);
+        data.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+
+        let reports = eval(&set, data.clone());
+        let names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["stub", "mz"]);
+        assert!(reports[0].cause.contains(&format!("{stub_offset:#x}")));
+
+        // anchored pattern is not found at other offset
+        data[0] = 0;
+        data[1..3].copy_from_slice(b"MZ");
+        let names: Vec<_> = eval(&set, data).into_iter().map(|r| r.name).collect();
+        assert_eq!(names, ["stub"]);
+    }
+}
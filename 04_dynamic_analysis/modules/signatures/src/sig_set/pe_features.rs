@@ -0,0 +1,191 @@
+use crate::{
+    sig_set::{pe_hashes::ordered_imports, signature::PeFeature},
+    SigSetError,
+};
+use object::{
+    pe::{
+        ImageNtHeaders32, ImageNtHeaders64, IMAGE_DIRECTORY_ENTRY_SECURITY,
+        IMAGE_DIRECTORY_ENTRY_TLS, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE,
+    },
+    read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile},
+    FileKind, LittleEndian as LE,
+};
+
+// section names given by compilers, linkers and common tools. Packers and crypters give others
+const USUAL_SECTION_NAMES: &[&str] = &[
+    ".00cfg", ".bss", ".CRT", ".data", ".debug", ".didat", ".edata", ".gfids", ".idata", ".pdata",
+    ".rdata", ".reloc", ".rodata", ".rsrc", ".sdata", ".sxdata", ".text", ".textbss", ".tls",
+    ".voltbl", ".xdata", "BSS", "CODE", "DATA", "INIT", "PAGE",
+];
+
+// callbacks of TLS directory are counted up to this number
+const MAX_TLS_CALLBACKS: usize = 256;
+
+/// Structural features of PE, read with one parse of file
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PeFeatures {
+    pub(crate) max_section_entropy: f64,
+    pub(crate) writable_executable_sections: u32,
+    pub(crate) entry_point_outside_text: bool,
+    pub(crate) unusual_section_names: u32,
+    pub(crate) tls_callbacks: u32,
+    pub(crate) overlay_size: u64,
+    pub(crate) missing_rich_header: bool,
+    pub(crate) import_count: u32,
+}
+
+impl PeFeatures {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, SigSetError> {
+        match FileKind::parse(data)? {
+            FileKind::Pe32 => Self::from_pe(&PeFile::<ImageNtHeaders32>::parse(data)?, data),
+            FileKind::Pe64 => Self::from_pe(&PeFile::<ImageNtHeaders64>::parse(data)?, data),
+            kind => Err(SigSetError::NotPeError(format!("{kind:?}"))),
+        }
+    }
+
+    pub(crate) fn from_pe<Pe: ImageNtHeaders>(
+        pe: &PeFile<Pe>,
+        data: &[u8],
+    ) -> Result<Self, SigSetError> {
+        let optional_header = pe.nt_headers().optional_header();
+        let entry_point = optional_header.address_of_entry_point();
+        let mut features = PeFeatures {
+            // DLL without entry point has it zero
+            entry_point_outside_text: entry_point != 0,
+            missing_rich_header: pe.rich_header_info().is_none(),
+            ..Default::default()
+        };
+
+        let mut sections_end = optional_header.size_of_headers() as u64;
+        for section in pe.section_table().iter() {
+            let offset = section.pointer_to_raw_data.get(LE) as usize;
+            let size = section.size_of_raw_data.get(LE) as usize;
+            if size != 0 {
+                sections_end = sections_end.max((offset + size) as u64);
+            }
+            if let Some(raw) = data.get(offset..offset.saturating_add(size)) {
+                features.max_section_entropy = features.max_section_entropy.max(entropy(raw));
+            }
+
+            let characteristics = section.characteristics.get(LE);
+            let writable_executable = IMAGE_SCN_MEM_WRITE | IMAGE_SCN_MEM_EXECUTE;
+            if characteristics & writable_executable == writable_executable {
+                features.writable_executable_sections += 1;
+            }
+
+            let name = String::from_utf8_lossy(section.raw_name());
+            if !USUAL_SECTION_NAMES.contains(&&*name) {
+                features.unusual_section_names += 1;
+            }
+
+            let start = section.virtual_address.get(LE);
+            let size = section
+                .virtual_size
+                .get(LE)
+                .max(section.size_of_raw_data.get(LE));
+            if name == ".text" && (start..start.saturating_add(size)).contains(&entry_point) {
+                features.entry_point_outside_text = false;
+            }
+        }
+
+        // Authenticode signature is appended to file, but it isn't overlay. Address of
+        // security directory is offset in file
+        let mut overlay_size = (data.len() as u64).saturating_sub(sections_end);
+        if let Some(security) = pe.data_directory(IMAGE_DIRECTORY_ENTRY_SECURITY) {
+            if security.virtual_address.get(LE) as u64 >= sections_end {
+                overlay_size = overlay_size.saturating_sub(security.size.get(LE) as u64);
+            }
+        }
+        features.overlay_size = overlay_size;
+
+        features.tls_callbacks = tls_callbacks(pe, data).unwrap_or_else(|e| {
+            log::debug!("Incorrect TLS directory: {e}");
+            0
+        });
+        features.import_count = match ordered_imports(pe) {
+            Ok(imports) => imports.len() as u32,
+            Err(e) => {
+                log::debug!("Incorrect import table: {e}");
+                0
+            },
+        };
+        Ok(features)
+    }
+
+    pub(crate) fn value(&self, feature: PeFeature) -> f64 {
+        match feature {
+            PeFeature::MaxSectionEntropy => self.max_section_entropy,
+            PeFeature::WritableExecutableSections => self.writable_executable_sections.into(),
+            PeFeature::EntryPointOutsideText => self.entry_point_outside_text.into(),
+            PeFeature::UnusualSectionNames => self.unusual_section_names.into(),
+            PeFeature::TlsCallbacks => self.tls_callbacks.into(),
+            PeFeature::OverlaySize => self.overlay_size as f64,
+            PeFeature::MissingRichHeader => self.missing_rich_header.into(),
+            PeFeature::ImportCount => self.import_count.into(),
+        }
+    }
+}
+
+// callbacks are virtual addresses in array ended by zero, the array is given by virtual address
+// in TLS directory too
+fn tls_callbacks<Pe: ImageNtHeaders>(pe: &PeFile<Pe>, data: &[u8]) -> Result<u32, SigSetError> {
+    let Some(directory) = pe.data_directory(IMAGE_DIRECTORY_ENTRY_TLS) else {
+        return Ok(0);
+    };
+    let directory = directory.data(data, &pe.section_table())?;
+    let pointer_size = if pe.nt_headers().is_type_64() { 8 } else { 4 };
+    let read_pointer = |bytes: &[u8]| -> Option<u64> {
+        let mut pointer = [0u8; 8];
+        pointer[..pointer_size].copy_from_slice(bytes.get(..pointer_size)?);
+        Some(u64::from_le_bytes(pointer))
+    };
+
+    // AddressOfCallBacks follows start and end of raw data and address of index
+    let callbacks_address = directory
+        .get(3 * pointer_size..)
+        .and_then(read_pointer)
+        .ok_or_else(|| SigSetError::NotPeError("TLS directory is too short".into()))?;
+    let image_base = pe.nt_headers().optional_header().image_base();
+    let Some(callbacks) = (callbacks_address.checked_sub(image_base))
+        .and_then(|rva| u32::try_from(rva).ok())
+        .and_then(|rva| pe.section_table().pe_data_at(data, rva))
+    else {
+        return Ok(0);
+    };
+    let count = (callbacks.chunks_exact(pointer_size))
+        .take(MAX_TLS_CALLBACKS)
+        .take_while(|pointer| read_pointer(pointer) != Some(0))
+        .count();
+    Ok(count as u32)
+}
+
+/// Shannon entropy of data in bits per byte, 0 for empty data. Compressed or encrypted data has
+/// it close to 8
+pub(crate) fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    (counts.iter())
+        .filter(|count| **count != 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entropy_of_uniform_and_constant_data() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[0x90; 100]), 0.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert!((entropy(&all_bytes) - 8.0).abs() < 1e-9);
+        assert!((entropy(b"abab") - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::{
+    sha256_utils::{sha256_from_vec, Sha256},
+    sig_set::{
+        described_set::{lookup_data, DescribedSet},
+        feature_index::{FeatureIndex, StoredIndex},
+        pe_hashes::PeHashes,
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{SigBase, SigPeHash, SigSectionHash},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+};
+
+type PeHashSigId = u32;
+
+pub struct PeHashSet {
+    sig_id_to_description: BTreeMap<PeHashSigId, Description>,
+    // signatures of loaded set. Their index is searched in place and descriptions are read only
+    // when they match
+    view: Option<SetView>,
+    // signatures by every their hash (see hash_key), each of them is required
+    index: FeatureIndex,
+}
+
+impl PeHashSet {
+    pub const SET_MAGIC_U32: u32 = 0x54453549; //I5ET
+
+    // ids of signatures whose every hash is in file, in order of ids. Section of file is found
+    // both by its md5 alone and with its name
+    fn match_(&self, hashes: &PeHashes) -> Result<Vec<PeHashSigId>, SigSetError> {
+        let keys = (hashes.imphash.iter().map(|hash| format!("imphash:{hash}")))
+            .chain(hashes.rich_hash.iter().map(|hash| format!("rich:{hash}")))
+            .chain((hashes.sections.iter()).flat_map(|section| {
+                [
+                    format!("section:{}", section.md5),
+                    format!("section:{}:{}", section.name, section.md5),
+                ]
+            }));
+        let features = keys.map(hash_key).collect::<Result<Vec<_>, _>>()?;
+        match &self.view {
+            Some(view) => StoredIndex::new(lookup_data(view)?)?.match_(&features),
+            None => Ok(self.index.match_(&features)),
+        }
+    }
+
+    // every PE file gives signatures of its imphash, Rich header and executable sections. Files
+    // sharing a hash give one signature, the last name is kept
+    pub fn from_dir(path_to_dir: &str) -> Result<PeHashSet, SigSetError> {
+        let mut entries = vec![];
+        for entry_res in std::fs::read_dir(path_to_dir)? {
+            let entry = entry_res?;
+            if entry.file_type()?.is_file() {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut hash_to_sig = BTreeMap::new();
+        for entry in entries {
+            let file_name = entry.file_name().into_string()?;
+            let hashes = match PeHashes::parse(&std::fs::read(entry.path())?) {
+                Ok(hashes) => hashes,
+                Err(e) => {
+                    log::warn!("Skipped {file_name}: {e}");
+                    continue;
+                },
+            };
+
+            let new_sig = |kind: &str, description: &str| SigPeHash {
+                sig_base: SigBase {
+                    name: format!("{file_name}:{kind}"),
+                    description: format!("{description} of {file_name}"),
+                    priority: 0,
+                    tests: Default::default(),
+                },
+                imphash: None,
+                rich_hash: None,
+                section: None,
+            };
+            if let Some(imphash) = hashes.imphash {
+                let sig = new_sig("imphash", "Imports");
+                hash_to_sig.insert(
+                    format!("imphash:{imphash}"),
+                    SigPeHash {
+                        imphash: Some(imphash),
+                        ..sig
+                    },
+                );
+            }
+            if let Some(rich_hash) = hashes.rich_hash {
+                let sig = new_sig("rich", "Rich header");
+                hash_to_sig.insert(
+                    format!("rich:{rich_hash}"),
+                    SigPeHash {
+                        rich_hash: Some(rich_hash),
+                        ..sig
+                    },
+                );
+            }
+            for section in hashes.sections {
+                if !section.executable {
+                    continue;
+                }
+                let sig = new_sig(&section.name, &format!("Section {}", section.name));
+                let key = format!("section:{}:{}", section.name, section.md5);
+                hash_to_sig.insert(
+                    key,
+                    SigPeHash {
+                        section: Some(SigSectionHash {
+                            name: Some(section.name),
+                            md5: section.md5,
+                        }),
+                        ..sig
+                    },
+                );
+            }
+            log::trace!("path: {:?}", &entry);
+        }
+
+        let descriptions = (hash_to_sig.values())
+            .map(serde_yaml::to_string)
+            .collect::<Result<_, _>>()?;
+        let set = Self::from_descriptions(descriptions)?;
+        log::info!("pe hash set size: {}", set.sig_id_to_description.len());
+        Ok(set)
+    }
+}
+
+// feature of index of key of lowercase hash, e.g. "imphash:<md5>" or "section:.text:<md5>"
+fn hash_key(key: String) -> Result<Sha256, SigSetError> {
+    Ok(sha256_from_vec(key.into_bytes())?)
+}
+
+impl DescribedSet for PeHashSet {
+    const KIND: &'static str = "pe hash";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            index: FeatureIndex::new_empty(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: PeHashSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let mut sig: SigPeHash = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        let hashes = [
+            sig.imphash.as_mut(),
+            sig.rich_hash.as_mut(),
+            sig.section.as_mut().map(|section| &mut section.md5),
+        ];
+        for hash in hashes.into_iter().flatten() {
+            if hash.len() != 32 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(SigSetError::IncorrectSignatureError {
+                    info: format!("{}: {hash} is not md5", sig.sig_base.name),
+                });
+            }
+            hash.make_ascii_lowercase();
+        }
+
+        let mut keys = vec![];
+        if let Some(imphash) = &sig.imphash {
+            keys.push(format!("imphash:{imphash}"));
+        }
+        if let Some(rich_hash) = &sig.rich_hash {
+            keys.push(format!("rich:{rich_hash}"));
+        }
+        if let Some(section) = &sig.section {
+            keys.push(match &section.name {
+                Some(name) => format!("section:{name}:{}", section.md5),
+                None => format!("section:{}", section.md5),
+            });
+        }
+        if keys.is_empty() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: no imphash, rich_hash or section", sig.sig_base.name),
+            });
+        }
+
+        let features = keys
+            .into_iter()
+            .map(hash_key)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.index.insert(sig_id, &features);
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        Ok(self.index.to_bytes(&BTreeSet::new()))
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        StoredIndex::new(lookup_data(&view)?)?;
+        let mut set = Self::new_empty();
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for PeHashSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let hashes = match PeHashes::parse(&buffer) {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                log::debug!("Not executable: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+
+        let mut reports = vec![];
+        for sig_id in self.match_(&hashes)? {
+            let sig: SigPeHash = self.decode_sig(sig_id)?;
+            reports.push(sig.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::{pe_hashes::SectionHash, sigset_deserializer::SigSetDeserializer};
+
+    #[test]
+    fn every_hash_of_signature_must_match() {
+        let md5 = |c: char| c.to_string().repeat(32);
+        let descriptions = vec![
+            format!("name: imp\ndescription: i\nimphash: {}\n", md5('A')),
+            format!(
+                "name: imp_rich\ndescription: ir\nimphash: {}\nrich_hash: {}\n",
+                md5('a'),
+                md5('b')
+            ),
+            format!(
+                "name: text\ndescription: t\nsection: {{name: .text, md5: {}}}\n",
+                md5('c')
+            ),
+            format!(
+                "name: any\ndescription: a\nsection: {{md5: {}}}\n",
+                md5('c')
+            ),
+        ];
+        let compiled = PeHashSet::from_descriptions(descriptions).unwrap();
+        let bytes = (compiled.to_sig_set().unwrap())
+            .to_bytes(PeHashSet::SET_MAGIC_U32)
+            .unwrap();
+        let loaded = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<PeHashSet>()
+            .unwrap();
+        assert!(loaded.view.is_some());
+
+        for set in [&compiled, &loaded] {
+            let names = |hashes: &PeHashes| -> Vec<String> {
+                (set.match_(hashes).unwrap().into_iter())
+                    .map(|sig_id| set.decode_sig::<SigPeHash>(sig_id).unwrap().sig_base.name)
+                    .collect()
+            };
+
+            let mut hashes = PeHashes {
+                imphash: Some(md5('a')),
+                ..Default::default()
+            };
+            assert_eq!(names(&hashes), ["imp"]);
+            hashes.rich_hash = Some(md5('b'));
+            hashes.sections.push(SectionHash {
+                name: "UPX1".into(),
+                md5: md5('c'),
+                executable: true,
+            });
+            assert_eq!(names(&hashes), ["imp", "imp_rich", "any"]);
+            hashes.sections[0].name = ".text".into();
+            assert_eq!(names(&hashes), ["imp", "imp_rich", "text", "any"]);
+        }
+
+        for incorrect in [
+            "imphash: abc",
+            "rich_hash: null",
+            "section: {name: .text, md5: x}",
+        ] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                PeHashSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+}
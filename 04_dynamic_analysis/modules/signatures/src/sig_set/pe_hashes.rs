@@ -0,0 +1,205 @@
+use crate::{
+    sig_set::import_name::{canonical_library, ordinal_name},
+    SigSetError,
+};
+use md5::{Digest, Md5};
+use object::{
+    pe::{ImageNtHeaders32, ImageNtHeaders64, IMAGE_SCN_CNT_CODE, IMAGE_SCN_MEM_EXECUTE},
+    read::pe::{ImageNtHeaders, Import, PeFile},
+    FileKind, LittleEndian as LE,
+};
+
+/// Import of PE as it is in import table
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ImportName {
+    Name(Vec<u8>),
+    Ordinal(u16),
+}
+
+/// Libraries and their imports
+pub(crate) type Imports = Vec<(Vec<u8>, ImportName)>;
+
+/// md5 of raw data of section
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SectionHash {
+    pub(crate) name: String,
+    pub(crate) md5: String,
+    pub(crate) executable: bool,
+}
+
+/// Hashes of PE structures. Files built by the same packer or builder share them, even if
+/// their payload differs
+#[derive(Debug, Default)]
+pub(crate) struct PeHashes {
+    /// md5 of "library.function" of all imports in order, as imphash of pefile. None if PE has
+    /// no imports
+    pub(crate) imphash: Option<String>,
+    /// md5 of Rich header without its XOR mask
+    pub(crate) rich_hash: Option<String>,
+    pub(crate) sections: Vec<SectionHash>,
+}
+
+impl PeHashes {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, SigSetError> {
+        match FileKind::parse(data)? {
+            FileKind::Pe32 => Self::from_pe(&PeFile::<ImageNtHeaders32>::parse(data)?, data),
+            FileKind::Pe64 => Self::from_pe(&PeFile::<ImageNtHeaders64>::parse(data)?, data),
+            kind => Err(SigSetError::NotPeError(format!("{kind:?}"))),
+        }
+    }
+
+    fn from_pe<Pe: ImageNtHeaders>(pe: &PeFile<Pe>, data: &[u8]) -> Result<Self, SigSetError> {
+        let imports = ordered_imports(pe)?;
+        let imphash = (!imports.is_empty()).then(|| imphash(&imports));
+
+        let rich_hash = pe.rich_header_info().and_then(|rich| {
+            // "Rich" and XOR key are not hashed
+            let masked = data.get(rich.offset..rich.offset + rich.length.checked_sub(8)?)?;
+            let key = rich.xor_key.to_le_bytes();
+            let clear: Vec<u8> = (masked.iter().enumerate())
+                .map(|(i, byte)| byte ^ key[i % 4])
+                .collect();
+            Some(md5_hex(&clear))
+        });
+
+        let mut sections = vec![];
+        for section in pe.section_table().iter() {
+            let offset = section.pointer_to_raw_data.get(LE) as usize;
+            let size = section.size_of_raw_data.get(LE) as usize;
+            let Some(raw) = data.get(offset..offset.saturating_add(size)) else {
+                continue;
+            };
+            let characteristics = section.characteristics.get(LE);
+            sections.push(SectionHash {
+                name: String::from_utf8_lossy(section.raw_name()).into(),
+                md5: md5_hex(raw),
+                executable: characteristics & (IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_CNT_CODE) != 0,
+            });
+        }
+
+        Ok(Self {
+            imphash,
+            rich_hash,
+            sections,
+        })
+    }
+}
+
+// libraries and their imports in order of import table, imports by ordinal included
+pub(crate) fn ordered_imports<Pe: ImageNtHeaders>(pe: &PeFile<Pe>) -> Result<Imports, SigSetError> {
+    let Some(table) = pe.import_table()? else {
+        return Ok(vec![]);
+    };
+
+    let mut imports = vec![];
+    let mut descriptors = table.descriptors()?;
+    while let Some(descriptor) = descriptors.next()? {
+        let library = table.name(descriptor.name.get(LE))?;
+        // bound imports keep names only in original thunks
+        let thunks = match descriptor.original_first_thunk.get(LE) {
+            0 => descriptor.first_thunk.get(LE),
+            address => address,
+        };
+        let mut thunks = table.thunks(thunks)?;
+        while let Some(thunk) = thunks.next::<Pe>()? {
+            let name = match table.import::<Pe>(thunk)? {
+                Import::Name(_, name) => ImportName::Name(name.to_vec()),
+                Import::Ordinal(ordinal) => ImportName::Ordinal(ordinal),
+            };
+            imports.push((library.to_vec(), name));
+        }
+    }
+    Ok(imports)
+}
+
+// libraries and their imports in order of delay-load import table. They are loaded on first
+// call, so they are not in import table
+pub(crate) fn delay_load_imports<Pe: ImageNtHeaders>(
+    pe: &PeFile<Pe>,
+) -> Result<Imports, SigSetError> {
+    let Some(table) =
+        (pe.data_directories()).delay_load_import_table(pe.data(), &pe.section_table())?
+    else {
+        return Ok(vec![]);
+    };
+
+    let mut imports = vec![];
+    let mut descriptors = table.descriptors()?;
+    while let Some(descriptor) = descriptors.next()? {
+        // descriptors of old linkers keep virtual addresses instead of relative ones, they are
+        // skipped
+        if descriptor.attributes.get(LE) & 1 == 0 {
+            continue;
+        }
+        let library = table.name(descriptor.dll_name_rva.get(LE))?;
+        let mut thunks = table.thunks(descriptor.import_name_table_rva.get(LE))?;
+        while let Some(thunk) = thunks.next::<Pe>()? {
+            let name = match table.import::<Pe>(thunk)? {
+                Import::Name(_, name) => ImportName::Name(name.to_vec()),
+                Import::Ordinal(ordinal) => ImportName::Ordinal(ordinal),
+            };
+            imports.push((library.to_vec(), name));
+        }
+    }
+    Ok(imports)
+}
+
+// library without extension, imports by ordinal named from table as pefile does or "ord<N>".
+// Library is looked up in table with ".dll" added, so "WS2_32" is named as "WS2_32.dll"
+fn imphash(imports: &[(Vec<u8>, ImportName)]) -> String {
+    let names: Vec<String> = (imports.iter())
+        .map(|(library, name)| {
+            let library = String::from_utf8_lossy(library).to_lowercase();
+            let name = match name {
+                ImportName::Name(name) => String::from_utf8_lossy(name).to_lowercase(),
+                ImportName::Ordinal(ordinal) => {
+                    match ordinal_name(&canonical_library(&library), *ordinal) {
+                        Some(name) => name.to_lowercase(),
+                        None => format!("ord{ordinal}"),
+                    }
+                },
+            };
+            let library = match library.rsplit_once('.') {
+                Some((stem, "dll" | "ocx" | "sys")) => stem.to_string(),
+                _ => library,
+            };
+            format!("{library}.{name}")
+        })
+        .collect();
+    md5_hex(names.join(",").as_bytes())
+}
+
+pub(crate) fn md5_hex(data: &[u8]) -> String {
+    hex::encode(Md5::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imphash_as_pefile() {
+        let imports = [
+            (
+                b"KERNEL32.dll".to_vec(),
+                ImportName::Name(b"Sleep".to_vec()),
+            ),
+            (b"WS2_32.dll".to_vec(), ImportName::Ordinal(115)),
+            (b"WS2_32.dll".to_vec(), ImportName::Ordinal(999)),
+            (b"WS2_32".to_vec(), ImportName::Ordinal(3)),
+            (b"OLEAUT32.dll".to_vec(), ImportName::Ordinal(418)),
+            (
+                b"msvbvm60".to_vec(),
+                ImportName::Name(b"EVENT_SINK_AddRef".to_vec()),
+            ),
+        ];
+        assert_eq!(
+            imphash(&imports),
+            md5_hex(
+                b"kernel32.sleep,ws2_32.wsastartup,ws2_32.ord999,ws2_32.closesocket,\
+                  oleaut32.oleloadpicture,msvbvm60.event_sink_addref"
+            )
+        );
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    }
+}
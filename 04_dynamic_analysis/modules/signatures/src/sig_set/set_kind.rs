@@ -0,0 +1,216 @@
+use crate::{
+    file_hashes::HashAlgorithm,
+    sig_set::{
+        anomaly_set::AnomalySet,
+        block_set::BlockSet,
+        code_set::CodeSet,
+        described_set::DescribedSet,
+        fuzzy_set::FuzzySet,
+        heuristic_set::HeurSet,
+        model_set::ModelSet,
+        pattern_set::PatternSet,
+        pe_hash_set::PeHashSet,
+        sha_set::ShaSet,
+        sig_source::SigSource,
+        signature::{
+            SigAnomaly, SigBlocks, SigCode, SigDyn, SigFuzzy, SigHeur, SigModel, SigPattern,
+            SigPeHash, SigSha256, SigStrings,
+        },
+        sigset_container::magic_to_string,
+        sigset_deserializer::SigSetDeserializer,
+        sigset_info::{sig_info, Listed, ListedSig},
+        sigset_serializer::SigSetSerializer,
+        string_set::StringSet,
+        Description, SigSet,
+    },
+    DynSet, SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::path::Path;
+
+/// Set which signatures are added to one by one and which evaluates samples declared in them,
+/// when signatures are tested
+pub(crate) trait GrowingSet: SigSet {
+    fn add(&mut self, sig_id: u32, description: Description) -> Result<(), SigSetError>;
+
+    // files are scanned, api call traces are read one call per line
+    fn eval_sample(&self, sample: &Path) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut reader = redr::FileReader::from_file(std::fs::File::open(sample)?);
+        let mut variant = redr::FileScanInfo::real_file(sample.to_path_buf());
+        self.eval_file(&mut reader, &mut variant)
+    }
+}
+
+impl<T: DescribedSet> GrowingSet for T {
+    fn add(&mut self, sig_id: u32, description: Description) -> Result<(), SigSetError> {
+        self.add_description(sig_id, description)
+    }
+}
+
+// hash signatures are keyed by their hashes, ids are not used
+impl GrowingSet for ShaSet {
+    fn add(&mut self, _sig_id: u32, description: Description) -> Result<(), SigSetError> {
+        self.add_description(description)
+    }
+}
+
+impl GrowingSet for HeurSet {
+    fn add(&mut self, sig_id: u32, description: Description) -> Result<(), SigSetError> {
+        self.add_description(sig_id, description)
+    }
+}
+
+impl GrowingSet for DynSet {
+    fn add(&mut self, sig_id: u32, description: Description) -> Result<(), SigSetError> {
+        self.add_description(sig_id, description)
+    }
+
+    fn eval_sample(&self, sample: &Path) -> Result<Vec<DetectionReport>, SigSetError> {
+        let calls = std::fs::read_to_string(sample)?
+            .lines()
+            .map(str::trim)
+            .filter(|call| !call.is_empty())
+            .map(String::from)
+            .collect();
+        self.eval_api_calls(calls)
+    }
+}
+
+/// Type of set known to this crate and how its signatures are compiled, loaded and listed
+pub(crate) struct SetKind {
+    pub(crate) magic: u32,
+    // subdirectory of unpacked container, the same as the one container is compiled from
+    pub(crate) dir_name: &'static str,
+    // signature with any of these properties belongs to set
+    pub(crate) keys: &'static [&'static str],
+    pub(crate) new_empty: fn() -> Box<dyn GrowingSet>,
+    pub(crate) compile: fn(Vec<Description>) -> Result<SigSetSerializer, SigSetError>,
+    pub(crate) compile_source: fn(&SigSource) -> Result<SigSetSerializer, SigSetError>,
+    pub(crate) load: fn(&SigSetDeserializer) -> Result<Box<dyn SigSet>, SigSetError>,
+    pub(crate) descriptions: fn(&SigSetDeserializer) -> Result<Vec<Description>, SigSetError>,
+    pub(crate) info: fn(&str) -> Result<Listed, SigSetError>,
+}
+
+impl SetKind {
+    // set whose signatures are only their descriptions
+    const fn described<T: DescribedSet + 'static, S: ListedSig>(
+        magic: u32,
+        dir_name: &'static str,
+        keys: &'static [&'static str],
+    ) -> Self {
+        Self {
+            magic,
+            dir_name,
+            keys,
+            new_empty: new_empty::<T>,
+            compile: compile_described::<T>,
+            compile_source: compile_source::<T>,
+            load: load_described::<T>,
+            descriptions: described_descriptions::<T>,
+            info: sig_info::<S>,
+        }
+    }
+}
+
+fn new_empty<T: DescribedSet + 'static>() -> Box<dyn GrowingSet> {
+    Box::new(T::new_empty())
+}
+
+fn compile_described<T: DescribedSet>(
+    descriptions: Vec<Description>,
+) -> Result<SigSetSerializer, SigSetError> {
+    T::from_descriptions(descriptions)?.to_sig_set()
+}
+
+fn compile_source<T: SigSet>(source: &SigSource) -> Result<SigSetSerializer, SigSetError> {
+    T::from_source(source)?.to_sig_set()
+}
+
+fn load_described<T: DescribedSet + 'static>(
+    des: &SigSetDeserializer,
+) -> Result<Box<dyn SigSet>, SigSetError> {
+    Ok(Box::new(des.get_described_set::<T>()?))
+}
+
+fn described_descriptions<T: DescribedSet>(
+    des: &SigSetDeserializer,
+) -> Result<Vec<Description>, SigSetError> {
+    des.get_described_set::<T>()?.descriptions()
+}
+
+/// Every type of set, in order signature type is recognised by its properties. Heuristic set is
+/// the last one, "threshold" is its only property when signature has no imports, but other
+/// signatures have it as well
+pub(crate) static SET_KINDS: [SetKind; 11] = [
+    SetKind {
+        magic: ShaSet::SET_MAGIC_U32,
+        dir_name: "sha",
+        keys: &[
+            HashAlgorithm::Md5.name(),
+            HashAlgorithm::Sha1.name(),
+            HashAlgorithm::Sha256.name(),
+            HashAlgorithm::Sha512.name(),
+        ],
+        new_empty: || Box::new(ShaSet::new_empty()),
+        compile: |descriptions| ShaSet::from_descriptions(descriptions)?.to_sig_set(),
+        compile_source: compile_source::<ShaSet>,
+        load: |des| Ok(Box::new(des.get_sha_set()?)),
+        descriptions: |des| des.get_sha_set()?.descriptions(),
+        info: sig_info::<SigSha256>,
+    },
+    SetKind {
+        magic: DynSet::SET_MAGIC_U32,
+        dir_name: "dyn",
+        keys: &["calls"],
+        new_empty: || Box::new(DynSet::new_empty()),
+        compile: |descriptions| DynSet::from_descriptions(descriptions)?.to_sig_set(),
+        compile_source: compile_source::<DynSet>,
+        load: |des| Ok(Box::new(des.get_dyn_set()?)),
+        descriptions: |des| des.get_dyn_set()?.descriptions(),
+        info: sig_info::<SigDyn>,
+    },
+    SetKind::described::<PatternSet, SigPattern>(
+        PatternSet::SET_MAGIC_U32,
+        "pattern",
+        &["patterns"],
+    ),
+    SetKind::described::<FuzzySet, SigFuzzy>(FuzzySet::SET_MAGIC_U32, "fuzzy", &["ssdeep"]),
+    SetKind::described::<PeHashSet, SigPeHash>(
+        PeHashSet::SET_MAGIC_U32,
+        "pehash",
+        &["imphash", "rich_hash", "section"],
+    ),
+    SetKind::described::<AnomalySet, SigAnomaly>(
+        AnomalySet::SET_MAGIC_U32,
+        "anomaly",
+        &["anomalies"],
+    ),
+    SetKind::described::<StringSet, SigStrings>(StringSet::SET_MAGIC_U32, "strings", &["strings"]),
+    SetKind::described::<CodeSet, SigCode>(CodeSet::SET_MAGIC_U32, "code", &["code", "packer"]),
+    SetKind::described::<BlockSet, SigBlocks>(BlockSet::SET_MAGIC_U32, "blocks", &["blocks"]),
+    SetKind::described::<ModelSet, SigModel>(ModelSet::SET_MAGIC_U32, "model", &["weights"]),
+    SetKind {
+        magic: HeurSet::SET_MAGIC_U32,
+        dir_name: "heur",
+        keys: &["imports", "threshold", "format"],
+        new_empty: || Box::new(HeurSet::new_empty()),
+        compile: |descriptions| HeurSet::from_descriptions(descriptions)?.to_sig_set(),
+        compile_source: compile_source::<HeurSet>,
+        load: |des| Ok(Box::new(des.get_heur_set()?)),
+        descriptions: |des| des.get_heur_set()?.descriptions(),
+        info: sig_info::<SigHeur>,
+    },
+];
+
+pub(crate) fn set_kind(magic: u32) -> Result<&'static SetKind, SigSetError> {
+    SET_KINDS
+        .iter()
+        .find(|kind| kind.magic == magic)
+        .ok_or_else(|| SigSetError::IncorrectMagicError {
+            current: magic_to_string(magic),
+        })
+}
+
+pub(crate) fn is_known_magic(magic: u32) -> bool {
+    SET_KINDS.iter().any(|kind| kind.magic == magic)
+}
@@ -0,0 +1,333 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{set_view::SetBuffer, sigset_deserializer::SigSetDeserializer},
+    SigSetError,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::Digest;
+use std::{
+    mem::size_of,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+// keys used by every set loaded for scanning. Until they are configured nothing is trusted
+static TRUSTED_KEYS: RwLock<TrustedKeys> = RwLock::new(TrustedKeys::empty());
+
+/// Public keys which sets loaded for scanning must be signed with. Each set file (single set or
+/// container) has its Ed25519 signature in detached file "{set}.sig", with hashes of chunks of
+/// the file it signs
+#[derive(Debug, Clone)]
+pub struct TrustedKeys {
+    keys: Vec<VerifyingKey>,
+    allow_untrusted: bool,
+}
+
+impl TrustedKeys {
+    pub const fn empty() -> Self {
+        Self {
+            keys: vec![],
+            allow_untrusted: false,
+        }
+    }
+
+    /// Public keys in hex, one per line. Empty lines and lines starting with '#' are skipped
+    pub fn from_file(path: &str) -> Result<Self, SigSetError> {
+        let mut keys = Self::empty();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keys.add_key(line)?;
+        }
+        Ok(keys)
+    }
+
+    pub fn add_key(&mut self, public_key: &str) -> Result<(), SigSetError> {
+        let key = VerifyingKey::from_bytes(&key_from_hex(public_key)?)
+            .map_err(|e| SigSetError::IncorrectKeyError(e.to_string()))?;
+        self.keys.push(key);
+        Ok(())
+    }
+
+    /// Unsigned sets and sets not signed with trusted key are loaded anyway. Only warning is
+    /// logged
+    pub fn allow_untrusted(mut self, allow_untrusted: bool) -> Self {
+        self.allow_untrusted = allow_untrusted;
+        self
+    }
+
+    // set file has file_len bytes, its signature is in detached file. Returns hashes of its
+    // chunks, which are verified as they are read, or None when set is loaded without verified
+    // signature, because untrusted sets are allowed
+    pub(crate) fn verify(
+        &self,
+        set_path: &str,
+        file_len: usize,
+    ) -> Result<Option<SignedChunks>, SigSetError> {
+        let sig_path = signature_path(set_path);
+        let result = match std::fs::read(&sig_path) {
+            Ok(signature) => self.verify_signature(set_path, file_len, &signature),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(SigSetError::UnsignedSetError(set_path.into()))
+            },
+            Err(e) => Err(e.into()),
+        };
+
+        match result {
+            Err(SigSetError::UnsignedSetError(_) | SigSetError::UntrustedSetError(_))
+                if self.allow_untrusted =>
+            {
+                log::warn!("Loading untrusted set: {}", set_path);
+                Ok(None)
+            },
+            result => result.map(Some),
+        }
+    }
+
+    // signature file keeps Ed25519 signature followed by the table it signs
+    fn verify_signature(
+        &self,
+        set_path: &str,
+        file_len: usize,
+        signature_file: &[u8],
+    ) -> Result<SignedChunks, SigSetError> {
+        let untrusted = || SigSetError::UntrustedSetError(set_path.into());
+        if signature_file.len() < Signature::BYTE_SIZE {
+            return Err(untrusted());
+        }
+        let (signature, table) = signature_file.split_at(Signature::BYTE_SIZE);
+        let signature = Signature::from_slice(signature).map_err(|_| untrusted())?;
+        if !self
+            .keys
+            .iter()
+            .any(|key| key.verify(table, &signature).is_ok())
+        {
+            return Err(untrusted());
+        }
+        // file of other length than the signed one was changed after it was signed
+        SignedChunks::from_table(table, file_len).ok_or_else(untrusted)
+    }
+}
+
+// Hashes of chunks of set file, taken from its verified signature. Chunk is compared with its
+// hash when it is read for the first time, so set is verified as it is used and loading doesn't
+// depend on set size. Signed table is length of file (u64) followed by sha256 of each chunk
+#[derive(Debug)]
+pub(crate) struct SignedChunks {
+    hashes: Vec<Sha256>,
+    verified: Vec<AtomicBool>,
+}
+
+impl SignedChunks {
+    const CHUNK_SIZE: usize = 0x10000;
+    // 64 KB
+
+    fn table(data: &[u8]) -> Vec<u8> {
+        let mut table = (data.len() as u64).to_le_bytes().to_vec();
+        for chunk in data.chunks(Self::CHUNK_SIZE) {
+            table.extend_from_slice(&sha2::Sha256::digest(chunk));
+        }
+        table
+    }
+
+    fn from_table(table: &[u8], file_len: usize) -> Option<Self> {
+        let (len, hashes) = table.split_at_checked(size_of::<u64>())?;
+        if u64::from_le_bytes(len.try_into().unwrap()) != file_len as u64
+            || hashes.len() != file_len.div_ceil(Self::CHUNK_SIZE) * size_of::<Sha256>()
+        {
+            return None;
+        }
+        let hashes: Vec<Sha256> = (hashes.chunks_exact(size_of::<Sha256>()))
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+        let verified = hashes.iter().map(|_| AtomicBool::new(false)).collect();
+        Some(Self { hashes, verified })
+    }
+
+    // chunks which range of data lies in. Data is the whole file the chunks were signed for
+    pub(crate) fn verify(&self, data: &[u8], range: Range<usize>) -> Result<(), SigSetError> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        for i in range.start / Self::CHUNK_SIZE..=(range.end - 1) / Self::CHUNK_SIZE {
+            if self.verified[i].load(Ordering::Acquire) {
+                continue;
+            }
+            let chunk_end = data.len().min((i + 1) * Self::CHUNK_SIZE);
+            let hash: Sha256 = sha2::Sha256::digest(&data[i * Self::CHUNK_SIZE..chunk_end]).into();
+            if hash != self.hashes[i] {
+                return Err(SigSetError::IncorrectChecksumError {
+                    current: hex::encode(hash),
+                    expected: hex::encode(self.hashes[i]),
+                });
+            }
+            self.verified[i].store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn verified_count(&self) -> usize {
+        (self.verified.iter())
+            .filter(|verified| verified.load(Ordering::Acquire))
+            .count()
+    }
+}
+
+/// Keys used when sets are loaded for scanning
+pub fn set_trusted_keys(keys: TrustedKeys) {
+    *TRUSTED_KEYS.write().unwrap_or_else(|e| e.into_inner()) = keys;
+}
+
+pub(crate) fn verify_set(
+    set_path: &str,
+    file_len: usize,
+) -> Result<Option<SignedChunks>, SigSetError> {
+    TRUSTED_KEYS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .verify(set_path, file_len)
+}
+
+/// Writes detached signature of set file. Key file keeps 32 bytes of Ed25519 secret key in hex.
+/// Returns public key in hex, which must be trusted to load the set.
+/// Checksums and order of signatures are verified before the set is signed. Sets with verified
+/// signature are trusted to be correct, so they are not walked when they are loaded. Hashes of
+/// chunks of file are signed, not the file itself, so loaded set can be verified chunk by chunk
+pub fn sign_set_file(set_path: &str, key_path: &str) -> Result<String, SigSetError> {
+    let secret = key_from_hex(std::fs::read_to_string(key_path)?.trim())?;
+    let key = SigningKey::from_bytes(&secret);
+    let data = std::fs::read(set_path)?;
+    let table = SignedChunks::table(&data);
+    SigSetDeserializer::verify_file(Arc::new(SetBuffer::owned(data)))?;
+    let mut signature_file = key.sign(&table).to_bytes().to_vec();
+    signature_file.extend_from_slice(&table);
+    std::fs::write(signature_path(set_path), signature_file)?;
+    Ok(hex::encode(key.verifying_key().as_bytes()))
+}
+
+fn signature_path(set_path: &str) -> String {
+    format!("{set_path}.sig")
+}
+
+fn key_from_hex(key: &str) -> Result<[u8; 32], SigSetError> {
+    hex::decode(key)?
+        .try_into()
+        .map_err(|_| SigSetError::IncorrectKeyError(format!("key must have 32 bytes: {key}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::{sha_set::ShaSet, SigSet};
+
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn only_set_signed_with_trusted_key_is_loaded() {
+        let dir = std::env::temp_dir();
+        let set_path = dir.join(format!("sfi_signed_{}.sset", std::process::id()));
+        let key_path = dir.join(format!("sfi_signed_{}.key", std::process::id()));
+        let (set_path, key_path) = (set_path.to_str().unwrap(), key_path.to_str().unwrap());
+        let set_content = ShaSet::new_empty()
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        std::fs::write(set_path, &set_content).unwrap();
+        std::fs::write(key_path, SECRET_KEY).unwrap();
+
+        let mut keys = TrustedKeys::empty();
+        let len = set_content.len();
+        assert!(matches!(
+            keys.verify(set_path, len),
+            Err(SigSetError::UnsignedSetError(_))
+        ));
+
+        let public_key = sign_set_file(set_path, key_path).unwrap();
+        assert!(matches!(
+            keys.verify(set_path, len),
+            Err(SigSetError::UntrustedSetError(_))
+        ));
+
+        keys.add_key(&public_key).unwrap();
+        let chunks = keys.verify(set_path, len).unwrap().unwrap();
+        chunks.verify(&set_content, 0..len).unwrap();
+        // file of other length than the signed one
+        assert!(matches!(
+            keys.verify(set_path, len + 1),
+            Err(SigSetError::UntrustedSetError(_))
+        ));
+        assert!(keys
+            .clone()
+            .allow_untrusted(true)
+            .verify(set_path, len + 1)
+            .unwrap()
+            .is_none());
+
+        for path in [
+            set_path.to_string(),
+            signature_path(set_path),
+            key_path.into(),
+        ] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn only_read_chunks_are_verified() {
+        let mut data = vec![0; 3 * SignedChunks::CHUNK_SIZE + 10];
+        let table = SignedChunks::table(&data);
+        let chunks = SignedChunks::from_table(&table, data.len()).unwrap();
+        assert!(SignedChunks::from_table(&table, data.len() - 1).is_none());
+
+        data[2 * SignedChunks::CHUNK_SIZE] = 1;
+        chunks
+            .verify(&data, 10..SignedChunks::CHUNK_SIZE + 10)
+            .unwrap();
+        assert_eq!(chunks.verified_count(), 2);
+        assert!(matches!(
+            chunks.verify(
+                &data,
+                2 * SignedChunks::CHUNK_SIZE..2 * SignedChunks::CHUNK_SIZE + 1
+            ),
+            Err(SigSetError::IncorrectChecksumError { .. })
+        ));
+        chunks
+            .verify(&data, 3 * SignedChunks::CHUNK_SIZE..data.len())
+            .unwrap();
+        assert_eq!(chunks.verified_count(), 3);
+    }
+
+    #[test]
+    fn corrupted_set_is_not_signed() {
+        let dir = std::env::temp_dir();
+        let set_path = dir.join(format!("sfi_corrupted_{}.sset", std::process::id()));
+        let key_path = dir.join(format!("sfi_corrupted_{}.key", std::process::id()));
+        let (set_path, key_path) = (set_path.to_str().unwrap(), key_path.to_str().unwrap());
+        let mut set = ShaSet::new_empty();
+        set.append_signature([1; 32], "name: a\ndescription: a\nsha256: 01\n".into());
+        let mut set_content = set
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        *set_content.last_mut().unwrap() ^= 0xff;
+        std::fs::write(set_path, &set_content).unwrap();
+        std::fs::write(key_path, SECRET_KEY).unwrap();
+
+        assert!(matches!(
+            sign_set_file(set_path, key_path),
+            Err(SigSetError::IncorrectChecksumError { .. })
+        ));
+        assert!(!std::path::Path::new(&signature_path(set_path)).exists());
+
+        std::fs::remove_file(set_path).unwrap();
+        std::fs::remove_file(key_path).unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    sig_set::{
+        import_set_view::INDEX_SIG_ID, set_signing, set_signing::SignedChunks,
+        sigset_serializer::SigSetSerializer, SigHeader, SigId,
+    },
+    SigSetError,
+};
+use memmap2::Mmap;
+use std::{fs::File, mem::size_of, ops::Range, sync::Arc};
+
+// Whole set file (or container with set), mapped, so only pages which are really touched are
+// read, no matter how big the set is. Set loaded for scanning keeps hashes of chunks from its
+// verified signature, and every range is verified before it is read (see SignedChunks)
+#[derive(Debug)]
+pub(crate) struct SetBuffer {
+    data: BufferData,
+    chunks: Option<SignedChunks>,
+    // kept open, so other processes can't write the file while it is mapped (see map_signed_file)
+    _file: Option<File>,
+}
+
+#[derive(Debug)]
+enum BufferData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl SetBuffer {
+    pub(crate) fn owned(data: Vec<u8>) -> Self {
+        Self {
+            data: BufferData::Owned(data),
+            chunks: None,
+            _file: None,
+        }
+    }
+
+    // set opened by tools, its signature is not verified
+    pub(crate) fn map_file(name: &str) -> Result<Self, SigSetError> {
+        Self::map(File::open(name)?)
+    }
+
+    // set loaded for scanning, which must be signed with trusted key (see set_signing). Returns
+    // whether signature was verified, unsigned set can be loaded only when it is allowed.
+    // On Windows the file is opened without write sharing, so chunk once verified can't change
+    // while set is loaded. Elsewhere file written in place after its chunk was verified isn't
+    // noticed, our tools always replace set by rename (see write_set_file)
+    pub(crate) fn map_signed_file(name: &str) -> Result<(Self, bool), SigSetError> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            const FILE_SHARE_READ: u32 = 0x1;
+            const FILE_SHARE_DELETE: u32 = 0x4;
+            options.share_mode(FILE_SHARE_READ | FILE_SHARE_DELETE);
+        }
+
+        let mut buffer = Self::map(options.open(name)?)?;
+        buffer.chunks = set_signing::verify_set(name, buffer.len())?;
+        let signed = buffer.chunks.is_some();
+        Ok((buffer, signed))
+    }
+
+    fn map(file: File) -> Result<Self, SigSetError> {
+        if file.metadata()?.len() == 0 {
+            return Ok(Self::owned(vec![]));
+        }
+
+        // SAFETY: set files are never truncated or written in place by our tools. Serializers
+        // write new version of set to other file and rename it over this one (see
+        // write_set_file), so mapped content doesn't change while it is used
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            data: BufferData::Mapped(mmap),
+            chunks: None,
+            _file: Some(file),
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        match &self.data {
+            BufferData::Mapped(mmap) => mmap,
+            BufferData::Owned(data) => data,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data().len()
+    }
+
+    // bytes of range, verified against signature of set if it has one
+    pub(crate) fn get(&self, range: Range<usize>) -> Result<&[u8], SigSetError> {
+        let data = self.data();
+        let bytes = data
+            .get(range.clone())
+            .ok_or(SigSetError::IncorrectFileSizeError {
+                size: data.len() as u64,
+            })?;
+        if let Some(chunks) = &self.chunks {
+            chunks.verify(data, range)?;
+        }
+        Ok(bytes)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn slice(&self) -> SetSlice<'_> {
+        SetSlice {
+            buffer: self,
+            range: 0..self.len(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn verified_chunks(&self) -> usize {
+        self.chunks.as_ref().map_or(0, SignedChunks::verified_count)
+    }
+}
+
+// Part of set buffer which is read piece by piece, e.g. index stored in set, so only pieces which
+// are read are verified
+#[derive(Debug, Clone)]
+pub(crate) struct SetSlice<'a> {
+    buffer: &'a SetBuffer,
+    range: Range<usize>,
+}
+
+impl<'a> SetSlice<'a> {
+    pub(crate) fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    // range is relative to this slice
+    pub(crate) fn sub(&self, range: Range<usize>) -> Result<SetSlice<'a>, SigSetError> {
+        if range.start > range.end || range.end > self.len() {
+            return Err(SigSetError::IncorrectSignatureSizeError {
+                size: self.len() as u32,
+            });
+        }
+        Ok(SetSlice {
+            buffer: self.buffer,
+            range: self.range.start + range.start..self.range.start + range.end,
+        })
+    }
+
+    // range is relative to this slice
+    pub(crate) fn get(&self, range: Range<usize>) -> Result<&'a [u8], SigSetError> {
+        let sub = self.sub(range)?;
+        sub.buffer.get(sub.range)
+    }
+
+    pub(crate) fn bytes(&self) -> Result<&'a [u8], SigSetError> {
+        self.buffer.get(self.range.clone())
+    }
+
+    pub(crate) fn read_u32(&self, offset: usize) -> Result<u32, SigSetError> {
+        let bytes = self.get(offset..offset + size_of::<u32>())?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+// Signature headers and data of loaded set, read in place from its buffer. Signature headers
+// have fixed size, so n-th header is found without reading previous ones. Sets keyed by sha256
+// keep headers sorted by id, so signature can be found with binary search
+#[derive(Debug, Clone)]
+pub(crate) struct SetView {
+    buffer: Arc<SetBuffer>,
+    // signature headers followed by signature data
+    range: Range<usize>,
+    elem_count: usize,
+}
+
+impl SetView {
+    // single signature can't be bigger than that
+    const MAX_SIGNATURE_LEN: u32 = 0x400000;
+    // 4 MB
+    const SIG_HEADER_SIZE: usize = size_of::<SigHeader>();
+
+    pub(crate) fn new(
+        buffer: Arc<SetBuffer>,
+        range: Range<usize>,
+        elem_count: u32,
+    ) -> Result<Self, SigSetError> {
+        let elem_count = elem_count as usize;
+        let headers_len = elem_count.checked_mul(Self::SIG_HEADER_SIZE);
+        if headers_len.is_none_or(|len| len > range.len()) {
+            return Err(SigSetError::IncorrectFileSizeError {
+                size: range.len() as u64,
+            });
+        }
+
+        Ok(Self {
+            buffer,
+            range,
+            elem_count,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn buffer(&self) -> &SetBuffer {
+        &self.buffer
+    }
+
+    fn slice(&self) -> SetSlice<'_> {
+        SetSlice {
+            buffer: &self.buffer,
+            range: self.range.clone(),
+        }
+    }
+
+    // whole set, only for walking all of it
+    pub(crate) fn data(&self) -> Result<&[u8], SigSetError> {
+        self.slice().bytes()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.elem_count
+    }
+
+    fn sig_id(&self, i: usize) -> Result<&[u8], SigSetError> {
+        let offset = i * Self::SIG_HEADER_SIZE;
+        self.slice().get(offset..offset + size_of::<SigId>())
+    }
+
+    pub(crate) fn sig_header(&self, i: usize) -> Result<SigHeader, SigSetError> {
+        let offset = i * Self::SIG_HEADER_SIZE;
+        Ok(bincode::serde::decode_from_slice(
+            self.slice().get(offset..offset + Self::SIG_HEADER_SIZE)?,
+            bincode::config::legacy(),
+        )?
+        .0)
+    }
+
+    pub(crate) fn sig_data(&self, header: &SigHeader) -> Result<&[u8], SigSetError> {
+        self.sig_slice(header)?.bytes()
+    }
+
+    // data of signature which is read piece by piece, e.g. index stored with signatures
+    pub(crate) fn sig_slice(&self, header: &SigHeader) -> Result<SetSlice<'_>, SigSetError> {
+        if header.size > Self::MAX_SIGNATURE_LEN {
+            return Err(SigSetError::IncorrectSignatureSizeError { size: header.size });
+        }
+
+        let start = self.elem_count * Self::SIG_HEADER_SIZE + header.offset as usize;
+        let end = start + header.size as usize;
+        self.slice()
+            .sub(start..end)
+            .map_err(|_| SigSetError::IncorrectSignatureSizeError { size: header.size })
+    }
+
+    // every signature with its data, in order of headers
+    pub(crate) fn signatures(
+        &self,
+    ) -> impl Iterator<Item = Result<(SigHeader, &[u8]), SigSetError>> + '_ {
+        (0..self.elem_count).map(|i| {
+            let header = self.sig_header(i)?;
+            let data = self.sig_data(&header)?;
+            Ok((header, data))
+        })
+    }
+
+    // data of record at INDEX_SIG_ID, which follows signatures. None for set compiled before it
+    // was stored
+    pub(crate) fn index(&self) -> Result<Option<SetSlice<'_>>, SigSetError> {
+        let Some(last) = self.elem_count.checked_sub(1) else {
+            return Ok(None);
+        };
+        let header = self.sig_header(last)?;
+        if header.id != INDEX_SIG_ID {
+            return Ok(None);
+        }
+        Ok(Some(self.sig_slice(&header)?))
+    }
+
+    // records are copied as they are stored, index included
+    pub(crate) fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        let mut ser = SigSetSerializer::new_empty();
+        for record in self.signatures() {
+            let (sig_header, data) = record?;
+            match sig_header.id {
+                INDEX_SIG_ID => ser.serialize_index(data.to_vec()),
+                id => ser.serialize_signature(id, data.to_vec()),
+            }
+        }
+        Ok(ser)
+    }
+
+    // only for sets with headers sorted by id
+    pub(crate) fn find(&self, id: &SigId) -> Result<Option<&[u8]>, SigSetError> {
+        match self.search(|i| Ok(self.sig_id(i)?.cmp(id.as_slice())))? {
+            Some(i) => Ok(Some(self.sig_data(&self.sig_header(i)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    // only for heuristic and dynamic sets, which keep headers sorted by u32 id (see
+    // sig_id_from_u32)
+    pub(crate) fn find_u32(&self, id: u32) -> Result<Option<(SigHeader, &[u8])>, SigSetError> {
+        let Some(i) = self.search(|i| Ok(Self::u32_id(self.sig_id(i)?).cmp(&id)))? else {
+            return Ok(None);
+        };
+        let header = self.sig_header(i)?;
+        let data = self.sig_data(&header)?;
+        Ok(Some((header, data)))
+    }
+
+    // binary search of header for which compare returns Equal
+    fn search(
+        &self,
+        compare: impl Fn(usize) -> Result<std::cmp::Ordering, SigSetError>,
+    ) -> Result<Option<usize>, SigSetError> {
+        binary_search(self.elem_count, compare)
+    }
+
+    fn u32_id(id: &[u8]) -> u32 {
+        u32::from_le_bytes(id[..4].try_into().unwrap())
+    }
+
+    pub(crate) fn verify_sorted(&self) -> Result<(), SigSetError> {
+        self.verify_sorted_by(|a, b| a < b)
+    }
+
+    pub(crate) fn verify_sorted_u32(&self) -> Result<(), SigSetError> {
+        self.verify_sorted_by(|a, b| Self::u32_id(a) < Self::u32_id(b))
+    }
+
+    // headers are searched in place, so they are verified once, when set is signed
+    fn verify_sorted_by(&self, less: impl Fn(&[u8], &[u8]) -> bool) -> Result<(), SigSetError> {
+        for i in 1..self.elem_count {
+            if !less(self.sig_id(i - 1)?, self.sig_id(i)?) {
+                return Err(SigSetError::IncorrectSignatureError {
+                    info: format!("Signature {i} is not sorted by id"),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// index of item for which compare returns Equal. Items must be sorted
+pub(crate) fn binary_search(
+    count: usize,
+    compare: impl Fn(usize) -> Result<std::cmp::Ordering, SigSetError>,
+) -> Result<Option<usize>, SigSetError> {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match compare(mid)? {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => return Ok(Some(mid)),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_serializer::SigSetSerializer;
+
+    fn view(ids: &[u8]) -> SetView {
+        let mut ser = SigSetSerializer::new_empty();
+        for id in ids {
+            ser.serialize_signature([*id; 32], vec![*id; *id as usize]);
+        }
+        // set header is not needed here
+        let bytes = ser.to_bytes(0).unwrap()[40..].to_vec();
+        let len = bytes.len();
+        SetView::new(Arc::new(SetBuffer::owned(bytes)), 0..len, ids.len() as u32).unwrap()
+    }
+
+    #[test]
+    fn binary_search_in_place() {
+        let view = view(&[1, 3, 5, 7, 9]);
+        view.verify_sorted().unwrap();
+
+        for id in [1, 3, 5, 7, 9] {
+            assert_eq!(
+                view.find(&[id; 32]).unwrap().unwrap(),
+                vec![id; id as usize]
+            );
+        }
+        for id in [0, 2, 8, 10] {
+            assert!(view.find(&[id; 32]).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn not_sorted_set() {
+        assert!(view(&[1, 3, 2]).verify_sorted().is_err());
+        assert!(view(&[1, 1]).verify_sorted().is_err());
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let buffer = Arc::new(SetBuffer::owned(vec![0; 100]));
+        assert!(SetView::new(buffer, 0..100, 3).is_err());
+    }
+}
@@ -1,147 +1,391 @@
-use crate::sha256_utils::{sha256_from_path, Sha256};
+use crate::{
+    file_hashes::{hash_key, FileDigests, HashAlgorithm},
+    sha256_utils::Sha256,
+};
 use common::{detection::DetectionReport, redr};
 use serde_yaml;
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
     fs::DirEntry,
-    io::{Read, Seek, SeekFrom},
 };
 
 use crate::{
     error::SigSetError,
-    sha256_utils,
-    sig_set::{signature::SigSha256, sigset_serializer::SigSetSerializer, Description, SigSet},
+    sig_set::{
+        dir_files,
+        import_set_view::INDEX_SIG_ID,
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{SigBase, SigSha256},
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
+    },
 };
 
+// signature is keyed by every hash it declares, so it is found by any of them. Set keeps which
+// algorithms its keys are of, so only their digests are computed when file is scanned
 pub struct ShaSet {
-    sha_list: BTreeSet<Sha256>,
-    sha_to_description: HashMap<Sha256, Description>,
+    // signatures compiled from yaml or from files, kept in memory until set is serialized
+    sha_to_description: BTreeMap<Sha256, Description>,
+    // signatures of loaded set. They are searched in place, so loading doesn't depend on set size
+    view: Option<SetView>,
+    // in order of HashAlgorithm::ALL
+    algorithms: Vec<HashAlgorithm>,
 }
 
 impl ShaSet {
     pub const SET_MAGIC_U32: u32 = 0x54453535; //55ET
                                                //const SHASET_MAGIC: [u8; 4] = [0x35, 0x35, 0x45, 0x54]; //55ET
 
-    const PROPERTY_NAME: &'static str = "name";
-    const PROPERTY_SHA256: &'static str = "sha256";
-    const PROPERTY_DESC: &'static str = "description";
-
     pub(crate) fn new_empty() -> Self {
         Self {
-            sha_list: Default::default(),
             sha_to_description: Default::default(),
+            view: None,
+            algorithms: vec![],
         }
     }
 
+    // algorithms are in record at INDEX_SIG_ID, after every signature. Sets without it were
+    // compiled before it was added, their signatures may be of any algorithm
+    pub(crate) fn from_view(view: SetView) -> Result<Self, SigSetError> {
+        let algorithms = match view.find(&INDEX_SIG_ID)? {
+            Some(data) => read_algorithms(data)?,
+            None => HashAlgorithm::ALL.to_vec(),
+        };
+        Ok(Self {
+            sha_to_description: Default::default(),
+            view: Some(view),
+            algorithms,
+        })
+    }
+
+    fn find(&self, key: &Sha256) -> Result<Option<Cow<'_, str>>, SigSetError> {
+        Ok(match &self.view {
+            Some(view) => view.find(key)?.map(String::from_utf8_lossy),
+            None => (self.sha_to_description.get(key)).map(|d| Cow::from(d.as_str())),
+        })
+    }
+
+    #[cfg(test)]
     fn match_(&self, sha: &Sha256) -> Result<Option<SigSha256>, SigSetError> {
-        if self.sha_list.contains(sha) {
-            let properties: SigSha256 = serde_yaml::from_str(&self.sha_to_description[sha])?;
-            return Ok(Some(properties));
+        match self.find(sha)? {
+            Some(description) => Ok(Some(serde_yaml::from_str(&description)?)),
+            None => Ok(None),
         }
-
-        Ok(None)
     }
 
-    pub fn from_dir(path_to_dir: &str) -> Result<ShaSet, SigSetError> {
-        let paths = std::fs::read_dir(path_to_dir)?;
+    // key of each signature is taken from its description
+    pub(crate) fn from_descriptions(descriptions: Vec<Description>) -> Result<Self, SigSetError> {
+        let mut sha_set = Self::new_empty();
+        for description in descriptions {
+            sha_set.add_description(description)?;
+        }
+        Ok(sha_set)
+    }
 
-        let mut sha_set = ShaSet::new_empty();
-        for entry_res in paths {
-            let entry = entry_res?;
-            //log::trace!("path: {:?}", &path);
-            if entry.file_type()?.is_file() {
-                let sha = sha256_from_path(entry.path().into_os_string().into_string()?.as_str())?;
-                sha_set.append_signature(sha, Self::create_file_info(&entry, &sha)?);
-                log::trace!("path: {:?}", &entry);
+    pub(crate) fn add_description(&mut self, description: Description) -> Result<(), SigSetError> {
+        let sig: SigSha256 = serde_yaml::from_str(&description)?;
+        let digests = sig.digests()?;
+        for (algorithm, digest) in &digests {
+            if self
+                .sha_to_description
+                .contains_key(&hash_key(*algorithm, digest))
+            {
+                return Err(SigSetError::DuplicatedSignatureError(format!(
+                    "{}: {}",
+                    algorithm.name(),
+                    hex::encode_upper(digest)
+                )));
             }
         }
+        for (algorithm, digest) in digests {
+            self.index(algorithm, &digest, description.clone());
+        }
+        Ok(())
+    }
 
-        log::info!("mset size: {}", sha_set.sha_list.len());
-        Ok(sha_set)
+    // signature under key of digest, algorithm of digest is added to set
+    fn index(&mut self, algorithm: HashAlgorithm, digest: &[u8], desc: Description) {
+        self.sha_to_description
+            .insert(hash_key(algorithm, digest), desc);
+        if !self.algorithms.contains(&algorithm) {
+            self.algorithms.push(algorithm);
+            self.algorithms
+                .sort_by_key(|algorithm| HashAlgorithm::ALL.iter().position(|a| a == algorithm));
+        }
+    }
+
+    // descriptions in order of sha. Signature keyed by more hashes is listed once
+    pub(crate) fn descriptions(&self) -> Result<Vec<Description>, SigSetError> {
+        let mut listed = HashSet::new();
+        Ok(self
+            .signatures()?
+            .into_iter()
+            .filter(|(_, desc)| listed.insert(desc.clone()))
+            .map(|(_, desc)| desc.into_owned())
+            .collect())
     }
 
-    fn create_file_info(path: &DirEntry, sha256: &Sha256) -> Result<String, SigSetError> {
-        Ok(format!(
-            "{}: {}\n{}: {}\n{}: {:?}\n",
-            Self::PROPERTY_NAME,
-            path.file_name().into_string()?,
-            Self::PROPERTY_SHA256,
-            hex::encode_upper(&sha256),
-            Self::PROPERTY_DESC,
-            path.metadata()?
-        ))
+    // every signature of set under every its key, no matter if it is loaded or compiled
+    fn signatures(&self) -> Result<Vec<(Sha256, Cow<'_, str>)>, SigSetError> {
+        let Some(view) = &self.view else {
+            return Ok(self
+                .sha_to_description
+                .iter()
+                .map(|(sha, desc)| (*sha, Cow::from(desc.as_str())))
+                .collect());
+        };
+
+        view.signatures()
+            .filter(|sig| !matches!(sig, Ok((header, _)) if header.id == INDEX_SIG_ID))
+            .map(|sig| {
+                let (header, data) = sig?;
+                Ok((header.id, String::from_utf8_lossy(data)))
+            })
+            .collect()
     }
 
-    pub fn unpack_to_dir(&self, out_dir: &String) -> Result<usize, SigSetError> {
-        let path = std::path::Path::new(&out_dir);
-        for (sha, desc) in self.sha_to_description.iter() {
-            let file_path = path.join(hex::encode_upper(&sha));
-            std::fs::write(file_path, desc)?;
+    pub fn from_dir(path_to_dir: &str) -> Result<ShaSet, SigSetError> {
+        let mut sha_set = ShaSet::new_empty();
+        // the same file under other name gets the same hashes, the last name is kept
+        for entry in dir_files(path_to_dir)? {
+            let digests = FileDigests::from_path(
+                entry.path().into_os_string().into_string()?.as_str(),
+                &HashAlgorithm::ALL,
+            )?;
+            let description = Self::create_file_info(&entry, &digests)?;
+            for algorithm in HashAlgorithm::ALL {
+                if let Some(digest) = algorithm.digest(&digests) {
+                    sha_set.index(algorithm, digest, description.clone());
+                }
+            }
+            log::trace!("path: {:?}", &entry);
         }
 
-        Ok(self.sha_to_description.len())
+        log::info!("mset size: {}", sha_set.descriptions()?.len());
+        Ok(sha_set)
+    }
+
+    // only properties of file which don't change when it is copied, so set compiled from the
+    // same files is always the same
+    fn create_file_info(path: &DirEntry, digests: &FileDigests) -> Result<String, SigSetError> {
+        let hashes = digests.to_file_hashes();
+        let sig = SigSha256 {
+            sig_base: SigBase {
+                name: path.file_name().into_string()?,
+                description: format!("File size: {}", path.metadata()?.len()),
+                priority: 0,
+                tests: Default::default(),
+            },
+            md5: hashes.md5,
+            sha1: hashes.sha1,
+            sha256: hashes.sha256,
+            sha512: hashes.sha512,
+        };
+        Ok(serde_yaml::to_string(&sig)?)
     }
 
+    // signature keyed by its sha256
+    #[cfg(test)]
     pub(crate) fn append_signature(&mut self, sig_id: Sha256, desc: Description) {
-        self.sha_list.insert(sig_id);
-        self.sha_to_description.insert(sig_id, desc);
+        self.index(HashAlgorithm::Sha256, &sig_id, desc);
     }
 }
 
+// names of algorithms separated by ","
+fn read_algorithms(data: &[u8]) -> Result<Vec<HashAlgorithm>, SigSetError> {
+    let names = String::from_utf8_lossy(data);
+    (names.split(',').filter(|name| !name.is_empty()))
+        .map(|name| {
+            HashAlgorithm::from_name(name).ok_or_else(|| SigSetError::IncorrectSignatureError {
+                info: format!("Unknown hash algorithm {name}"),
+            })
+        })
+        .collect()
+}
+
 impl SigSet for ShaSet {
     fn eval_file(
         &self,
         file: &mut redr::FileReader,
         variant: &mut redr::FileScanInfo,
-    ) -> Result<Option<DetectionReport>, SigSetError> {
-        let sha256 = crate::sha256_utils::sha256_from_file_pointer(file)?;
-        variant.set_sha(sha256_utils::convert_sha256_to_string(&sha256)?);
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        if self.algorithms.is_empty() {
+            return Ok(vec![]);
+        }
+        let digests = FileDigests::from_file_pointer(file, &self.algorithms)?;
+        variant.set_hashes(digests.to_file_hashes());
 
-        let sig_info = self.match_(&sha256)?;
-        let desc_and_info = sig_info.map(|sig| sig.into());
-        Ok(desc_and_info)
+        // key is unique in set, so each algorithm gives at most one signature. Signature keyed by
+        // more hashes of file is reported once
+        let mut matched = vec![];
+        for algorithm in &self.algorithms {
+            let Some(digest) = algorithm.digest(&digests) else {
+                continue;
+            };
+            if let Some(description) = self.find(&hash_key(*algorithm, digest))? {
+                if !matched.contains(&description) {
+                    matched.push(description);
+                }
+            }
+        }
+        let mut reports = vec![];
+        for description in matched {
+            reports.push(serde_yaml::from_str::<SigSha256>(&description)?.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
     }
 
-    fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError> {
-        let paths = std::fs::read_dir(path_to_dir)?;
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
         let mut sha_set = Self::new_empty();
+        // sha signatures have no ids, they are sorted by sha
+        source.compile(|_, description| sha_set.add_description(description))?;
+        log::info!("mset size: {}", sha_set.descriptions()?.len());
+        Ok(sha_set)
+    }
 
-        for entry_res in paths {
-            let entry = entry_res?;
-            //log::trace!("path: {:?}", &path);
-            if entry.file_type()?.is_file() {
-                let mut f = std::fs::File::open(entry.path())?;
-                let properties: BTreeMap<String, String> = serde_yaml::from_reader(&f)?;
-                log::info!("Properies: {:?}", properties);
-
-                //let name = properties.get(Self::PROPERTY_NAME).ok_or(MsetError::NoSuchPropertyError(PROPERTY_NAME.into()))?;
-                let sha256 = properties.get(Self::PROPERTY_SHA256).ok_or(
-                    SigSetError::NoSuchPropertyError(Self::PROPERTY_SHA256.into()),
-                )?;
-                //let desc = properties.get(Self::PROPERTY_DESC).ok_or(MsetError::NoSuchPropertyError(PROPERTY_DESC.into()))?;
-
-                f.seek(SeekFrom::Start(0))?;
-                let mut data = Vec::new();
-                f.read_to_end(&mut data)?;
-                sha_set.append_signature(
-                    sha256_utils::convert_string_to_sha256(sha256)?,
-                    String::from_utf8_lossy(&data).into(),
-                )
-            }
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        // signatures are serialized sorted by sha, so loaded set can be binary searched. Record
+        // of algorithms is the last one, its id is bigger than any sha
+        let mut ser = SigSetSerializer::new_empty();
+        for (sha, desc) in self.signatures()? {
+            ser.serialize_signature(sha, desc.into_owned().into_bytes());
         }
+        let algorithms: Vec<&str> = self.algorithms.iter().map(|a| a.name()).collect();
+        ser.serialize_index(algorithms.join(",").into_bytes());
+        Ok(ser)
+    }
+}
 
-        log::info!("mset size: {}", sha_set.sha_list.len());
-        Ok(sha_set)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sha256_utils::sha256_from_vec,
+        sig_set::{set_view::SetBuffer, sigset_deserializer::SigSetDeserializer},
+    };
+    use common::redr::FileHashes;
+    use std::sync::Arc;
+
+    #[test]
+    fn set_bigger_than_4mb_is_searched_in_place() {
+        const SIG_COUNT: u32 = 120_000;
+
+        let mut sha_set = ShaSet::new_empty();
+        for i in 0..SIG_COUNT {
+            let sha = sha256_from_vec(i.to_le_bytes().to_vec()).unwrap();
+            let desc = format!(
+                "name: sample{i}\nsha256: {}\ndescription: sample {i}\n",
+                hex::encode_upper(sha)
+            );
+            sha_set.append_signature(sha, desc);
+        }
+
+        let path = std::env::temp_dir().join(format!("sfi_big_{}.sset", std::process::id()));
+        let path = path.to_str().unwrap();
+        sha_set
+            .to_sig_set()
+            .unwrap()
+            .serialize(path, ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        assert!(std::fs::metadata(path).unwrap().len() > 0x400000);
+
+        let sets = SigSetDeserializer::new_untrusted_sections(path).unwrap();
+        let sha_set = SigSetDeserializer::find_section(sets, ShaSet::SET_MAGIC_U32)
+            .unwrap()
+            .get_sha_set()
+            .unwrap();
+        for i in [0, 1, 65_536, SIG_COUNT - 1] {
+            let sha = sha256_from_vec(i.to_le_bytes().to_vec()).unwrap();
+            let sig = sha_set.match_(&sha).unwrap().unwrap();
+            assert_eq!(sig.sig_base.name, format!("sample{i}"));
+        }
+        let sha = sha256_from_vec(SIG_COUNT.to_le_bytes().to_vec()).unwrap();
+        assert!(sha_set.match_(&sha).unwrap().is_none());
+
+        drop(sha_set);
+        std::fs::remove_file(path).unwrap();
     }
 
-    fn to_sig_set(&self) -> SigSetSerializer {
-        let mut ser = SigSetSerializer::new_empty();
-        let sorted_map: BTreeMap<Sha256, Description> =
-            self.sha_to_description.clone().into_iter().collect();
+    #[test]
+    fn file_is_matched_by_any_of_its_hashes() {
+        let data = b"evil".to_vec();
+        let digests =
+            FileDigests::from_file_pointer(&mut data.as_slice(), &HashAlgorithm::ALL).unwrap();
+        let hashes = digests.to_file_hashes();
+        let md5 = hashes.md5.clone().unwrap();
+        let by_md5 = format!(
+            "name: by_md5\ndescription: m\nmd5: {}\n",
+            md5.to_lowercase()
+        );
+        let descriptions = vec![
+            by_md5.clone(),
+            format!(
+                "name: by_sha1_and_sha512\ndescription: s\nsha1: {}\nsha512: {}\n",
+                hashes.sha1.clone().unwrap(),
+                hashes.sha512.clone().unwrap()
+            ),
+            format!("name: other\ndescription: o\nsha512: {}\n", "00".repeat(64)),
+        ];
+        let set = ShaSet::from_descriptions(descriptions).unwrap();
+        let bytes = set
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        let set = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_sha_set()
+            .unwrap();
+        assert_eq!(set.descriptions().unwrap().len(), 3);
+
+        let mut file = redr::FileReader::from_buff(std::io::Cursor::new(data));
+        let mut variant = redr::FileScanInfo::real_file("evil".into());
+        let reports = set.eval_file(&mut file, &mut variant).unwrap();
+        let names: Vec<&str> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["by_sha1_and_sha512", "by_md5"]);
+        assert!(reports[1].cause.contains("md5: "));
+        // set has no sha256 signature, so sha256 of file is not computed
+        let reported = FileHashes {
+            sha256: None,
+            ..hashes
+        };
+        assert_eq!(variant.get_origin_file().borrow().hashes, Some(reported));
 
-        for (sha, desc) in sorted_map {
-            ser.serialize_signature(sha, desc.into_bytes());
+        // signature is a duplicate if any of its hashes is in set
+        let again = format!(
+            "name: again\ndescription: a\nsha1: {}\nmd5: {md5}\n",
+            "11".repeat(20)
+        );
+        assert!(matches!(
+            ShaSet::from_descriptions(vec![by_md5, again]),
+            Err(SigSetError::DuplicatedSignatureError(_))
+        ));
+        for incorrect in ["md5: 00", "sha1: null", "name2: x"] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                ShaSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
         }
-        ser
+    }
+
+    #[test]
+    fn set_with_unreadable_signature_is_not_serialized() {
+        let mut sha_set = ShaSet::new_empty();
+        sha_set.append_signature([1; 32], "name: a\ndescription: a\nsha256: 01\n".into());
+        sha_set.append_signature([2; 32], "name: b\ndescription: b\nsha256: 02\n".into());
+        // header of second signature claims more data than set has
+        let mut bytes = sha_set.to_sig_set().unwrap().to_bytes(0).unwrap()[40..].to_vec();
+        bytes[72..76].copy_from_slice(&0x1000u32.to_le_bytes());
+        let len = bytes.len();
+        let view = SetView::new(Arc::new(SetBuffer::owned(bytes)), 0..len, 3).unwrap();
+
+        assert!(matches!(
+            ShaSet::from_view(view).unwrap().to_sig_set(),
+            Err(SigSetError::IncorrectSignatureSizeError { size: 0x1000 })
+        ));
     }
 }
@@ -0,0 +1,202 @@
+use crate::{
+    file_hashes::hash_key,
+    sha256_utils::Sha256,
+    sig_set::{
+        heuristic_set::HeurSet,
+        import_name::is_import_feature,
+        sha_set::ShaSet,
+        sig_source::{split_documents, SigPosition, SigSource},
+        signature::{SigHeur, SigSha256},
+        sigset_file::{signature_name, signature_set_magic, SigSetFile},
+    },
+    SigSetError,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Problem found in signature file
+#[derive(Debug)]
+pub struct LintIssue {
+    pub path: PathBuf,
+    pub position: SigPosition,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = location(&self.path, self.position);
+        write!(f, "{location}: {}", self.message)
+    }
+}
+
+fn location(path: &Path, position: SigPosition) -> String {
+    format!("{}, {position}", path.display())
+}
+
+// first signature with given name or sha, so duplicates point to it
+#[derive(Default)]
+struct Seen {
+    names: HashMap<(u32, String), String>,
+    hashes: HashMap<Sha256, String>,
+}
+
+/// Checks every signature of chosen files. Each signature is compiled alone, so every problem
+/// which would stop compilation is reported, plus duplicates among signatures
+pub fn lint(source: &SigSource) -> Result<Vec<LintIssue>, SigSetError> {
+    let mut issues = vec![];
+    let mut seen = Seen::default();
+    for path in source.files()? {
+        let text = String::from_utf8_lossy(&std::fs::read(&path)?).into_owned();
+        let descriptions = match split_documents(&text) {
+            Ok(descriptions) => descriptions,
+            Err((position, e)) => {
+                issues.push(LintIssue {
+                    path,
+                    position,
+                    message: e.to_string(),
+                });
+                continue;
+            },
+        };
+
+        for (position, description) in &descriptions {
+            let location = location(&path, *position);
+            for message in lint_signature(description, &location, &mut seen) {
+                issues.push(LintIssue {
+                    path: path.clone(),
+                    position: *position,
+                    message,
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn lint_signature(description: &str, location: &str, seen: &mut Seen) -> Vec<String> {
+    let magic = match signature_set_magic(description) {
+        Ok(magic) => magic,
+        Err(e) => return vec![e.to_string()],
+    };
+    let mut messages = vec![];
+    if let Err(e) = SigSetFile::compile(magic, vec![description.to_string()]) {
+        messages.push(e.to_string());
+    }
+
+    match magic {
+        ShaSet::SET_MAGIC_U32 => {
+            // signature is keyed by every its hash, like in set
+            let digests = serde_yaml::from_str::<SigSha256>(description)
+                .ok()
+                .and_then(|sig| sig.digests().ok())
+                .unwrap_or_default();
+            for (algorithm, digest) in digests {
+                let key = hash_key(algorithm, &digest);
+                if let Some(first) = seen.hashes.get(&key) {
+                    messages.push(format!(
+                        "Duplicated {} hash, first in {first}",
+                        algorithm.name()
+                    ));
+                } else {
+                    seen.hashes.insert(key, location.into());
+                }
+            }
+        },
+        HeurSet::SET_MAGIC_U32 => {
+            if let Ok(sig) = serde_yaml::from_str::<SigHeur>(description) {
+                for import in (sig.imports.iter()).filter(|i| !is_import_feature(i.name())) {
+                    messages.push(format!(
+                        "Import '{}' is not in form 'library+function', 'clr:Type::Member', \
+                         'lib:library' or 'sym:symbol'",
+                        import.name()
+                    ));
+                }
+            }
+        },
+        _ => {},
+    }
+
+    // sha signatures of one family can share name
+    if let (false, Ok(name)) = (magic == ShaSet::SET_MAGIC_U32, signature_name(description)) {
+        if let Some(first) = seen.names.get(&(magic, name.clone())) {
+            messages.push(format!("Duplicated name '{name}', first in {first}"));
+        } else {
+            seen.names.insert((magic, name), location.into());
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_problem_is_reported_with_its_file() {
+        let dir = std::env::temp_dir().join(format!("sfi_lint_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("heur")).unwrap();
+        let sha = "A".repeat(64);
+        let files = [
+            (
+                "sha1.sig",
+                format!("name: a\ndescription: a\nsha256: {sha}\n"),
+            ),
+            (
+                "sha2.sig",
+                format!("name: a\ndescription: a\nsha256: {sha}\n"),
+            ),
+            ("sha3.sig", "name: b\ndescription: b\nsha256: 0xZZ\n".into()),
+            (
+                "heur/1.sig",
+                "name: h\ndescription: h\nimports: [a.dll+a]\n".into(),
+            ),
+            (
+                "heur/2.sig",
+                "name: h\ndescription: h\nimports: [a.dll]\n".into(),
+            ),
+            (
+                "heur/3.sig",
+                "name: e\ndescription: e\nimports: []\n".into(),
+            ),
+            ("heur/4.sig", "name: [\n".into()),
+            (
+                "heur/5.yml",
+                "name: g\ndescription: g\nimports: [a.dll+a]\n---\nname: g\n".into(),
+            ),
+            (
+                "heur/6.yml",
+                "- name: l\n  description: l\n  imports: [a.dll+a]\n- name: l\n".into(),
+            ),
+        ];
+        for (name, content) in &files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+
+        let issues = lint(&SigSource::new(dir.to_str().unwrap())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let issues: Vec<_> = issues
+            .iter()
+            .map(|issue| {
+                let path = issue.path.strip_prefix(&dir).unwrap();
+                let path = path.to_str().unwrap().replace('\\', "/");
+                format!("{path}, {}", issue.position)
+            })
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "heur/2.sig, document 0",
+                "heur/2.sig, document 0",
+                "heur/3.sig, document 0",
+                "heur/4.sig, document 0",
+                "heur/5.yml, document 1",
+                "heur/6.yml, document 0, item 1",
+                "sha2.sig, document 0",
+                "sha3.sig, document 0"
+            ]
+        );
+    }
+}
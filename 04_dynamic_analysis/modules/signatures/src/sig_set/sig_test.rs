@@ -0,0 +1,149 @@
+use crate::{
+    sig_set::{
+        set_kind::set_kind,
+        sig_source::{SigPosition, SigSource},
+        signature::SigBase,
+        sigset_file::signature_set_magic,
+    },
+    SigSetError,
+};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    path::{Path, PathBuf},
+};
+
+/// Sample declared in signature and whether it was matched as declared
+#[derive(Debug)]
+pub struct SampleResult {
+    pub path: PathBuf,
+    pub position: SigPosition,
+    pub name: String,
+    pub sample: PathBuf,
+    /// Sample must be matched by signature
+    pub positive: bool,
+    pub matched: bool,
+}
+
+impl SampleResult {
+    pub fn passed(&self) -> bool {
+        self.positive == self.matched
+    }
+}
+
+impl std::fmt::Display for SampleResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.positive {
+            "positive"
+        } else {
+            "negative"
+        };
+        let result = if self.passed() { "ok" } else { "FAILED" };
+        write!(
+            f,
+            "{}, {}: '{}' {kind} sample {}: {result}",
+            self.path.display(),
+            self.position,
+            self.name,
+            self.sample.display(),
+        )
+    }
+}
+
+/// Compiles chosen signatures in memory and evaluates samples declared in their "tests".
+/// Signature is found among detections by its name
+pub fn test_source(source: &SigSource) -> Result<Vec<SampleResult>, SigSetError> {
+    let sigs = source.read()?;
+    // signatures of one type compiled together, so sample is evaluated like in real scan
+    let mut sets = BTreeMap::new();
+    let mut magics = vec![];
+    for (sig_id, sig) in (0..).zip(&sigs) {
+        let magic = signature_set_magic(&sig.description).map_err(|e| sig.error(e))?;
+        let set = match sets.entry(magic) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let kind = set_kind(magic).map_err(|e| sig.error(e))?;
+                entry.insert((kind.new_empty)())
+            },
+        };
+        set.add(sig_id, sig.description.clone())
+            .map_err(|e| sig.error(e))?;
+        magics.push(magic);
+    }
+
+    let mut results = vec![];
+    for (sig, magic) in sigs.iter().zip(magics) {
+        let base: SigBase =
+            serde_yaml::from_str(&sig.description).map_err(|e| sig.error(e.into()))?;
+        let samples = base.tests.positive.iter().map(|sample| (sample, true));
+        let samples = samples.chain(base.tests.negative.iter().map(|sample| (sample, false)));
+        for (sample, positive) in samples {
+            let sample = sig.path.parent().unwrap_or(Path::new("")).join(sample);
+            let reports = sets[&magic]
+                .eval_sample(&sample)
+                .map_err(|e| sig.error(e))?;
+            results.push(SampleResult {
+                path: sig.path.clone(),
+                position: sig.position,
+                name: base.name.clone(),
+                matched: reports.iter().any(|report| report.name == base.name),
+                sample,
+                positive,
+            });
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    #[test]
+    fn samples_are_matched_as_declared() {
+        let dir = std::env::temp_dir().join(format!("sfi_sig_test_{}", std::process::id()));
+        let samples = dir.join("samples");
+        std::fs::create_dir_all(dir.join("sigs")).unwrap();
+        std::fs::create_dir_all(&samples).unwrap();
+        std::fs::write(samples.join("evil.bin"), b"evil").unwrap();
+        std::fs::write(samples.join("clean.bin"), b"clean").unwrap();
+        std::fs::write(
+            samples.join("evil.trace"),
+            "Sleep\nBlockInput\nMessageBoxW\n",
+        )
+        .unwrap();
+        std::fs::write(samples.join("clean.trace"), "Sleep\n").unwrap();
+
+        let sha = hex::encode_upper(sha2::Sha256::digest(b"evil"));
+        let sha_sig = format!(
+            "name: evil\ndescription: e\nsha256: {sha}\ntests:\n  positive: \
+             [../samples/evil.bin]\n  negative: [../samples/clean.bin]\n"
+        );
+        let dyn_sigs =
+            "- name: input\n  description: i\n  calls: [Sleep, BlockInput]\n  tests:\n    \
+             positive: [../samples/evil.trace]\n    negative: [../samples/clean.trace]\n- name: \
+             sleep\n  description: s\n  calls: [Sleep]\n  tests:\n    negative: \
+             [../samples/clean.trace]\n";
+        std::fs::write(dir.join("sigs").join("sha.yml"), sha_sig).unwrap();
+        std::fs::write(dir.join("sigs").join("dyn.yml"), dyn_sigs).unwrap();
+
+        let source = SigSource::new(dir.join("sigs").to_str().unwrap());
+        let results = test_source(&source).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let results: Vec<_> = results
+            .iter()
+            .map(|r| (r.name.as_str(), r.position.item, r.positive, r.passed()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("input", Some(0), true, true),
+                ("input", Some(0), false, true),
+                ("sleep", Some(1), false, false),
+                ("evil", None, true, true),
+                ("evil", None, false, true),
+            ]
+        );
+    }
+}
@@ -1,47 +1,886 @@
+use crate::{
+    file_hashes::HashAlgorithm,
+    sig_set::condition::{CompiledCondition, Condition},
+    SigSetError,
+};
 use common::detection::DetectionReport;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SigBase {
     pub name: String,
     pub description: String,
+    /// When more signatures match, detections with higher priority are reported first
+    #[serde(default)]
+    pub priority: u32,
+    /// Samples the signature is tested with by "signature test"
+    #[serde(default, skip_serializing_if = "SigSamples::is_empty")]
+    pub tests: SigSamples,
 }
 
+/// Paths relative to directory of signature file. Samples are files for sha and heuristic
+/// signatures and API call traces (one call per line) for dynamic ones
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SigSamples {
+    /// Samples which signature must match
+    #[serde(default)]
+    pub positive: Vec<String>,
+    /// Samples which signature must not match
+    #[serde(default)]
+    pub negative: Vec<String>,
+}
+
+impl SigSamples {
+    pub fn is_empty(&self) -> bool {
+        self.positive.is_empty() && self.negative.is_empty()
+    }
+}
+
+/// Hash signature of file. At least one hash must be given, file matches if any of them is its
+/// hash
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SigSha256 {
     #[serde(flatten)]
     pub sig_base: SigBase,
-    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+}
+
+impl SigSha256 {
+    pub fn hash(&self, algorithm: HashAlgorithm) -> Option<&String> {
+        match algorithm {
+            HashAlgorithm::Md5 => self.md5.as_ref(),
+            HashAlgorithm::Sha1 => self.sha1.as_ref(),
+            HashAlgorithm::Sha256 => self.sha256.as_ref(),
+            HashAlgorithm::Sha512 => self.sha512.as_ref(),
+        }
+    }
+
+    /// Declared digests in order of HashAlgorithm::ALL. The first one is key of signature in set
+    pub fn digests(&self) -> Result<Vec<(HashAlgorithm, Vec<u8>)>, SigSetError> {
+        let mut digests = vec![];
+        for algorithm in HashAlgorithm::ALL {
+            if let Some(hash) = self.hash(algorithm) {
+                digests.push((algorithm, algorithm.digest_from_string(hash)?));
+            }
+        }
+        if digests.is_empty() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("{}: no md5, sha1, sha256 or sha512", self.sig_base.name),
+            });
+        }
+        Ok(digests)
+    }
+
+    // hashes in form they are reported, e.g. "sha256: <hex>"
+    pub(crate) fn features(&self) -> Vec<String> {
+        (HashAlgorithm::ALL.into_iter())
+            .filter_map(|algorithm| {
+                let hash = self.hash(algorithm)?;
+                Some(format!("{}: {hash}", algorithm.name()))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SigHeur {
     #[serde(flatten)]
     pub sig_base: SigBase,
-    pub imports: Vec<String>,
+    /// Imports as "library+function". Case, missing ".dll" and api set dlls don't matter, function
+    /// imported by ordinal may be given as "#<N>". Delay-load imports and P/Invoke of .NET
+    /// assemblies are imports too, members of other assemblies are "clr:Namespace.Type::Member".
+    /// ELF and Mach-O binaries have "lib:<library>" and "sym:<symbol>" instead
+    #[serde(default)]
+    pub imports: Vec<HeurImport>,
+    /// Minimal score of present imports. Each import scores 1 unless it has a weight, so for
+    /// unweighted imports it means "at least N of these imports". If not given, every import is
+    /// required
+    #[serde(default)]
+    pub threshold: Option<u32>,
+    /// Boolean expression over imports which must be true as well as imports rule
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    /// Format of files signature matches, any executable if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<FileFormat>,
+}
+
+/// Executable format
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FileFormat {
+    #[serde(rename = "pe")]
+    Pe,
+    #[serde(rename = "elf")]
+    Elf,
+    #[serde(rename = "macho")]
+    MachO,
+}
+
+/// Import in form "library+function", optionally with its weight
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HeurImport {
+    Plain(String),
+    Weighted { import: String, weight: u32 },
+}
+
+impl HeurImport {
+    pub fn name(&self) -> &str {
+        match self {
+            HeurImport::Plain(import) => import,
+            HeurImport::Weighted { import, .. } => import,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            HeurImport::Plain(_) => 1,
+            HeurImport::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+impl SigHeur {
+    pub(crate) fn import_weights(&self) -> Vec<u32> {
+        self.imports.iter().map(HeurImport::weight).collect()
+    }
+
+    // score of file with every import. None if it overflows, such signature can't be compiled
+    pub(crate) fn total_weight(&self) -> Option<u32> {
+        (self.imports.iter()).try_fold(0u32, |total, import| total.checked_add(import.weight()))
+    }
+
+    // imports followed by features of condition. Signature features are hashed in this order
+    pub(crate) fn features(&self) -> Vec<&str> {
+        let mut features: Vec<&str> = self.imports.iter().map(HeurImport::name).collect();
+        if let Some(condition) = &self.condition {
+            features.append(&mut condition.features());
+        }
+        features
+    }
+
+    // imports rule and condition combined into one condition. None if signature has no
+    // condition, then imports rule alone is enough
+    pub(crate) fn compile_condition(&self) -> Result<Option<CompiledCondition>, SigSetError> {
+        let Some(condition) = &self.condition else {
+            return Ok(None);
+        };
+
+        let condition = condition.compile(self.imports.len() as u32)?;
+        if self.imports.is_empty() {
+            return Ok(Some(condition));
+        }
+
+        let weights = self.import_weights();
+        let imports_rule = CompiledCondition::Weighted {
+            threshold: (self.threshold)
+                .unwrap_or_else(|| weights.iter().fold(0, |total, w| total.saturating_add(*w))),
+            features: (0..).zip(weights).collect(),
+        };
+        Ok(Some(CompiledCondition::AllOf(vec![
+            imports_rule,
+            condition,
+        ])))
+    }
+}
+
+/// Matched heuristic signature with imports which were found and which were missing in file
+#[derive(Debug)]
+pub(crate) struct HeurMatch {
+    pub sig: SigHeur,
+    pub present: Vec<String>,
+    pub missing: Vec<String>,
+    pub score: u32,
+    pub threshold: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SigDyn {
     #[serde(flatten)]
     pub sig_base: SigBase,
+    #[serde(default)]
     pub calls: Vec<String>,
+    /// Boolean expression over calls which must be true when all calls are present
+    #[serde(default)]
+    pub condition: Option<Condition>,
+}
+
+impl SigDyn {
+    // calls followed by features of condition. Signature features are hashed in this order
+    pub(crate) fn features(&self) -> Vec<&str> {
+        let mut features: Vec<&str> = self.calls.iter().map(String::as_str).collect();
+        if let Some(condition) = &self.condition {
+            features.append(&mut condition.features());
+        }
+        features
+    }
+
+    pub(crate) fn compile_condition(&self) -> Result<Option<CompiledCondition>, SigSetError> {
+        let Some(condition) = &self.condition else {
+            return Ok(None);
+        };
+
+        let mut group: Vec<_> = (0..self.calls.len() as u32)
+            .map(CompiledCondition::Feature)
+            .collect();
+        group.push(condition.compile(self.calls.len() as u32)?);
+        Ok(Some(CompiledCondition::AllOf(group)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigPattern {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// Every pattern must be found in file
+    pub patterns: Vec<HexPattern>,
+}
+
+/// Hex bytes with wildcards "??" and jumps "[min-max]", e.g. "4D 5A ?? 00 [4-16] 50 45",
+/// optionally anchored at offset of file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HexPattern {
+    Plain(String),
+    Anchored { pattern: String, offset: u64 },
+}
+
+impl HexPattern {
+    pub fn pattern(&self) -> &str {
+        match self {
+            HexPattern::Plain(pattern) => pattern,
+            HexPattern::Anchored { pattern, .. } => pattern,
+        }
+    }
+
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            HexPattern::Plain(_) => None,
+            HexPattern::Anchored { offset, .. } => Some(*offset),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigFuzzy {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// ssdeep hash of sample, "block_size:digest:digest"
+    pub ssdeep: String,
+    /// Minimal similarity (0-100) of file to sample. If not given, default of set is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u32>,
+}
+
+/// Matched fuzzy hash signature with hash of file and its similarity
+#[derive(Debug)]
+pub(crate) struct FuzzyMatch {
+    pub sig: SigFuzzy,
+    pub ssdeep: String,
+    pub similarity: u32,
+    pub threshold: u32,
+}
+
+/// Signature of PE structures. Every given hash must match, at least one has to be given
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigPeHash {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// md5 of imports, computed as imphash of pefile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imphash: Option<String>,
+    /// md5 of Rich header without its XOR mask
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rich_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<SigSectionHash>,
+}
+
+/// md5 of raw data of section with given name, or of any section if name is not given
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigSectionHash {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub md5: String,
+}
+
+impl SigPeHash {
+    // hashes in form they are reported, e.g. "imphash: <md5>"
+    pub(crate) fn features(&self) -> Vec<String> {
+        let mut features = vec![];
+        if let Some(imphash) = &self.imphash {
+            features.push(format!("imphash: {imphash}"));
+        }
+        if let Some(rich_hash) = &self.rich_hash {
+            features.push(format!("rich_hash: {rich_hash}"));
+        }
+        if let Some(section) = &self.section {
+            let name = section.name.as_deref().unwrap_or("*");
+            features.push(format!("section {name}: {}", section.md5));
+        }
+        features
+    }
+}
+
+/// Signature of anomalies of PE structure, e.g. of packed or hand-crafted files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigAnomaly {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    pub anomalies: Vec<PeAnomaly>,
+    /// Minimal score of present anomalies. Each anomaly scores 1 unless it has a weight. If not
+    /// given, every anomaly is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u32>,
+}
+
+/// Feature of PE. Anomaly given by feature alone is present if the feature is not zero,
+/// otherwise if the feature is within given bounds
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PeAnomaly {
+    Plain(PeFeature),
+    Bounded {
+        feature: PeFeature,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        #[serde(default = "default_weight")]
+        weight: u32,
+    },
 }
 
-impl From<SigHeur> for DetectionReport {
-    fn from(sig: SigHeur) -> Self {
+fn default_weight() -> u32 {
+    1
+}
+
+/// Features PE anomalies are given by
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeFeature {
+    /// The highest entropy (0-8 bits per byte) of raw data of a section
+    MaxSectionEntropy,
+    /// Number of sections which are both writable and executable
+    WritableExecutableSections,
+    /// 1 if entry point is not in ".text" section, 0 otherwise
+    EntryPointOutsideText,
+    /// Number of sections whose names compilers and linkers don't give
+    UnusualSectionNames,
+    /// Number of TLS callbacks, they run before entry point
+    TlsCallbacks,
+    /// Size of data after the last section, without Authenticode signature
+    OverlaySize,
+    /// 1 if PE has no Rich header of Microsoft linker, 0 otherwise
+    MissingRichHeader,
+    /// Number of imported functions
+    ImportCount,
+}
+
+impl PeFeature {
+    pub const ALL: [PeFeature; 8] = [
+        PeFeature::MaxSectionEntropy,
+        PeFeature::WritableExecutableSections,
+        PeFeature::EntryPointOutsideText,
+        PeFeature::UnusualSectionNames,
+        PeFeature::TlsCallbacks,
+        PeFeature::OverlaySize,
+        PeFeature::MissingRichHeader,
+        PeFeature::ImportCount,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeFeature::MaxSectionEntropy => "max_section_entropy",
+            PeFeature::WritableExecutableSections => "writable_executable_sections",
+            PeFeature::EntryPointOutsideText => "entry_point_outside_text",
+            PeFeature::UnusualSectionNames => "unusual_section_names",
+            PeFeature::TlsCallbacks => "tls_callbacks",
+            PeFeature::OverlaySize => "overlay_size",
+            PeFeature::MissingRichHeader => "missing_rich_header",
+            PeFeature::ImportCount => "import_count",
+        }
+    }
+}
+
+impl PeAnomaly {
+    pub fn feature(&self) -> PeFeature {
+        match self {
+            PeAnomaly::Plain(feature) => *feature,
+            PeAnomaly::Bounded { feature, .. } => *feature,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            PeAnomaly::Plain(_) => 1,
+            PeAnomaly::Bounded { weight, .. } => *weight,
+        }
+    }
+
+    /// True if value of feature is anomalous
+    pub fn is_present(&self, value: f64) -> bool {
+        match self {
+            PeAnomaly::Plain(_)
+            | PeAnomaly::Bounded {
+                min: None,
+                max: None,
+                ..
+            } => value != 0.0,
+            PeAnomaly::Bounded { min, max, .. } => {
+                min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for PeAnomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.feature().name();
+        match self {
+            PeAnomaly::Plain(_) => write!(f, "{name}"),
+            PeAnomaly::Bounded { min, max, .. } => match (min, max) {
+                (Some(min), Some(max)) => write!(f, "{min} <= {name} <= {max}"),
+                (Some(min), None) => write!(f, "{name} >= {min}"),
+                (None, Some(max)) => write!(f, "{name} <= {max}"),
+                (None, None) => write!(f, "{name}"),
+            },
+        }
+    }
+}
+
+impl SigAnomaly {
+    pub(crate) fn threshold(&self) -> u32 {
+        (self.threshold).unwrap_or_else(|| self.total_weight().unwrap_or(u32::MAX))
+    }
+
+    // score of file with every anomaly. None if it overflows, such signature can't be compiled
+    pub(crate) fn total_weight(&self) -> Option<u32> {
+        (self.anomalies.iter()).try_fold(0u32, |total, anomaly| total.checked_add(anomaly.weight()))
+    }
+
+    // anomalies in form they are listed, e.g. "overlay_size >= 4096"
+    pub(crate) fn features(&self) -> Vec<String> {
+        self.anomalies.iter().map(ToString::to_string).collect()
+    }
+}
+
+/// Matched anomaly signature with present anomalies and values of their features
+#[derive(Debug)]
+pub(crate) struct AnomalyMatch {
+    pub sig: SigAnomaly,
+    pub present: Vec<String>,
+    pub score: u32,
+}
+
+/// Signature of ASCII and UTF-16LE strings found in file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigStrings {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// Patterns of strings of each kind, case insensitive, "*" is any sequence of characters and
+    /// "?" any character. Pattern matches if any string of its kind matches it
+    pub strings: BTreeMap<StringKind, StringPatterns>,
+    /// Minimal number of matched patterns. If not given, every pattern is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u32>,
+}
+
+/// Kind of string found in file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StringKind {
+    /// "http://", "https://" or "ftp://" URL
+    Url,
+    /// IPv4 address
+    Ip,
+    /// Host name with common top level domain
+    Domain,
+    /// Registry key, e.g. "HKLM\..." or "Software\..."
+    Registry,
+    /// Absolute path of Windows or Unix file
+    Path,
+    /// Name of object in "Global\", "Local\" or "Session\" namespace, or GUID in braces
+    Mutex,
+    /// Base64 encoded data
+    Base64,
+}
+
+impl StringKind {
+    pub const ALL: [StringKind; 7] = [
+        StringKind::Url,
+        StringKind::Ip,
+        StringKind::Domain,
+        StringKind::Registry,
+        StringKind::Path,
+        StringKind::Mutex,
+        StringKind::Base64,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringKind::Url => "url",
+            StringKind::Ip => "ip",
+            StringKind::Domain => "domain",
+            StringKind::Registry => "registry",
+            StringKind::Path => "path",
+            StringKind::Mutex => "mutex",
+            StringKind::Base64 => "base64",
+        }
+    }
+}
+
+/// One pattern or list of them
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StringPatterns {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringPatterns {
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            StringPatterns::One(pattern) => std::slice::from_ref(pattern),
+            StringPatterns::Many(patterns) => patterns,
+        }
+    }
+}
+
+impl SigStrings {
+    // patterns with their kinds, in order they are matched
+    pub(crate) fn patterns(&self) -> Vec<(StringKind, &str)> {
+        (self.strings.iter())
+            .flat_map(|(kind, patterns)| {
+                (patterns.patterns().iter()).map(move |pattern| (*kind, pattern.as_str()))
+            })
+            .collect()
+    }
+
+    pub(crate) fn threshold(&self) -> u32 {
+        (self.threshold).unwrap_or(self.patterns().len() as u32)
+    }
+
+    // patterns in form they are listed, e.g. "registry: *\Run"
+    pub(crate) fn features(&self) -> Vec<String> {
+        (self.patterns().into_iter())
+            .map(|(kind, pattern)| format!("{}: {pattern}", kind.name()))
+            .collect()
+    }
+}
+
+/// Matched string signature with strings of file which matched its patterns
+#[derive(Debug)]
+pub(crate) struct StringsMatch {
+    pub sig: SigStrings,
+    pub present: Vec<String>,
+}
+
+/// Signature of code disassembled from entry point and exported functions of PE. At least one of
+/// code and packer has to be given, all of them must match
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigCode {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// Sequences of instructions separated by ";", e.g. "push reg; call mem; test reg,reg".
+    /// Instruction is mnemonic and kinds of its operands: "reg", "imm", "mem" or "rel" (target of
+    /// branch), so registers, values and addresses don't matter. "*" is any instruction. Each
+    /// sequence must be found in code of entry point or of an exported function
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code: Vec<String>,
+    /// Packer whose stub is at entry point
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packer: Option<Packer>,
+}
+
+/// Packer known by its stub
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Packer {
+    Upx,
+    Mpress,
+}
+
+impl Packer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Packer::Upx => "upx",
+            Packer::Mpress => "mpress",
+        }
+    }
+}
+
+impl SigCode {
+    // sequences followed by packer, e.g. "packer: upx"
+    pub(crate) fn features(&self) -> Vec<String> {
+        let mut features = self.code.clone();
+        if let Some(packer) = self.packer {
+            features.push(format!("packer: {}", packer.name()));
+        }
+        features
+    }
+}
+
+/// Matched code signature with places its sequences were found at
+#[derive(Debug)]
+pub(crate) struct CodeMatch {
+    pub sig: SigCode,
+    pub present: Vec<String>,
+}
+
+/// Signature of basic blocks of code of PE. Blocks are hashed without addresses, so they are the
+/// same in variants of malware built or linked in other way
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigBlocks {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// Hashes of basic blocks, 16 hex digits each
+    pub blocks: Vec<String>,
+    /// Fraction (above 0, at most 1) of blocks which must be found. If not given, half of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fraction: Option<f64>,
+}
+
+impl SigBlocks {
+    pub const DEFAULT_FRACTION: f64 = 0.5;
+
+    pub(crate) fn fraction(&self) -> f64 {
+        self.fraction.unwrap_or(Self::DEFAULT_FRACTION)
+    }
+
+    // number of blocks and fraction, hashes are too many to be listed
+    pub(crate) fn features(&self) -> Vec<String> {
+        vec![format!(
+            "{} blocks, fraction {}",
+            self.blocks.len(),
+            self.fraction()
+        )]
+    }
+}
+
+/// Logistic regression over features of PE. File is reported if probability that it is malicious
+/// reaches threshold
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigModel {
+    #[serde(flatten)]
+    pub sig_base: SigBase,
+    /// Probability (above 0, below 1) from which file is reported. If not given, 0.5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    pub bias: f64,
+    /// Weights of features by their names: "import: <library>+<function>", "pe: <anomaly
+    /// feature>", "header: <field>" or "strings: <kind>". Feature missing in file is 0
+    pub weights: BTreeMap<String, f64>,
+    /// Scaling of features by their names, as features of training files were scaled. Features
+    /// without it are weighted as they are
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scaling: BTreeMap<String, FeatureScaling>,
+}
+
+/// Feature is weighted as (value - mean) / deviation, so features of any magnitude are learned
+/// alike
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeatureScaling {
+    pub mean: f64,
+    pub deviation: f64,
+}
+
+impl FeatureScaling {
+    pub(crate) fn scale(&self, value: f64) -> f64 {
+        (value - self.mean) / self.deviation
+    }
+}
+
+impl SigModel {
+    pub const DEFAULT_THRESHOLD: f64 = 0.5;
+
+    pub(crate) fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or(Self::DEFAULT_THRESHOLD)
+    }
+
+    // number of weights and threshold, weights are too many to be listed
+    pub(crate) fn features(&self) -> Vec<String> {
+        vec![format!(
+            "{} weights, threshold {}",
+            self.weights.len(),
+            self.threshold()
+        )]
+    }
+}
+
+/// Matched model signature with probability and features which contributed most to it
+#[derive(Debug)]
+pub(crate) struct ModelMatch {
+    pub sig: SigModel,
+    pub probability: f64,
+    pub top_features: Vec<String>,
+}
+
+/// Matched block signature with number of its blocks which were found
+#[derive(Debug)]
+pub(crate) struct BlocksMatch {
+    pub sig: SigBlocks,
+    pub found: usize,
+}
+
+/// Matched pattern signature with offsets where its patterns start
+#[derive(Debug)]
+pub(crate) struct PatternMatch {
+    pub sig: SigPattern,
+    pub offsets: Vec<u64>,
+}
+
+/// Matched dynamic signature with its features which were found
+#[derive(Debug)]
+pub(crate) struct DynMatch {
+    pub sig: SigDyn,
+    pub present: Vec<String>,
+}
+
+impl From<HeurMatch> for DetectionReport {
+    fn from(heur_match: HeurMatch) -> Self {
+        let cause = if heur_match.missing.is_empty() {
+            format!("Used Imports: {:?}", heur_match.present)
+        } else {
+            format!(
+                "Used Imports: {:?}, Missing Imports: {:?}, Score: {}/{}",
+                heur_match.present, heur_match.missing, heur_match.score, heur_match.threshold
+            )
+        };
+
         Self {
-            desc: sig.sig_base.description,
-            cause: format!("Used Imports: {:?}", sig.imports),
+            name: heur_match.sig.sig_base.name,
+            desc: heur_match.sig.sig_base.description,
+            cause,
+            priority: heur_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<DynMatch> for DetectionReport {
+    fn from(dyn_match: DynMatch) -> Self {
+        Self {
+            name: dyn_match.sig.sig_base.name,
+            desc: dyn_match.sig.sig_base.description,
+            cause: format!("Used Imports: {:?}", dyn_match.present),
+            priority: dyn_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<PatternMatch> for DetectionReport {
+    fn from(pattern_match: PatternMatch) -> Self {
+        let found: Vec<_> = (pattern_match.sig.patterns.iter())
+            .zip(&pattern_match.offsets)
+            .map(|(pattern, offset)| format!("{} at {offset:#x}", pattern.pattern()))
+            .collect();
+        Self {
+            name: pattern_match.sig.sig_base.name,
+            desc: pattern_match.sig.sig_base.description,
+            cause: format!("Found Patterns: {found:?}"),
+            priority: pattern_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<FuzzyMatch> for DetectionReport {
+    fn from(fuzzy_match: FuzzyMatch) -> Self {
+        Self {
+            name: fuzzy_match.sig.sig_base.name,
+            desc: fuzzy_match.sig.sig_base.description,
+            cause: format!(
+                "Similar ssdeep: {:?}, File ssdeep: {:?}, Similarity: {}, Threshold: {}",
+                fuzzy_match.sig.ssdeep,
+                fuzzy_match.ssdeep,
+                fuzzy_match.similarity,
+                fuzzy_match.threshold
+            ),
+            priority: fuzzy_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<AnomalyMatch> for DetectionReport {
+    fn from(anomaly_match: AnomalyMatch) -> Self {
+        let threshold = anomaly_match.sig.threshold();
+        Self {
+            cause: format!(
+                "PE Anomalies: {:?}, Score: {}/{threshold}",
+                anomaly_match.present, anomaly_match.score
+            ),
+            name: anomaly_match.sig.sig_base.name,
+            desc: anomaly_match.sig.sig_base.description,
+            priority: anomaly_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<StringsMatch> for DetectionReport {
+    fn from(strings_match: StringsMatch) -> Self {
+        Self {
+            name: strings_match.sig.sig_base.name,
+            desc: strings_match.sig.sig_base.description,
+            cause: format!("Found Strings: {:?}", strings_match.present),
+            priority: strings_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<CodeMatch> for DetectionReport {
+    fn from(code_match: CodeMatch) -> Self {
+        Self {
+            name: code_match.sig.sig_base.name,
+            desc: code_match.sig.sig_base.description,
+            cause: format!("Found Code: {:?}", code_match.present),
+            priority: code_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<BlocksMatch> for DetectionReport {
+    fn from(blocks_match: BlocksMatch) -> Self {
+        Self {
+            cause: format!(
+                "Found Blocks: {}/{}",
+                blocks_match.found,
+                blocks_match.sig.blocks.len()
+            ),
+            name: blocks_match.sig.sig_base.name,
+            desc: blocks_match.sig.sig_base.description,
+            priority: blocks_match.sig.sig_base.priority,
+        }
+    }
+}
+
+impl From<ModelMatch> for DetectionReport {
+    fn from(model_match: ModelMatch) -> Self {
+        Self {
+            cause: format!(
+                "Probability: {:.3}, Top Features: {:?}",
+                model_match.probability, model_match.top_features
+            ),
+            name: model_match.sig.sig_base.name,
+            desc: model_match.sig.sig_base.description,
+            priority: model_match.sig.sig_base.priority,
         }
     }
 }
 
-impl From<SigDyn> for DetectionReport {
-    fn from(sig: SigDyn) -> Self {
+impl From<SigPeHash> for DetectionReport {
+    fn from(sig: SigPeHash) -> Self {
         Self {
+            cause: format!("Known PE Hashes: {:?}", sig.features()),
+            name: sig.sig_base.name,
             desc: sig.sig_base.description,
-            cause: format!("Used Imports: {:?}", sig.calls),
+            priority: sig.sig_base.priority,
         }
     }
 }
@@ -49,8 +888,10 @@ impl From<SigDyn> for DetectionReport {
 impl From<SigSha256> for DetectionReport {
     fn from(sig: SigSha256) -> Self {
         Self {
+            cause: format!("Known hashes: {:?}", sig.features()),
+            name: sig.sig_base.name,
             desc: sig.sig_base.description,
-            cause: format!("Known sha: {:?}", sig.sha256),
+            priority: sig.sig_base.priority,
         }
     }
 }
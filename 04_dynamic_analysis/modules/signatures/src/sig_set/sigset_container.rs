@@ -0,0 +1,251 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{set_view::SetBuffer, sigset_serializer::SigSetSerializer, write_set_file},
+    SigSetError,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::{io::Write, mem::size_of, ops::Range};
+
+// Container keeps sets of different types (sha, heuristic, dynamic, pattern, fuzzy, pe hash,
+// anomaly, string, code, block, model, behavioural) in one file: ContainerHeader, SectionEntry for
+// each section and data of sections. Each section is complete set, exactly the same as single set file, so it can be read
+// by SigSetDeserializer
+#[derive(Debug, Serialize, Deserialize)]
+struct ContainerHeader {
+    magic: u32,
+    version: u32,
+    section_count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SectionEntry {
+    magic: u32,
+    // offset from the beginning of container
+    offset: u32,
+    size: u32,
+    checksum: Sha256,
+}
+
+pub struct SetContainer {
+    sections: Vec<(u32, Vec<u8>)>,
+}
+
+impl SetContainer {
+    pub const CONTAINER_MAGIC_U32: u32 = 0x54453543; //C5ET
+    pub const VERSION: u32 = 1;
+
+    const HEADER_SIZE: usize = size_of::<ContainerHeader>();
+    const SECTION_ENTRY_SIZE: usize = size_of::<SectionEntry>();
+
+    pub fn new_empty() -> Self {
+        Self { sections: vec![] }
+    }
+
+    pub fn add_section(&mut self, ser: &SigSetSerializer, magic: u32) -> Result<(), SigSetError> {
+        self.add_raw_section(ser.to_bytes(magic)?)
+    }
+
+    // section compiled earlier, e.g. behavioural set compiled by bedet tools. Set magic is taken
+    // from its header
+    pub fn add_raw_section(&mut self, set: Vec<u8>) -> Result<(), SigSetError> {
+        let magic = Self::read_magic(&set)?;
+        if magic == Self::CONTAINER_MAGIC_U32 {
+            return Err(SigSetError::IncorrectMagicError {
+                current: magic_to_string(magic),
+            });
+        }
+        if self.sections.iter().any(|(m, _)| *m == magic) {
+            return Err(SigSetError::DuplicatedSectionError(magic_to_string(magic)));
+        }
+
+        self.sections.push((magic, set));
+        Ok(())
+    }
+
+    pub fn serialize(&self, container_name: &str) -> Result<usize, SigSetError> {
+        write_set_file(container_name, &self.to_bytes()?)?;
+        Ok(self.sections.len())
+    }
+
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>, SigSetError> {
+        let header = ContainerHeader {
+            magic: Self::CONTAINER_MAGIC_U32,
+            version: Self::VERSION,
+            section_count: self.sections.len() as u32,
+        };
+        let mut bytes = bincode::serde::encode_to_vec(&header, bincode::config::legacy())?;
+
+        let mut offset = Self::HEADER_SIZE + self.sections.len() * Self::SECTION_ENTRY_SIZE;
+        for (magic, data) in &self.sections {
+            let entry = SectionEntry {
+                magic: *magic,
+                offset: offset as u32,
+                size: data.len() as u32,
+                checksum: sha2::Sha256::digest(data).into(),
+            };
+            bytes.write_all(&bincode::serde::encode_to_vec(
+                &entry,
+                bincode::config::legacy(),
+            )?)?;
+            offset += data.len();
+        }
+
+        for (_, data) in &self.sections {
+            bytes.write_all(data)?;
+        }
+        Ok(bytes)
+    }
+
+    pub(crate) fn is_container(data: &SetBuffer) -> bool {
+        (data.get(0..size_of::<u32>())).is_ok_and(|magic| {
+            Self::read_magic(magic).is_ok_and(|m| m == Self::CONTAINER_MAGIC_U32)
+        })
+    }
+
+    // returns magic and range of each section in data. Checksums are not verified here, see
+    // verify_sections. Only header and section table are read
+    pub(crate) fn read_sections(data: &SetBuffer) -> Result<Vec<(u32, Range<usize>)>, SigSetError> {
+        Ok(Self::read_entries(data)?
+            .into_iter()
+            .map(|(entry, range)| (entry.magic, range))
+            .collect())
+    }
+
+    fn read_entries(data: &SetBuffer) -> Result<Vec<(SectionEntry, Range<usize>)>, SigSetError> {
+        if data.len() < Self::HEADER_SIZE {
+            return Err(SigSetError::IncorrectFileSizeError {
+                size: data.len() as u64,
+            });
+        }
+        let header: ContainerHeader = bincode::serde::decode_from_slice(
+            data.get(0..Self::HEADER_SIZE)?,
+            bincode::config::legacy(),
+        )?
+        .0;
+        if header.magic != Self::CONTAINER_MAGIC_U32 {
+            return Err(SigSetError::IncorrectMagicError {
+                current: magic_to_string(header.magic),
+            });
+        }
+        if header.version != Self::VERSION {
+            return Err(SigSetError::UnsupportedVersionError(header.version));
+        }
+
+        let table_end =
+            Self::HEADER_SIZE + header.section_count as usize * Self::SECTION_ENTRY_SIZE;
+        if table_end > data.len() {
+            return Err(SigSetError::IncorrectFileSizeError {
+                size: data.len() as u64,
+            });
+        }
+
+        let mut sections = vec![];
+        for i in 0..header.section_count as usize {
+            let entry_offset = Self::HEADER_SIZE + i * Self::SECTION_ENTRY_SIZE;
+            let entry: SectionEntry = bincode::serde::decode_from_slice(
+                data.get(entry_offset..entry_offset + Self::SECTION_ENTRY_SIZE)?,
+                bincode::config::legacy(),
+            )?
+            .0;
+            log::debug!("section: {:?}", entry);
+
+            let start = entry.offset as usize;
+            let end = start + entry.size as usize;
+            if start < table_end || end > data.len() {
+                return Err(SigSetError::IncorrectSignatureSizeError { size: entry.size });
+            }
+
+            sections.push((entry, start..end));
+        }
+        Ok(sections)
+    }
+
+    // checksum of every section. It reads whole container, so it is done before container is
+    // signed and for containers which signature is not verified
+    pub(crate) fn verify_sections(data: &SetBuffer) -> Result<(), SigSetError> {
+        for (entry, range) in Self::read_entries(data)? {
+            let checksum: Sha256 = sha2::Sha256::digest(data.get(range)?).into();
+            if checksum != entry.checksum {
+                return Err(SigSetError::IncorrectChecksumError {
+                    current: hex::encode(checksum),
+                    expected: hex::encode(entry.checksum),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn read_magic(data: &[u8]) -> Result<u32, SigSetError> {
+        let magic = data
+            .get(..size_of::<u32>())
+            .ok_or(SigSetError::IncorrectFileSizeError {
+                size: data.len() as u64,
+            })?;
+        Ok(u32::from_le_bytes(magic.try_into().unwrap()))
+    }
+}
+
+pub(crate) fn magic_to_string(magic: u32) -> String {
+    String::from_utf8_lossy(&magic.to_le_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(bytes: &[u8]) -> SetBuffer {
+        SetBuffer::owned(bytes.to_vec())
+    }
+
+    fn section(magic: u32, content: &[u8]) -> Vec<u8> {
+        let mut section = magic.to_le_bytes().to_vec();
+        section.extend_from_slice(content);
+        section
+    }
+
+    #[test]
+    fn sections_round_trip() {
+        let mut container = SetContainer::new_empty();
+        container.add_raw_section(section(1, b"first")).unwrap();
+        container.add_raw_section(section(2, b"second")).unwrap();
+        assert!(container.add_raw_section(section(2, b"again")).is_err());
+
+        let bytes = container.to_bytes().unwrap();
+        assert!(SetContainer::is_container(&buffer(&bytes)));
+
+        let sections = SetContainer::read_sections(&buffer(&bytes)).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].0, 1);
+        assert_eq!(bytes[sections[0].1.clone()], section(1, b"first"));
+        assert_eq!(sections[1].0, 2);
+        assert_eq!(bytes[sections[1].1.clone()], section(2, b"second"));
+    }
+
+    #[test]
+    fn corrupted_section() {
+        let mut container = SetContainer::new_empty();
+        container.add_raw_section(section(1, b"first")).unwrap();
+        let mut bytes = container.to_bytes().unwrap();
+
+        *bytes.last_mut().unwrap() ^= 0xff;
+        SetContainer::read_sections(&buffer(&bytes)).unwrap();
+        assert!(matches!(
+            SetContainer::verify_sections(&buffer(&bytes)),
+            Err(SigSetError::IncorrectChecksumError { .. })
+        ));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(SetContainer::read_sections(&buffer(&bytes)).is_err());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = SetContainer::new_empty().to_bytes().unwrap();
+        bytes[4] = 2;
+        assert!(matches!(
+            SetContainer::read_sections(&buffer(&bytes)),
+            Err(SigSetError::UnsupportedVersionError(2))
+        ));
+    }
+}
@@ -1,72 +1,161 @@
 use crate::{
-    sha256_utils,
     sha256_utils::Sha256,
     sig_set::{
+        described_set::DescribedSet,
         heuristic_set::HeurSet,
+        import_set_view::{ImportSetView, ImportSig},
+        set_kind::{is_known_magic, set_kind},
+        set_view::{SetBuffer, SetView},
         sha_set::ShaSet,
-        signature::{SigDyn, SigHeur},
-        DynSigHeader, HeurSigHeader, SetHeader, ShaSigHeader, SigHeader, SigSet,
+        signature::SigHeur,
+        sigset_container::{magic_to_string, SetContainer},
+        SetHeader, SigSet,
     },
     DynSet, SigSetError,
 };
 use sha2::Digest;
-use std::{io::Read, mem::size_of};
+use std::{mem::size_of, ops::Range, sync::Arc};
 
 #[derive(Debug)]
 pub(crate) struct SigSetDeserializer {
     ser_set_header: SetHeader,
-    data: Vec<u8>,
+    view: SetView,
 }
 
 impl SigSetDeserializer {
-    const MAX_BUF_LEN: u64 = 0x400000;
-    // 4 MB
     const HEADER_SIZE: usize = size_of::<SetHeader>();
 
-    pub fn new(name: &str) -> Result<Self, SigSetError> {
-        let mut file = std::fs::File::open(name)?;
-        let metadata = file.metadata()?;
+    // sets are loaded for scanning only from file signed with trusted key (see set_signing).
+    // File is mapped and its chunks are verified as they are read, so loading doesn't depend on
+    // set size. Returns whether signature was verified, unsigned set can be loaded only when it
+    // is allowed
+    fn map_trusted_file(name: &str) -> Result<(Arc<SetBuffer>, bool), SigSetError> {
+        let (buffer, signed) = SetBuffer::map_signed_file(name)?;
+        Ok((Arc::new(buffer), signed))
+    }
 
-        if metadata.len() > Self::MAX_BUF_LEN {
-            return Err(SigSetError::IncorrectFileSizeError {
-                size: metadata.len(),
-            });
+    pub fn new(name: &str) -> Result<Self, SigSetError> {
+        let (buffer, signed) = Self::map_trusted_file(name)?;
+        let len = buffer.len();
+        let set = Self::new_in_buffer(buffer, 0..len)?;
+        if !signed {
+            set.verify()?;
         }
+        Ok(set)
+    }
 
-        let mut buffer = vec![0; metadata.len() as usize];
-        let _ = file.read(&mut buffer)?;
+    // file is either single set or container with set in each section. Sections of sets not
+    // known here (e.g. behavioural) are skipped
+    pub fn new_sections(name: &str) -> Result<Vec<Self>, SigSetError> {
+        let (buffer, signed) = Self::map_trusted_file(name)?;
+        Self::sections_in_buffer(buffer, !signed)
+    }
 
-        Self::new_with_buffer(buffer)
+    // set with given magic from single set file or from container
+    pub fn new_section(name: &str, magic: u32) -> Result<Self, SigSetError> {
+        Self::find_section(Self::new_sections(name)?, magic)
     }
 
-    fn new_with_buffer(mut data: Vec<u8>) -> Result<Self, SigSetError> {
-        if data.len() < Self::HEADER_SIZE {
-            return Err(SigSetError::IncorrectFileSizeError {
-                size: data.len() as u64,
-            });
+    // sets of file which signature is not verified
+    #[cfg(test)]
+    pub(crate) fn new_untrusted_sections(name: &str) -> Result<Vec<Self>, SigSetError> {
+        Self::sections_in_buffer(Arc::new(SetBuffer::map_file(name)?), true)
+    }
+
+    // checksums and order of signatures of every set of file. Signature of file covers all its
+    // bytes, so this is done once, when file is signed, not every time it is loaded
+    pub(crate) fn verify_file(data: Arc<SetBuffer>) -> Result<(), SigSetError> {
+        Self::sections_in_buffer(data, true).map(|_| ())
+    }
+
+    fn sections_in_buffer(buffer: Arc<SetBuffer>, verify: bool) -> Result<Vec<Self>, SigSetError> {
+        if !SetContainer::is_container(&buffer) {
+            let len = buffer.len();
+            let set = Self::new_in_buffer(buffer, 0..len)?;
+            if verify {
+                set.verify()?;
+            }
+            return Ok(vec![set]);
         }
-        let set_header: SetHeader = bincode::serde::decode_from_slice(
-            &data[..Self::HEADER_SIZE],
-            bincode::config::legacy(),
-        )?
-        .0;
+
+        if verify {
+            SetContainer::verify_sections(&buffer)?;
+        }
+        let mut sets = vec![];
+        for (magic, section) in SetContainer::read_sections(&buffer)? {
+            if !is_known_magic(magic) {
+                log::debug!("skipped section: {}", magic_to_string(magic));
+                continue;
+            }
+
+            let set = Self::new_in_buffer(buffer.clone(), section)?;
+            if set.magic() != magic {
+                return Err(SigSetError::IncorrectMagicError {
+                    current: magic_to_string(set.magic()),
+                });
+            }
+            if verify {
+                set.verify()?;
+            }
+            sets.push(set);
+        }
+        Ok(sets)
+    }
+
+    pub(crate) fn find_section(sets: Vec<Self>, magic: u32) -> Result<Self, SigSetError> {
+        sets.into_iter()
+            .find(|set| set.magic() == magic)
+            .ok_or(SigSetError::NoSuchSectionError(magic_to_string(magic)))
+    }
+
+    pub fn magic(&self) -> u32 {
+        self.ser_set_header.magic
+    }
+
+    pub(crate) fn new_with_buffer(data: Vec<u8>) -> Result<Self, SigSetError> {
+        let len = data.len();
+        let set = Self::new_in_buffer(Arc::new(SetBuffer::owned(data)), 0..len)?;
+        set.verify()?;
+        Ok(set)
+    }
+
+    // set is in given range of buffer. Nothing is copied, set data is read in place. Nothing is
+    // verified either, see verify
+    pub(crate) fn new_in_buffer(
+        buffer: Arc<SetBuffer>,
+        range: Range<usize>,
+    ) -> Result<Self, SigSetError> {
+        let header_end = range.end.min(range.start + Self::HEADER_SIZE);
+        let set_header = SetHeader::read(buffer.get(range.start..header_end)?)?;
 
         set_header.verify_magic()?;
-        data.drain(..Self::HEADER_SIZE);
+        let view = SetView::new(
+            buffer,
+            range.start + Self::HEADER_SIZE..range.end,
+            set_header.elem_count,
+        )?;
 
-        let reader = Self {
+        Ok(Self {
             ser_set_header: set_header,
-            data,
-        };
-        reader.verify_checksum()?;
+            view,
+        })
+    }
 
-        Ok(reader)
+    // walks whole set, so it is done for sets which signature is not verified and before set is
+    // signed
+    pub(crate) fn verify(&self) -> Result<(), SigSetError> {
+        self.verify_checksum()?;
+        match self.magic() {
+            ShaSet::SET_MAGIC_U32 => self.view.verify_sorted(),
+            // other sets are keyed by u32 id of signature
+            _ => self.view.verify_sorted_u32(),
+        }
     }
 
     fn verify_checksum(&self) -> Result<(), SigSetError> {
         let mut hasher = sha2::Sha256::new();
-        hasher.update(&self.ser_set_header.elem_count.to_le_bytes());
-        hasher.update(&self.data);
+        hasher.update(self.ser_set_header.elem_count.to_le_bytes());
+        hasher.update(self.view.data()?);
         let mut checksum_buf = Sha256::default();
         checksum_buf.copy_from_slice(&hasher.finalize()[..]);
         if self.ser_set_header.checksum != checksum_buf {
@@ -79,194 +168,247 @@ impl SigSetDeserializer {
     }
 
     pub fn get_set(&self) -> Result<Box<dyn SigSet>, SigSetError> {
-        match self.ser_set_header.magic {
-            HeurSet::SET_MAGIC_U32 => Ok(Box::new(self.get_heur_set()?)),
-            ShaSet::SET_MAGIC_U32 => Ok(Box::new(self.get_sha_set()?)),
-            _ => Err(SigSetError::IncorrectMagicError {
-                current: String::from_utf8_lossy(&self.ser_set_header.magic.to_le_bytes()).into(),
-            }),
-        }
+        (set_kind(self.magic())?.load)(self)
     }
 
-    fn get_heur_set(&self) -> Result<HeurSet, SigSetError> {
-        let elem_count = self.ser_set_header.elem_count as usize;
-        let signature_header_size = size_of::<SigHeader>();
-        let start_of_data = elem_count * signature_header_size;
+    // heuristic set is searched in place with index stored after its signatures. Set compiled
+    // before index was stored is rebuilt from its signatures
+    pub(crate) fn get_heur_set(&self) -> Result<HeurSet, SigSetError> {
+        if let Some(view) = ImportSetView::new(self.view.clone())? {
+            return Ok(HeurSet::from_view(view));
+        }
 
         let mut heurset = HeurSet::new_empty();
-        for i in 0..elem_count {
-            let curr_header_offset = i * signature_header_size;
-
-            let sig_header: SigHeader = bincode::serde::decode_from_slice(
-                &self.data[curr_header_offset..],
-                bincode::config::legacy(),
-            )?
-            .0;
-            let sig_header: HeurSigHeader = sig_header.into();
-
-            log::debug!("sig_header: {:?}", sig_header);
-
-            if sig_header.size > Self::MAX_BUF_LEN as u32 {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
-
-            let start_offset = sig_header.offset as usize + start_of_data;
-            let end_offset = start_offset + sig_header.size as usize;
-            if end_offset > self.data.len() {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
-
-            let mut curr_offset = start_offset;
-            let imports_count: u32 = bincode::serde::decode_from_slice(
-                &self.data[start_offset..],
-                bincode::config::standard(),
-            )?
-            .0;
-            log::debug!("imports_count: {:?}", imports_count);
-
-            curr_offset += size_of::<u32>();
-
-            let mut imports_vec = vec![];
-            //let signature_data = self.data[curr_offset..];
-            for _i in 0..imports_count {
-                let import: Sha256 = bincode::serde::decode_from_slice(
-                    &self.data[curr_offset..],
-                    bincode::config::legacy(),
-                )?
-                .0;
-                imports_vec.push(import);
-                curr_offset += size_of::<Sha256>();
-            }
-            //log::debug!("imports: {:?}", imports_vec);
-
-            let sig_heur: SigHeur = serde_yaml::from_slice(&self.data[curr_offset..end_offset])?;
+        for sig in ImportSig::read_all(&self.view)? {
+            let sig_heur: SigHeur = serde_yaml::from_str(&sig.description)?;
             log::info!("Properties: {:?}", sig_heur);
-
-            let imports = sig_heur
-                .imports
-                .iter()
-                .map(|s| sha256_utils::sha256_from_vec(s.as_bytes().to_vec()).unwrap())
-                .collect();
-
-            let description = String::from_utf8_lossy(&self.data[curr_offset..end_offset]);
-            heurset.append_signature(imports, sig_header.id, description.into());
+            heurset.append_signature(
+                sig.imports,
+                &sig_heur,
+                sig.id,
+                sig.description,
+                sig.condition,
+            );
         }
 
         Ok(heurset)
     }
 
-    //you duplicate the same code. TODO: Create template
+    // the same as heuristic set
     pub fn get_dyn_set(&self) -> Result<DynSet, SigSetError> {
-        let elem_count = self.ser_set_header.elem_count as usize;
-        let signature_header_size = size_of::<SigHeader>();
-        let start_of_data = elem_count * signature_header_size;
+        if let Some(view) = ImportSetView::new(self.view.clone())? {
+            return Ok(DynSet::from_view(view));
+        }
 
         let mut dynset = DynSet::new_empty();
-        for i in 0..elem_count {
-            let curr_header_offset = i * signature_header_size;
-
-            let sig_header: SigHeader = bincode::serde::decode_from_slice(
-                &self.data[curr_header_offset..],
-                bincode::config::legacy(),
-            )?
-            .0;
-            let sig_header: DynSigHeader = sig_header.into();
-
-            log::debug!("sig_header: {:?}", sig_header);
-
-            if sig_header.size > Self::MAX_BUF_LEN as u32 {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
-
-            let start_offset = sig_header.offset as usize + start_of_data;
-            let end_offset = start_offset + sig_header.size as usize;
-            if end_offset > self.data.len() {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
-
-            let mut curr_offset = start_offset;
-            let imports_count: u32 = bincode::serde::decode_from_slice(
-                &self.data[start_offset..],
-                bincode::config::standard(),
-            )?
-            .0;
-            log::debug!("imports_count: {:?}", imports_count);
-
-            curr_offset += size_of::<u32>();
-
-            let mut imports_vec = vec![];
-            //let signature_data = self.data[curr_offset..];
-            for _i in 0..imports_count {
-                let import: Sha256 = bincode::serde::decode_from_slice(
-                    &self.data[curr_offset..],
-                    bincode::config::legacy(),
-                )?
-                .0;
-                imports_vec.push(import);
-                curr_offset += size_of::<Sha256>();
-            }
-            //log::debug!("imports: {:?}", imports_vec);
-
-            let sig_dyn: SigDyn = serde_yaml::from_slice(&self.data[curr_offset..end_offset])?;
-            log::info!("Properties: {:?}", sig_dyn);
-
-            let imports = sig_dyn
-                .calls
-                .iter()
-                .map(|s| sha256_utils::sha256_from_vec(s.as_bytes().to_vec()).unwrap())
-                .collect();
-
-            let description = String::from_utf8_lossy(&self.data[curr_offset..end_offset]);
-            dynset.append_signature(imports, sig_header.id, description.into());
+        for sig in ImportSig::read_all(&self.view)? {
+            dynset.append_signature(sig.imports, sig.id, sig.description, sig.condition);
         }
 
         Ok(dynset)
     }
 
+    // signatures of other sets are serialized as yaml descriptions, they are compiled when set is
+    // loaded
+    pub(crate) fn get_described_set<T: DescribedSet>(&self) -> Result<T, SigSetError> {
+        T::from_view(&self.view)
+    }
+
+    // sha set is searched in place, descriptions are read only for matched signatures
     pub(crate) fn get_sha_set(&self) -> Result<ShaSet, SigSetError> {
-        let elem_count = self.ser_set_header.elem_count as usize;
-        let signature_header_size = size_of::<SigHeader>();
-        let start_of_data = elem_count * signature_header_size;
-
-        let mut sha_set = ShaSet::new_empty();
-        for i in 0..elem_count {
-            let curr_header_offset = i * signature_header_size;
-
-            let sig_header: SigHeader = bincode::serde::decode_from_slice(
-                &self.data[curr_header_offset..],
-                bincode::config::legacy(),
-            )?
-            .0;
-            let sig_header: ShaSigHeader = sig_header.into();
-
-            if sig_header.size > Self::MAX_BUF_LEN as u32 {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
+        ShaSet::from_view(self.view.clone())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sha256_utils::sha256_from_vec,
+        sig_set::{
+            import_set_view::INDEX_SIG_ID, set_signing, sigset_serializer::SigSetSerializer,
+        },
+    };
+
+    fn dyn_set() -> SigSetSerializer {
+        let desc = "name: Sleeper\ndescription: sleeper\ncalls: [Sleep]\n";
+        let mut dynset = DynSet::new_empty();
+        let calls = vec![sha256_from_vec(b"Sleep".to_vec()).unwrap()];
+        dynset.append_signature(calls, 0, desc.to_string(), None);
+        dynset.to_sig_set().unwrap()
+    }
 
-            let start_offset = sig_header.offset as usize + start_of_data;
-            let end_offset = start_offset + sig_header.size as usize;
-            if end_offset > self.data.len() {
-                return Err(SigSetError::IncorrectSignatureSizeError {
-                    size: sig_header.size,
-                });
-            }
+    fn sha_set() -> SigSetSerializer {
+        let mut shaset = ShaSet::new_empty();
+        shaset.append_signature(
+            [7; 32],
+            "name: Known\ndescription: known\nsha256: 07\n".to_string(),
+        );
+        shaset.to_sig_set().unwrap()
+    }
 
-            let signature_data = self.data[start_offset..end_offset].to_vec();
-            let description = String::from_utf8_lossy(&signature_data);
+    #[test]
+    fn open_single_set_and_container() {
+        let dir = std::env::temp_dir().join(format!("sfi_sections_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let single = dir.join("single.sset");
+        let container = dir.join("container.sset");
+
+        dyn_set()
+            .serialize(single.to_str().unwrap(), DynSet::SET_MAGIC_U32)
+            .unwrap();
+        let sets = SigSetDeserializer::new_untrusted_sections(single.to_str().unwrap()).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].magic(), DynSet::SET_MAGIC_U32);
+
+        let mut sections = SetContainer::new_empty();
+        sections
+            .add_section(&sha_set(), ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        sections
+            .add_section(&dyn_set(), DynSet::SET_MAGIC_U32)
+            .unwrap();
+        // section of set unknown here is skipped
+        sections
+            .add_raw_section(0x54453542u32.to_le_bytes().to_vec())
+            .unwrap();
+        sections.serialize(container.to_str().unwrap()).unwrap();
+
+        let container = container.to_str().unwrap();
+        assert!(matches!(
+            SigSetDeserializer::new_sections(container),
+            Err(SigSetError::UnsignedSetError(_))
+        ));
+        let sets = SigSetDeserializer::new_untrusted_sections(container).unwrap();
+        let magics: Vec<_> = sets.iter().map(|s| s.magic()).collect();
+        assert_eq!(magics, [ShaSet::SET_MAGIC_U32, DynSet::SET_MAGIC_U32]);
+
+        let sections = || SigSetDeserializer::new_untrusted_sections(container).unwrap();
+        let dynset = SigSetDeserializer::find_section(sections(), DynSet::SET_MAGIC_U32)
+            .unwrap()
+            .get_dyn_set()
+            .unwrap();
+        assert_eq!(
+            dynset.eval_api_calls(vec!["Sleep".into()]).unwrap().len(),
+            1
+        );
+        assert!(matches!(
+            SigSetDeserializer::find_section(sections(), HeurSet::SET_MAGIC_U32),
+            Err(SigSetError::NoSuchSectionError(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn set_in_use_is_replaced_not_truncated() {
+        let path = std::env::temp_dir().join(format!("sfi_in_use_{}.sset", std::process::id()));
+        let path = path.to_str().unwrap();
+        sha_set().serialize(path, ShaSet::SET_MAGIC_U32).unwrap();
+        let sets = SigSetDeserializer::new_untrusted_sections(path).unwrap();
+        let in_use = SigSetDeserializer::find_section(sets, ShaSet::SET_MAGIC_U32).unwrap();
+
+        ShaSet::new_empty()
+            .to_sig_set()
+            .unwrap()
+            .serialize(path, ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        assert_eq!(
+            in_use.get_sha_set().unwrap().descriptions().unwrap().len(),
+            1
+        );
+        let sets = SigSetDeserializer::new_untrusted_sections(path).unwrap();
+        assert_eq!(
+            sets[0].get_sha_set().unwrap().descriptions().unwrap().len(),
+            0
+        );
+
+        drop(in_use);
+        std::fs::remove_file(path).unwrap();
+    }
 
-            sha_set.append_signature(sig_header.id, description.into());
+    #[test]
+    fn set_compiled_without_index_is_rebuilt() {
+        let desc = "name: Sleeper\ndescription: sleeper\ncondition:\n  all_of: [Sleep, Beep]\n";
+        let dynset = DynSet::from_descriptions(vec![desc.to_string()]).unwrap();
+        let indexed = dynset
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(DynSet::SET_MAGIC_U32)
+            .unwrap();
+        let view = SigSetDeserializer::new_with_buffer(indexed).unwrap().view;
+
+        let mut ser = SigSetSerializer::new_empty();
+        for record in view.signatures() {
+            let (header, data) = record.unwrap();
+            if header.id != INDEX_SIG_ID {
+                ser.serialize_signature(header.id, data.to_vec());
+            }
         }
+        let bytes = ser.to_bytes(DynSet::SET_MAGIC_U32).unwrap();
+        let des = SigSetDeserializer::new_with_buffer(bytes).unwrap();
+        assert!(ImportSetView::new(des.view.clone()).unwrap().is_none());
+
+        let dynset = des.get_dyn_set().unwrap();
+        let calls = |calls: &[&str]| calls.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            dynset
+                .eval_api_calls(calls(&["Beep", "Sleep"]))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(dynset.eval_api_calls(calls(&["Sleep"])).unwrap().is_empty());
+    }
 
-        Ok(sha_set)
+    #[test]
+    fn signed_set_is_verified_as_it_is_read() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("sfi_chunks_{}.sset", std::process::id()));
+        let key_path = dir.join(format!("sfi_chunks_{}.key", std::process::id()));
+        let (path, key_path) = (path.to_str().unwrap(), key_path.to_str().unwrap());
+        // a few chunks of signed file
+        let mut shaset = ShaSet::new_empty();
+        for i in 0..200u8 {
+            let padding = "x".repeat(1000);
+            let desc = format!("name: sample{i}\ndescription: {padding}\nsha256: 00\n");
+            shaset.append_signature([i; 32], desc);
+        }
+        (shaset.to_sig_set().unwrap())
+            .serialize(path, ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        std::fs::write(
+            key_path,
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        )
+        .unwrap();
+        let mut keys = set_signing::TrustedKeys::empty();
+        keys.add_key(&set_signing::sign_set_file(path, key_path).unwrap())
+            .unwrap();
+        set_signing::set_trusted_keys(keys);
+
+        // changed after it was signed, length is the same
+        let mut bytes = std::fs::read(path).unwrap();
+        let changed = (bytes.windows(10))
+            .position(|name| name == b"sample100\n")
+            .unwrap();
+        bytes[changed] = b'S';
+        std::fs::write(path, bytes).unwrap();
+
+        let loaded = SigSetDeserializer::new_section(path, ShaSet::SET_MAGIC_U32).unwrap();
+        loaded.get_sha_set().unwrap();
+        let verified = loaded.view.buffer().verified_chunks();
+        assert!(verified < 4);
+
+        let found = loaded.view.find(&[1; 32]).unwrap().unwrap();
+        assert!(found.starts_with(b"name: sample1\n"));
+        assert!(matches!(
+            loaded.view.find(&[100; 32]),
+            Err(SigSetError::IncorrectChecksumError { .. })
+        ));
+
+        drop(loaded);
+        for path in [path.to_string(), format!("{path}.sig"), key_path.into()] {
+            std::fs::remove_file(path).unwrap();
+        }
     }
 }
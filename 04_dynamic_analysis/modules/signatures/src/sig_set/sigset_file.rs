@@ -0,0 +1,510 @@
+use crate::{
+    sig_set::{
+        set_kind::{is_known_magic, set_kind, SET_KINDS},
+        set_view::SetBuffer,
+        sha_set::ShaSet,
+        sig_source::SigSource,
+        signature::{SigBase, SigSha256},
+        sigset_container::{magic_to_string, SetContainer},
+        sigset_deserializer::SigSetDeserializer,
+        sigset_info::read_descriptions,
+        sigset_serializer::SigSetSerializer,
+        Description,
+    },
+    SigSetError,
+};
+
+// Set of given type kept as signature descriptions, so signatures can be added and removed.
+// Section is compiled again when file is saved, so header checksum is always valid and ids of
+// heuristic and dynamic signatures are renumbered from 0
+enum Section {
+    Sigs {
+        magic: u32,
+        descriptions: Vec<Description>,
+    },
+    // section of set which can't be edited here (e.g. behavioural), kept as it is
+    Raw {
+        magic: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl Section {
+    fn magic(&self) -> u32 {
+        match self {
+            Section::Sigs { magic, .. } | Section::Raw { magic, .. } => *magic,
+        }
+    }
+}
+
+/// Compiled set or container opened for editing
+pub struct SigSetFile {
+    sections: Vec<Section>,
+    is_container: bool,
+}
+
+impl SigSetFile {
+    pub fn open(path: &str) -> Result<Self, SigSetError> {
+        let buffer = SetBuffer::map_file(path)?;
+        if !SetContainer::is_container(&buffer) {
+            return Ok(Self {
+                sections: vec![Self::read_section(buffer.get(0..buffer.len())?)?],
+                is_container: false,
+            });
+        }
+
+        SetContainer::verify_sections(&buffer)?;
+        let mut sections = vec![];
+        for (magic, range) in SetContainer::read_sections(&buffer)? {
+            let data = buffer.get(range)?;
+            if is_known_magic(magic) {
+                sections.push(Self::read_section(data)?);
+            } else {
+                sections.push(Section::Raw {
+                    magic,
+                    data: data.to_vec(),
+                });
+            }
+        }
+        Ok(Self {
+            sections,
+            is_container: true,
+        })
+    }
+
+    fn read_section(data: &[u8]) -> Result<Section, SigSetError> {
+        let des = SigSetDeserializer::new_with_buffer(data.to_vec())?;
+        Ok(Section::Sigs {
+            magic: des.magic(),
+            descriptions: read_descriptions(&des)?,
+        })
+    }
+
+    // signature goes to set of its type
+    pub fn add_signature(&mut self, description: Description) -> Result<(), SigSetError> {
+        let magic = signature_set_magic(&description)?;
+        let name = signature_name(&description)?;
+        let descriptions = self.descriptions_mut(magic)?;
+        if magic != ShaSet::SET_MAGIC_U32 && Self::contains_name(descriptions, &name)? {
+            return Err(SigSetError::DuplicatedSignatureError(name));
+        }
+        descriptions.push(description);
+        Ok(())
+    }
+
+    // returns number of removed signatures
+    pub fn remove_by_name(&mut self, name: &str) -> Result<usize, SigSetError> {
+        self.remove_signatures(|_, description| Ok(signature_name(description)? == name))
+    }
+
+    // removes hash signatures which declare given md5, sha1, sha256 or sha512. Returns number of
+    // removed signatures
+    pub fn remove_by_hash(&mut self, hash: &str) -> Result<usize, SigSetError> {
+        let hash = hex::decode(hash)?;
+        self.remove_signatures(|magic, description| {
+            if magic != ShaSet::SET_MAGIC_U32 {
+                return Ok(false);
+            }
+            let sig: SigSha256 = serde_yaml::from_str(description)?;
+            Ok(sig.digests()?.iter().any(|(_, digest)| *digest == hash))
+        })
+    }
+
+    fn remove_signatures(
+        &mut self,
+        should_remove: impl Fn(u32, &str) -> Result<bool, SigSetError>,
+    ) -> Result<usize, SigSetError> {
+        let mut removed = 0;
+        for section in self.sections.iter_mut() {
+            let Section::Sigs {
+                magic,
+                descriptions,
+            } = section
+            else {
+                continue;
+            };
+
+            let mut kept = vec![];
+            for description in descriptions.drain(..) {
+                if should_remove(*magic, &description)? {
+                    removed += 1;
+                } else {
+                    kept.push(description);
+                }
+            }
+            *descriptions = kept;
+        }
+        Ok(removed)
+    }
+
+    // signatures of other file are added after signatures of this one
+    pub fn merge(&mut self, other: SigSetFile) -> Result<(), SigSetError> {
+        self.is_container |= other.is_container;
+        for section in other.sections {
+            let magic = section.magic();
+            let existing = self.sections.iter_mut().find(|s| s.magic() == magic);
+            match (existing, section) {
+                (None, section) => {
+                    self.sections.push(section);
+                    self.is_container = true;
+                },
+                (
+                    Some(Section::Sigs { descriptions, .. }),
+                    Section::Sigs {
+                        descriptions: other_descriptions,
+                        ..
+                    },
+                ) => {
+                    for description in other_descriptions {
+                        let name = signature_name(&description)?;
+                        if magic != ShaSet::SET_MAGIC_U32
+                            && Self::contains_name(descriptions, &name)?
+                        {
+                            return Err(SigSetError::DuplicatedSignatureError(name));
+                        }
+                        descriptions.push(description);
+                    }
+                },
+                _ => return Err(SigSetError::DuplicatedSectionError(magic_to_string(magic))),
+            }
+        }
+        Ok(())
+    }
+
+    // returns number of signatures in file. Serializers replace file as a whole (see
+    // write_set_file), so set which is mapped by scanner is never modified in place
+    pub fn save(&self, path: &str) -> Result<usize, SigSetError> {
+        let mut count = 0;
+        let mut container = SetContainer::new_empty();
+        for section in &self.sections {
+            match section {
+                Section::Sigs {
+                    magic,
+                    descriptions,
+                } => {
+                    count += descriptions.len();
+                    let ser = Self::compile(*magic, descriptions.clone())?;
+                    if !self.is_container {
+                        ser.serialize(path, *magic)?;
+                        return Ok(count);
+                    }
+                    container.add_section(&ser, *magic)?;
+                },
+                Section::Raw { data, .. } => container.add_raw_section(data.clone())?,
+            }
+        }
+
+        container.serialize(path)?;
+        Ok(count)
+    }
+
+    // returns number of unpacked signatures. Signatures of single set are written to out_dir,
+    // signatures of container to its subdirectories (like the ones container is compiled from).
+    // Sections which can't be unpacked are written as they are. Signature files are named so
+    // that set compiled from them has the same ids
+    pub fn unpack_to_dir(&self, out_dir: &str) -> Result<usize, SigSetError> {
+        let out_dir = std::path::Path::new(out_dir);
+        let mut count = 0;
+        for section in &self.sections {
+            match section {
+                Section::Sigs {
+                    magic,
+                    descriptions,
+                } => {
+                    let dir = match self.is_container {
+                        true => out_dir.join(section_dir_name(*magic)?),
+                        false => out_dir.to_path_buf(),
+                    };
+                    std::fs::create_dir_all(&dir)?;
+                    for (i, description) in descriptions.iter().enumerate() {
+                        let file_name = match *magic {
+                            ShaSet::SET_MAGIC_U32 => {
+                                let sig: SigSha256 = serde_yaml::from_str(description)?;
+                                hex::encode_upper(&sig.digests()?[0].1)
+                            },
+                            _ => format!("{i:06}_{}.sig", file_name(&signature_name(description)?)),
+                        };
+                        std::fs::write(dir.join(file_name), description)?;
+                    }
+                    count += descriptions.len();
+                },
+                Section::Raw { magic, data } => {
+                    std::fs::create_dir_all(out_dir)?;
+                    std::fs::write(
+                        out_dir.join(format!("{}.set", magic_to_string(*magic))),
+                        data,
+                    )?;
+                },
+            }
+        }
+        Ok(count)
+    }
+
+    pub(crate) fn compile(
+        magic: u32,
+        descriptions: Vec<Description>,
+    ) -> Result<SigSetSerializer, SigSetError> {
+        (set_kind(magic)?.compile)(descriptions)
+    }
+
+    /// Set of given type compiled from signatures of source
+    pub fn compile_source(magic: u32, source: &SigSource) -> Result<SigSetSerializer, SigSetError> {
+        (set_kind(magic)?.compile_source)(source)
+    }
+
+    fn descriptions_mut(&mut self, magic: u32) -> Result<&mut Vec<Description>, SigSetError> {
+        self.sections
+            .iter_mut()
+            .find_map(|section| match section {
+                Section::Sigs {
+                    magic: section_magic,
+                    descriptions,
+                } if *section_magic == magic => Some(descriptions),
+                _ => None,
+            })
+            .ok_or(SigSetError::NoSuchSectionError(magic_to_string(magic)))
+    }
+
+    fn contains_name(descriptions: &[Description], name: &str) -> Result<bool, SigSetError> {
+        for description in descriptions {
+            if signature_name(description)? == name {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+// magic of set signature belongs to, by properties only signatures of one type have (see
+// SET_KINDS). Condition alone is accepted by both heuristic and dynamic signatures, so signature
+// with only condition must state its type with empty "imports" or "calls"
+pub(crate) fn signature_set_magic(description: &str) -> Result<u32, SigSetError> {
+    let properties: serde_yaml::Mapping = serde_yaml::from_str(description)?;
+    let kind =
+        (SET_KINDS.iter()).find(|kind| kind.keys.iter().any(|key| properties.contains_key(*key)));
+    match kind {
+        Some(kind) => Ok(kind.magic),
+        None if properties.contains_key("condition") => Err(SigSetError::IncorrectSignatureError {
+            info: "Signature with only condition needs \"imports: []\" or \"calls: []\"".into(),
+        }),
+        None => Err(SigSetError::IncorrectSignatureError {
+            info: "Unknown type of signature".into(),
+        }),
+    }
+}
+
+/// Subdirectory of unpacked container with signatures of set, the same as the one container is
+/// compiled from
+pub fn section_dir_name(magic: u32) -> Result<&'static str, SigSetError> {
+    Ok(set_kind(magic)?.dir_name)
+}
+
+// signature name with characters which can't be used in file name replaced
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect()
+}
+
+pub(crate) fn signature_name(description: &str) -> Result<String, SigSetError> {
+    let sig_base: SigBase = serde_yaml::from_str(description)?;
+    Ok(sig_base.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sha256_utils::{sha256_from_vec, Sha256},
+        sig_set::{heuristic_set::HeurSet, sig_source::SigSource, SigSet},
+        DynSet,
+    };
+
+    fn heur_sig(name: &str, import: &str) -> Description {
+        format!("name: {name}\ndescription: {name}\nimports: [{import}]\n")
+    }
+
+    fn sha_sig(i: u32) -> (Sha256, Description) {
+        let sha = sha256_from_vec(i.to_le_bytes().to_vec()).unwrap();
+        let desc = format!(
+            "name: sample{i}\nsha256: {}\ndescription: sample\n",
+            hex::encode_upper(sha)
+        );
+        (sha, desc)
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("sfi_{name}_{}.sset", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn add_and_remove_heuristic_signatures() {
+        let path = temp_path("edit_heur");
+        let descriptions = vec![heur_sig("first", "a.dll+a"), heur_sig("second", "b.dll+b")];
+        HeurSet::from_descriptions(descriptions)
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .serialize(&path, HeurSet::SET_MAGIC_U32)
+            .unwrap();
+
+        let mut file = SigSetFile::open(&path).unwrap();
+        assert!(file.add_signature(heur_sig("second", "c.dll+c")).is_err());
+        file.add_signature(heur_sig("third", "c.dll+c")).unwrap();
+        assert_eq!(file.remove_by_name("first").unwrap(), 1);
+        assert_eq!(file.remove_by_name("first").unwrap(), 0);
+        assert_eq!(file.save(&path).unwrap(), 2);
+
+        // file is valid set with renumbered ids
+        let heurset = SigSetDeserializer::new_with_buffer(std::fs::read(&path).unwrap())
+            .unwrap()
+            .get_heur_set()
+            .unwrap();
+        let names: Vec<_> = heurset
+            .descriptions()
+            .unwrap()
+            .iter()
+            .map(|d| signature_name(d).unwrap())
+            .collect();
+        assert_eq!(names, ["second", "third"]);
+        assert!(file.add_signature(sha_sig(0).1).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn remove_sha_signature() {
+        let path = temp_path("edit_sha");
+        let descriptions = (0..3).map(|i| sha_sig(i).1).collect();
+        ShaSet::from_descriptions(descriptions)
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .serialize(&path, ShaSet::SET_MAGIC_U32)
+            .unwrap();
+
+        let mut file = SigSetFile::open(&path).unwrap();
+        assert_eq!(file.remove_by_hash(&hex::encode(sha_sig(1).0)).unwrap(), 1);
+        file.add_signature(sha_sig(7).1).unwrap();
+        file.save(&path).unwrap();
+
+        let sets = SigSetDeserializer::new_untrusted_sections(&path).unwrap();
+        let descriptions = SigSetDeserializer::find_section(sets, ShaSet::SET_MAGIC_U32)
+            .unwrap()
+            .get_sha_set()
+            .unwrap()
+            .descriptions()
+            .unwrap();
+        assert_eq!(descriptions.len(), 3);
+        assert!(!descriptions.contains(&sha_sig(1).1));
+        assert!(descriptions.contains(&sha_sig(7).1));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unpacked_container_compiles_to_the_same_sets() {
+        let (path, out_dir) = (temp_path("unpack"), temp_path("unpack_dir"));
+        let heur = vec![
+            heur_sig("first sig", "a.dll+a"),
+            heur_sig("second", "b.dll+b"),
+        ];
+        let dyns = vec!["name: dyn\ndescription: dyn\ncalls: [Sleep]\n".to_string()];
+        let mut container = SetContainer::new_empty();
+        for (magic, descriptions) in [
+            (
+                ShaSet::SET_MAGIC_U32,
+                (0..3).map(|i| sha_sig(i).1).collect(),
+            ),
+            (HeurSet::SET_MAGIC_U32, heur),
+            (DynSet::SET_MAGIC_U32, dyns),
+        ] {
+            let ser = SigSetFile::compile(magic, descriptions).unwrap();
+            container.add_section(&ser, magic).unwrap();
+        }
+        container.serialize(&path).unwrap();
+
+        let count = SigSetFile::open(&path)
+            .unwrap()
+            .unpack_to_dir(&out_dir)
+            .unwrap();
+        assert_eq!(count, 6);
+
+        let sets = SigSetDeserializer::new_untrusted_sections(&path).unwrap();
+        for des in sets {
+            let dir = std::path::Path::new(&out_dir).join(section_dir_name(des.magic()).unwrap());
+            let descriptions = read_descriptions(&des).unwrap();
+            let unpacked: Vec<_> = SigSource::new(dir.to_str().unwrap())
+                .read()
+                .unwrap()
+                .into_iter()
+                .map(|sig| sig.description)
+                .collect();
+            assert_eq!(descriptions, unpacked);
+        }
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn merge_sets_into_container() {
+        let (sha_path, heur_path, merged_path) = (
+            temp_path("merge_sha"),
+            temp_path("merge_heur"),
+            temp_path("merged"),
+        );
+        ShaSet::from_descriptions(vec![sha_sig(0).1])
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .serialize(&sha_path, ShaSet::SET_MAGIC_U32)
+            .unwrap();
+        HeurSet::from_descriptions(vec![heur_sig("first", "a.dll+a")])
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .serialize(&heur_path, HeurSet::SET_MAGIC_U32)
+            .unwrap();
+
+        let mut file = SigSetFile::open(&sha_path).unwrap();
+        file.merge(SigSetFile::open(&heur_path).unwrap()).unwrap();
+        assert!(file.merge(SigSetFile::open(&heur_path).unwrap()).is_err());
+        assert_eq!(file.save(&merged_path).unwrap(), 2);
+
+        let magics: Vec<_> = SigSetDeserializer::new_untrusted_sections(&merged_path)
+            .unwrap()
+            .iter()
+            .map(|s| s.magic())
+            .collect();
+        assert_eq!(magics, [ShaSet::SET_MAGIC_U32, HeurSet::SET_MAGIC_U32]);
+
+        for path in [sha_path, heur_path, merged_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn signature_with_only_condition_states_its_type() {
+        let condition = "name: a\ndescription: a\ncondition:\n  all_of: [Sleep, Beep]\n";
+        assert!(signature_set_magic(condition).is_err());
+        assert_eq!(
+            signature_set_magic(&format!("{condition}calls: []\n")).unwrap(),
+            DynSet::SET_MAGIC_U32
+        );
+        assert_eq!(
+            signature_set_magic(&format!("{condition}imports: []\n")).unwrap(),
+            HeurSet::SET_MAGIC_U32
+        );
+        assert_eq!(
+            signature_set_magic(&format!("{condition}threshold: 1\n")).unwrap(),
+            HeurSet::SET_MAGIC_U32
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{
+        set_kind::{is_known_magic, set_kind},
+        set_view::SetBuffer,
+        signature::{
+            SigAnomaly, SigBase, SigBlocks, SigCode, SigDyn, SigFuzzy, SigHeur, SigModel,
+            SigPattern, SigPeHash, SigSha256, SigStrings,
+        },
+        sigset_container::{magic_to_string, SetContainer},
+        sigset_deserializer::SigSetDeserializer,
+        Description, SetHeader,
+    },
+    SigSetError,
+};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+/// Signature as it is listed
+#[derive(Debug)]
+pub struct SigInfo {
+    pub name: String,
+    pub description: String,
+    /// hashes of hash signature, imports of heuristic one, calls of dynamic one, byte patterns,
+    /// ssdeep, PE hashes, PE anomalies, string patterns, code, number of blocks or of weights of
+    /// model, followed by features of its condition
+    pub features: Vec<String>,
+}
+
+impl SigInfo {
+    fn from_description(magic: u32, description: &str) -> Result<Self, SigSetError> {
+        let (sig_base, features) = (set_kind(magic)?.info)(description)?;
+        Ok(Self {
+            name: sig_base.name,
+            description: sig_base.description,
+            features,
+        })
+    }
+}
+
+// base and listed features of signature
+pub(crate) type Listed = (SigBase, Vec<String>);
+
+/// Signature which can be listed: its base and features
+pub(crate) trait ListedSig: DeserializeOwned {
+    fn into_listed(self) -> Listed;
+}
+
+pub(crate) fn sig_info<T: ListedSig>(description: &str) -> Result<Listed, SigSetError> {
+    Ok(serde_yaml::from_str::<T>(description)?.into_listed())
+}
+
+impl ListedSig for SigSha256 {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigHeur {
+    fn into_listed(self) -> Listed {
+        let features = self.features().into_iter().map(String::from).collect();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigDyn {
+    fn into_listed(self) -> Listed {
+        let features = self.features().into_iter().map(String::from).collect();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigPattern {
+    fn into_listed(self) -> Listed {
+        let features = (self.patterns.iter())
+            .map(|pattern| match pattern.offset() {
+                Some(offset) => format!("{} at {offset:#x}", pattern.pattern()),
+                None => pattern.pattern().to_string(),
+            })
+            .collect();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigFuzzy {
+    fn into_listed(self) -> Listed {
+        (self.sig_base, vec![self.ssdeep])
+    }
+}
+
+impl ListedSig for SigPeHash {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigAnomaly {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigStrings {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigCode {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigBlocks {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+impl ListedSig for SigModel {
+    fn into_listed(self) -> Listed {
+        let features = self.features();
+        (self.sig_base, features)
+    }
+}
+
+/// Header and signatures of set, or of one section of container
+#[derive(Debug)]
+pub struct SetInfo {
+    pub magic: String,
+    pub checksum: Sha256,
+    pub elem_count: u32,
+    /// Empty for sets not known here (e.g. behavioural section of container)
+    pub signatures: Vec<SigInfo>,
+}
+
+impl SetInfo {
+    /// Every set of single set file or of container
+    pub fn read_all(path: &str) -> Result<Vec<SetInfo>, SigSetError> {
+        let buffer = Arc::new(SetBuffer::map_file(path)?);
+        let sections = if SetContainer::is_container(&buffer) {
+            SetContainer::verify_sections(&buffer)?;
+            SetContainer::read_sections(&buffer)?
+        } else {
+            vec![(
+                SetHeader::read(buffer.get(0..buffer.len())?)?.magic,
+                0..buffer.len(),
+            )]
+        };
+
+        let mut sets = vec![];
+        for (_, range) in sections {
+            let header = SetHeader::read(buffer.get(range.clone())?)?;
+            if !is_known_magic(header.magic) {
+                sets.push(Self::from_header(&header, vec![]));
+                continue;
+            }
+
+            let des = SigSetDeserializer::new_in_buffer(buffer.clone(), range)?;
+            des.verify()?;
+            let signatures = read_descriptions(&des)?
+                .iter()
+                .map(|desc| SigInfo::from_description(header.magic, desc))
+                .collect::<Result<_, _>>()?;
+            sets.push(Self::from_header(&header, signatures));
+        }
+        Ok(sets)
+    }
+
+    fn from_header(header: &SetHeader, signatures: Vec<SigInfo>) -> Self {
+        Self {
+            magic: magic_to_string(header.magic),
+            checksum: header.checksum,
+            elem_count: header.elem_count,
+            signatures,
+        }
+    }
+}
+
+// descriptions of set in order of signature ids
+pub(crate) fn read_descriptions(des: &SigSetDeserializer) -> Result<Vec<Description>, SigSetError> {
+    (set_kind(des.magic())?.descriptions)(des)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::{heuristic_set::HeurSet, SigSet};
+
+    #[test]
+    fn list_heuristic_set() {
+        let descriptions = vec![
+            "name: first\ndescription: first sig\nimports: [a.dll+a, b.dll+b]\n".to_string(),
+            "name: second\ndescription: second sig\nimports: [c.dll+c]\ncondition: d.dll+d\n"
+                .to_string(),
+        ];
+        let bytes = HeurSet::from_descriptions(descriptions)
+            .unwrap()
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(HeurSet::SET_MAGIC_U32)
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("sfi_list_{}.sset", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let sets = SetInfo::read_all(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].magic, "H5ET");
+        // signatures and their index
+        assert_eq!(sets[0].elem_count, 3);
+        assert_eq!(sets[0].checksum, bytes[4..36]);
+        let sigs = &sets[0].signatures;
+        assert_eq!(
+            (sigs[0].name.as_str(), sigs[1].name.as_str()),
+            ("first", "second")
+        );
+        assert_eq!(sigs[0].description, "first sig");
+        assert_eq!(sigs[1].features, ["c.dll+c", "d.dll+d"]);
+    }
+}
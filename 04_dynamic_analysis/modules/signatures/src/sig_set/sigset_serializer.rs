@@ -1,6 +1,6 @@
 use crate::{
     sha256_utils::Sha256,
-    sig_set::{SetHeader, SigHeader, SigId},
+    sig_set::{import_set_view::INDEX_SIG_ID, write_set_file, SetHeader, SigHeader, SigId},
     SigSetError,
 };
 use sha2::Digest;
@@ -10,6 +10,8 @@ pub struct SigSetSerializer {
     sig_headers_vec: Vec<SigHeader>,
     curr_offset: u32,
     descriptions: Vec<u8>,
+    // sets end with record of their index or lookup structure, which is not signature
+    has_index: bool,
 }
 
 impl SigSetSerializer {
@@ -18,6 +20,7 @@ impl SigSetSerializer {
             sig_headers_vec: Vec::new(),
             curr_offset: 0,
             descriptions: Vec::new(),
+            has_index: false,
         }
     }
 }
@@ -34,6 +37,12 @@ impl SigSetSerializer {
         self.curr_offset = self.descriptions.len() as u32;
     }
 
+    // the last record, after every signature
+    pub(crate) fn serialize_index(&mut self, data: Vec<u8>) {
+        self.serialize_signature(INDEX_SIG_ID, data);
+        self.has_index = true;
+    }
+
     // fn serialize_shaset(&self, set_name: &str) -> Result<(), SigSetError> {
     //     self.serialize(set_name, ShaSet::SET_MAGIC_U32)
     // }
@@ -43,7 +52,12 @@ impl SigSetSerializer {
     // }
 
     pub fn serialize(&self, set_name: &str, magic: u32) -> Result<usize, SigSetError> {
-        let mut file = std::fs::File::create(set_name)?;
+        write_set_file(set_name, &self.to_bytes(magic)?)?;
+        Ok(self.sig_headers_vec.len() - usize::from(self.has_index))
+    }
+
+    pub(crate) fn to_bytes(&self, magic: u32) -> Result<Vec<u8>, SigSetError> {
+        let mut bytes = vec![];
 
         let mut checksum_buf = Sha256::default();
         checksum_buf.copy_from_slice(&self.calculate_checksum()?);
@@ -55,27 +69,27 @@ impl SigSetSerializer {
         };
 
         let header = bincode::serde::encode_to_vec(&mset_header, bincode::config::legacy())?;
-        file.write_all(&header)?;
+        bytes.write_all(&header)?;
 
         //write info about each sig
         for header in &self.sig_headers_vec {
-            let data = bincode::serde::encode_to_vec(&header, bincode::config::legacy())?;
-            file.write_all(&data)?;
+            let data = bincode::serde::encode_to_vec(header, bincode::config::legacy())?;
+            bytes.write_all(&data)?;
         }
 
         //write descriptions to file
-        file.write_all(&self.descriptions)?;
-        Ok(self.sig_headers_vec.len())
+        bytes.write_all(&self.descriptions)?;
+        Ok(bytes)
     }
 
     fn calculate_checksum(&self) -> Result<Sha256, SigSetError> {
         let mut hasher = sha2::Sha256::new();
 
-        hasher.update(&(self.sig_headers_vec.len() as u32).to_le_bytes());
+        hasher.update((self.sig_headers_vec.len() as u32).to_le_bytes());
 
         for header in &self.sig_headers_vec {
             hasher.update(&bincode::serde::encode_to_vec(
-                &header,
+                header,
                 bincode::config::legacy(),
             )?);
         }
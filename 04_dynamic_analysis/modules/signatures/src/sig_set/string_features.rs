@@ -0,0 +1,276 @@
+use crate::{sig_set::signature::StringKind, SigSetError};
+use common::redr;
+use std::{collections::BTreeSet, io::Read};
+
+// shorter runs of printable characters are mostly parts of code or data
+const MIN_STRING_LENGTH: usize = 5;
+// base64 blobs are long, shorter strings of its alphabet are mostly identifiers
+const MIN_BASE64_LENGTH: usize = 20;
+
+// top level domains of most hosts in malware. Host with other ending is not told from file
+// names like "kernel32.dll"
+const DOMAIN_SUFFIXES: &[&str] = &[
+    "biz", "br", "cc", "cn", "co", "com", "de", "eu", "fr", "gov", "in", "info", "io", "ir", "jp",
+    "kr", "me", "net", "nl", "online", "org", "pw", "ru", "site", "su", "tk", "to", "top", "tv",
+    "ua", "uk", "us", "ws", "xyz",
+];
+
+const REGISTRY_ROOTS: &[&str] = &[
+    "hkey_",
+    "hklm\\",
+    "hkcu\\",
+    "hkcr\\",
+    "hku\\",
+    "\\registry\\",
+    "software\\",
+    "system\\",
+];
+
+// prefixes of names of kernel objects, as mutexes, in namespaces of sessions
+const OBJECT_NAMESPACES: &[&str] = &["global\\", "local\\", "session\\"];
+
+const URL_SCHEMES: &[&str] = &["http://", "https://", "ftp://"];
+
+/// String of file with its kind
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct FileString {
+    pub(crate) kind: StringKind,
+    pub(crate) value: String,
+}
+
+/// ASCII and UTF-16LE strings of file, each string once
+pub(crate) fn extract_strings(reader: &mut redr::FileReader) -> Result<Vec<String>, SigSetError> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    Ok(data_strings(&buffer))
+}
+
+/// ASCII and UTF-16LE strings of data, each string once
+pub(crate) fn data_strings(buffer: &[u8]) -> Vec<String> {
+    let mut strings = BTreeSet::new();
+    let is_printable = |byte: u8| byte == b'\t' || (0x20..0x7f).contains(&byte);
+    for run in buffer.split(|byte| !is_printable(*byte)) {
+        if run.len() >= MIN_STRING_LENGTH {
+            strings.insert(String::from_utf8_lossy(run).into_owned());
+        }
+    }
+    // the same for both alignments of UTF-16 characters
+    for start in 0..2 {
+        let mut run = String::new();
+        for pair in buffer[start.min(buffer.len())..].chunks_exact(2) {
+            if pair[1] == 0 && is_printable(pair[0]) {
+                run.push(pair[0] as char);
+                continue;
+            }
+            if run.len() >= MIN_STRING_LENGTH {
+                strings.insert(std::mem::take(&mut run));
+            }
+            run.clear();
+        }
+        if run.len() >= MIN_STRING_LENGTH {
+            strings.insert(run);
+        }
+    }
+    strings.into_iter().collect()
+}
+
+/// Indicators of strings, sorted. String may give more of them, e.g. URL and its domain
+pub(crate) fn classify_strings(strings: &[String]) -> Vec<FileString> {
+    let mut found = BTreeSet::new();
+    let mut add = |kind, value: &str| {
+        found.insert(FileString {
+            kind,
+            value: value.to_string(),
+        });
+    };
+
+    for string in strings {
+        let string = string.trim();
+        let lowercase = string.to_lowercase();
+        if REGISTRY_ROOTS
+            .iter()
+            .any(|root| lowercase.starts_with(root))
+        {
+            add(StringKind::Registry, string);
+        }
+        if OBJECT_NAMESPACES
+            .iter()
+            .any(|namespace| lowercase.len() > namespace.len() && lowercase.starts_with(namespace))
+            || is_guid(string)
+        {
+            add(StringKind::Mutex, string);
+        }
+        if is_path(string) {
+            add(StringKind::Path, string);
+        }
+        if is_base64(string) {
+            add(StringKind::Base64, string);
+        }
+
+        for scheme in URL_SCHEMES {
+            let mut rest = &lowercase[..];
+            while let Some(start) = rest.find(scheme) {
+                let offset = lowercase.len() - rest.len() + start;
+                let end = string[offset..]
+                    .find(|c: char| c.is_whitespace() || "\"'<>".contains(c))
+                    .map_or(string.len(), |end| offset + end);
+                add(StringKind::Url, &string[offset..end]);
+                rest = &lowercase[end..];
+            }
+        }
+
+        for token in string.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-')) {
+            let token = token.trim_matches('.');
+            if is_ip(token) {
+                add(StringKind::Ip, token);
+            } else if is_domain(token) {
+                add(StringKind::Domain, &token.to_lowercase());
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+fn is_ip(token: &str) -> bool {
+    let octets: Vec<_> = token.split('.').collect();
+    octets.len() == 4
+        && (octets.iter()).all(|octet| {
+            (1..=3).contains(&octet.len())
+                && octet.bytes().all(|c| c.is_ascii_digit())
+                && octet.parse::<u8>().is_ok()
+        })
+}
+
+fn is_domain(token: &str) -> bool {
+    let labels: Vec<_> = token.split('.').collect();
+    let suffix = labels[labels.len() - 1].to_lowercase();
+    labels.len() >= 2
+        && (labels.iter())
+            .all(|label| !label.is_empty() && label.len() <= 63 && !label.starts_with('-'))
+        && DOMAIN_SUFFIXES.contains(&suffix.as_str())
+}
+
+// drive, environment variable or UNC path of Windows, or absolute path of Unix
+fn is_path(string: &str) -> bool {
+    let bytes = string.as_bytes();
+    let drive = bytes.len() > 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    let variable = string.starts_with('%')
+        && (string[1..].find('%')).is_some_and(|end| string[end + 2..].starts_with('\\'));
+    let unc = string.starts_with("\\\\") && string.len() > 2;
+    let unix = string.starts_with('/')
+        && string[1..].contains('/')
+        && !string.contains(char::is_whitespace)
+        && !string.contains("//");
+    drive || variable || unc || unix
+}
+
+// "{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}", named objects are often named by GUID
+fn is_guid(string: &str) -> bool {
+    let Some(inner) = (string.strip_prefix('{')).and_then(|string| string.strip_suffix('}')) else {
+        return false;
+    };
+    let groups: Vec<_> = inner.split('-').collect();
+    (groups.iter().map(|group| group.len())).eq([8, 4, 4, 4, 12])
+        && inner.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+}
+
+fn is_base64(string: &str) -> bool {
+    let data = string.trim_end_matches('=');
+    string.len() >= MIN_BASE64_LENGTH
+        && string.len().is_multiple_of(4)
+        && string.len() - data.len() <= 2
+        && (data.bytes()).all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/')
+        && data.bytes().any(|c| c.is_ascii_uppercase())
+        && data.bytes().any(|c| c.is_ascii_lowercase())
+        && data.bytes().any(|c| c.is_ascii_digit())
+}
+
+/// Case insensitive match of whole string with pattern, where "*" is any sequence of characters
+/// and "?" any character
+pub(crate) fn wildcard_match(pattern: &str, string: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let string: Vec<char> = string.to_lowercase().chars().collect();
+    let (mut p, mut s) = (0, 0);
+    // position of the last "*" in pattern and in string, when it matched
+    let mut star: Option<(usize, usize)> = None;
+    while s < string.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == string[s]) {
+            p += 1;
+            s += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, s));
+            p += 1;
+        } else if let Some((star_p, star_s)) = star {
+            // "*" takes one more character
+            p = star_p + 1;
+            s = star_s + 1;
+            star = Some((star_p, star_s + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_extracted_and_classified() {
+        let mut data =
+            b"\x00\x01MZ\x90Software\\Microsoft\\Windows\\CurrentVersion\\Run\x00ab\xff\xff"
+                .to_vec();
+        for c in "Global\\qazwsx123\0http://evil.example.com/gate.php?id=1\0".encode_utf16() {
+            data.extend(c.to_le_bytes());
+        }
+        data.extend(b"\x00\xffC:\\Users\\Public\\svc.exe\x00 185.100.87.21:443 kernel32.dll\x00");
+        data.extend(b"\x00SGVsbG8sIFdvcmxkISAxMjM0NTY=\x00");
+        let mut reader = redr::FileReader::from_buff(std::io::Cursor::new(data));
+
+        let strings = extract_strings(&mut reader).unwrap();
+        assert!(strings.contains(&"Software\\Microsoft\\Windows\\CurrentVersion\\Run".into()));
+        assert!(strings.contains(&"http://evil.example.com/gate.php?id=1".into()));
+        assert!(!strings
+            .iter()
+            .any(|string| string.len() < MIN_STRING_LENGTH));
+
+        let found: Vec<_> = (classify_strings(&strings).into_iter())
+            .map(|found| (found.kind, found.value))
+            .collect();
+        let expected = [
+            (StringKind::Url, "http://evil.example.com/gate.php?id=1"),
+            (StringKind::Ip, "185.100.87.21"),
+            (StringKind::Domain, "evil.example.com"),
+            (
+                StringKind::Registry,
+                "Software\\Microsoft\\Windows\\CurrentVersion\\Run",
+            ),
+            (StringKind::Path, "C:\\Users\\Public\\svc.exe"),
+            (StringKind::Mutex, "Global\\qazwsx123"),
+            (StringKind::Base64, "SGVsbG8sIFdvcmxkISAxMjM0NTY="),
+        ];
+        let expected: Vec<_> = (expected.into_iter())
+            .map(|(kind, value)| (kind, value.to_string()))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn wildcards_match_case_insensitive() {
+        assert!(wildcard_match(
+            "*\\CurrentVersion\\Run*",
+            "SOFTWARE\\x\\currentversion\\RunOnce"
+        ));
+        assert!(wildcard_match(
+            "http://*.ru/?ate.php",
+            "http://a.b.ru/gate.php"
+        ));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*\\Run", "Software\\RunOnce"));
+        assert!(!wildcard_match("a?c", "ac"));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    sig_set::{
+        described_set::{decode_lookup, encode_lookup, DescribedSet},
+        set_view::SetView,
+        sig_source::SigSource,
+        signature::{SigStrings, StringKind, StringsMatch},
+        sigset_serializer::SigSetSerializer,
+        string_features::{classify_strings, extract_strings, wildcard_match, FileString},
+        Description, SigSet,
+    },
+    SigSetError,
+};
+use common::{detection::DetectionReport, redr};
+use std::collections::BTreeMap;
+
+type StringSigId = u32;
+
+pub struct StringSet {
+    sig_id_to_description: BTreeMap<StringSigId, Description>,
+    // signatures of loaded set, their descriptions are read only when they match
+    view: Option<SetView>,
+    // patterns of each signature with their kinds and number of patterns which must match. They
+    // are stored in set
+    sig_id_to_patterns: BTreeMap<StringSigId, (Vec<(StringKind, String)>, u32)>,
+}
+
+impl StringSet {
+    pub const SET_MAGIC_U32: u32 = 0x54453554; //T5ET
+
+    // signatures with enough matched patterns and strings which matched them, in order of ids
+    fn match_(&self, strings: &[FileString]) -> Vec<(StringSigId, Vec<String>)> {
+        let mut matches = vec![];
+        for (sig_id, (patterns, threshold)) in &self.sig_id_to_patterns {
+            let mut present = vec![];
+            let mut matched_patterns = 0;
+            for (kind, pattern) in patterns {
+                let found = (strings.iter())
+                    .find(|string| string.kind == *kind && wildcard_match(pattern, &string.value));
+                if let Some(found) = found {
+                    present.push(format!("{}: {}", kind.name(), found.value));
+                    matched_patterns += 1;
+                }
+            }
+            if matched_patterns >= *threshold {
+                matches.push((*sig_id, present));
+            }
+        }
+        matches
+    }
+}
+
+impl DescribedSet for StringSet {
+    const KIND: &'static str = "string";
+
+    fn new_empty() -> Self {
+        Self {
+            sig_id_to_description: Default::default(),
+            view: None,
+            sig_id_to_patterns: Default::default(),
+        }
+    }
+
+    fn add_description(
+        &mut self,
+        sig_id: StringSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let sig: SigStrings = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", sig);
+        let patterns = sig.patterns();
+        let incorrect = |info: String| SigSetError::IncorrectSignatureError {
+            info: format!("{}: {info}", sig.sig_base.name),
+        };
+        if patterns.is_empty() {
+            return Err(incorrect("no strings".into()));
+        }
+        if (patterns.iter()).any(|(_, pattern)| pattern.chars().all(|c| c == '*')) {
+            return Err(incorrect("pattern matches any string".into()));
+        }
+        match sig.threshold {
+            Some(0) => return Err(incorrect("threshold is 0".into())),
+            Some(threshold) if threshold as usize > patterns.len() => {
+                return Err(incorrect(format!(
+                    "threshold {threshold} is above number of patterns {}",
+                    patterns.len()
+                )))
+            },
+            _ => {},
+        }
+
+        let patterns = (patterns.into_iter())
+            .map(|(kind, pattern)| (kind, pattern.to_string()))
+            .collect();
+        (self.sig_id_to_patterns).insert(sig_id, (patterns, sig.threshold()));
+        self.sig_id_to_description.insert(sig_id, description);
+        Ok(())
+    }
+
+    fn sig_id_to_description(&self) -> &BTreeMap<u32, Description> {
+        &self.sig_id_to_description
+    }
+
+    fn view(&self) -> Option<&SetView> {
+        self.view.as_ref()
+    }
+
+    fn stored_lookup(&self) -> Result<Vec<u8>, SigSetError> {
+        encode_lookup(&self.sig_id_to_patterns)
+    }
+
+    fn from_stored(view: SetView) -> Result<Self, SigSetError> {
+        let mut set = Self::new_empty();
+        set.sig_id_to_patterns = decode_lookup(&view)?;
+        set.view = Some(view);
+        Ok(set)
+    }
+}
+
+impl SigSet for StringSet {
+    fn eval_file(
+        &self,
+        file: &mut redr::FileReader,
+        _variant: &mut redr::FileScanInfo,
+    ) -> Result<Vec<DetectionReport>, SigSetError> {
+        let strings = classify_strings(&extract_strings(file)?);
+        log::debug!("Strings: {:?}", strings);
+
+        let mut reports = vec![];
+        for (sig_id, present) in self.match_(&strings) {
+            let sig: SigStrings = self.decode_sig(sig_id)?;
+            reports.push(StringsMatch { sig, present }.into());
+        }
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
+    }
+
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
+        Self::compile_source(source)
+    }
+
+    fn to_sig_set(&self) -> Result<SigSetSerializer, SigSetError> {
+        self.serialize_descriptions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn matched_strings_are_in_cause() {
+        let descriptions = vec![
+            "name: Persistence\ndescription: p\nstrings: {registry: \
+             \"*\\\\CurrentVersion\\\\Run*\"}\n"
+                .to_string(),
+            "name: Downloader\ndescription: d\nthreshold: 1\nstrings:\n  url: [\"http://*.ru/*\", \
+             \"*/gate.php*\"]\n  ip: \"10.*\"\n"
+                .to_string(),
+        ];
+        let set = StringSet::from_descriptions(descriptions).unwrap();
+        let bytes = set
+            .to_sig_set()
+            .unwrap()
+            .to_bytes(StringSet::SET_MAGIC_U32)
+            .unwrap();
+        let set = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_described_set::<StringSet>()
+            .unwrap();
+
+        let eval = |data: &[u8]| {
+            let mut file = redr::FileReader::from_buff(std::io::Cursor::new(data.to_vec()));
+            let mut variant = redr::FileScanInfo::real_file("sample".into());
+            set.eval_file(&mut file, &mut variant).unwrap()
+        };
+        let reports = eval(b"\x00SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\RunOnce\x00");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            reports[0].cause,
+            "Found Strings: [\"registry: \
+             SOFTWARE\\\\Microsoft\\\\Windows\\\\CurrentVersion\\\\RunOnce\"]"
+        );
+
+        let reports = eval(b"\x00https://cdn.example.com/gate.php?x=1\x00");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].name, "Downloader");
+        assert!(eval(b"\x00Software\\Classes\\Run\x00https://example.com/\x00").is_empty());
+
+        for incorrect in [
+            "strings: {}",
+            "strings: {url: \"**\"}",
+            "strings: {email: \"*@*\"}",
+        ] {
+            let description = format!("name: x\ndescription: x\n{incorrect}\n");
+            assert!(
+                StringSet::from_descriptions(vec![description]).is_err(),
+                "{incorrect}"
+            );
+        }
+    }
+}
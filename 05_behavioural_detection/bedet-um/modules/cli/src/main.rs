@@ -3,23 +3,102 @@ mod detection;
 use clap::{Parser, Subcommand};
 use std::{env, ffi::OsString};
 
-use signatures::sig_set::{bedet_set::BedetSet, SigSet};
+use signatures::sig_set::{
+    bedet_set::BedetSet,
+    set_signing::{self, TrustedKeys},
+    sig_lint,
+    sig_source::SigSource,
+    sig_test,
+    sigset_info::SetInfo,
+    SigSet,
+};
 
 #[derive(clap::Args)]
 pub struct Compile {
-    /// Signature directory
+    /// Signature directory. Subdirectories are read too
     #[clap(long)]
     dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
     /// Output name/path of sigset. Extenstion should be "bset"
     #[clap(short, long)]
     out_path: String,
+    /// File with Ed25519 secret key in hex. Detached signature "{out_path}.sig" is written
+    #[clap(long)]
+    sign_key: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Unpack {
+    /// Path to bset or container with behavioural set
+    #[clap(short, long)]
+    set_path: String,
+    /// Directory where sigs should be unpack
+    #[clap(short, long)]
+    out_dir: String,
+}
+
+#[derive(clap::Args)]
+pub struct List {
+    /// Path to bset or container with behavioural set
+    #[clap(value_name = "SET")]
+    set_path: String,
+    /// List only signatures which name contains given text
+    #[clap(short, long)]
+    name: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct Lint {
+    /// Signature directory. Subdirectories are checked too
+    #[clap(long)]
+    dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
+}
+
+#[derive(clap::Args)]
+pub struct Test {
+    /// Signature directory. Subdirectories are read too
+    #[clap(long)]
+    dir: String,
+    #[clap(flatten)]
+    filter: SourceFilter,
+}
+
+/// Sets are loaded only if they are signed with one of trusted keys
+#[derive(clap::Args)]
+pub struct Trust {
+    /// File with trusted Ed25519 public keys in hex, one per line
+    #[clap(long)]
+    trusted_keys: Option<String>,
+    /// Load also unsigned sets and sets not signed with trusted key
+    #[clap(long)]
+    allow_untrusted: bool,
+}
+
+/// Globs without '/' match file names, other globs match paths relative to signature directory
+#[derive(clap::Args)]
+pub struct SourceFilter {
+    /// Only signature files matching any of these globs are read, e.g. "ransom/*/*.yml"
+    #[clap(long)]
+    include: Vec<String>,
+    /// Signature files matching any of these globs are skipped
+    #[clap(long)]
+    exclude: Vec<String>,
 }
 
 #[derive(Subcommand)]
 pub enum SignatureCommand {
     Compile(Compile),
-    //Unpack(Unpack),
-    //List(),
+    Unpack(Unpack),
+    /// Print header and signatures of compiled set
+    List(List),
+    /// Check signatures before they are compiled. Fails if any problem is found
+    Lint(Lint),
+    /// Compile signatures in memory and check samples declared in their "tests". Fails if any
+    /// sample is not matched as declared
+    Test(Test),
 }
 
 #[derive(Subcommand)]
@@ -32,6 +111,8 @@ enum Commands {
         /// Path to sha signature set
         #[clap(short)]
         bedet_sig_path: String,
+        #[clap(flatten)]
+        trust: Trust,
     },
 }
 
@@ -69,19 +150,86 @@ pub fn main() -> anyhow::Result<()> {
     match args.commands {
         Commands::Signature(signature_command) => match signature_command {
             SignatureCommand::Compile(args) => {
-                let set = BedetSet::from_signatures(args.dir.as_str())?;
+                let set = BedetSet::from_source(&sig_source(&args.dir, &args.filter)?)?;
                 let ser = set.to_set_serializer();
 
-                match ser.serialize_bedet_set(&args.out_path) {
-                    Ok(number) => println!("SUCCESS to compile set. Count: {number}"),
-                    Err(e) => log::error!("Failed to compile sigs. Err: {e}"),
+                let number = ser.serialize_bedet_set(&args.out_path)?;
+                println!("SUCCESS to compile set. Count: {number}");
+                if let Some(sign_key) = &args.sign_key {
+                    let public_key = set_signing::sign_set_file(&args.out_path, sign_key)?;
+                    println!("SUCCESS to sign set. Public key: {public_key}");
+                }
+            },
+            SignatureCommand::Unpack(args) => {
+                let set = signatures::deserialize_untrusted_bedet_set_from_path(&args.set_path)?;
+                std::fs::create_dir_all(&args.out_dir)?;
+                match set.unpack_to_dir(&args.out_dir) {
+                    Ok(number) => println!("SUCCESS to unpack set. Count: {number}"),
+                    Err(e) => log::error!("Failed to unpack set to: {}. Err: {e}", &args.out_dir),
+                }
+            },
+            SignatureCommand::List(args) => {
+                let set = SetInfo::read(&args.set_path)?;
+                let checksum = signatures::sha256_utils::convert_sha256_to_string(&set.checksum)?;
+                println!("magic: {}", set.magic);
+                println!("checksum: {checksum}");
+                println!("count: {}", set.elem_count);
+                let signatures = set.signatures.iter().filter(|sig| {
+                    args.name
+                        .as_ref()
+                        .is_none_or(|name| sig.name.contains(name.as_str()))
+                });
+                for sig in signatures {
+                    println!("  - name: {}", sig.name);
+                    println!("    description: {}", sig.description);
+                    println!("    features: {}", sig.features.join(", "));
+                }
+            },
+            SignatureCommand::Lint(args) => {
+                let issues = sig_lint::lint(&sig_source(&args.dir, &args.filter)?)?;
+                for issue in &issues {
+                    println!("{issue}");
                 }
+                if !issues.is_empty() {
+                    anyhow::bail!("Found {} problems in signatures", issues.len());
+                }
+                println!("SUCCESS to lint signatures");
+            },
+            SignatureCommand::Test(args) => {
+                let results = sig_test::test_source(&sig_source(&args.dir, &args.filter)?)?;
+                for result in &results {
+                    println!("{result}");
+                }
+                let failed = results.iter().filter(|result| !result.passed()).count();
+                if failed > 0 {
+                    anyhow::bail!("{failed} of {} samples failed", results.len());
+                }
+                println!("SUCCESS to test signatures. Samples: {}", results.len());
             },
         },
-        Commands::StartDetection { bedet_sig_path } => {
+        Commands::StartDetection {
+            bedet_sig_path,
+            trust,
+        } => {
+            let keys = match &trust.trusted_keys {
+                Some(path) => TrustedKeys::from_file(path)?,
+                None => TrustedKeys::empty(),
+            };
+            set_signing::set_trusted_keys(keys.allow_untrusted(trust.allow_untrusted));
             detection::start_detection(bedet_sig_path).unwrap()
         },
     }
 
     Ok(())
 }
+
+fn sig_source(dir: &str, filter: &SourceFilter) -> anyhow::Result<SigSource> {
+    let mut source = SigSource::new(dir);
+    for glob in &filter.include {
+        source = source.include(glob)?;
+    }
+    for glob in &filter.exclude {
+        source = source.exclude(glob)?;
+    }
+    Ok(source)
+}
@@ -2,16 +2,25 @@ use std::fmt::{Display, Formatter};
 
 #[derive(Debug)]
 pub struct DetectionReport {
+    pub name: String,
     pub desc: String,
     pub cause: String,
+    pub priority: u32,
+}
+
+impl DetectionReport {
+    // most important detections first. Sort is stable so equal priorities keep signature order
+    pub fn sort_by_priority(reports: &mut [DetectionReport]) {
+        reports.sort_by_key(|report| std::cmp::Reverse(report.priority));
+    }
 }
 
 impl Display for DetectionReport {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Detection {{ desc: \"{}\", cause: \"{}\" }}",
-            self.desc, self.cause
+            "Detection {{ name: \"{}\", desc: \"{}\", cause: \"{}\", priority: {} }}",
+            self.name, self.desc, self.cause, self.priority
         )
     }
 }
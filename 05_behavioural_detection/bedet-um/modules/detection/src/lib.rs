@@ -109,14 +109,18 @@ fn message_loop(connection_port: SmartHandle, signatures: BedetSet) {
                 },
                 RegistrySetValueEvent::EVENT_CLASS => {
                     if let Some(e) = RegistrySetValueEvent::deserialize(event_buff) {
-                        if let Ok(Some(s)) = signatures.eval_event(e.hash_members()) {
-                            let detection = format!("{:?}", s);
-                            println!(
-                                "{} - {}",
-                                Red.paint("MALWARE"),
-                                Style::new().bold().paint(&detection)
-                            );
-                            output_debug_string(detection);
+                        let detections =
+                            signatures.eval_event(e.hash_members()).unwrap_or_default();
+                        if !detections.is_empty() {
+                            for s in detections {
+                                let detection = format!("{:?}", s);
+                                println!(
+                                    "{} - {}",
+                                    Red.paint("MALWARE"),
+                                    Style::new().bold().paint(&detection)
+                                );
+                                output_debug_string(detection);
+                            }
                             if cleaner::process_cleaner::try_to_kill_process(e.get_pid()) {
                                 println!(
                                     "{} Process terminated. Pid: {}",
@@ -176,35 +180,33 @@ fn message_loop(connection_port: SmartHandle, signatures: BedetSet) {
 #[cfg(test)]
 mod test {
     use common::{event::registry_set_value::RegistrySetValueEvent, hasher::MemberHasher};
-    use signatures::sig_set::SigSet;
+    use signatures::sig_set::{bedet_set::BedetSet, SigSet};
 
     #[test]
     fn compile_and_eval_signature() {
-        let path = std::path::Path::new("..\\..\\malset.bset");
-        if path.exists() {
-            let signatures =
-                signatures::deserialize_bedet_set_from_path(path.to_str().unwrap()).unwrap();
-            let e1 = RegistrySetValueEvent::new(
-                123,
-                234,
-                r#"\REGISTRY\MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Run"#.to_string(),
-                "Windows Live Messenger".to_string(),
-                1,
-                r#"C:\WINDOWS\system32\evil.exe"#.as_bytes().to_vec(),
-            );
-
-            let v = e1.hash_members();
-
-            let x = signatures.eval_event(v).unwrap().unwrap();
-
-            assert_eq!(x.desc, "Watacat - behavioural detection");
-            assert_eq!(
-                x.cause,
-                "Detected Event: RegSetValue: { {\"data\": \
-                 \"C:\\\\WINDOWS\\\\system32\\\\evil.exe\", \"data_type\": \"1\", \"key_name\": \
-                 \"\\\\REGISTRY\\\\MACHINE\\\\SOFTWARE\\\\Microsoft\\\\Windows\\\\CurrentVersion\\\
-                 \\Run\", \"value_name\": \"Windows Live Messenger\"} }"
-            );
-        }
+        // compiled from repository signatures, so the test doesn't depend on set built earlier
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../signatures/bedet");
+        let signatures = BedetSet::from_signatures(dir.to_str().unwrap()).unwrap();
+        let e1 = RegistrySetValueEvent::new(
+            123,
+            234,
+            r#"\REGISTRY\MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Run"#.to_string(),
+            "Windows Live Messenger".to_string(),
+            1,
+            r#"C:\WINDOWS\system32\evil.exe"#.as_bytes().to_vec(),
+        );
+
+        let v = e1.hash_members();
+
+        let x = signatures.eval_event(v).unwrap().remove(0);
+
+        assert_eq!(x.desc, "Watacat - behavioural detection");
+        assert_eq!(
+            x.cause,
+            "Detected Event: RegSetValue: { {\"data\": \
+             \"C:\\\\WINDOWS\\\\system32\\\\evil.exe\", \"key_name\": \
+             \"\\\\REGISTRY\\\\MACHINE\\\\SOFTWARE\\\\Microsoft\\\\Windows\\\\CurrentVersion\\\
+             \\Run\", \"value_name\": \"Windows Live Messenger\"} }"
+        );
     }
 }
@@ -8,12 +8,18 @@ pub enum SigSetError {
     BincodeDeserializeError(#[from] bincode::error::DecodeError),
     #[error("Bincode serialize error: {0}")]
     BincodeSerializeError(#[from] bincode::error::EncodeError),
+    #[error("Section '{0}' is already in container")]
+    DuplicatedSectionError(String),
+    #[error("Glob error: {0}")]
+    GlobError(#[from] globset::Error),
     #[error("Incorrect magic. Found '{current}'")]
     IncorrectMagicError { current: String },
     #[error("Incorrect checksum. Expected '{expected}' but found '{current}'")]
     IncorrectChecksumError { current: String, expected: String },
     #[error("Incorrect file size. Size: '{size}'")]
     IncorrectFileSizeError { size: u64 },
+    #[error("Incorrect key: {0}")]
+    IncorrectKeyError(String),
     #[error("Incorrect signature size. Size: '{size}'")]
     IncorrectSignatureSizeError { size: u32 },
     #[error("Incorrect signature. Info: '{info}'")]
@@ -22,12 +28,26 @@ pub enum SigSetError {
     IoError(#[from] std::io::Error),
     #[error("Given property doesn't exist in map: {0}")]
     NoSuchPropertyError(String),
+    #[error("There is no '{0}' set in file")]
+    NoSuchSectionError(String),
     #[error("Can't convert OsString to String. After to_string_lossy(): {0}")]
     OsStringError(String),
     #[error("Serde yaml error: {0}")]
     SerdeYamlError(#[from] serde_yaml::Error),
+    #[error("{path}, document {document}: {source}")]
+    SignatureFileError {
+        path: String,
+        document: usize,
+        source: Box<SigSetError>,
+    },
     #[error("ToHex error: {0}")]
     ToHexError(#[from] hex::FromHexError),
+    #[error("Set '{0}' is not signed")]
+    UnsignedSetError(String),
+    #[error("Set '{0}' is not signed with trusted key")]
+    UntrustedSetError(String),
+    #[error("Unsupported version of container: {0}")]
+    UnsupportedVersionError(u32),
 }
 
 impl From<OsString> for SigSetError {
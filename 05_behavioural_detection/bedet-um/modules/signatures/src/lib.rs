@@ -21,13 +21,8 @@ pub fn deserialize_bedet_set_from_path(set_path: &str) -> Result<BedetSet, SigSe
     des.get_bedet_set()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
+/// Set which signature is not verified, only for tools reading its signatures
+pub fn deserialize_untrusted_bedet_set_from_path(set_path: &str) -> Result<BedetSet, SigSetError> {
+    let des = SigSetDeserializer::new_untrusted(set_path)?;
+    des.get_bedet_set()
 }
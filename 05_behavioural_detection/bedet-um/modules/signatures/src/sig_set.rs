@@ -2,11 +2,20 @@ use crate::{sha256_utils::Sha256, SigSetError};
 use serde::Deserialize;
 
 pub mod bedet_set;
+mod feature_index;
+pub mod set_signing;
+pub mod sig_lint;
+pub mod sig_source;
+pub mod sig_test;
 mod signature;
+pub mod sigset_container;
 pub mod sigset_deserializer;
+pub mod sigset_info;
 pub mod sigset_serializer;
 
-use crate::sig_set::{bedet_set::BedetSet, sigset_serializer::SigSetSerializer};
+use crate::sig_set::{
+    bedet_set::BedetSet, sig_source::SigSource, sigset_serializer::SigSetSerializer,
+};
 use common_um::detection::DetectionReport;
 use serde::Serialize;
 
@@ -32,6 +41,13 @@ impl SetHeader {
 
 pub(crate) type SigId = [u8; 32];
 
+// bedet set keeps u32 id of signature on first 4 bytes of SigId
+pub(crate) fn sig_id_from_u32(id: u32) -> SigId {
+    let mut sig_id = SigId::default();
+    sig_id[..4].copy_from_slice(&id.to_le_bytes());
+    sig_id
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct SigHeader {
     id: SigId,
@@ -56,16 +72,17 @@ impl From<SigHeader> for HeurSigHeader {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Signature {
-    header: SigHeader,
-    data: Vec<u8>,
-}
-
 pub trait SigSet {
     //fn append_signature(&mut self, sha: SigIdType, desc: Description);
-    fn eval_event(&self, file: Vec<Sha256>) -> Result<Option<DetectionReport>, SigSetError>;
+    fn eval_event(&self, file: Vec<Sha256>) -> Result<Vec<DetectionReport>, SigSetError>;
     fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError>
+    where
+        Self: Sized,
+    {
+        Self::from_source(&SigSource::new(path_to_dir))
+    }
+    /// Signatures of chosen files of directory tree, see [SigSource]
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError>
     where
         Self: Sized;
 
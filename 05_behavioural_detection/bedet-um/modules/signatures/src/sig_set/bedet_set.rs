@@ -1,26 +1,25 @@
 use crate::{
-    sha256_utils::{convert_sha256_to_string, Sha256},
+    sha256_utils::Sha256,
     sig_set::{
-        signature::SigBedet, sigset_serializer::SigSetSerializer, Description, SigId, SigSet,
+        feature_index::{FeatureIndex, SigIndex},
+        sig_id_from_u32,
+        sig_source::SigSource,
+        signature::SigBedet,
+        sigset_serializer::SigSetSerializer,
+        Description, SigSet,
     },
     SigSetError,
 };
 use common::hasher::member_to_hash;
 use common_um::detection::DetectionReport;
-use std::{
-    collections::{BTreeMap, HashMap},
-    io::{Read, Seek, SeekFrom},
-};
+use std::collections::BTreeMap;
 
-type BedetSigId = u32;
-type ImportInSigs = u32;
+type BedetSigId = SigIndex;
 
 pub struct BedetSet {
-    import_count: u32,
-    sha_to_import_index: BTreeMap<Sha256, u32>,
-    imports_in_sig: Vec<ImportInSigs>,
-    sig_id_to_description: HashMap<BedetSigId, Description>,
-    sig_id_to_imports: HashMap<BedetSigId, Vec<Sha256>>,
+    index: FeatureIndex,
+    sig_id_to_description: BTreeMap<BedetSigId, Description>,
+    sig_id_to_imports: BTreeMap<BedetSigId, Vec<Sha256>>,
 }
 
 impl BedetSet {
@@ -31,77 +30,76 @@ impl BedetSet {
 
     pub(crate) fn new_empty() -> Self {
         Self {
-            import_count: 0,
-            sha_to_import_index: Default::default(),
-            imports_in_sig: Default::default(),
+            index: FeatureIndex::new_empty(),
             sig_id_to_description: Default::default(),
             sig_id_to_imports: Default::default(),
         }
     }
 
-    fn match_(&self, sha_vec: &Vec<Sha256>) -> Result<Option<SigBedet>, SigSetError> {
-        //--------------ALGORITHM------------------
-        // matching sha_vec with each signature has very low efficacy. There is better way
-        // imports_in_sig field tell as which signatures has particular import. For example:
-        //
-        // 1) lets assume, "kernel32+sleep" after converted to sha belongs to "self.imports" on
-        // first (zero index) position. Then if "kernel32+sleep" import appears in signatures
-        // with id's 2,3,7,11, then first (zero index) value in "self.imports_to_sig" is
-        // 1094, 0x446, 0b010001000110, because we fill "2,3,7,11" bits
-        //
-        // 2) next step is iterate by each import and if import exists in "imports_in_sig", then
-        // clear value for import_id
-        //
-        // 3) then if some bit is 0 in each "imports_in_sig" value, then we found our matched signature
-        // because each "import" hit clear for us one entry.
-        // 4) How to find out each "sig" is hit? perform in loop bitwise or on each "import_to_sig"
-        // then negate result, and then we know which sig is hit
-
-        //todo: add algorithm example step by step
-
-        let sig_count = self.sig_id_to_imports.len();
-        log::trace!("{:?}", self.imports_in_sig);
-        let mut imports_in_sig = self.imports_in_sig.clone();
-        for sha256 in sha_vec {
-            let Some(import_id) = self.sha_to_import_index.get(sha256) else {
-                continue;
-            };
-            log::debug!(
-                "self.imports_in_sig[*import_id as usize]: {:08b}",
-                self.imports_in_sig[*import_id as usize]
-            );
-            // if some imports hit, then we remove it from array. At the end it tell us in which
-            // signature all imports were hit
-
-            imports_in_sig[*import_id as usize] = 0;
-        }
-
-        // we need calculate mask. If we have 5 signatures, then mask should be
-        // 0x11111111111111111111111111100000, 5 first bits empty. So to get this in first step we get:
-        // 0x00000000000000000000000000011111 and then negate it
-        let mut shared_imports: u32 = (1 << sig_count) - 1;
-        shared_imports = !shared_imports;
-
-        for ids in imports_in_sig {
-            shared_imports |= ids;
-        }
+    // returns every matched signature in order of ids
+    fn match_(&self, sha_vec: &[Sha256]) -> Result<Vec<SigBedet>, SigSetError> {
+        // feature_index tell us which signatures have all their attributes in sha_vec. Look at
+        // FeatureIndex to see how it works
+        let matched_sigs = self.index.match_(sha_vec);
+        log::trace!("matched_sigs: {:?}", matched_sigs);
+
+        let properties = matched_sigs
+            .iter()
+            .map(|sig_id| serde_yaml::from_str(&self.sig_id_to_description[sig_id]))
+            .collect::<Result<Vec<SigBedet>, _>>()?;
+        Ok(properties)
+    }
 
-        shared_imports = !shared_imports;
+    // descriptions in order of signature ids
+    pub fn descriptions(&self) -> Vec<&Description> {
+        self.sig_id_to_description.values().collect()
+    }
 
-        if shared_imports == 0 {
-            // no match
-            return Ok(None);
+    // returns number of unpacked signatures. Files are named so that set compiled from them has
+    // the same ids
+    pub fn unpack_to_dir(&self, out_dir: &str) -> Result<usize, SigSetError> {
+        let path = std::path::Path::new(out_dir);
+        let descriptions = self.descriptions();
+        for (i, desc) in descriptions.iter().enumerate() {
+            let sig: SigBedet = serde_yaml::from_str(desc)?;
+            let name: String = sig
+                .sig_base
+                .name
+                .chars()
+                .map(
+                    |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                        true => c,
+                        false => '_',
+                    },
+                )
+                .collect();
+            std::fs::write(path.join(format!("{i:06}_{name}.sig")), desc.as_bytes())?;
         }
-        //some signatures are matched. Take first signature matched
-        //todo: add to signatures Priority field in future
-        log::trace!("matched {} sigs", shared_imports.count_ones());
 
-        let matched_sig = shared_imports.trailing_zeros();
-        log::trace!("matched_sig {} id", matched_sig);
-        log::trace!("matched_sig {:?} id", self.sig_id_to_description);
+        Ok(descriptions.len())
+    }
 
-        let properties: SigBedet = serde_yaml::from_str(&self.sig_id_to_description[&matched_sig])?;
-        return Ok(Some(properties));
+    pub(crate) fn add_description(
+        &mut self,
+        sig_id: BedetSigId,
+        description: Description,
+    ) -> Result<(), SigSetError> {
+        let properties: SigBedet = serde_yaml::from_str(&description)?;
+        log::info!("Properties: {:?}", properties);
+
+        properties.verify()?;
+        let event_type = properties.event_type;
+
+        let imports: Vec<_> = properties
+            .attributes
+            .iter()
+            .map(|s| member_to_hash(event_type.as_ref(), s.0, s.1))
+            .collect();
+        let hashes: Vec<_> = imports.iter().map(hex::encode_upper).collect();
+        log::debug!("imports: {}", hashes.join(", "));
+
+        self.append_signature(imports, sig_id, description);
+        Ok(())
     }
 
     pub(crate) fn append_signature(
@@ -110,83 +108,35 @@ impl BedetSet {
         sig_id: BedetSigId,
         desc: Description,
     ) {
-        self.sig_id_to_imports.insert(sig_id, imports.clone());
-
-        for sha in imports {
-            let import_mask = 1 << sig_id;
-            if !self.sha_to_import_index.contains_key(&sha) {
-                log::trace!("{}", self.import_count);
-                self.sha_to_import_index.insert(sha, self.import_count);
-                self.imports_in_sig.push(import_mask);
-                self.import_count += 1;
-            } else {
-                let import_id = self.sha_to_import_index.get_mut(&sha).unwrap();
-                let import_ids = self.imports_in_sig.get_mut(*import_id as usize).unwrap();
-                *import_ids |= import_mask;
-            }
-        }
+        self.index.insert(sig_id, &imports);
+        self.sig_id_to_imports.insert(sig_id, imports);
         self.sig_id_to_description.insert(sig_id, desc);
     }
 }
 
 impl SigSet for BedetSet {
-    fn eval_event(&self, fields: Vec<Sha256>) -> Result<Option<DetectionReport>, SigSetError> {
-        let sig_info = self.match_(&fields)?;
-        let desc_and_info = sig_info.map(|sig| sig.into());
-        Ok(desc_and_info)
+    fn eval_event(&self, fields: Vec<Sha256>) -> Result<Vec<DetectionReport>, SigSetError> {
+        let mut reports: Vec<DetectionReport> = self
+            .match_(&fields)?
+            .into_iter()
+            .map(|sig| sig.into())
+            .collect();
+        DetectionReport::sort_by_priority(&mut reports);
+        Ok(reports)
     }
 
-    fn from_signatures(path_to_dir: &str) -> Result<Self, SigSetError> {
-        let paths = std::fs::read_dir(path_to_dir)?;
+    fn from_source(source: &SigSource) -> Result<Self, SigSetError> {
         let mut set = BedetSet::new_empty();
-
-        let mut sig_id = 0;
-        for entry_res in paths {
-            let entry = entry_res?;
-            //log::trace!("path: {:?}", &path);
-            if entry.file_type()?.is_file() {
-                let mut f = std::fs::File::open(entry.path())?;
-                let properties: SigBedet = serde_yaml::from_reader(&f)?;
-                log::info!("Properties: {:?}", properties);
-
-                let event_type = properties.event_type;
-                //todo: check if event type is valid
-
-                // for s in properties.attributes.iter() {
-                //     println!("{}+{}+{}", event_type, s.0, s.1);
-                // }
-
-                let imports: Vec<_> = properties
-                    .attributes
-                    .iter()
-                    .map(|s| member_to_hash(event_type.as_ref(), s.0, s.1))
-                    .collect();
-
-                println!(
-                    "{}",
-                    imports
-                        .iter()
-                        .map(|sha| convert_sha256_to_string(sha).unwrap())
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                );
-
-                f.seek(SeekFrom::Start(0))?;
-                let mut data = Vec::new();
-                f.read_to_end(&mut data)?;
-                set.append_signature(imports, sig_id, String::from_utf8_lossy(&data).into());
-                sig_id += 1;
-            }
-        }
-
-        log::info!("set size: {}", sig_id);
+        source.compile(|sig_id, description| set.add_description(sig_id, description))?;
+        log::info!("set size: {}", set.sig_id_to_description.len());
         Ok(set)
     }
 
     fn to_set_serializer(&self) -> SigSetSerializer {
         let mut ser = SigSetSerializer::new_empty();
+        // in order of ids, so set compiled from the same signatures is always the same
         for (sig_id, imports) in self.sig_id_to_imports.iter() {
-            let mut desc = self.sig_id_to_description[&sig_id].clone();
+            let mut desc = self.sig_id_to_description[sig_id].clone();
 
             let mut v = vec![];
             let imports_len = imports.len() as u32;
@@ -198,11 +148,92 @@ impl SigSet for BedetSet {
                 v.append(desc.as_mut_vec());
             }
 
-            let mut sig_id_bytes = [0u32; 4];
-            sig_id_bytes[0] = *sig_id;
-            let sig_id = unsafe { *(sig_id_bytes.as_ptr() as *const SigId) };
-            ser.serialize_signature(sig_id, v);
+            ser.serialize_signature(sig_id_from_u32(*sig_id), v);
         }
         ser
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::sigset_deserializer::SigSetDeserializer;
+
+    #[test]
+    fn compile_serialize_and_match_many_signatures() {
+        const SIG_COUNT: u32 = 10_240;
+        const KEY_NAME: &str = r"\REGISTRY\MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+
+        let mut set = BedetSet::new_empty();
+        for i in 0..SIG_COUNT {
+            let attributes = vec![
+                member_to_hash("RegSetValue", "key_name", KEY_NAME),
+                member_to_hash("RegSetValue", "value_name", format!("value{i}")),
+            ];
+            let desc = format!(
+                "name: sig{i}\ndescription: sig {i}\nevent_type: RegSetValue\nattributes:\n  \
+                 key_name: {KEY_NAME}\n  value_name: value{i}\n"
+            );
+            set.append_signature(attributes, i, desc);
+        }
+
+        let bytes = set
+            .to_set_serializer()
+            .to_bytes(BedetSet::SET_MAGIC_U32)
+            .unwrap();
+        let set = SigSetDeserializer::new_with_buffer(bytes)
+            .unwrap()
+            .get_bedet_set()
+            .unwrap();
+
+        for i in [0, 31, 32, 5_000, SIG_COUNT - 1] {
+            let event = vec![
+                member_to_hash("RegSetValue", "pid", 4),
+                member_to_hash("RegSetValue", "value_name", format!("value{i}")),
+                member_to_hash("RegSetValue", "key_name", KEY_NAME),
+            ];
+            let reports = set.eval_event(event).unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].desc, format!("sig {i}"));
+        }
+
+        let event = vec![member_to_hash("RegSetValue", "key_name", KEY_NAME)];
+        assert!(set.eval_event(event).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unpacked_set_compiles_to_the_same_set() {
+        let mut set = BedetSet::new_empty();
+        for (i, value_name) in ["first", "second/value", "third"].iter().enumerate() {
+            let desc = format!(
+                "name: {value_name}\ndescription: sig {i}\nevent_type: RegSetValue\n\
+                 attributes:\n  value_name: {value_name}\n"
+            );
+            let attributes = vec![member_to_hash("RegSetValue", "value_name", value_name)];
+            set.append_signature(attributes, i as u32, desc);
+        }
+        let bytes = set
+            .to_set_serializer()
+            .to_bytes(BedetSet::SET_MAGIC_U32)
+            .unwrap();
+
+        let out_dir = std::env::temp_dir().join(format!("sfi_unpack_{}", std::process::id()));
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let out_dir = out_dir.to_str().unwrap();
+        let unpacked = SigSetDeserializer::new_with_buffer(bytes.clone())
+            .unwrap()
+            .get_bedet_set()
+            .unwrap();
+        assert_eq!(unpacked.unpack_to_dir(out_dir).unwrap(), 3);
+
+        let compiled = BedetSet::from_signatures(out_dir).unwrap();
+        std::fs::remove_dir_all(out_dir).unwrap();
+        assert_eq!(
+            compiled
+                .to_set_serializer()
+                .to_bytes(BedetSet::SET_MAGIC_U32)
+                .unwrap(),
+            bytes
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::sha256_utils::Sha256;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub(crate) type SigIndex = u32;
+
+// Inverted index used by sets which match a signature when all of its features (hashed event
+// attributes) are present. Instead of one bit per signature (which limits set to 32 signatures) each
+// feature keeps a posting list with ids of signatures containing it.
+//
+//--------------ALGORITHM------------------
+// 1) lets assume "RegSetValue+value_name+Evil" is feature with index 0 and it appears in signatures 2,3,7,11.
+// Then postings[0] == [2,3,7,11]
+//
+// 2) for each distinct feature found in file we walk its posting list and increment hit counter
+// of each signature on the list
+//
+// 3) signature is matched if its hit counter equals to number of its distinct features
+//
+// Cost of matching depends only on features found in file, not on the number of signatures
+pub(crate) struct FeatureIndex {
+    feature_to_index: BTreeMap<Sha256, u32>,
+    postings: Vec<Vec<SigIndex>>,
+    features_in_sig: BTreeMap<SigIndex, u32>,
+}
+
+impl FeatureIndex {
+    pub(crate) fn new_empty() -> Self {
+        Self {
+            feature_to_index: Default::default(),
+            postings: Default::default(),
+            features_in_sig: Default::default(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, sig_id: SigIndex, features: &[Sha256]) {
+        let unique: BTreeSet<&Sha256> = features.iter().collect();
+        for sha in unique.iter() {
+            let feature_id = match self.feature_to_index.get(*sha) {
+                Some(id) => *id,
+                None => {
+                    let id = self.postings.len() as u32;
+                    log::trace!("new feature: {id}");
+                    self.feature_to_index.insert(**sha, id);
+                    self.postings.push(vec![]);
+                    id
+                },
+            };
+            self.postings[feature_id as usize].push(sig_id);
+        }
+        self.features_in_sig.insert(sig_id, unique.len() as u32);
+    }
+
+    // returns ids of all matched signatures in ascending order
+    pub(crate) fn match_(&self, features: &[Sha256]) -> Vec<SigIndex> {
+        let found: BTreeSet<u32> = features
+            .iter()
+            .filter_map(|sha| self.feature_to_index.get(sha).copied())
+            .collect();
+
+        let mut hits: HashMap<SigIndex, u32> = HashMap::new();
+        for feature_id in found {
+            for sig_id in &self.postings[feature_id as usize] {
+                *hits.entry(*sig_id).or_default() += 1;
+            }
+        }
+
+        let mut matched: Vec<SigIndex> = hits
+            .into_iter()
+            .filter(|(sig_id, count)| self.features_in_sig.get(sig_id) == Some(count))
+            .map(|(sig_id, _)| sig_id)
+            .collect();
+        matched.sort_unstable();
+
+        log::trace!("matched {} sigs", matched.len());
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256_utils::sha256_from_vec;
+
+    fn feature(s: &str) -> Sha256 {
+        sha256_from_vec(s.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn matches_only_complete_signatures() {
+        let mut index = FeatureIndex::new_empty();
+        index.insert(0, &[feature("a"), feature("b")]);
+        index.insert(1, &[feature("b"), feature("c")]);
+        index.insert(2, &[feature("b"), feature("b")]);
+
+        assert_eq!(index.match_(&[feature("a"), feature("b")]), vec![0, 2]);
+        assert_eq!(
+            index.match_(&[feature("c"), feature("b"), feature("c")]),
+            vec![1, 2]
+        );
+        assert!(index.match_(&[feature("a"), feature("x")]).is_empty());
+    }
+
+    #[test]
+    fn more_than_32_signatures() {
+        let mut index = FeatureIndex::new_empty();
+        for i in 0..20_000u32 {
+            index.insert(i, &[feature(&format!("f{i}")), feature("shared")]);
+        }
+        assert_eq!(
+            index.match_(&[feature("shared"), feature("f19999")]),
+            vec![19_999]
+        );
+        assert_eq!(index.match_(&[feature("f40"), feature("shared")]), vec![40]);
+        assert!(index.match_(&[feature("f40")]).is_empty());
+    }
+}
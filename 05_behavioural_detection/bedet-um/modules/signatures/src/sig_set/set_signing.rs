@@ -0,0 +1,195 @@
+use crate::SigSetError;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::Digest;
+use std::sync::RwLock;
+
+// keys used by every set loaded for detection. Until they are configured nothing is trusted
+static TRUSTED_KEYS: RwLock<TrustedKeys> = RwLock::new(TrustedKeys::empty());
+
+/// Public keys which sets loaded for detection must be signed with. Each set file (behavioural set
+/// or container with behavioural section) has its Ed25519 signature in detached file "{set}.sig".
+/// Container is signed as whole file, with the same key as its other sections. Hashes of chunks
+/// of the file are signed, the same as by scanner, which verifies chunks as it reads them
+#[derive(Debug, Clone)]
+pub struct TrustedKeys {
+    keys: Vec<VerifyingKey>,
+    allow_untrusted: bool,
+}
+
+impl TrustedKeys {
+    pub const fn empty() -> Self {
+        Self {
+            keys: vec![],
+            allow_untrusted: false,
+        }
+    }
+
+    /// Public keys in hex, one per line. Empty lines and lines starting with '#' are skipped
+    pub fn from_file(path: &str) -> Result<Self, SigSetError> {
+        let mut keys = Self::empty();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            keys.add_key(line)?;
+        }
+        Ok(keys)
+    }
+
+    pub fn add_key(&mut self, public_key: &str) -> Result<(), SigSetError> {
+        let key = VerifyingKey::from_bytes(&key_from_hex(public_key)?)
+            .map_err(|e| SigSetError::IncorrectKeyError(e.to_string()))?;
+        self.keys.push(key);
+        Ok(())
+    }
+
+    /// Unsigned sets and sets not signed with trusted key are loaded anyway. Only warning is
+    /// logged
+    pub fn allow_untrusted(mut self, allow_untrusted: bool) -> Self {
+        self.allow_untrusted = allow_untrusted;
+        self
+    }
+
+    // data is content of set file, its signature is in detached file
+    pub(crate) fn verify(&self, set_path: &str, data: &[u8]) -> Result<(), SigSetError> {
+        let sig_path = signature_path(set_path);
+        let result = match std::fs::read(&sig_path) {
+            Ok(signature) => self.verify_signature(set_path, data, &signature),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(SigSetError::UnsignedSetError(set_path.into()))
+            },
+            Err(e) => Err(e.into()),
+        };
+
+        match result {
+            Err(SigSetError::UnsignedSetError(_) | SigSetError::UntrustedSetError(_))
+                if self.allow_untrusted =>
+            {
+                log::warn!("Loading untrusted set: {}", set_path);
+                Ok(())
+            },
+            result => result,
+        }
+    }
+
+    // signature file keeps Ed25519 signature followed by the table it signs. Whole set is read
+    // here, so all its chunks are compared with the table at once
+    fn verify_signature(
+        &self,
+        set_path: &str,
+        data: &[u8],
+        signature_file: &[u8],
+    ) -> Result<(), SigSetError> {
+        let untrusted = || SigSetError::UntrustedSetError(set_path.into());
+        if signature_file.len() < Signature::BYTE_SIZE {
+            return Err(untrusted());
+        }
+        let (signature, table) = signature_file.split_at(Signature::BYTE_SIZE);
+        let signature = Signature::from_slice(signature).map_err(|_| untrusted())?;
+        if !self
+            .keys
+            .iter()
+            .any(|key| key.verify(table, &signature).is_ok())
+        {
+            return Err(untrusted());
+        }
+        match table == chunks_table(data) {
+            true => Ok(()),
+            false => Err(untrusted()),
+        }
+    }
+}
+
+/// Keys used when sets are loaded for detection
+pub fn set_trusted_keys(keys: TrustedKeys) {
+    *TRUSTED_KEYS.write().unwrap_or_else(|e| e.into_inner()) = keys;
+}
+
+pub(crate) fn verify_set(set_path: &str, data: &[u8]) -> Result<(), SigSetError> {
+    TRUSTED_KEYS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .verify(set_path, data)
+}
+
+/// Writes detached signature of set file. Key file keeps 32 bytes of Ed25519 secret key in hex.
+/// Returns public key in hex, which must be trusted to load the set
+pub fn sign_set_file(set_path: &str, key_path: &str) -> Result<String, SigSetError> {
+    let secret = key_from_hex(std::fs::read_to_string(key_path)?.trim())?;
+    let key = SigningKey::from_bytes(&secret);
+    let table = chunks_table(&std::fs::read(set_path)?);
+    let mut signature_file = key.sign(&table).to_bytes().to_vec();
+    signature_file.extend_from_slice(&table);
+    std::fs::write(signature_path(set_path), signature_file)?;
+    Ok(hex::encode(key.verifying_key().as_bytes()))
+}
+
+// length of file (u64) followed by sha256 of each its chunk of 64 KB
+fn chunks_table(data: &[u8]) -> Vec<u8> {
+    const CHUNK_SIZE: usize = 0x10000;
+
+    let mut table = (data.len() as u64).to_le_bytes().to_vec();
+    for chunk in data.chunks(CHUNK_SIZE) {
+        table.extend_from_slice(&sha2::Sha256::digest(chunk));
+    }
+    table
+}
+
+fn signature_path(set_path: &str) -> String {
+    format!("{set_path}.sig")
+}
+
+fn key_from_hex(key: &str) -> Result<[u8; 32], SigSetError> {
+    hex::decode(key)?
+        .try_into()
+        .map_err(|_| SigSetError::IncorrectKeyError(format!("key must have 32 bytes: {key}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn only_set_signed_with_trusted_key_is_loaded() {
+        let dir = std::env::temp_dir();
+        let set_path = dir.join(format!("sfi_signed_{}.sset", std::process::id()));
+        let key_path = dir.join(format!("sfi_signed_{}.key", std::process::id()));
+        let (set_path, key_path) = (set_path.to_str().unwrap(), key_path.to_str().unwrap());
+        std::fs::write(set_path, b"set content").unwrap();
+        std::fs::write(key_path, SECRET_KEY).unwrap();
+
+        let mut keys = TrustedKeys::empty();
+        assert!(matches!(
+            keys.verify(set_path, b"set content"),
+            Err(SigSetError::UnsignedSetError(_))
+        ));
+
+        let public_key = sign_set_file(set_path, key_path).unwrap();
+        assert!(matches!(
+            keys.verify(set_path, b"set content"),
+            Err(SigSetError::UntrustedSetError(_))
+        ));
+
+        keys.add_key(&public_key).unwrap();
+        keys.verify(set_path, b"set content").unwrap();
+        assert!(matches!(
+            keys.verify(set_path, b"changed content"),
+            Err(SigSetError::UntrustedSetError(_))
+        ));
+        keys.clone()
+            .allow_untrusted(true)
+            .verify(set_path, b"changed content")
+            .unwrap();
+
+        for path in [
+            set_path.to_string(),
+            signature_path(set_path),
+            key_path.into(),
+        ] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{
+        sig_source::{split_documents, SigSource},
+        signature::SigBedet,
+    },
+    SigSetError,
+};
+use common::hasher::member_to_hash;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Problem found in signature file
+#[derive(Debug)]
+pub struct LintIssue {
+    pub path: PathBuf,
+    /// Index of YAML document (or list item) with signature
+    pub document: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = location(&self.path, self.document);
+        write!(f, "{location}: {}", self.message)
+    }
+}
+
+fn location(path: &Path, document: usize) -> String {
+    format!("{}, document {document}", path.display())
+}
+
+// first signature with given name or attributes, so duplicates point to it
+#[derive(Default)]
+struct Seen {
+    names: HashMap<String, String>,
+    hashes: HashMap<BTreeSet<Sha256>, String>,
+}
+
+/// Checks every signature of chosen files: yaml, event type and its attributes, and duplicates
+/// among signatures
+pub fn lint(source: &SigSource) -> Result<Vec<LintIssue>, SigSetError> {
+    let mut issues = vec![];
+    let mut seen = Seen::default();
+    for path in source.files()? {
+        let text = String::from_utf8_lossy(&std::fs::read(&path)?).into_owned();
+        let descriptions = match split_documents(&text) {
+            Ok(descriptions) => descriptions,
+            Err((document, e)) => {
+                issues.push(LintIssue {
+                    path,
+                    document,
+                    message: e.to_string(),
+                });
+                continue;
+            },
+        };
+
+        for (document, description) in descriptions.iter().enumerate() {
+            let location = location(&path, document);
+            for message in lint_signature(description, &location, &mut seen) {
+                issues.push(LintIssue {
+                    path: path.clone(),
+                    document,
+                    message,
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn lint_signature(description: &str, location: &str, seen: &mut Seen) -> Vec<String> {
+    let sig: SigBedet = match serde_yaml::from_str(description) {
+        Ok(sig) => sig,
+        Err(e) => return vec![SigSetError::from(e).to_string()],
+    };
+    let mut messages = vec![];
+    if let Err(e) = sig.verify() {
+        messages.push(e.to_string());
+    }
+
+    if let Some(first) = seen.names.get(&sig.sig_base.name) {
+        messages.push(format!(
+            "Duplicated name '{}', first in {first}",
+            sig.sig_base.name
+        ));
+    } else {
+        seen.names
+            .insert(sig.sig_base.name.clone(), location.into());
+    }
+
+    // signatures with the same attributes always match together
+    let hashes: BTreeSet<_> = sig
+        .attributes
+        .iter()
+        .map(|(attribute, value)| member_to_hash(&sig.event_type, attribute, value))
+        .collect();
+    if let Some(first) = seen.hashes.get(&hashes) {
+        messages.push(format!("Duplicated attributes, first in {first}"));
+    } else if !hashes.is_empty() {
+        seen.hashes.insert(hashes, location.into());
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_problem_is_reported_with_its_file() {
+        let dir = std::env::temp_dir().join(format!("sfi_bedet_lint_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sig = |name: &str, event_type: &str, attribute: &str| {
+            format!(
+                "name: {name}\ndescription: d\nevent_type: {event_type}\nattributes:\n  \
+                 {attribute}: value\n"
+            )
+        };
+        let files = [
+            ("1.sig", sig("a", "RegSetValue", "key_name")),
+            ("2.sig", sig("a", "RegSetValue", "value_name")),
+            ("3.sig", sig("b", "RegSetValue", "key_name")),
+            ("4.sig", sig("c", "RegCreateKey", "key_name")),
+            ("5.sig", sig("d", "FileCreate", "name")),
+            (
+                "6.sig",
+                "name: e\ndescription: e\nevent_type: FileCreate\nattributes: {}\n".into(),
+            ),
+            ("7.sig", "name: f\n".into()),
+            (
+                "8.yml",
+                format!(
+                    "{}---\n{}",
+                    sig("g", "FileCreate", "path"),
+                    sig("a", "FileCreate", "path")
+                ),
+            ),
+        ];
+        for (name, content) in &files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+
+        let issues = lint(&SigSource::new(dir.to_str().unwrap())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let issues: Vec<_> = issues
+            .iter()
+            .map(|issue| {
+                let name = issue.path.file_name().unwrap().to_str().unwrap();
+                format!("{name}:{}", issue.document)
+            })
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                "2.sig:0", "3.sig:0", "4.sig:0", "5.sig:0", "6.sig:0", "7.sig:0", "8.yml:1",
+                "8.yml:1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{sig_set::Description, SigSetError};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+// glob without '/' is matched with file name at any depth, other globs with path relative to
+// signature directory
+struct SourceGlob {
+    matcher: GlobMatcher,
+    whole_path: bool,
+}
+
+impl SourceGlob {
+    fn new(glob: &str) -> Result<Self, SigSetError> {
+        let matcher = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        Ok(Self {
+            matcher,
+            whole_path: glob.contains('/'),
+        })
+    }
+
+    fn is_match(&self, relative_path: &Path) -> bool {
+        match (self.whole_path, relative_path.file_name()) {
+            (false, Some(name)) => self.matcher.is_match(name),
+            _ => self.matcher.is_match(relative_path),
+        }
+    }
+}
+
+/// Signature directory, e.g. "family/variant/*.yml". Files are read from its whole tree in
+/// order of paths. Each file keeps one signature, several YAML documents or YAML list of
+/// signatures
+pub struct SigSource {
+    dir: PathBuf,
+    include: Vec<SourceGlob>,
+    exclude: Vec<SourceGlob>,
+}
+
+/// Signature read from source with file and document it comes from
+pub(crate) struct SourceSig {
+    pub(crate) path: PathBuf,
+    pub(crate) document: usize,
+    pub(crate) description: Description,
+}
+
+impl SigSource {
+    /// Every file of directory tree
+    pub fn new(path_to_dir: &str) -> Self {
+        Self {
+            dir: path_to_dir.into(),
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+
+    /// Only files matching any of include globs are read
+    pub fn include(mut self, glob: &str) -> Result<Self, SigSetError> {
+        self.include.push(SourceGlob::new(glob)?);
+        Ok(self)
+    }
+
+    /// Files matching any of exclude globs are skipped, even if they are included
+    pub fn exclude(mut self, glob: &str) -> Result<Self, SigSetError> {
+        self.exclude.push(SourceGlob::new(glob)?);
+        Ok(self)
+    }
+
+    // ids are given in order of paths, so unpacked set is compiled with the same ids
+    pub(crate) fn files(&self) -> Result<Vec<PathBuf>, SigSetError> {
+        let mut paths = vec![];
+        collect_files(&self.dir, &mut paths)?;
+        paths.retain(|path| self.is_chosen(path));
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn is_chosen(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        let is_included =
+            self.include.is_empty() || self.include.iter().any(|g| g.is_match(relative));
+        is_included && !self.exclude.iter().any(|g| g.is_match(relative))
+    }
+
+    // every signature of every file. First file which can't be read stops reading
+    pub(crate) fn read(&self) -> Result<Vec<SourceSig>, SigSetError> {
+        let mut sigs = vec![];
+        for path in self.files()? {
+            let descriptions = read_documents(&path)?;
+            sigs.extend(
+                descriptions
+                    .into_iter()
+                    .enumerate()
+                    .map(|(document, description)| SourceSig {
+                        path: path.clone(),
+                        document,
+                        description,
+                    }),
+            );
+        }
+        Ok(sigs)
+    }
+
+    // signatures are added in order with consecutive ids. Error tells which signature caused it
+    pub(crate) fn compile(
+        &self,
+        mut add: impl FnMut(u32, Description) -> Result<(), SigSetError>,
+    ) -> Result<(), SigSetError> {
+        for (sig_id, sig) in (0..).zip(self.read()?) {
+            add(sig_id, sig.description).map_err(|e| sig_file_error(&sig.path, sig.document, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl SourceSig {
+    pub(crate) fn error(&self, error: SigSetError) -> SigSetError {
+        sig_file_error(&self.path, self.document, error)
+    }
+}
+
+fn sig_file_error(path: &Path, document: usize, error: SigSetError) -> SigSetError {
+    SigSetError::SignatureFileError {
+        path: path.display().to_string(),
+        document,
+        source: Box::new(error),
+    }
+}
+
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), SigSetError> {
+    for entry_res in std::fs::read_dir(dir)? {
+        let entry = entry_res?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), paths)?;
+        } else if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// signatures of file. Error tells which document of file is broken
+pub(crate) fn read_documents(path: &Path) -> Result<Vec<Description>, SigSetError> {
+    let text = String::from_utf8_lossy(&std::fs::read(path)?).into_owned();
+    split_documents(&text).map_err(|(document, e)| sig_file_error(path, document, e))
+}
+
+// File with single signature is kept as it is, so unpacked set is compiled to the same set.
+// Other documents and list items are serialized again, each to its own description. Empty
+// documents are skipped
+pub(crate) fn split_documents(text: &str) -> Result<Vec<Description>, (usize, SigSetError)> {
+    let mut documents = vec![];
+    for (i, document) in serde_yaml::Deserializer::from_str(text).enumerate() {
+        let value = serde_yaml::Value::deserialize(document).map_err(|e| (i, e.into()))?;
+        documents.push(value);
+    }
+
+    if let [serde_yaml::Value::Mapping(_)] = documents.as_slice() {
+        return Ok(vec![text.to_string()]);
+    }
+
+    let mut descriptions = vec![];
+    for value in documents {
+        let values = match value {
+            serde_yaml::Value::Null => vec![],
+            serde_yaml::Value::Sequence(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let index = descriptions.len();
+            descriptions.push(serde_yaml::to_string(&value).map_err(|e| (index, e.into()))?);
+        }
+    }
+    Ok(descriptions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sig_set::{bedet_set::BedetSet, SigSet};
+
+    #[test]
+    fn documents_and_lists_are_split() {
+        let single = "name: a\ndescription: a\n";
+        assert_eq!(split_documents(single).unwrap(), [single]);
+
+        let multi = "name: a\n---\nname: b\n---\n- name: c\n- name: d\n---\n";
+        assert_eq!(
+            split_documents(multi).unwrap(),
+            ["name: a\n", "name: b\n", "name: c\n", "name: d\n"]
+        );
+
+        let broken = "name: a\n---\nname: [\n";
+        assert_eq!(split_documents(broken).unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn tree_is_filtered_with_globs() {
+        let dir = std::env::temp_dir().join(format!("sfi_source_{}", std::process::id()));
+        for sub_dir in ["emotet/a", "emotet/old", "qakbot"] {
+            std::fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+        for file in [
+            "emotet/a/1.yml",
+            "emotet/a/2.yaml",
+            "emotet/old/1.yml",
+            "qakbot/1.yml",
+            "readme.md",
+        ] {
+            std::fs::write(dir.join(file), "name: a\n").unwrap();
+        }
+
+        let files = |source: SigSource| -> Vec<String> {
+            source
+                .files()
+                .unwrap()
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(&dir).unwrap();
+                    path.to_str().unwrap().replace('\\', "/")
+                })
+                .collect()
+        };
+        let source = || SigSource::new(dir.to_str().unwrap());
+        assert_eq!(files(source()).len(), 5);
+        assert_eq!(
+            files(source().include("*.yml").unwrap()),
+            ["emotet/a/1.yml", "emotet/old/1.yml", "qakbot/1.yml"]
+        );
+        assert_eq!(
+            files(
+                source()
+                    .include("emotet/*/*")
+                    .unwrap()
+                    .exclude("emotet/old/**")
+                    .unwrap()
+            ),
+            ["emotet/a/1.yml", "emotet/a/2.yaml"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn error_tells_file_and_document() {
+        let dir = std::env::temp_dir().join(format!("sfi_bedet_source_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sigs = "- name: a\n  description: a\n  event_type: FileCreate\n  attributes:\n    \
+                    path: a.exe\n- name: b\n  description: b\n  event_type: FileCreate\n  \
+                    attributes: {}\n";
+        std::fs::write(dir.join("sigs.yml"), sigs).unwrap();
+
+        let result = BedetSet::from_source(&SigSource::new(dir.to_str().unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(SigSetError::SignatureFileError { path, document, .. }) => {
+                assert!(path.ends_with("sigs.yml"));
+                assert_eq!(document, 1);
+            },
+            _ => panic!("compiled broken signature"),
+        }
+    }
+}
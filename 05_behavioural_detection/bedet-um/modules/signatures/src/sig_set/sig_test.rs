@@ -0,0 +1,158 @@
+use crate::{
+    sig_set::{
+        bedet_set::BedetSet,
+        sig_source::{split_documents, SigSource},
+        signature::SigBase,
+        SigSet,
+    },
+    SigSetError,
+};
+use common::hasher::member_to_hash;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Sample declared in signature and whether it was matched as declared
+#[derive(Debug)]
+pub struct SampleResult {
+    pub path: PathBuf,
+    /// Index of YAML document (or list item) with signature
+    pub document: usize,
+    pub name: String,
+    pub sample: PathBuf,
+    /// Sample must be matched by signature
+    pub positive: bool,
+    pub matched: bool,
+}
+
+impl SampleResult {
+    pub fn passed(&self) -> bool {
+        self.positive == self.matched
+    }
+}
+
+impl std::fmt::Display for SampleResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.positive {
+            "positive"
+        } else {
+            "negative"
+        };
+        let result = if self.passed() { "ok" } else { "FAILED" };
+        write!(
+            f,
+            "{}, document {}: '{}' {kind} sample {}: {result}",
+            self.path.display(),
+            self.document,
+            self.name,
+            self.sample.display(),
+        )
+    }
+}
+
+// event of sample. Its members are hashed like members of event reported by driver
+#[derive(Debug, Deserialize)]
+struct SampleEvent {
+    event_type: String,
+    members: BTreeMap<String, String>,
+}
+
+impl SampleEvent {
+    fn hash_members(&self) -> Vec<crate::sha256_utils::Sha256> {
+        self.members
+            .iter()
+            .map(|(member, value)| member_to_hash(&self.event_type, member, value))
+            .collect()
+    }
+}
+
+// names of signatures matched by any event of sample
+fn eval_sample(set: &BedetSet, sample: &Path) -> Result<Vec<String>, SigSetError> {
+    let text = String::from_utf8_lossy(&std::fs::read(sample)?).into_owned();
+    let mut names = vec![];
+    for event in split_documents(&text).map_err(|(_, e)| e)? {
+        let event: SampleEvent = serde_yaml::from_str(&event)?;
+        let reports = set.eval_event(event.hash_members())?;
+        names.extend(reports.into_iter().map(|report| report.name));
+    }
+    Ok(names)
+}
+
+/// Compiles chosen signatures in memory and evaluates samples declared in their "tests".
+/// Signature is found among detections by its name
+pub fn test_source(source: &SigSource) -> Result<Vec<SampleResult>, SigSetError> {
+    let sigs = source.read()?;
+    let mut set = BedetSet::new_empty();
+    for (sig_id, sig) in (0..).zip(&sigs) {
+        set.add_description(sig_id, sig.description.clone())
+            .map_err(|e| sig.error(e))?;
+    }
+
+    let mut results = vec![];
+    for sig in &sigs {
+        let base: SigBase =
+            serde_yaml::from_str(&sig.description).map_err(|e| sig.error(e.into()))?;
+        let samples = base.tests.positive.iter().map(|sample| (sample, true));
+        let samples = samples.chain(base.tests.negative.iter().map(|sample| (sample, false)));
+        for (sample, positive) in samples {
+            let sample = sig.path.parent().unwrap_or(Path::new("")).join(sample);
+            let names = eval_sample(&set, &sample).map_err(|e| sig.error(e))?;
+            results.push(SampleResult {
+                path: sig.path.clone(),
+                document: sig.document,
+                name: base.name.clone(),
+                matched: names.contains(&base.name),
+                sample,
+                positive,
+            });
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_matched_as_declared() {
+        let dir = std::env::temp_dir().join(format!("sfi_bedet_sig_test_{}", std::process::id()));
+        let samples = dir.join("samples");
+        std::fs::create_dir_all(dir.join("sigs")).unwrap();
+        std::fs::create_dir_all(&samples).unwrap();
+        let event = |value_name: &str| {
+            format!(
+                "event_type: RegSetValue\nmembers:\n  pid: 123\n  key_name: Run\n  value_name: \
+                 {value_name}\n  data_type: 1\n"
+            )
+        };
+        let evil = format!("{}---\n{}", event("other"), event("evil"));
+        std::fs::write(samples.join("evil.yml"), evil).unwrap();
+        std::fs::write(samples.join("clean.yml"), event("clean")).unwrap();
+
+        let sigs = "- name: run\n  description: r\n  event_type: RegSetValue\n  attributes:\n    \
+                    key_name: Run\n    value_name: evil\n  tests:\n    positive: \
+                    [../samples/evil.yml]\n    negative: [../samples/clean.yml]\n- name: any\n  \
+                    description: a\n  event_type: RegSetValue\n  attributes:\n    key_name: Run\n  \
+                    tests:\n    negative: [../samples/clean.yml]\n";
+        std::fs::write(dir.join("sigs").join("run.yml"), sigs).unwrap();
+
+        let results = test_source(&SigSource::new(dir.join("sigs").to_str().unwrap())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let results: Vec<_> = results
+            .iter()
+            .map(|r| (r.name.as_str(), r.positive, r.passed()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("run", true, true),
+                ("run", false, true),
+                ("any", false, false)
+            ]
+        );
+    }
+}
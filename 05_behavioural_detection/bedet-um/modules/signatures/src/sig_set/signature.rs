@@ -1,3 +1,5 @@
+use crate::SigSetError;
+use common::event::event_member_names;
 use common_um::detection::DetectionReport;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -6,6 +8,30 @@ use std::collections::BTreeMap;
 pub struct SigBase {
     pub name: String,
     pub description: String,
+    /// When more signatures match, detections with higher priority are reported first
+    #[serde(default)]
+    pub priority: u32,
+    /// Samples the signature is tested with by "signature test"
+    #[serde(default, skip_serializing_if = "SigSamples::is_empty")]
+    pub tests: SigSamples,
+}
+
+/// Paths relative to directory of signature file. Each sample keeps events in YAML: "event_type"
+/// and map of "members" (several events as several documents)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SigSamples {
+    /// Samples which signature must match
+    #[serde(default)]
+    pub positive: Vec<String>,
+    /// Samples which signature must not match
+    #[serde(default)]
+    pub negative: Vec<String>,
+}
+
+impl SigSamples {
+    pub fn is_empty(&self) -> bool {
+        self.positive.is_empty() && self.negative.is_empty()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,14 +42,43 @@ pub struct SigBedet {
     pub attributes: BTreeMap<String, String>,
 }
 
+impl SigBedet {
+    // event type and attributes must be ones of events reported by driver, otherwise signature
+    // never matches
+    pub(crate) fn verify(&self) -> Result<(), SigSetError> {
+        let Some(member_names) = event_member_names(&self.event_type) else {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("Unknown event type '{}'", self.event_type),
+            });
+        };
+        if self.attributes.is_empty() {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: "No attributes".into(),
+            });
+        }
+        if let Some(attribute) = self
+            .attributes
+            .keys()
+            .find(|attribute| !member_names.contains(&attribute.as_str()))
+        {
+            return Err(SigSetError::IncorrectSignatureError {
+                info: format!("Unknown attribute '{attribute}' of {}", self.event_type),
+            });
+        }
+        Ok(())
+    }
+}
+
 impl From<SigBedet> for DetectionReport {
     fn from(sig: SigBedet) -> Self {
         Self {
+            name: sig.sig_base.name,
             desc: sig.sig_base.description,
             cause: format!(
                 "Detected Event: {}: {{ {:?} }}",
                 sig.event_type, sig.attributes
             ),
+            priority: sig.sig_base.priority,
         }
     }
 }
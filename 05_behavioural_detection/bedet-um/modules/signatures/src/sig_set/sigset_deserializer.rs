@@ -1,7 +1,11 @@
 use crate::{
     sha256_utils::Sha256,
     sig_set::{
-        bedet_set::BedetSet, signature::SigBedet, HeurSigHeader, SetHeader, SigHeader, SigSet,
+        bedet_set::BedetSet,
+        set_signing,
+        signature::SigBedet,
+        sigset_container::{magic_to_string, SetContainer},
+        HeurSigHeader, SetHeader, SigHeader, SigSet,
     },
     SigSetError,
 };
@@ -20,7 +24,20 @@ impl SigSetDeserializer {
     // 4 MB
     const HEADER_SIZE: usize = size_of::<SetHeader>();
 
+    // sets are loaded for detection only from file signed with trusted key (see set_signing).
+    // Signature is verified over the same copy of file which is then parsed
     pub fn new(name: &str) -> Result<Self, SigSetError> {
+        let buffer = Self::read_file(name)?;
+        set_signing::verify_set(name, &buffer)?;
+        Self::new_in_file(buffer)
+    }
+
+    // for tools which only read signatures (list, unpack). Signature of file is not verified
+    pub fn new_untrusted(name: &str) -> Result<Self, SigSetError> {
+        Self::new_in_file(Self::read_file(name)?)
+    }
+
+    fn read_file(name: &str) -> Result<Vec<u8>, SigSetError> {
         let mut file = std::fs::File::open(name)?;
         let metadata = file.metadata()?;
 
@@ -31,12 +48,31 @@ impl SigSetDeserializer {
         }
 
         let mut buffer = vec![0; metadata.len() as usize];
-        let _ = file.read(&mut buffer)?;
+        file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    // file is behavioural set or container with behavioural section
+    fn new_in_file(buffer: Vec<u8>) -> Result<Self, SigSetError> {
+        if !SetContainer::is_container(&buffer) {
+            return Self::new_with_buffer(buffer);
+        }
+
+        // container keeps also sets of other types, only behavioural one is used here
+        let (_, section) = SetContainer::read_sections(&buffer)?
+            .into_iter()
+            .find(|(magic, _)| *magic == BedetSet::SET_MAGIC_U32)
+            .ok_or(SigSetError::NoSuchSectionError(magic_to_string(
+                BedetSet::SET_MAGIC_U32,
+            )))?;
+        Self::new_with_buffer(buffer[section].to_vec())
+    }
 
-        Self::new_with_buffer(buffer)
+    pub(super) fn header(&self) -> &SetHeader {
+        &self.ser_set_header
     }
 
-    fn new_with_buffer(mut data: Vec<u8>) -> Result<Self, SigSetError> {
+    pub(crate) fn new_with_buffer(mut data: Vec<u8>) -> Result<Self, SigSetError> {
         if data.len() < Self::HEADER_SIZE {
             return Err(SigSetError::IncorrectFileSizeError {
                 size: data.len() as u64,
@@ -62,7 +98,7 @@ impl SigSetDeserializer {
 
     fn verify_checksum(&self) -> Result<(), SigSetError> {
         let mut hasher = sha2::Sha256::new();
-        hasher.update(&self.ser_set_header.elem_count.to_le_bytes());
+        hasher.update(self.ser_set_header.elem_count.to_le_bytes());
         hasher.update(&self.data);
         let mut checksum_buf = Sha256::default();
         checksum_buf.copy_from_slice(&hasher.finalize()[..]);
@@ -110,7 +146,7 @@ impl SigSetDeserializer {
 
             let start_offset = sig_header.offset as usize + start_of_data;
             let end_offset = start_offset + sig_header.size as usize;
-            if end_offset > self.data.len() {
+            if end_offset > self.data.len() || sig_header.size < size_of::<u32>() as u32 {
                 return Err(SigSetError::IncorrectSignatureSizeError {
                     size: sig_header.size,
                 });
@@ -119,7 +155,7 @@ impl SigSetDeserializer {
             let mut curr_offset = start_offset;
             let imports_count: u32 = bincode::serde::decode_from_slice(
                 &self.data[start_offset..],
-                bincode::config::standard(),
+                bincode::config::legacy(),
             )?
             .0;
             log::debug!("imports_count: {:?}", imports_count);
@@ -128,6 +164,11 @@ impl SigSetDeserializer {
 
             let mut imports_vec = vec![];
             //let signature_data = self.data[curr_offset..];
+            if curr_offset + imports_count as usize * size_of::<Sha256>() > end_offset {
+                return Err(SigSetError::IncorrectSignatureSizeError {
+                    size: sig_header.size,
+                });
+            }
             for _i in 0..imports_count {
                 let import: Sha256 = bincode::serde::decode_from_slice(
                     &self.data[curr_offset..],
@@ -155,3 +196,49 @@ impl SigSetDeserializer {
         Ok(heurset)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_signed_set_is_loaded_for_detection() {
+        let dir = std::env::temp_dir();
+        let set_path = dir.join(format!("sfi_bedet_signed_{}.bset", std::process::id()));
+        let key_path = dir.join(format!("sfi_bedet_signed_{}.key", std::process::id()));
+        let (set_path, key_path) = (set_path.to_str().unwrap(), key_path.to_str().unwrap());
+        let desc = "name: Run\ndescription: run\nevent_type: RegSetValue\nattributes:\n  \
+                    value_name: run\n";
+        let mut set = BedetSet::new_empty();
+        set.add_description(0, desc.to_string()).unwrap();
+        set.to_set_serializer()
+            .serialize_bedet_set(set_path)
+            .unwrap();
+        std::fs::write(
+            key_path,
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            SigSetDeserializer::new(set_path),
+            Err(SigSetError::UnsignedSetError(_))
+        ));
+        SigSetDeserializer::new_untrusted(set_path).unwrap();
+
+        let mut keys = set_signing::TrustedKeys::empty();
+        keys.add_key(&set_signing::sign_set_file(set_path, key_path).unwrap())
+            .unwrap();
+        set_signing::set_trusted_keys(keys);
+        let set = SigSetDeserializer::new(set_path).unwrap();
+        assert_eq!(set.get_bedet_set().unwrap().descriptions().len(), 1);
+
+        for path in [
+            set_path.to_string(),
+            format!("{set_path}.sig"),
+            key_path.into(),
+        ] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
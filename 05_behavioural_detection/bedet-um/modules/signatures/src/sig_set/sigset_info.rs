@@ -0,0 +1,62 @@
+use crate::{
+    sha256_utils::Sha256,
+    sig_set::{
+        signature::SigBedet, sigset_container::magic_to_string,
+        sigset_deserializer::SigSetDeserializer,
+    },
+    SigSetError,
+};
+
+/// Signature as it is listed
+#[derive(Debug)]
+pub struct SigInfo {
+    pub name: String,
+    pub description: String,
+    /// event type followed by "attribute: value" of each attribute
+    pub features: Vec<String>,
+}
+
+impl From<SigBedet> for SigInfo {
+    fn from(sig: SigBedet) -> Self {
+        let mut features = vec![sig.event_type];
+        features.extend(
+            sig.attributes
+                .iter()
+                .map(|(attribute, value)| format!("{attribute}: {value}")),
+        );
+        Self {
+            name: sig.sig_base.name,
+            description: sig.sig_base.description,
+            features,
+        }
+    }
+}
+
+/// Header and signatures of behavioural set, or of its section of container
+#[derive(Debug)]
+pub struct SetInfo {
+    pub magic: String,
+    pub checksum: Sha256,
+    pub elem_count: u32,
+    pub signatures: Vec<SigInfo>,
+}
+
+impl SetInfo {
+    pub fn read(path: &str) -> Result<SetInfo, SigSetError> {
+        let des = SigSetDeserializer::new_untrusted(path)?;
+        let signatures = des
+            .get_bedet_set()?
+            .descriptions()
+            .into_iter()
+            .map(|desc| Ok(serde_yaml::from_str::<SigBedet>(desc)?.into()))
+            .collect::<Result<_, SigSetError>>()?;
+
+        let header = des.header();
+        Ok(Self {
+            magic: magic_to_string(header.magic),
+            checksum: header.checksum,
+            elem_count: header.elem_count,
+            signatures,
+        })
+    }
+}
@@ -40,6 +40,12 @@ impl SigSetSerializer {
 
     fn serialize(&self, set_name: &str, magic: u32) -> Result<usize, SigSetError> {
         let mut file = std::fs::File::create(set_name)?;
+        file.write_all(&self.to_bytes(magic)?)?;
+        Ok(self.sig_headers_vec.len())
+    }
+
+    pub(crate) fn to_bytes(&self, magic: u32) -> Result<Vec<u8>, SigSetError> {
+        let mut bytes = vec![];
 
         let mut checksum_buf = Sha256::default();
         checksum_buf.copy_from_slice(&self.calculate_checksum()?);
@@ -51,27 +57,27 @@ impl SigSetSerializer {
         };
 
         let header = bincode::serde::encode_to_vec(&set_header, bincode::config::legacy())?;
-        file.write_all(&header)?;
+        bytes.write_all(&header)?;
 
         //write info about each sig
         for header in &self.sig_headers_vec {
-            let data = bincode::serde::encode_to_vec(&header, bincode::config::legacy())?;
-            file.write_all(&data)?;
+            let data = bincode::serde::encode_to_vec(header, bincode::config::legacy())?;
+            bytes.write_all(&data)?;
         }
 
         //write descriptions to file
-        file.write_all(&self.descriptions)?;
-        Ok(self.sig_headers_vec.len())
+        bytes.write_all(&self.descriptions)?;
+        Ok(bytes)
     }
 
     fn calculate_checksum(&self) -> Result<Sha256, SigSetError> {
         let mut hasher = sha2::Sha256::new();
 
-        hasher.update(&(self.sig_headers_vec.len() as u32).to_le_bytes());
+        hasher.update((self.sig_headers_vec.len() as u32).to_le_bytes());
 
         for header in &self.sig_headers_vec {
             hasher.update(&bincode::serde::encode_to_vec(
-                &header,
+                header,
                 bincode::config::legacy(),
             )?);
         }
@@ -8,6 +8,20 @@ pub mod registry_set_value;
 
 use crate::{deserializer::Deserializer, hasher::MemberHasher, serializer::Serializer};
 pub use file_create::FileCreateEvent;
+use image_load::ImageLoadEvent;
+use process_create::ProcessCreateEvent;
+use registry_set_value::RegistrySetValueEvent;
+
+/// Names of hashed members of event with given name, None if there is no such event
+pub fn event_member_names(event_name: &str) -> Option<&'static [&'static str]> {
+    match event_name {
+        FileCreateEvent::EVENT_NAME => Some(FileCreateEvent::MEMBER_NAMES),
+        ImageLoadEvent::EVENT_NAME => Some(ImageLoadEvent::MEMBER_NAMES),
+        ProcessCreateEvent::EVENT_NAME => Some(ProcessCreateEvent::MEMBER_NAMES),
+        RegistrySetValueEvent::EVENT_NAME => Some(RegistrySetValueEvent::MEMBER_NAMES),
+        _ => None,
+    }
+}
 
 pub fn get_event_type(bytes: &[u8]) -> u32 {
     u32::from_blob(bytes)
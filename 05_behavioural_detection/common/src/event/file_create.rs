@@ -40,6 +40,7 @@ impl<'a> Deserializer for FileCreateEvent {
 
 impl MemberHasher for FileCreateEvent {
     const EVENT_NAME: &'static str = "FileCreate";
+    const MEMBER_NAMES: &'static [&'static str] = &["path"];
 
     fn hash_members(&self) -> Vec<Sha256> {
         let mut v = Vec::new();
@@ -75,6 +75,7 @@ impl<'a> Deserializer for ImageLoadEvent {
 
 impl MemberHasher for ImageLoadEvent {
     const EVENT_NAME: &'static str = "ImageLoad";
+    const MEMBER_NAMES: &'static [&'static str] = &["pid", "image_base", "image_size", "path"];
 
     fn hash_members(&self) -> Vec<Sha256> {
         let mut v = Vec::new();
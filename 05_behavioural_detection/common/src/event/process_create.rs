@@ -66,6 +66,7 @@ impl<'a> Deserializer for ProcessCreateEvent {
 
 impl MemberHasher for ProcessCreateEvent {
     const EVENT_NAME: &'static str = "ProcessCreate";
+    const MEMBER_NAMES: &'static [&'static str] = &["pid", "parent_id", "path"];
 
     fn hash_members(&self) -> Vec<Sha256> {
         let mut v = Vec::new();
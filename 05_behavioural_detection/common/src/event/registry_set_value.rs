@@ -165,6 +165,8 @@ impl<'a> Deserializer for RegistrySetValueEvent {
 
 impl MemberHasher for RegistrySetValueEvent {
     const EVENT_NAME: &'static str = "RegSetValue";
+    const MEMBER_NAMES: &'static [&'static str] =
+        &["pid", "tid", "key_name", "value_name", "data_type", "data"];
 
     fn hash_members(&self) -> Vec<Sha256> {
         let mut v = Vec::new();
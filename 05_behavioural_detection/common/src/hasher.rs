@@ -4,6 +4,8 @@ use alloc::vec::Vec;
 
 pub trait MemberHasher {
     const EVENT_NAME: &'static str;
+    /// Names of members which are hashed, so signatures can refer to them
+    const MEMBER_NAMES: &'static [&'static str];
 
     fn hash_members(&self) -> Vec<Sha256>;
 }